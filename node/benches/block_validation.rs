@@ -0,0 +1,83 @@
+//! Measures how many blocks per second are validated by a single thread and by several of them,
+//! which is how the block downloader workers validate the blocks they receive during the IBD.
+//! Run it with `cargo bench -p node --bench block_validation -- [blocks_file]`. By default it uses
+//! the blocks stored for the IBD tests.
+
+use node::blocks::blockchain::Block;
+use node::node::block_validation::validate_blocks_in_parallel;
+use std::{env, fs, thread, time::Instant};
+
+const DEFAULT_BLOCKS_FILE: &str = "tests_txt/ibd_test_blocks.bin";
+const ROUNDS: usize = 5;
+
+/// Reads the blocks stored one after the other in the file, stopping at the first one that can not be read.
+fn read_blocks(file_path: &str) -> Vec<Block> {
+    let bytes = match fs::read(file_path) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            eprintln!("No se pudo leer {file_path}: {error}");
+            return Vec::new();
+        }
+    };
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    while let Ok(block) = Block::from_bytes(&bytes[start..]) {
+        start += block.amount_of_bytes();
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Returns the best throughput, in blocks per second, of validating every block with the given amount of threads.
+fn measure(blocks: &[Block], threads: usize) -> f64 {
+    let mut best_rate: f64 = 0.0;
+    for _ in 0..ROUNDS {
+        let start = Instant::now();
+        if let Err(error) = validate_blocks_in_parallel(blocks, threads) {
+            eprintln!("Se encontro un bloque invalido: {:?}", error);
+            return 0.0;
+        }
+        let rate = blocks.len() as f64 / start.elapsed().as_secs_f64();
+        best_rate = best_rate.max(rate);
+    }
+    best_rate
+}
+
+fn main() {
+    let file_path = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .unwrap_or(DEFAULT_BLOCKS_FILE.to_string());
+    let blocks = read_blocks(&file_path);
+    if blocks.is_empty() {
+        eprintln!("No hay bloques en {file_path}");
+        return;
+    }
+    let transactions: usize = blocks
+        .iter()
+        .map(|block| block.get_transactions().len())
+        .sum();
+    println!(
+        "{} blocks with {} transactions read from {}",
+        blocks.len(),
+        transactions,
+        file_path
+    );
+
+    let available_threads = thread::available_parallelism().map_or(4, |threads| threads.get());
+    let mut thread_counts = vec![1, 2, 4, available_threads];
+    thread_counts.retain(|threads| *threads <= available_threads);
+    thread_counts.dedup();
+
+    let single_thread_rate = measure(&blocks, 1);
+    for threads in thread_counts {
+        let rate = match threads {
+            1 => single_thread_rate,
+            _ => measure(&blocks, threads),
+        };
+        println!(
+            "{threads} threads: {rate:.0} blocks/s ({:.2}x)",
+            rate / single_thread_rate
+        );
+    }
+}
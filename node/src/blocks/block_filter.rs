@@ -0,0 +1,261 @@
+use crate::blocks::blockchain::Block;
+use crate::utils::{btc_errors::BlockChainError, variable_length_integer::VarLenInt};
+use bitcoin_hashes::{sha256d, siphash24, Hash};
+use std::collections::HashSet;
+
+/// Filter type of the basic filters defined in BIP158.
+pub const BASIC_FILTER_TYPE: u8 = 0;
+/// Basic filter of the testnet genesis block, whose block is not stored by the node.
+pub const GENESIS_BLOCK_FILTER: [u8; 4] = [0x01, 0x9d, 0xfc, 0xa8];
+const FALSE_POSITIVE_BITS: u8 = 19;
+const FALSE_POSITIVE_RATE_INVERSE: u64 = 784931;
+const OP_RETURN: u8 = 0x6a;
+
+/// Basic compact block filter (BIP158). It holds a Golomb-Rice coded set with the output scripts a block
+/// creates and the ones its inputs spend, so a light client can tell whether the block may be relevant to it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BlockFilter {
+    element_count: usize,
+    encoded_set: Vec<u8>,
+}
+
+/// Writes the bits of a Golomb-Rice coded set, most significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    used_bits: u8,
+}
+
+/// Reads the bits of a Golomb-Rice coded set, most significant bit first.
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl BitWriter {
+    fn write_bit(&mut self, bit: bool) {
+        if self.bytes.is_empty() || self.used_bits == 8 {
+            self.bytes.push(0);
+            self.used_bits = 0;
+        }
+        if bit {
+            if let Some(last) = self.bytes.last_mut() {
+                *last |= 0x80 >> self.used_bits;
+            }
+        }
+        self.used_bits += 1;
+    }
+
+    fn write_bits(&mut self, value: u64, amount: u8) {
+        for bit in (0..amount).rev() {
+            self.write_bit((value >> bit) & 1 == 1);
+        }
+    }
+}
+
+impl<'a> BitReader<'a> {
+    fn read_bit(&mut self) -> Option<bool> {
+        let byte = self.bytes.get(self.position / 8)?;
+        let bit = byte & (0x80 >> (self.position % 8)) != 0;
+        self.position += 1;
+        Some(bit)
+    }
+
+    fn read_bits(&mut self, amount: u8) -> Option<u64> {
+        let mut value = 0;
+        for _ in 0..amount {
+            value = (value << 1) | self.read_bit()? as u64;
+        }
+        Some(value)
+    }
+}
+
+impl BlockFilter {
+    /// Creates the basic filter of the block, given the scripts of the outputs its inputs spend. Empty
+    /// scripts and the outputs that start with OP_RETURN are left out.
+    pub fn new(block: &Block, spent_scripts: &[Vec<u8>]) -> BlockFilter {
+        let mut elements: HashSet<&[u8]> = HashSet::new();
+        for tx in block.get_transactions() {
+            for tx_out in &tx.tx_out {
+                if matches!(tx_out.pk_script.first(), Some(op) if *op != OP_RETURN) {
+                    elements.insert(&tx_out.pk_script);
+                }
+            }
+        }
+        for script in spent_scripts {
+            if !script.is_empty() {
+                elements.insert(script);
+            }
+        }
+
+        let keys = filter_keys(&block.header_hash());
+        let element_count = elements.len();
+        let mut values: Vec<u64> = elements
+            .into_iter()
+            .map(|element| hash_to_range(keys, element, element_count))
+            .collect();
+        values.sort_unstable();
+
+        let mut writer = BitWriter::default();
+        let mut last_value = 0;
+        for value in values {
+            let delta = value - last_value;
+            for _ in 0..(delta >> FALSE_POSITIVE_BITS) {
+                writer.write_bit(true);
+            }
+            writer.write_bit(false);
+            writer.write_bits(delta, FALSE_POSITIVE_BITS);
+            last_value = value;
+        }
+
+        BlockFilter {
+            element_count,
+            encoded_set: writer.bytes,
+        }
+    }
+
+    /// Returns the filter as it is sent in a cfilter message: the amount of elements followed by the encoded set.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = VarLenInt::new(self.element_count).to_bytes();
+        bytes.extend(&self.encoded_set);
+        bytes
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<BlockFilter, BlockChainError> {
+        let element_count =
+            VarLenInt::from_bytes(slice).ok_or(BlockChainError::ErrorCreatingBlockFilter)?;
+        Ok(BlockFilter {
+            element_count: element_count.to_usize(),
+            encoded_set: slice[element_count.amount_of_bytes()..].to_vec(),
+        })
+    }
+
+    /// Returns the double sha256 of the filter.
+    pub fn hash(&self) -> [u8; 32] {
+        sha256d::Hash::hash(&self.to_bytes()).to_byte_array()
+    }
+
+    /// Returns the filter header, which commits to this filter and to the header of the filter of the previous block.
+    pub fn header(&self, previous_filter_header: &[u8; 32]) -> [u8; 32] {
+        let mut bytes = self.hash().to_vec();
+        bytes.extend(previous_filter_header);
+        sha256d::Hash::hash(&bytes).to_byte_array()
+    }
+
+    /// Returns true if any of the scripts may be in the filter of the block with the given hash.
+    /// False positives happen with a rate of 1 in 784931, but there are no false negatives.
+    pub fn matches_any(&self, block_hash: &[u8; 32], scripts: &[Vec<u8>]) -> bool {
+        if self.element_count == 0 || scripts.is_empty() {
+            return false;
+        }
+        let keys = filter_keys(block_hash);
+        let mut queries: Vec<u64> = scripts
+            .iter()
+            .map(|script| hash_to_range(keys, script, self.element_count))
+            .collect();
+        queries.sort_unstable();
+
+        let mut reader = BitReader {
+            bytes: &self.encoded_set,
+            position: 0,
+        };
+        let mut queries = queries.into_iter().peekable();
+        let mut value = 0;
+        for _ in 0..self.element_count {
+            let mut quotient = 0;
+            loop {
+                match reader.read_bit() {
+                    Some(true) => quotient += 1,
+                    Some(false) => break,
+                    None => return false,
+                }
+            }
+            let remainder = match reader.read_bits(FALSE_POSITIVE_BITS) {
+                Some(remainder) => remainder,
+                None => return false,
+            };
+            value += (quotient << FALSE_POSITIVE_BITS) + remainder;
+            while let Some(query) = queries.peek() {
+                if *query == value {
+                    return true;
+                }
+                if *query > value {
+                    break;
+                }
+                queries.next();
+            }
+            if queries.peek().is_none() {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+/// Returns the SipHash keys of the filter of the block: the first 16 bytes of its hash.
+fn filter_keys(block_hash: &[u8; 32]) -> (u64, u64) {
+    let mut k0 = [0; 8];
+    let mut k1 = [0; 8];
+    k0.copy_from_slice(&block_hash[..8]);
+    k1.copy_from_slice(&block_hash[8..16]);
+    (u64::from_le_bytes(k0), u64::from_le_bytes(k1))
+}
+
+/// Maps the element uniformly to the range [0, element_count * M).
+fn hash_to_range(keys: (u64, u64), element: &[u8], element_count: usize) -> u64 {
+    let hash = siphash24::Hash::hash_to_u64_with_keys(keys.0, keys.1, element);
+    let range = element_count as u64 * FALSE_POSITIVE_RATE_INVERSE;
+    ((hash as u128 * range as u128) >> 64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::get_bytes_from_hex;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn testnet_genesis_block() -> Block {
+        let header = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4adae5494dffff001d1aa4ae18";
+        let coinbase = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+        Block::from_bytes(&get_bytes_from_hex(format!("{header}01{coinbase}")).unwrap()).unwrap()
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn block_filter_test_1_matches_the_bip158_test_vector() {
+        let block = testnet_genesis_block();
+
+        let filter = BlockFilter::new(&block, &[]);
+
+        let mut expected_header = get_bytes_from_hex(
+            "21584579b7eb08997773e5aeff3a7f932700042d0ed2a6129012b7d7ae81b750".to_string(),
+        )
+        .unwrap();
+        expected_header.reverse();
+        assert_eq!(
+            filter.to_bytes(),
+            get_bytes_from_hex("019dfca8".to_string()).unwrap()
+        );
+        assert_eq!(filter.to_bytes(), GENESIS_BLOCK_FILTER);
+        assert_eq!(filter.header(&[0; 32]).to_vec(), expected_header);
+    }
+
+    #[test]
+    fn block_filter_test_2_matches_the_scripts_of_the_block() {
+        let block = testnet_genesis_block();
+        let spent_script = vec![0x51, 0x52];
+        let filter =
+            BlockFilter::from_bytes(&BlockFilter::new(&block, &[spent_script.clone()]).to_bytes())
+                .unwrap();
+        let block_hash = block.header_hash();
+        let output_script = block.get_transactions()[0].tx_out[0].pk_script.clone();
+
+        assert!(filter.matches_any(&block_hash, &[output_script]));
+        assert!(filter.matches_any(&block_hash, &[vec![0x00], spent_script]));
+        assert!(!filter.matches_any(&block_hash, &[vec![0x53]]));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::utils::{btc_errors::BlockChainError, variable_length_integer::VarLenInt};
+
+/// Flag by which the peer adds to the filter the outpoints of the outputs that match it, so the
+/// transactions that spend them match too.
+pub const BLOOM_UPDATE_ALL: u8 = 1;
+const MAX_FILTER_SIZE: usize = 36000;
+const MAX_HASH_FUNCTIONS: u32 = 50;
+const HASH_FUNCTION_SEED_MULTIPLIER: u32 = 0xFBA4C795;
+const LN2: f64 = std::f64::consts::LN_2;
+
+/// Bloom filter (BIP37) a light client loads in a peer, so the peer only relays the transactions
+/// that may be relevant to it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BloomFilter {
+    data: Vec<u8>,
+    hash_functions: u32,
+    tweak: u32,
+    flags: u8,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized to hold the given amount of elements with the given false positive rate.
+    pub fn new(elements: usize, false_positive_rate: f64, tweak: u32, flags: u8) -> BloomFilter {
+        let elements = elements.max(1);
+        let size = (-(elements as f64) * false_positive_rate.ln() / (LN2 * LN2) / 8.0) as usize;
+        let size = size.clamp(1, MAX_FILTER_SIZE);
+        let hash_functions = ((size * 8 / elements) as f64 * LN2) as u32;
+        BloomFilter {
+            data: vec![0; size],
+            hash_functions: hash_functions.clamp(1, MAX_HASH_FUNCTIONS),
+            tweak,
+            flags,
+        }
+    }
+
+    /// Adds the element to the filter.
+    pub fn insert(&mut self, element: &[u8]) {
+        for hash_number in 0..self.hash_functions {
+            let bit = self.bit_index(hash_number, element);
+            self.data[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Returns true if the element may be in the filter. There are no false negatives.
+    pub fn contains(&self, element: &[u8]) -> bool {
+        (0..self.hash_functions).all(|hash_number| {
+            let bit = self.bit_index(hash_number, element);
+            self.data[bit / 8] & (1 << (bit % 8)) != 0
+        })
+    }
+
+    fn bit_index(&self, hash_number: u32, element: &[u8]) -> usize {
+        let seed = hash_number
+            .wrapping_mul(HASH_FUNCTION_SEED_MULTIPLIER)
+            .wrapping_add(self.tweak);
+        murmur3(seed, element) as usize % (self.data.len() * 8)
+    }
+
+    /// Returns the filter as it is sent in a filterload message.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = VarLenInt::new(self.data.len()).to_bytes();
+        bytes.extend(&self.data);
+        bytes.extend(self.hash_functions.to_le_bytes());
+        bytes.extend(self.tweak.to_le_bytes());
+        bytes.push(self.flags);
+        bytes
+    }
+
+    pub fn from_bytes(slice: &[u8]) -> Result<BloomFilter, BlockChainError> {
+        Self::_from_bytes(slice).ok_or(BlockChainError::ErrorCreatingBloomFilter)
+    }
+
+    fn _from_bytes(slice: &[u8]) -> Option<BloomFilter> {
+        let size = VarLenInt::from_bytes(slice)?;
+        let mut i = size.amount_of_bytes();
+        let data = slice.get(i..i + size.to_usize())?.to_vec();
+        i += size.to_usize();
+        if data.is_empty() || data.len() > MAX_FILTER_SIZE || slice.len() != i + 9 {
+            return None;
+        }
+        let hash_functions = u32::from_le_bytes(slice[i..i + 4].try_into().ok()?);
+        let tweak = u32::from_le_bytes(slice[i + 4..i + 8].try_into().ok()?);
+        Some(BloomFilter {
+            data,
+            hash_functions: hash_functions.min(MAX_HASH_FUNCTIONS),
+            tweak,
+            flags: slice[i + 8],
+        })
+    }
+}
+
+/// 32 bit MurmurHash3, the hash function of the bloom filters.
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e2d51;
+    const C2: u32 = 0x1b873593;
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe6546b64);
+    }
+
+    let mut k = 0;
+    for (i, byte) in chunks.remainder().iter().enumerate() {
+        k ^= (*byte as u32) << (8 * i);
+    }
+    if k != 0 {
+        hash ^= k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85ebca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2ae35);
+    hash ^ (hash >> 16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::get_bytes_from_hex;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn filter_with_test_elements(tweak: u32) -> BloomFilter {
+        let mut filter = BloomFilter::new(3, 0.01, tweak, BLOOM_UPDATE_ALL);
+        filter.insert(
+            &get_bytes_from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8".to_string()).unwrap(),
+        );
+        filter.insert(
+            &get_bytes_from_hex("b5a2c786d9ef4658287ced5914b37a1b4aa32eee".to_string()).unwrap(),
+        );
+        filter.insert(
+            &get_bytes_from_hex("b9300670b4c5366e95b2699e8b18bc75e5f729c5".to_string()).unwrap(),
+        );
+        filter
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn bloom_filter_test_1_murmur3_matches_the_reference_values() {
+        assert_eq!(murmur3(0, &[]), 0);
+        assert_eq!(murmur3(0xFBA4C795, &[]), 0x6a396f08);
+        assert_eq!(murmur3(0, &[0x00]), 0x514E28B7);
+        assert_eq!(murmur3(0, &[0x21, 0x43, 0x65, 0x87]), 0xF55B516B);
+        assert_eq!(murmur3(0x5082EDEE, &[0x21, 0x43, 0x65, 0x87]), 0x2362F9DE);
+    }
+
+    #[test]
+    fn bloom_filter_test_2_serializes_like_the_reference_filter() {
+        let filter = filter_with_test_elements(0);
+        let tweaked_filter = filter_with_test_elements(2147483649);
+
+        assert_eq!(
+            filter.to_bytes(),
+            get_bytes_from_hex("03614e9b050000000000000001".to_string()).unwrap()
+        );
+        assert_eq!(
+            tweaked_filter.to_bytes(),
+            get_bytes_from_hex("03ce4299050000000100008001".to_string()).unwrap()
+        );
+        assert_eq!(BloomFilter::from_bytes(&filter.to_bytes()).unwrap(), filter);
+    }
+
+    #[test]
+    fn bloom_filter_test_3_contains_the_inserted_elements() {
+        let filter = filter_with_test_elements(0);
+
+        assert!(filter.contains(
+            &get_bytes_from_hex("99108ad8ed9bb6274d3980bab5a85c048f0950c8".to_string()).unwrap()
+        ));
+        assert!(!filter.contains(
+            &get_bytes_from_hex("19108ad8ed9bb6274d3980bab5a85c048f0950c8".to_string()).unwrap()
+        ));
+    }
+}
@@ -1,7 +1,11 @@
+pub mod block_filter;
+pub mod bloom_filter;
 pub mod blockchain;
 pub mod proof;
+pub mod timelock;
 pub mod transaction;
 
 pub use blockchain::*;
 pub use proof::*;
+pub use timelock::*;
 pub use transaction::*;
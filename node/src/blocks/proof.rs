@@ -28,6 +28,26 @@ pub fn validate_proof_of_work(block_header: &BlockHeader) -> bool {
     block_header_hash <= threshold
 }
 
+/// Returns the work a header adds to the chain, which is the expected amount of hashes needed to find
+/// one below its target. It saturates for targets too small for the work to fit in a u128.
+pub fn header_work(block_header: &BlockHeader) -> u128 {
+    let threshold = get_target_threshold(block_header.get_n_bits());
+    let last_byte = match threshold.iter().rposition(|byte| *byte != 0) {
+        Some(last_byte) => last_byte,
+        None => return 0,
+    };
+    let significand = threshold[..=last_byte]
+        .iter()
+        .fold(0_u128, |significand, byte| {
+            (significand << 8) | *byte as u128
+        });
+    let shifted_bits = ((31 - last_byte) * 8) as u32;
+    match 1_u128.checked_shl(256 - shifted_bits) {
+        Some(power) => power / significand,
+        None => u128::MAX,
+    }
+}
+
 /// Returns a hash of the concatenation of two hashes.
 pub fn hash_pairs_for_merkle_tree(hash_1: [u8; 32], hash_2: [u8; 32]) -> [u8; 32] {
     let mut total_hash: Vec<u8> = Vec::from(hash_1);
@@ -60,6 +80,13 @@ fn calculate_merkle_tree_level(
     merkle_tree.push(hash_vector);
 }
 
+/// Returns the merkle root of the transactions with the given hashes, which must not be empty.
+pub fn calculate_merkle_root(tx_hashes: Vec<[u8; 32]>) -> [u8; 32] {
+    let mut merkle_tree = Vec::new();
+    calculate_merkle_tree_level(tx_hashes, &mut merkle_tree);
+    merkle_tree[0][0]
+}
+
 /// Validates the proof of inclusion of a block, by checking if the merkle root of the block
 /// header is equal to the merkle root calculated from its transactions. Returns true if it is valid.
 pub fn validate_block_proof_of_inclusion(block: &Block) -> bool {
@@ -68,10 +95,98 @@ pub fn validate_block_proof_of_inclusion(block: &Block) -> bool {
         return true;
     }
 
-    let mut merkle_tree = Vec::new();
-    calculate_merkle_tree_level(hash_vector, &mut merkle_tree);
     let header_merkle_root = *block.get_header().get_merkle_root();
-    merkle_tree[0][0] == header_merkle_root
+    calculate_merkle_root(hash_vector) == header_merkle_root
+}
+
+/// Validates the partial merkle tree of a merkleblock message (BIP37) against the merkle root of the header.
+/// If it is valid, returns the hashes of the matched transactions in the order of the block.
+pub fn validate_partial_merkle_tree(
+    header: &BlockHeader,
+    total_transactions: u32,
+    hashes: &[[u8; 32]],
+    flags: &[u8],
+) -> Option<Vec<[u8; 32]>> {
+    if total_transactions == 0 || hashes.len() > total_transactions as usize {
+        return None;
+    }
+    let mut tree = PartialMerkleTree {
+        total_transactions: total_transactions as usize,
+        hashes,
+        flags,
+        used_hashes: 0,
+        used_flags: 0,
+        matched: Vec::new(),
+    };
+    let mut height = 0;
+    while tree.width(height) > 1 {
+        height += 1;
+    }
+
+    let merkle_root = tree.traverse(height, 0)?;
+    if merkle_root != header.merkle_root_hash
+        || tree.used_hashes != hashes.len()
+        || tree.used_flags.div_ceil(8) != flags.len()
+    {
+        return None;
+    }
+    Some(tree.matched)
+}
+
+/// Partial merkle tree being traversed depth first, consuming its flags and hashes.
+struct PartialMerkleTree<'a> {
+    total_transactions: usize,
+    hashes: &'a [[u8; 32]],
+    flags: &'a [u8],
+    used_hashes: usize,
+    used_flags: usize,
+    matched: Vec<[u8; 32]>,
+}
+
+impl PartialMerkleTree<'_> {
+    /// Amount of nodes in the level of the tree at the given height, where the leaves are at height 0.
+    fn width(&self, height: u32) -> usize {
+        (self.total_transactions + (1 << height) - 1) >> height
+    }
+
+    fn next_flag(&mut self) -> Option<bool> {
+        let byte = self.flags.get(self.used_flags / 8)?;
+        let flag = byte & (1 << (self.used_flags % 8)) != 0;
+        self.used_flags += 1;
+        Some(flag)
+    }
+
+    fn next_hash(&mut self) -> Option<[u8; 32]> {
+        let hash = *self.hashes.get(self.used_hashes)?;
+        self.used_hashes += 1;
+        Some(hash)
+    }
+
+    /// Returns the hash of the node. Nodes that are not ancestors of a matched transaction come with
+    /// their hash, while the rest are calculated from their children.
+    fn traverse(&mut self, height: u32, position: usize) -> Option<[u8; 32]> {
+        let parent_of_match = self.next_flag()?;
+        if height == 0 || !parent_of_match {
+            let hash = self.next_hash()?;
+            if height == 0 && parent_of_match {
+                self.matched.push(hash);
+            }
+            return Some(hash);
+        }
+
+        let left = self.traverse(height - 1, position * 2)?;
+        let right = if position * 2 + 1 < self.width(height - 1) {
+            let right = self.traverse(height - 1, position * 2 + 1)?;
+            // Equal siblings would let a tree with duplicated transactions match the merkle root.
+            if right == left {
+                return None;
+            }
+            right
+        } else {
+            left
+        };
+        Some(hash_pairs_for_merkle_tree(left, right))
+    }
 }
 
 pub struct HashPair {
@@ -223,6 +338,17 @@ mod test {
         assert!(validate_proof_of_work(&header))
     }
 
+    #[test]
+    fn proof_of_work_test_3_header_work_grows_as_the_target_shrinks() {
+        let min_difficulty_header = BlockHeader::new(70015, [0u8; 32], [0u8; 32], 0xffff001d);
+        let regtest_header = BlockHeader::new(70015, [0u8; 32], [0u8; 32], 0xffff7f20);
+        let hardest_header = BlockHeader::new(70015, [0u8; 32], [0u8; 32], 0x01000003);
+
+        assert_eq!(header_work(&min_difficulty_header), 0x100010001);
+        assert_eq!(header_work(&regtest_header), 2);
+        assert_eq!(header_work(&hardest_header), u128::MAX);
+    }
+
     #[test]
     fn proof_of_inclusion_test_1_invalidad_merkle_root() {
         let block = get_block(false);
@@ -234,4 +360,40 @@ mod test {
         let block = get_block(true);
         assert!(validate_block_proof_of_inclusion(&block))
     }
+
+    #[test]
+    fn partial_merkle_tree_test_1_returns_the_matched_transactions() {
+        let block = get_block(true);
+        let tx_hashes = block.get_tx_hashes();
+        let hashes = [
+            tx_hashes[0],
+            tx_hashes[1],
+            hash_pairs_for_merkle_tree(tx_hashes[2], tx_hashes[2]),
+        ];
+        // Flags 1, 1, 0, 1, 0: the root and its left child are ancestors of the second transaction.
+        let flags = [0b01011];
+
+        assert_eq!(
+            validate_partial_merkle_tree(&block.get_header(), 3, &hashes, &flags),
+            Some(vec![tx_hashes[1]])
+        );
+    }
+
+    #[test]
+    fn partial_merkle_tree_test_2_rejects_a_tree_that_does_not_match_the_root() {
+        let block = get_block(true);
+        let tx_hashes = block.get_tx_hashes();
+        let hashes = [
+            tx_hashes[1],
+            tx_hashes[0],
+            hash_pairs_for_merkle_tree(tx_hashes[2], tx_hashes[2]),
+        ];
+
+        assert!(
+            validate_partial_merkle_tree(&block.get_header(), 3, &hashes, &[0b01011]).is_none()
+        );
+        assert!(
+            validate_partial_merkle_tree(&block.get_header(), 4, &hashes, &[0b01011]).is_none()
+        );
+    }
 }
@@ -0,0 +1,264 @@
+use super::{BlockHeader, Transaction};
+
+/// Lock times below this value are block heights, the rest are unix timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9; // Relative times are in units of 512 seconds.
+pub const RELATIVE_LOCKTIME_MIN_VERSION: i32 = 2;
+const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Absolute lock of a transaction, interpreted from its lock_time field.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum LockTime {
+    Height(u32),
+    Time(u32),
+}
+
+/// Height of the block that confirmed the output spent by an input, alongside the
+/// median time past of the block previous to it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct InputConfirmation {
+    pub height: u32,
+    pub median_time_past: u32,
+}
+
+/// Minimum height and time (exclusive) a block must have for a transaction to be
+/// included, according to the relative locks of its inputs. None means there is no lock.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SequenceLocks {
+    pub min_height: Option<u32>,
+    pub min_time: Option<u32>,
+}
+
+impl LockTime {
+    pub fn from_consensus(lock_time: u32) -> LockTime {
+        if lock_time < LOCKTIME_THRESHOLD {
+            LockTime::Height(lock_time)
+        } else {
+            LockTime::Time(lock_time)
+        }
+    }
+
+    /// Returns true if a block with that height and median time past can include a
+    /// transaction with this lock.
+    pub fn is_satisfied_by(&self, block_height: u32, median_time_past: u32) -> bool {
+        match self {
+            LockTime::Height(height) => *height < block_height,
+            LockTime::Time(time) => *time < median_time_past,
+        }
+    }
+}
+
+impl SequenceLocks {
+    /// Returns true if a block with that height and whose previous block has that
+    /// median time past can include the transaction.
+    pub fn are_satisfied_by(&self, block_height: u32, prev_median_time_past: u32) -> bool {
+        if let Some(min_height) = self.min_height {
+            if min_height >= block_height {
+                return false;
+            }
+        }
+        if let Some(min_time) = self.min_time {
+            if min_time >= prev_median_time_past {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Transaction {
+    /// Returns true if the transaction can be included in a block with that height and
+    /// median time past (BIP113). A transaction whose inputs all have the final sequence
+    /// ignores its lock_time.
+    pub fn is_final(&self, block_height: u32, median_time_past: u32) -> bool {
+        let lock_time = self.get_lock_time();
+        if lock_time == 0
+            || LockTime::from_consensus(lock_time).is_satisfied_by(block_height, median_time_past)
+        {
+            return true;
+        }
+        self.tx_in
+            .iter()
+            .all(|tx_in| tx_in.get_sequence() == SEQUENCE_FINAL)
+    }
+
+    /// Calculates the relative locks of the transaction as BIP68 defines them, given where
+    /// the output spent by each input was confirmed. Inputs without a confirmation are
+    /// ignored, as well as every input if the transaction version is lower than 2.
+    pub fn sequence_locks(&self, confirmations: &[Option<InputConfirmation>]) -> SequenceLocks {
+        let mut locks = SequenceLocks {
+            min_height: None,
+            min_time: None,
+        };
+        if self.get_version() < RELATIVE_LOCKTIME_MIN_VERSION {
+            return locks;
+        }
+
+        for (tx_in, confirmation) in self.tx_in.iter().zip(confirmations) {
+            let sequence = tx_in.get_sequence();
+            let confirmation = match confirmation {
+                Some(confirmation) if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0 => {
+                    confirmation
+                }
+                _ => continue,
+            };
+            let value = sequence & SEQUENCE_LOCKTIME_MASK;
+            if sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+                let min_time = (confirmation.median_time_past
+                    + (value << SEQUENCE_LOCKTIME_GRANULARITY))
+                    .saturating_sub(1);
+                locks.min_time = Some(locks.min_time.map_or(min_time, |t| t.max(min_time)));
+            } else {
+                let min_height = (confirmation.height + value).saturating_sub(1);
+                locks.min_height = Some(locks.min_height.map_or(min_height, |h| h.max(min_height)));
+            }
+        }
+        locks
+    }
+
+    /// Evaluates OP_CHECKSEQUENCEVERIFY (BIP112) for the input at input_index with the
+    /// operand taken from the stack. Returns false if the script should fail.
+    pub fn check_sequence_verify(&self, input_index: usize, operand: u32) -> bool {
+        if operand & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return true;
+        }
+        if self.get_version() < RELATIVE_LOCKTIME_MIN_VERSION {
+            return false;
+        }
+        let sequence = match self.tx_in.get(input_index) {
+            Some(tx_in) => tx_in.get_sequence(),
+            None => return false,
+        };
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return false;
+        }
+
+        let type_mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let operand = operand & type_mask;
+        let sequence = sequence & type_mask;
+        if (operand & SEQUENCE_LOCKTIME_TYPE_FLAG) != (sequence & SEQUENCE_LOCKTIME_TYPE_FLAG) {
+            return false;
+        }
+        operand <= sequence
+    }
+}
+
+/// Returns the median of the times of the last 11 headers, or 0 if there are none.
+pub fn median_time_past(block_headers: &[BlockHeader]) -> u32 {
+    let first = block_headers.len().saturating_sub(MEDIAN_TIME_SPAN);
+    let mut times: Vec<u32> = block_headers[first..]
+        .iter()
+        .map(|header| header.time)
+        .collect();
+    if times.is_empty() {
+        return 0;
+    }
+    times.sort_unstable();
+    times[times.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Outpoint, TxIn, TxOut};
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn transaction_with(version: i32, sequences: &[u32], lock_time: u32) -> Transaction {
+        let tx_in = sequences
+            .iter()
+            .map(|sequence| TxIn::new(Outpoint::new([1; 32], 0), Vec::new(), *sequence))
+            .collect();
+        Transaction::new(version, tx_in, vec![TxOut::new(10, vec![])], lock_time)
+    }
+
+    fn header_with_time(time: u32) -> BlockHeader {
+        let mut header = BlockHeader::new(1, [0; 32], [0; 32], 0);
+        header.time = time;
+        header
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn timelock_test_1_height_locked_transaction_is_final_after_the_height() {
+        let tx = transaction_with(1, &[SEQUENCE_FINAL - 1], 100);
+
+        assert!(!tx.is_final(100, 0));
+        assert!(tx.is_final(101, 0));
+    }
+
+    #[test]
+    fn timelock_test_2_time_locked_transaction_uses_median_time_past() {
+        let tx = transaction_with(1, &[SEQUENCE_FINAL - 1], 1_700_000_000);
+
+        assert!(!tx.is_final(u32::MAX, 1_700_000_000));
+        assert!(tx.is_final(0, 1_700_000_001));
+    }
+
+    #[test]
+    fn timelock_test_3_final_sequences_ignore_lock_time() {
+        let tx = transaction_with(1, &[SEQUENCE_FINAL, SEQUENCE_FINAL], 100);
+
+        assert!(tx.is_final(1, 0));
+    }
+
+    #[test]
+    fn timelock_test_4_sequence_locks_by_height_and_time() {
+        let time_sequence = SEQUENCE_LOCKTIME_TYPE_FLAG | 2;
+        let tx = transaction_with(2, &[10, time_sequence, SEQUENCE_FINAL], 0);
+        let confirmation = Some(InputConfirmation {
+            height: 100,
+            median_time_past: 1_000_000,
+        });
+
+        let locks = tx.sequence_locks(&[confirmation, confirmation, confirmation]);
+
+        assert_eq!(locks.min_height, Some(109));
+        assert_eq!(locks.min_time, Some(1_000_000 + 1024 - 1));
+        assert!(!locks.are_satisfied_by(109, 2_000_000));
+        assert!(!locks.are_satisfied_by(110, 1_001_023));
+        assert!(locks.are_satisfied_by(110, 1_001_024));
+    }
+
+    #[test]
+    fn timelock_test_5_sequence_locks_do_not_apply_to_version_1() {
+        let tx = transaction_with(1, &[10], 0);
+        let confirmation = Some(InputConfirmation {
+            height: 100,
+            median_time_past: 0,
+        });
+
+        assert!(tx.sequence_locks(&[confirmation]).are_satisfied_by(1, 0));
+    }
+
+    #[test]
+    fn timelock_test_6_check_sequence_verify() {
+        let tx = transaction_with(2, &[10, SEQUENCE_LOCKTIME_TYPE_FLAG | 10], 0);
+
+        assert!(tx.check_sequence_verify(0, 10));
+        assert!(!tx.check_sequence_verify(0, 11));
+        assert!(!tx.check_sequence_verify(0, SEQUENCE_LOCKTIME_TYPE_FLAG | 5));
+        assert!(tx.check_sequence_verify(1, SEQUENCE_LOCKTIME_TYPE_FLAG | 5));
+        assert!(tx.check_sequence_verify(1, SEQUENCE_LOCKTIME_DISABLE_FLAG));
+        assert!(!transaction_with(1, &[10], 0).check_sequence_verify(0, 10));
+    }
+
+    #[test]
+    fn timelock_test_7_median_time_past_of_last_eleven_headers() {
+        let headers: Vec<BlockHeader> = [50, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]
+            .iter()
+            .map(|time| header_with_time(*time))
+            .collect();
+
+        assert_eq!(median_time_past(&headers), 6);
+        assert_eq!(median_time_past(&headers[..3]), 2);
+        assert_eq!(median_time_past(&[]), 0);
+    }
+}
@@ -1,6 +1,7 @@
+use super::timelock::SEQUENCE_FINAL;
 use crate::utils::{btc_errors::TransactionError, variable_length_integer::VarLenInt};
 use bitcoin_hashes::{hash160, sha256d, Hash};
-use secp256k1::{constants::PUBLIC_KEY_SIZE, Message, PublicKey, SecretKey};
+use secp256k1::{constants::PUBLIC_KEY_SIZE, ecdsa::Signature, Message, PublicKey, SecretKey};
 
 const MIN_BYTES_TX_IN: usize = 41;
 const MIN_BYTES_TX_OUT: usize = 9;
@@ -20,6 +21,7 @@ const OP_CHECKSIG: u8 = 0xAC;
 const OP_CHECKSIG_POSITION: usize = 24;
 
 const SIGHASH_ALL: [u8; 4] = [0x01, 0x00, 0x00, 0x00]; // Already in BigEndian
+const MAX_DIRECT_PUSH_LENGTH: usize = 0x4B;
 
 /// Struct that represents the Outpoint, that is used in the TxIn struct.
 
@@ -54,7 +56,7 @@ pub struct Transaction {
     pub tx_in: Vec<TxIn>,
     tx_out_count: VarLenInt,
     pub tx_out: Vec<TxOut>,
-    lock_time: u32, // siempre va 0.
+    lock_time: u32,
 }
 
 impl Outpoint {
@@ -124,7 +126,10 @@ impl TxOut {
         match VarLenInt::from_bytes(&slice[8..]) {
             Some(pk_script_length) => {
                 let (_left_bytes, slice) = slice.split_at(8 + pk_script_length.amount_of_bytes());
-                let pk_script = slice[0..pk_script_length.to_usize()].to_vec();
+                let pk_script = match slice.get(0..pk_script_length.to_usize()) {
+                    Some(pk_script) => pk_script.to_vec(),
+                    None => return Err(TransactionError::ErrorCreatingTxOutFromBytes),
+                };
 
                 Ok(TxOut {
                     value,
@@ -197,7 +202,16 @@ impl TxIn {
 
     /// Creates a txin with no signature_script
     pub fn create_unsigned_with(previous_output: Outpoint) -> TxIn {
-        TxIn::new(previous_output, Vec::new(), u32::MAX)
+        TxIn::new(previous_output, Vec::new(), SEQUENCE_FINAL)
+    }
+
+    /// Creates a txin with no signature_script and the given sequence
+    pub fn create_unsigned_with_sequence(previous_output: Outpoint, sequence: u32) -> TxIn {
+        TxIn::new(previous_output, Vec::new(), sequence)
+    }
+
+    pub fn get_sequence(&self) -> u32 {
+        self.sequence
     }
 
     pub fn insert_script_signature(&mut self, signature_script: Vec<u8>) {
@@ -311,7 +325,10 @@ impl Transaction {
         }
     }
 
-    /// Creates and signs a transaction according to the p2pkh protocol
+    /// Creates and signs a transaction according to the p2pkh protocol.
+    /// A lock_time different from 0 is a block height if it is lower than 500000000, or
+    /// a unix timestamp otherwise, and the inputs are marked as non final so it is enforced.
+    #[allow(clippy::too_many_arguments)]
     pub fn create(
         amount: i64,
         fee: i64,
@@ -320,30 +337,74 @@ impl Transaction {
         pub_key: PublicKey,
         priv_key: SecretKey,
         address: [u8; 25],
+        lock_time: u32,
     ) -> Result<Transaction, TransactionError> {
         let change: i64 = unspent_balance - amount - fee;
         let tx_out_vector = create_tx_out_vector(change, amount, pub_key, address);
 
+        let mut sequence = SEQUENCE_FINAL;
+        if lock_time != 0 {
+            sequence = SEQUENCE_FINAL - 1;
+        }
+        let tx_in_vector = unspent_outpoints
+            .into_iter()
+            .map(|outpoint| TxIn::create_unsigned_with_sequence(outpoint, sequence))
+            .collect();
+
+        let mut raw_tx = Transaction::new(1, tx_in_vector, tx_out_vector, lock_time);
+        raw_tx.sign(pub_key, priv_key)?;
+
+        Ok(raw_tx)
+    }
+
+    /// Creates and signs a transaction that spends all the outpoints, sending their whole
+    /// value minus the fee to the address, without any change output.
+    pub fn create_sweep(
+        fee: i64,
+        unspent_outpoints: Vec<Outpoint>,
+        unspent_balance: i64,
+        pub_key: PublicKey,
+        priv_key: SecretKey,
+        address: [u8; 25],
+    ) -> Result<Transaction, TransactionError> {
+        let amount = unspent_balance - fee;
+        if unspent_outpoints.is_empty() || amount <= 0 {
+            return Err(TransactionError::ErrorCreatingSweepTransaction);
+        }
+        let mut receiver_pk_hash: [u8; 20] = [0; 20];
+        receiver_pk_hash.copy_from_slice(&address[1..21]);
+        let tx_out_vector = vec![TxOut::new(
+            amount,
+            Vec::from(get_pk_script(receiver_pk_hash)),
+        )];
+
         let tx_in_vector = create_unsigned_tx_in_vector(unspent_outpoints);
 
         let mut raw_tx = Transaction::new(1, tx_in_vector, tx_out_vector, 0);
+        raw_tx.sign(pub_key, priv_key)?;
+
+        Ok(raw_tx)
+    }
 
+    /// Signs every tx_in of the transaction according to the p2pkh protocol,
+    /// assuming all of them belong to pub_key
+    fn sign(&mut self, pub_key: PublicKey, priv_key: SecretKey) -> Result<(), TransactionError> {
         let mut signature_vec: Vec<Vec<u8>> = Vec::new();
 
-        for i in 0..raw_tx.tx_in_count.to_usize() {
-            raw_tx.tx_in[i].insert_script_signature(Vec::from(get_pk_script_from_pubkey(pub_key)));
+        for i in 0..self.tx_in_count.to_usize() {
+            self.tx_in[i].insert_script_signature(Vec::from(get_pk_script_from_pubkey(pub_key)));
 
-            let signature_script = raw_tx.get_signature_script(pub_key, priv_key)?;
+            let signature_script = self.get_signature_script(pub_key, priv_key)?;
             signature_vec.push(signature_script);
 
-            raw_tx.tx_in[i].insert_script_signature(Vec::new());
+            self.tx_in[i].insert_script_signature(Vec::new());
         }
 
-        for (signature_script, tx_in) in signature_vec.into_iter().zip(raw_tx.tx_in.iter_mut()) {
+        for (signature_script, tx_in) in signature_vec.into_iter().zip(self.tx_in.iter_mut()) {
             tx_in.insert_script_signature(signature_script);
         }
 
-        Ok(raw_tx)
+        Ok(())
     }
 
     //firmar
@@ -385,6 +446,55 @@ impl Transaction {
         Ok(signature_script)
     }
 
+    /// Checks the signature of the input against the output it spends, if the output follows the p2pkh
+    /// protocol and the input was signed with SIGHASH_ALL, which are the only scripts the node can validate.
+    /// Returns None if the scripts are of another kind, or the input does not exist.
+    pub fn verify_p2pkh_input(&self, input_index: usize, previous_output: &TxOut) -> Option<bool> {
+        let tx_in = self.tx_in.get(input_index)?;
+        let pk_hash = previous_output.pk_hash_under_p2pkh_protocol()?;
+        // Signature scripts that do not just push a signature and a public key are valid as long as
+        // they leave them on top of the stack, so they are not checked.
+        let (signature, pub_key) = split_signature_script(&tx_in.signature_script)?;
+        let (hash_type, der_signature) = signature.split_last()?;
+        if *hash_type != SIGHASH_ALL[0] {
+            return None;
+        }
+        if hash160::Hash::hash(pub_key).as_byte_array() != pk_hash {
+            return Some(false);
+        }
+        let (pub_key, mut signature) = match (
+            PublicKey::from_slice(pub_key),
+            Signature::from_der_lax(der_signature),
+        ) {
+            (Ok(pub_key), Ok(signature)) => (pub_key, signature),
+            _ => return Some(false),
+        };
+        signature.normalize_s();
+
+        // Same steps as when signing: every signature_script is emptied but the one of the input,
+        // which is replaced by the pk_script it spends.
+        let mut tx_bytes = Vec::new();
+        tx_bytes.extend_from_slice(&self.version.to_le_bytes());
+        tx_bytes.extend_from_slice(&self.tx_in_count.to_bytes());
+        for (i, tx_in) in self.tx_in.iter().enumerate() {
+            let mut signature_script = Vec::new();
+            if i == input_index {
+                signature_script = previous_output.pk_script.clone();
+            }
+            let unsigned_tx_in = TxIn::new(tx_in.previous_output, signature_script, tx_in.sequence);
+            tx_bytes.extend_from_slice(&unsigned_tx_in.to_bytes());
+        }
+        tx_bytes.extend_from_slice(&self.tx_out_count.to_bytes());
+        for tx_out in &self.tx_out {
+            tx_bytes.extend_from_slice(&tx_out.to_bytes());
+        }
+        tx_bytes.extend_from_slice(&self.lock_time.to_le_bytes());
+        tx_bytes.extend(SIGHASH_ALL);
+
+        let message = Message::from_hashed_data::<sha256d::Hash>(&tx_bytes);
+        Some(signature.verify(&message, &pub_key).is_ok())
+    }
+
     /// Returns the contents of Transaction as a bytes vector
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes_vector = Vec::new();
@@ -461,6 +571,14 @@ impl Transaction {
         self.to_bytes().len()
     }
 
+    pub fn get_version(&self) -> i32 {
+        self.version
+    }
+
+    pub fn get_lock_time(&self) -> u32 {
+        self.lock_time
+    }
+
     pub fn hash(&self) -> [u8; 32] {
         *sha256d::Hash::hash(&self.to_bytes()).as_byte_array()
     }
@@ -492,6 +610,22 @@ fn assemble_signature_script(signature: Vec<u8>, pub_key: PublicKey) -> Vec<u8>
     signature_script
 }
 
+/// Splits a p2pkh signature_script into the signature and the public key it pushes.
+/// Returns None if it does not consist of exactly those two pushes.
+fn split_signature_script(signature_script: &[u8]) -> Option<(&[u8], &[u8])> {
+    let (signature_length, rest) = signature_script.split_first()?;
+    let signature_length = *signature_length as usize;
+    if signature_length > MAX_DIRECT_PUSH_LENGTH || rest.len() < signature_length {
+        return None;
+    }
+    let (signature, rest) = rest.split_at(signature_length);
+    let (pub_key_length, pub_key) = rest.split_first()?;
+    if *pub_key_length as usize != pub_key.len() || pub_key.len() > MAX_DIRECT_PUSH_LENGTH {
+        return None;
+    }
+    Some((signature, pub_key))
+}
+
 /// Creates a Txout vector with 2 txout one going to the address with the amount.
 /// And another with the remainder of value not used in amount or fee to the sending account
 fn create_tx_out_vector(
@@ -518,7 +652,7 @@ pub fn get_pk_script_from_pubkey(pub_key: PublicKey) -> [u8; P2PKH_SCRIPT_LENGTH
 }
 
 /// Returns the pk_script according to the p2pkh protocol
-fn get_pk_script(pk_hash: [u8; 20]) -> [u8; P2PKH_SCRIPT_LENGTH] {
+pub fn get_pk_script(pk_hash: [u8; 20]) -> [u8; P2PKH_SCRIPT_LENGTH] {
     let mut pk_script: [u8; P2PKH_SCRIPT_LENGTH] = [0; P2PKH_SCRIPT_LENGTH];
 
     pk_script[OP_DUP_POSITION] = OP_DUP;
@@ -690,4 +824,65 @@ mod tests {
         assert_eq!(transaction_bytes, transaction.to_bytes());
         Ok(())
     }
+
+    #[test]
+    fn transaction_test_5_sweep_spends_everything() -> Result<(), TransactionError> {
+        let priv_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pub_key = priv_key.public_key(&secp256k1::Secp256k1::new());
+        let outpoints = vec![
+            Outpoint::new(outpoint_32_byte_array(), 0),
+            Outpoint::new(outpoint_32_byte_array(), 1),
+        ];
+        let mut address: [u8; 25] = [0; 25];
+        address[1..21].copy_from_slice(&[3; 20]);
+
+        let transaction =
+            Transaction::create_sweep(1000, outpoints, 50000, pub_key, priv_key, address)?;
+
+        assert_eq!(transaction.tx_in.len(), 2);
+        assert!(transaction
+            .tx_in
+            .iter()
+            .all(|tx_in| tx_in.belongs_to(&pub_key)));
+        assert_eq!(transaction.tx_out.len(), 1);
+        assert_eq!(transaction.tx_out[0].value, 49000);
+        assert!(transaction.tx_out[0].belongs_to([3; 20]));
+        assert!(
+            Transaction::create_sweep(50000, vec![], 50000, pub_key, priv_key, address).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn transaction_test_6_verifies_p2pkh_signatures() -> Result<(), TransactionError> {
+        let priv_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pub_key = priv_key.public_key(&secp256k1::Secp256k1::new());
+        let outpoints = vec![
+            Outpoint::new(outpoint_32_byte_array(), 0),
+            Outpoint::new(outpoint_32_byte_array(), 1),
+        ];
+        let mut address: [u8; 25] = [0; 25];
+        address[1..21].copy_from_slice(&[3; 20]);
+        let spent_output = TxOut::new(50000, Vec::from(get_pk_script_from_pubkey(pub_key)));
+        let other_output = TxOut::new(50000, Vec::from(get_pk_script([3; 20])));
+        let non_p2pkh_output = TxOut::new(50000, vec![0x51]);
+
+        let mut transaction =
+            Transaction::create_sweep(1000, outpoints, 50000, pub_key, priv_key, address)?;
+
+        assert_eq!(transaction.verify_p2pkh_input(0, &spent_output), Some(true));
+        assert_eq!(transaction.verify_p2pkh_input(1, &spent_output), Some(true));
+        assert_eq!(
+            transaction.verify_p2pkh_input(0, &other_output),
+            Some(false)
+        );
+        assert_eq!(transaction.verify_p2pkh_input(0, &non_p2pkh_output), None);
+        assert_eq!(transaction.verify_p2pkh_input(2, &spent_output), None);
+        transaction.tx_out[0].value = 49999;
+        assert_eq!(
+            transaction.verify_p2pkh_input(0, &spent_output),
+            Some(false)
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,202 @@
+use super::message_trait::*;
+use crate::utils::variable_length_integer::VarLenInt;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+
+const NETWORK_ADDRESS_SIZE: usize = 30;
+pub const MAX_ADDR_ENTRIES: usize = 1000;
+
+/// Address of a peer as it is announced in addr messages.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct NetworkAddress {
+    pub time: u32,
+    pub services: u64,
+    pub ip: [u8; 16],
+    pub port: u16,
+}
+
+/// Message used to announce the addresses of known peers.
+#[derive(Debug, PartialEq)]
+pub struct AddrMessage {
+    pub addresses: Vec<NetworkAddress>,
+}
+
+/// Message used to ask a peer for the addresses it knows, it has no payload.
+#[derive(Debug, PartialEq, Default)]
+pub struct GetAddrMessage {}
+
+impl NetworkAddress {
+    pub fn new(time: u32, services: u64, address: SocketAddr) -> NetworkAddress {
+        let ip = match address.ip() {
+            IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped().octets(),
+            IpAddr::V6(ipv6) => ipv6.octets(),
+        };
+        NetworkAddress {
+            time,
+            services,
+            ip,
+            port: address.port(),
+        }
+    }
+
+    /// Returns the address as a SocketAddr, IPv4 mapped addresses are returned as IPv4.
+    pub fn socket_address(&self) -> SocketAddr {
+        let ipv6 = Ipv6Addr::from(self.ip);
+        match ipv6.to_ipv4_mapped() {
+            Some(ipv4) => SocketAddr::new(IpAddr::V4(ipv4), self.port),
+            None => SocketAddr::new(IpAddr::V6(ipv6), self.port),
+        }
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes_vector = Vec::new();
+        bytes_vector.extend_from_slice(&self.time.to_le_bytes());
+        bytes_vector.extend_from_slice(&self.services.to_le_bytes());
+        bytes_vector.extend_from_slice(&self.ip);
+        bytes_vector.extend_from_slice(&self.port.to_be_bytes());
+        bytes_vector
+    }
+
+    fn from_bytes(slice: &[u8]) -> Option<NetworkAddress> {
+        Some(NetworkAddress {
+            time: u32::from_le_bytes(slice.get(0..4)?.try_into().ok()?),
+            services: u64::from_le_bytes(slice.get(4..12)?.try_into().ok()?),
+            ip: slice.get(12..28)?.try_into().ok()?,
+            port: u16::from_be_bytes(slice.get(28..30)?.try_into().ok()?),
+        })
+    }
+}
+
+impl MessageTrait for AddrMessage {
+    type MessageType = AddrMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingAddrMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = VarLenInt::new(self.addresses.len()).to_bytes();
+        for address in &self.addresses {
+            bytes_vector.extend(address.to_bytes());
+        }
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    /// Messages with more than 1000 addresses are rejected, as the protocol does not allow them.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        let count = VarLenInt::from_bytes(slice).ok_or(MessageError::ErrorCreatingAddrMessage)?;
+        let entries = &slice[count.amount_of_bytes()..];
+        if count.to_usize() > MAX_ADDR_ENTRIES
+            || entries.len() != count.to_usize() * NETWORK_ADDRESS_SIZE
+        {
+            return Err(MessageError::ErrorCreatingAddrMessage);
+        }
+
+        let mut addresses = Vec::new();
+        for entry in entries.chunks_exact(NETWORK_ADDRESS_SIZE) {
+            match NetworkAddress::from_bytes(entry) {
+                Some(address) => addresses.push(address),
+                None => return Err(MessageError::ErrorCreatingAddrMessage),
+            }
+        }
+        Ok(AddrMessage { addresses })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("addr\0\0\0\0\0\0\0\0", &self.to_bytes())
+    }
+}
+
+impl AddrMessage {
+    /// Creates an addr message, keeping at most the first 1000 addresses.
+    pub fn new(mut addresses: Vec<NetworkAddress>) -> AddrMessage {
+        addresses.truncate(MAX_ADDR_ENTRIES);
+        AddrMessage { addresses }
+    }
+}
+
+impl MessageTrait for GetAddrMessage {
+    type MessageType = GetAddrMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingGetAddrMessage;
+
+    /// Returns an empty vector of bytes, since the GetAddrMessage has no payload.
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Returns a GetAddrMessage if the slice of bytes is empty, otherwise returns a MessageError.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        if !slice.is_empty() {
+            return Err(MessageError::ErrorCreatingGetAddrMessage);
+        }
+        Ok(GetAddrMessage {})
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("getaddr\0\0\0\0\0", &self.to_bytes())
+    }
+}
+
+impl GetAddrMessage {
+    pub fn new() -> GetAddrMessage {
+        GetAddrMessage {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn network_addresses() -> Vec<NetworkAddress> {
+        vec![
+            NetworkAddress::new(1681084800, 1, SocketAddr::from(([127, 0, 0, 1], 18333))),
+            NetworkAddress::new(1681084900, 9, "[2001:db8::1]:18333".parse().unwrap()),
+        ]
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn addr_message_test_1_round_trip() {
+        let addr_message = AddrMessage::new(network_addresses());
+
+        let bytes = addr_message.to_bytes();
+
+        assert_eq!(bytes.len(), 1 + 2 * NETWORK_ADDRESS_SIZE);
+        assert_eq!(AddrMessage::from_bytes(&bytes).unwrap(), addr_message);
+    }
+
+    #[test]
+    fn addr_message_test_2_ipv4_addresses_are_mapped() {
+        let addresses = network_addresses();
+
+        assert_eq!(addresses[0].ip[10..12], [0xff, 0xff]);
+        assert_eq!(
+            addresses[0].socket_address(),
+            SocketAddr::from(([127, 0, 0, 1], 18333))
+        );
+        assert_eq!(
+            addresses[1].socket_address(),
+            "[2001:db8::1]:18333".parse::<SocketAddr>().unwrap()
+        );
+    }
+
+    #[test]
+    fn addr_message_test_3_wrong_length_is_an_error() {
+        let mut bytes = AddrMessage::new(network_addresses()).to_bytes();
+        bytes.pop();
+
+        assert!(AddrMessage::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn addr_message_test_4_get_addr_has_no_payload() {
+        assert!(GetAddrMessage::new().to_bytes().is_empty());
+        assert!(GetAddrMessage::from_bytes(&[]).is_ok());
+        assert!(GetAddrMessage::from_bytes(&[1]).is_err());
+    }
+}
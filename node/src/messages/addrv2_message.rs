@@ -0,0 +1,256 @@
+use super::addr_message::{NetworkAddress, MAX_ADDR_ENTRIES};
+use super::message_trait::*;
+use crate::utils::variable_length_integer::VarLenInt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const IPV4_NETWORK_ID: u8 = 0x01;
+const IPV6_NETWORK_ID: u8 = 0x02;
+const IPV4_ADDRESS_LENGTH: usize = 4;
+const IPV6_ADDRESS_LENGTH: usize = 16;
+const MAX_ADDRESS_LENGTH: usize = 512;
+
+/// Address of a peer as it is announced in addrv2 messages (BIP155). Besides IP addresses
+/// it can hold addresses of other networks, like Tor or I2P, that are kept as raw bytes.
+#[derive(Debug, PartialEq, Clone)]
+pub struct NetworkAddressV2 {
+    pub time: u32,
+    pub services: u64,
+    pub network_id: u8,
+    pub address: Vec<u8>,
+    pub port: u16,
+}
+
+/// Message used to announce the addresses of known peers, in the BIP155 format.
+#[derive(Debug, PartialEq)]
+pub struct AddrV2Message {
+    pub addresses: Vec<NetworkAddressV2>,
+}
+
+/// Message sent during the handshake to signal that addrv2 messages are preferred.
+#[derive(Debug, PartialEq, Default)]
+pub struct SendAddrV2Message {}
+
+impl NetworkAddressV2 {
+    /// Returns the address as an addr message entry, or None if it does not belong to an IP network.
+    pub fn to_network_address(&self) -> Option<NetworkAddress> {
+        let ip = match (self.network_id, self.address.len()) {
+            (IPV4_NETWORK_ID, IPV4_ADDRESS_LENGTH) => {
+                let octets: [u8; IPV4_ADDRESS_LENGTH] = self.address.as_slice().try_into().ok()?;
+                IpAddr::V4(Ipv4Addr::from(octets))
+            }
+            (IPV6_NETWORK_ID, IPV6_ADDRESS_LENGTH) => {
+                let octets: [u8; IPV6_ADDRESS_LENGTH] = self.address.as_slice().try_into().ok()?;
+                IpAddr::V6(Ipv6Addr::from(octets))
+            }
+            _ => return None,
+        };
+        Some(NetworkAddress::new(
+            self.time,
+            self.services,
+            SocketAddr::new(ip, self.port),
+        ))
+    }
+
+    /// Creates an addrv2 entry from an addr message entry.
+    pub fn from_network_address(network_address: &NetworkAddress) -> NetworkAddressV2 {
+        let (network_id, address) = match network_address.socket_address().ip() {
+            IpAddr::V4(ipv4) => (IPV4_NETWORK_ID, ipv4.octets().to_vec()),
+            IpAddr::V6(ipv6) => (IPV6_NETWORK_ID, ipv6.octets().to_vec()),
+        };
+        NetworkAddressV2 {
+            time: network_address.time,
+            services: network_address.services,
+            network_id,
+            address,
+            port: network_address.port,
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = Vec::new();
+        bytes_vector.extend_from_slice(&self.time.to_le_bytes());
+        bytes_vector.extend(VarLenInt::new(self.services as usize).to_bytes());
+        bytes_vector.push(self.network_id);
+        bytes_vector.extend(VarLenInt::new(self.address.len()).to_bytes());
+        bytes_vector.extend_from_slice(&self.address);
+        bytes_vector.extend_from_slice(&self.port.to_be_bytes());
+        bytes_vector
+    }
+
+    /// Reads an entry from the start of the slice, returning it with the amount of bytes it used.
+    fn from_bytes(slice: &[u8]) -> Option<(NetworkAddressV2, usize)> {
+        let time = u32::from_le_bytes(slice.get(0..4)?.try_into().ok()?);
+        let mut position = 4;
+        let services = VarLenInt::from_bytes(slice.get(position..)?)?;
+        position += services.amount_of_bytes();
+        let network_id = *slice.get(position)?;
+        position += 1;
+        let address_length = VarLenInt::from_bytes(slice.get(position..)?)?;
+        position += address_length.amount_of_bytes();
+        if address_length.to_usize() > MAX_ADDRESS_LENGTH {
+            return None;
+        }
+        let address = slice
+            .get(position..position + address_length.to_usize())?
+            .to_vec();
+        position += address_length.to_usize();
+        let port = u16::from_be_bytes(slice.get(position..position + 2)?.try_into().ok()?);
+        position += 2;
+
+        let network_address = NetworkAddressV2 {
+            time,
+            services: services.to_usize() as u64,
+            network_id,
+            address,
+            port,
+        };
+        Some((network_address, position))
+    }
+}
+
+impl MessageTrait for AddrV2Message {
+    type MessageType = AddrV2Message;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingAddrV2Message;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = VarLenInt::new(self.addresses.len()).to_bytes();
+        for address in &self.addresses {
+            bytes_vector.extend(address.to_bytes());
+        }
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        let count = VarLenInt::from_bytes(slice).ok_or(MessageError::ErrorCreatingAddrV2Message)?;
+        if count.to_usize() > MAX_ADDR_ENTRIES {
+            return Err(MessageError::ErrorCreatingAddrV2Message);
+        }
+        let mut position = count.amount_of_bytes();
+        let mut addresses = Vec::new();
+        for _ in 0..count.to_usize() {
+            match NetworkAddressV2::from_bytes(&slice[position..]) {
+                Some((address, length)) => {
+                    addresses.push(address);
+                    position += length;
+                }
+                None => return Err(MessageError::ErrorCreatingAddrV2Message),
+            }
+        }
+        if position != slice.len() {
+            return Err(MessageError::ErrorCreatingAddrV2Message);
+        }
+        Ok(AddrV2Message { addresses })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("addrv2\0\0\0\0\0\0", &self.to_bytes())
+    }
+}
+
+impl AddrV2Message {
+    /// Creates an addrv2 message, keeping at most the first 1000 addresses.
+    pub fn new(mut addresses: Vec<NetworkAddressV2>) -> AddrV2Message {
+        addresses.truncate(MAX_ADDR_ENTRIES);
+        AddrV2Message { addresses }
+    }
+
+    /// Returns the announced addresses that belong to an IP network.
+    pub fn get_ip_addresses(&self) -> Vec<NetworkAddress> {
+        self.addresses
+            .iter()
+            .filter_map(|address| address.to_network_address())
+            .collect()
+    }
+}
+
+impl MessageTrait for SendAddrV2Message {
+    type MessageType = SendAddrV2Message;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingAddrV2Message;
+
+    /// Returns an empty vector of bytes, since the SendAddrV2Message has no payload.
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Returns a SendAddrV2Message if the slice of bytes is empty, otherwise returns a MessageError.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        if !slice.is_empty() {
+            return Err(MessageError::ErrorCreatingAddrV2Message);
+        }
+        Ok(SendAddrV2Message {})
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("sendaddrv2\0\0", &self.to_bytes())
+    }
+}
+
+impl SendAddrV2Message {
+    pub fn new() -> SendAddrV2Message {
+        SendAddrV2Message {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn tor_address() -> NetworkAddressV2 {
+        NetworkAddressV2 {
+            time: 1681084800,
+            services: 1033,
+            network_id: 0x04,
+            address: vec![7; 32],
+            port: 18333,
+        }
+    }
+
+    fn ipv4_address() -> NetworkAddressV2 {
+        NetworkAddressV2::from_network_address(&NetworkAddress::new(
+            1681084800,
+            1,
+            SocketAddr::from(([10, 0, 0, 1], 18333)),
+        ))
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn addrv2_message_test_1_round_trip() {
+        let message = AddrV2Message::new(vec![ipv4_address(), tor_address()]);
+
+        let bytes = message.to_bytes();
+
+        assert_eq!(AddrV2Message::from_bytes(&bytes).unwrap(), message);
+    }
+
+    #[test]
+    fn addrv2_message_test_2_only_ip_addresses_are_converted() {
+        let message = AddrV2Message::new(vec![ipv4_address(), tor_address()]);
+
+        let addresses = message.get_ip_addresses();
+
+        assert_eq!(addresses.len(), 1);
+        assert_eq!(
+            addresses[0].socket_address(),
+            SocketAddr::from(([10, 0, 0, 1], 18333))
+        );
+        assert_eq!(ipv4_address().address, vec![10, 0, 0, 1]);
+    }
+
+    #[test]
+    fn addrv2_message_test_3_trailing_bytes_are_an_error() {
+        let mut bytes = AddrV2Message::new(vec![ipv4_address()]).to_bytes();
+        bytes.push(0);
+
+        assert!(AddrV2Message::from_bytes(&bytes).is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use super::message_trait::*;
+use crate::blocks::{blockchain::BlockHeader, bloom_filter::BloomFilter};
+use crate::utils::variable_length_integer::VarLenInt;
+
+const BLOCKHEADER_SIZE: usize = 80;
+const HASH_SIZE: usize = 32;
+
+/// Message by which a light client loads a bloom filter in a peer (BIP37). From then on the peer only
+/// relays the transactions that match it, and answers filtered block requests with merkleblock messages.
+#[derive(Debug, PartialEq)]
+pub struct FilterLoadMessage {
+    pub filter: BloomFilter,
+}
+
+/// Message with the header of a block and a partial merkle tree that proves which of its transactions
+/// match the bloom filter of the receiver. The matched transactions are sent next, in tx messages.
+#[derive(Debug, PartialEq)]
+pub struct MerkleBlockMessage {
+    pub header: BlockHeader,
+    pub total_transactions: u32,
+    pub hashes: Vec<[u8; 32]>,
+    pub flags: Vec<u8>,
+}
+
+impl MessageTrait for FilterLoadMessage {
+    type MessageType = FilterLoadMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingFilterLoadMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        self.filter.to_bytes()
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        let filter = BloomFilter::from_bytes(slice)
+            .map_err(|_| MessageError::ErrorCreatingFilterLoadMessage)?;
+        Ok(FilterLoadMessage { filter })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("filterload\0\0", &self.to_bytes())
+    }
+}
+
+impl MessageTrait for MerkleBlockMessage {
+    type MessageType = MerkleBlockMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingMerkleBlockMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = self.header.to_bytes();
+        bytes_vector.extend(self.total_transactions.to_le_bytes());
+        bytes_vector.extend(VarLenInt::new(self.hashes.len()).to_bytes());
+        for hash in &self.hashes {
+            bytes_vector.extend(hash);
+        }
+        bytes_vector.extend(VarLenInt::new(self.flags.len()).to_bytes());
+        bytes_vector.extend(&self.flags);
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(merkle_block_msg) => Ok(merkle_block_msg),
+            None => Err(MessageError::ErrorCreatingMerkleBlockMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("merkleblock\0", &self.to_bytes())
+    }
+}
+
+impl MerkleBlockMessage {
+    fn _from_bytes(slice: &[u8]) -> Option<MerkleBlockMessage> {
+        let header = BlockHeader::from_bytes(slice.get(..BLOCKHEADER_SIZE)?).ok()?;
+        let mut i = BLOCKHEADER_SIZE;
+        let total_transactions = u32::from_le_bytes(slice.get(i..i + 4)?.try_into().ok()?);
+        i += 4;
+
+        let hash_count = VarLenInt::from_bytes(slice.get(i..)?)?;
+        i += hash_count.amount_of_bytes();
+        let mut hashes = Vec::new();
+        for _ in 0..hash_count.to_usize() {
+            hashes.push(slice.get(i..i + HASH_SIZE)?.try_into().ok()?);
+            i += HASH_SIZE;
+        }
+
+        let flag_count = VarLenInt::from_bytes(slice.get(i..)?)?;
+        i += flag_count.amount_of_bytes();
+        let flags = slice.get(i..)?.to_vec();
+        if flags.len() != flag_count.to_usize() {
+            return None;
+        }
+
+        Some(MerkleBlockMessage {
+            header,
+            total_transactions,
+            hashes,
+            flags,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::bloom_filter::BLOOM_UPDATE_ALL;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn bloom_filter_messages_test_1_filterload_to_and_from_bytes() -> Result<(), MessageError> {
+        let mut filter = BloomFilter::new(2, 0.0001, 7, BLOOM_UPDATE_ALL);
+        filter.insert(&[1; 20]);
+        let filter_load_msg = FilterLoadMessage { filter };
+
+        let bytes = filter_load_msg.to_bytes();
+
+        assert_eq!(FilterLoadMessage::from_bytes(&bytes)?, filter_load_msg);
+        assert_eq!(
+            FilterLoadMessage::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MessageError::ErrorCreatingFilterLoadMessage)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn bloom_filter_messages_test_2_merkleblock_to_and_from_bytes() -> Result<(), MessageError> {
+        let merkle_block_msg = MerkleBlockMessage {
+            header: BlockHeader::new(1, [1; 32], [2; 32], 0x1d00ffff),
+            total_transactions: 3,
+            hashes: vec![[3; 32], [4; 32], [5; 32]],
+            flags: vec![0b01011],
+        };
+
+        let bytes = merkle_block_msg.to_bytes();
+
+        assert_eq!(bytes.len(), 80 + 4 + 1 + 3 * 32 + 1 + 1);
+        assert_eq!(MerkleBlockMessage::from_bytes(&bytes)?, merkle_block_msg);
+        assert_eq!(
+            MerkleBlockMessage::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(MessageError::ErrorCreatingMerkleBlockMessage)
+        );
+        Ok(())
+    }
+}
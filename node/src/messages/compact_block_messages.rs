@@ -0,0 +1,404 @@
+use super::message_trait::*;
+use crate::blocks::{
+    blockchain::{Block, BlockHeader},
+    Transaction,
+};
+use crate::utils::variable_length_integer::VarLenInt;
+use bitcoin_hashes::{sha256, siphash24, Hash};
+
+/// Only version 1 of compact blocks is supported, since it identifies transactions by their txid.
+pub const COMPACT_BLOCKS_VERSION: u64 = 1;
+const BLOCKHEADER_SIZE: usize = 80;
+const NONCE_SIZE: usize = 8;
+const SHORT_ID_SIZE: usize = 6;
+const HASH_SIZE: usize = 32;
+
+/// A transaction sent in full inside a compact block, alongside its position in the block.
+#[derive(Debug, PartialEq)]
+pub struct PrefilledTransaction {
+    pub index: usize,
+    pub tx: Transaction,
+}
+
+/// Compact block message (BIP152): the header of a block, the short ids of its transactions,
+/// and the transactions the receiver is not expected to have, such as the coinbase.
+#[derive(Debug, PartialEq)]
+pub struct CmpctBlockMessage {
+    pub header: BlockHeader,
+    pub nonce: u64,
+    pub short_ids: Vec<u64>,
+    pub prefilled_txs: Vec<PrefilledTransaction>,
+}
+
+/// Message asking for the transactions at the given positions of a block, which were missing to
+/// reconstruct it from a compact block.
+#[derive(Debug, PartialEq)]
+pub struct GetBlockTxnMessage {
+    pub block_hash: [u8; 32],
+    pub indexes: Vec<usize>,
+}
+
+/// Message with the transactions asked for with a GetBlockTxnMessage, in the order they were asked.
+#[derive(Debug, PartialEq)]
+pub struct BlockTxnMessage {
+    pub block_hash: [u8; 32],
+    pub transactions: Vec<Transaction>,
+}
+
+impl MessageTrait for CmpctBlockMessage {
+    type MessageType = CmpctBlockMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingCmpctBlockMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol. Prefilled transaction indexes
+    /// are encoded as the difference with the previous one.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = self.header.to_bytes();
+        bytes_vector.extend(self.nonce.to_le_bytes());
+        bytes_vector.extend(VarLenInt::new(self.short_ids.len()).to_bytes());
+        for short_id in &self.short_ids {
+            bytes_vector.extend(&short_id.to_le_bytes()[..SHORT_ID_SIZE]);
+        }
+        bytes_vector.extend(VarLenInt::new(self.prefilled_txs.len()).to_bytes());
+        let indexes: Vec<usize> = self
+            .prefilled_txs
+            .iter()
+            .map(|prefilled| prefilled.index)
+            .collect();
+        for (differential_index, prefilled) in differential_indexes(&indexes)
+            .iter()
+            .zip(&self.prefilled_txs)
+        {
+            bytes_vector.extend(VarLenInt::new(*differential_index).to_bytes());
+            bytes_vector.extend(prefilled.tx.to_bytes());
+        }
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(cmpct_block_msg) => Ok(cmpct_block_msg),
+            None => Err(MessageError::ErrorCreatingCmpctBlockMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("cmpctblock\0\0", &self.to_bytes())
+    }
+}
+
+impl CmpctBlockMessage {
+    /// Creates the compact block of the block, prefilling only its coinbase transaction.
+    pub fn from_block(block: &Block, nonce: u64) -> Result<CmpctBlockMessage, MessageError> {
+        let mut cmpct_block_msg = CmpctBlockMessage {
+            header: block.get_header(),
+            nonce,
+            short_ids: Vec::new(),
+            prefilled_txs: Vec::new(),
+        };
+        let keys = cmpct_block_msg.short_id_keys();
+        for (index, tx) in block.get_transactions().iter().enumerate() {
+            if index == 0 {
+                let tx = Transaction::from_bytes(&tx.to_bytes())
+                    .map_err(|_| MessageError::ErrorCreatingCmpctBlockMessage)?;
+                cmpct_block_msg
+                    .prefilled_txs
+                    .push(PrefilledTransaction { index, tx });
+            } else {
+                cmpct_block_msg.short_ids.push(short_id(keys, &tx.hash()));
+            }
+        }
+        Ok(cmpct_block_msg)
+    }
+
+    /// Returns the amount of transactions of the block.
+    pub fn transaction_count(&self) -> usize {
+        self.short_ids.len() + self.prefilled_txs.len()
+    }
+
+    /// Returns the SipHash keys used for the short ids, which are the first 16 bytes of the single
+    /// SHA256 of the header followed by the nonce.
+    pub fn short_id_keys(&self) -> (u64, u64) {
+        let mut bytes = self.header.to_bytes();
+        bytes.extend(self.nonce.to_le_bytes());
+        let hash = sha256::Hash::hash(&bytes).to_byte_array();
+        let mut k0 = [0; 8];
+        let mut k1 = [0; 8];
+        k0.copy_from_slice(&hash[..8]);
+        k1.copy_from_slice(&hash[8..16]);
+        (u64::from_le_bytes(k0), u64::from_le_bytes(k1))
+    }
+
+    fn _from_bytes(slice: &[u8]) -> Option<CmpctBlockMessage> {
+        if slice.len() < BLOCKHEADER_SIZE + NONCE_SIZE {
+            return None;
+        }
+        let (header_bytes, slice) = slice.split_at(BLOCKHEADER_SIZE);
+        let header = BlockHeader::from_bytes(header_bytes).ok()?;
+        let (nonce_bytes, slice) = slice.split_at(NONCE_SIZE);
+        let nonce = u64::from_le_bytes(nonce_bytes.try_into().ok()?);
+
+        let short_ids_count = VarLenInt::from_bytes(slice)?;
+        let mut slice = &slice[short_ids_count.amount_of_bytes()..];
+        if slice.len() < short_ids_count.to_usize() * SHORT_ID_SIZE {
+            return None;
+        }
+        let mut short_ids = Vec::new();
+        for _ in 0..short_ids_count.to_usize() {
+            let mut short_id = [0; 8];
+            short_id[..SHORT_ID_SIZE].copy_from_slice(&slice[..SHORT_ID_SIZE]);
+            short_ids.push(u64::from_le_bytes(short_id));
+            slice = &slice[SHORT_ID_SIZE..];
+        }
+
+        let prefilled_count = VarLenInt::from_bytes(slice)?;
+        slice = &slice[prefilled_count.amount_of_bytes()..];
+        let mut differential_indexes = Vec::new();
+        let mut transactions = Vec::new();
+        for _ in 0..prefilled_count.to_usize() {
+            let differential_index = VarLenInt::from_bytes(slice)?;
+            slice = &slice[differential_index.amount_of_bytes()..];
+            let tx = Transaction::from_bytes(slice).ok()?;
+            slice = slice.get(tx.amount_of_bytes()..)?;
+            differential_indexes.push(differential_index.to_usize());
+            transactions.push(tx);
+        }
+        if !slice.is_empty() {
+            return None;
+        }
+
+        let prefilled_txs = absolute_indexes(&differential_indexes)?
+            .into_iter()
+            .zip(transactions)
+            .map(|(index, tx)| PrefilledTransaction { index, tx })
+            .collect();
+        Some(CmpctBlockMessage {
+            header,
+            nonce,
+            short_ids,
+            prefilled_txs,
+        })
+    }
+}
+
+impl MessageTrait for GetBlockTxnMessage {
+    type MessageType = GetBlockTxnMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingGetBlockTxnMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol. Indexes are encoded as the
+    /// difference with the previous one.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = Vec::from(self.block_hash);
+        bytes_vector.extend(VarLenInt::new(self.indexes.len()).to_bytes());
+        for differential_index in differential_indexes(&self.indexes) {
+            bytes_vector.extend(VarLenInt::new(differential_index).to_bytes());
+        }
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(get_block_txn_msg) => Ok(get_block_txn_msg),
+            None => Err(MessageError::ErrorCreatingGetBlockTxnMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("getblocktxn\0", &self.to_bytes())
+    }
+}
+
+impl GetBlockTxnMessage {
+    pub fn new(block_hash: [u8; 32], indexes: Vec<usize>) -> GetBlockTxnMessage {
+        GetBlockTxnMessage {
+            block_hash,
+            indexes,
+        }
+    }
+
+    fn _from_bytes(slice: &[u8]) -> Option<GetBlockTxnMessage> {
+        let block_hash: [u8; 32] = slice.get(..HASH_SIZE)?.try_into().ok()?;
+        let mut slice = &slice[HASH_SIZE..];
+        let indexes_count = VarLenInt::from_bytes(slice)?;
+        slice = &slice[indexes_count.amount_of_bytes()..];
+        let mut differential_indexes = Vec::new();
+        for _ in 0..indexes_count.to_usize() {
+            let differential_index = VarLenInt::from_bytes(slice)?;
+            slice = &slice[differential_index.amount_of_bytes()..];
+            differential_indexes.push(differential_index.to_usize());
+        }
+        if !slice.is_empty() {
+            return None;
+        }
+        Some(GetBlockTxnMessage {
+            block_hash,
+            indexes: absolute_indexes(&differential_indexes)?,
+        })
+    }
+}
+
+impl MessageTrait for BlockTxnMessage {
+    type MessageType = BlockTxnMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingBlockTxnMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = Vec::from(self.block_hash);
+        bytes_vector.extend(VarLenInt::new(self.transactions.len()).to_bytes());
+        for tx in &self.transactions {
+            bytes_vector.extend(tx.to_bytes());
+        }
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(block_txn_msg) => Ok(block_txn_msg),
+            None => Err(MessageError::ErrorCreatingBlockTxnMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("blocktxn\0\0\0\0", &self.to_bytes())
+    }
+}
+
+impl BlockTxnMessage {
+    pub fn new(block_hash: [u8; 32], transactions: Vec<Transaction>) -> BlockTxnMessage {
+        BlockTxnMessage {
+            block_hash,
+            transactions,
+        }
+    }
+
+    fn _from_bytes(slice: &[u8]) -> Option<BlockTxnMessage> {
+        let block_hash: [u8; 32] = slice.get(..HASH_SIZE)?.try_into().ok()?;
+        let mut slice = &slice[HASH_SIZE..];
+        let transaction_count = VarLenInt::from_bytes(slice)?;
+        slice = &slice[transaction_count.amount_of_bytes()..];
+        let mut transactions = Vec::new();
+        for _ in 0..transaction_count.to_usize() {
+            let tx = Transaction::from_bytes(slice).ok()?;
+            slice = slice.get(tx.amount_of_bytes()..)?;
+            transactions.push(tx);
+        }
+        if !slice.is_empty() {
+            return None;
+        }
+        Some(BlockTxnMessage {
+            block_hash,
+            transactions,
+        })
+    }
+}
+
+/// Returns the short id of the transaction: the SipHash-2-4 of its txid with the given keys,
+/// truncated to 6 bytes.
+pub fn short_id(keys: (u64, u64), txid: &[u8; 32]) -> u64 {
+    siphash24::Hash::hash_to_u64_with_keys(keys.0, keys.1, txid) & 0xffff_ffff_ffff
+}
+
+/// Returns each index as the difference with the previous one minus one, as BIP152 encodes them.
+fn differential_indexes(indexes: &[usize]) -> Vec<usize> {
+    let mut previous = None;
+    indexes
+        .iter()
+        .map(|index| {
+            let differential_index = match previous {
+                Some(previous) => index.saturating_sub(previous + 1),
+                None => *index,
+            };
+            previous = Some(*index);
+            differential_index
+        })
+        .collect()
+}
+
+/// Returns the indexes encoded as differences, or None if any of them overflows.
+fn absolute_indexes(differential_indexes: &[usize]) -> Option<Vec<usize>> {
+    let mut indexes: Vec<usize> = Vec::new();
+    for differential_index in differential_indexes {
+        let index = match indexes.last() {
+            Some(previous) => previous.checked_add(differential_index + 1)?,
+            None => *differential_index,
+        };
+        if index > u16::MAX as usize {
+            return None;
+        }
+        indexes.push(index);
+    }
+    Some(indexes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn block() -> Block {
+        let transactions = (0..3)
+            .map(|lock_time| Transaction::new(1, Vec::new(), Vec::new(), lock_time))
+            .collect();
+        Block::new(
+            BlockHeader::new(70015, [1; 32], [2; 32], 0x1d00ffff),
+            transactions,
+        )
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn compact_block_test_1_cmpct_block_to_and_from_bytes() -> Result<(), MessageError> {
+        let block = block();
+        let cmpct_block_msg = CmpctBlockMessage::from_block(&block, 7)?;
+
+        let received_msg = CmpctBlockMessage::from_bytes(&cmpct_block_msg.to_bytes())?;
+
+        assert_eq!(received_msg, cmpct_block_msg);
+        assert_eq!(received_msg.transaction_count(), 3);
+        assert_eq!(received_msg.prefilled_txs[0].index, 0);
+        let keys = received_msg.short_id_keys();
+        assert_eq!(
+            received_msg.short_ids[1],
+            short_id(keys, &block.get_transactions()[2].hash())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compact_block_test_2_indexes_are_differentially_encoded() -> Result<(), MessageError> {
+        let get_block_txn_msg = GetBlockTxnMessage::new([3; 32], vec![1, 2, 5]);
+
+        let bytes = get_block_txn_msg.to_bytes();
+
+        assert_eq!(bytes[HASH_SIZE..], [3, 1, 0, 2]);
+        assert_eq!(GetBlockTxnMessage::from_bytes(&bytes)?, get_block_txn_msg);
+        Ok(())
+    }
+
+    #[test]
+    fn compact_block_test_3_block_txn_to_and_from_bytes() -> Result<(), MessageError> {
+        let block_txn_msg = BlockTxnMessage::new(
+            [4; 32],
+            vec![Transaction::new(1, Vec::new(), Vec::new(), 9)],
+        );
+
+        let mut bytes = block_txn_msg.to_bytes();
+
+        assert_eq!(BlockTxnMessage::from_bytes(&bytes)?, block_txn_msg);
+        bytes.push(0);
+        assert_eq!(
+            BlockTxnMessage::from_bytes(&bytes),
+            Err(MessageError::ErrorCreatingBlockTxnMessage)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+use super::message_trait::*;
+use crate::utils::variable_length_integer::VarLenInt;
+
+/// Maximum amount of filters a getcfilters message can ask for.
+pub const MAX_CFILTERS_REQUEST: u32 = 1000;
+/// Maximum amount of filter headers a getcfheaders message can ask for.
+pub const MAX_CFHEADERS_REQUEST: u32 = 2000;
+/// Interval, in blocks, between the filter headers of a cfcheckpt message.
+pub const CFCHECKPT_INTERVAL: usize = 1000;
+const HASH_SIZE: usize = 32;
+const HEIGHT_SIZE: usize = 4;
+
+/// Message by which a peer asks for the filters of the given type of the blocks from start_height up to
+/// the block with stop_hash (BIP157).
+#[derive(Debug, PartialEq)]
+pub struct GetCFiltersMessage {
+    pub filter_type: u8,
+    pub start_height: u32,
+    pub stop_hash: [u8; 32],
+}
+
+/// Message with the filter of a block, answering a getcfilters message.
+#[derive(Debug, PartialEq)]
+pub struct CFilterMessage {
+    pub filter_type: u8,
+    pub block_hash: [u8; 32],
+    pub filter: Vec<u8>,
+}
+
+/// Message by which a peer asks for the filter headers of the given type of the blocks from start_height
+/// up to the block with stop_hash.
+#[derive(Debug, PartialEq)]
+pub struct GetCFHeadersMessage {
+    pub filter_type: u8,
+    pub start_height: u32,
+    pub stop_hash: [u8; 32],
+}
+
+/// Message answering a getcfheaders message with the hashes of the filters, alongside the filter header
+/// of the block before the first one, from which the rest of the filter headers can be derived.
+#[derive(Debug, PartialEq)]
+pub struct CFHeadersMessage {
+    pub filter_type: u8,
+    pub stop_hash: [u8; 32],
+    pub previous_filter_header: [u8; 32],
+    pub filter_hashes: Vec<[u8; 32]>,
+}
+
+/// Message by which a peer asks for the filter headers every CFCHECKPT_INTERVAL blocks up to the block with stop_hash.
+#[derive(Debug, PartialEq)]
+pub struct GetCFCheckptMessage {
+    pub filter_type: u8,
+    pub stop_hash: [u8; 32],
+}
+
+/// Message answering a getcfcheckpt message with the filter headers every CFCHECKPT_INTERVAL blocks.
+#[derive(Debug, PartialEq)]
+pub struct CFCheckptMessage {
+    pub filter_type: u8,
+    pub stop_hash: [u8; 32],
+    pub filter_headers: Vec<[u8; 32]>,
+}
+
+impl MessageTrait for GetCFiltersMessage {
+    type MessageType = GetCFiltersMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingGetCFiltersMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        range_request_to_bytes(self.filter_type, self.start_height, &self.stop_hash)
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        let (filter_type, start_height, stop_hash) =
+            range_request_from_bytes(slice).ok_or(MessageError::ErrorCreatingGetCFiltersMessage)?;
+        Ok(GetCFiltersMessage {
+            filter_type,
+            start_height,
+            stop_hash,
+        })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("getcfilters\0", &self.to_bytes())
+    }
+}
+
+impl MessageTrait for CFilterMessage {
+    type MessageType = CFilterMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingCFilterMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = vec![self.filter_type];
+        bytes_vector.extend(self.block_hash);
+        bytes_vector.extend(VarLenInt::new(self.filter.len()).to_bytes());
+        bytes_vector.extend(&self.filter);
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(cfilter_msg) => Ok(cfilter_msg),
+            None => Err(MessageError::ErrorCreatingCFilterMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("cfilter\0\0\0\0\0", &self.to_bytes())
+    }
+}
+
+impl CFilterMessage {
+    fn _from_bytes(slice: &[u8]) -> Option<CFilterMessage> {
+        let filter_type = *slice.first()?;
+        let block_hash: [u8; 32] = slice.get(1..1 + HASH_SIZE)?.try_into().ok()?;
+        let slice = &slice[1 + HASH_SIZE..];
+        let filter_length = VarLenInt::from_bytes(slice)?;
+        let filter = &slice[filter_length.amount_of_bytes()..];
+        if filter.len() != filter_length.to_usize() {
+            return None;
+        }
+        Some(CFilterMessage {
+            filter_type,
+            block_hash,
+            filter: filter.to_vec(),
+        })
+    }
+}
+
+impl MessageTrait for GetCFHeadersMessage {
+    type MessageType = GetCFHeadersMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingGetCFHeadersMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        range_request_to_bytes(self.filter_type, self.start_height, &self.stop_hash)
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        let (filter_type, start_height, stop_hash) = range_request_from_bytes(slice)
+            .ok_or(MessageError::ErrorCreatingGetCFHeadersMessage)?;
+        Ok(GetCFHeadersMessage {
+            filter_type,
+            start_height,
+            stop_hash,
+        })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("getcfheaders", &self.to_bytes())
+    }
+}
+
+impl MessageTrait for CFHeadersMessage {
+    type MessageType = CFHeadersMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingCFHeadersMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = vec![self.filter_type];
+        bytes_vector.extend(self.stop_hash);
+        bytes_vector.extend(self.previous_filter_header);
+        bytes_vector.extend(hashes_to_bytes(&self.filter_hashes));
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(cfheaders_msg) => Ok(cfheaders_msg),
+            None => Err(MessageError::ErrorCreatingCFHeadersMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("cfheaders\0\0\0", &self.to_bytes())
+    }
+}
+
+impl CFHeadersMessage {
+    fn _from_bytes(slice: &[u8]) -> Option<CFHeadersMessage> {
+        let filter_type = *slice.first()?;
+        let stop_hash: [u8; 32] = slice.get(1..1 + HASH_SIZE)?.try_into().ok()?;
+        let previous_filter_header: [u8; 32] = slice
+            .get(1 + HASH_SIZE..1 + 2 * HASH_SIZE)?
+            .try_into()
+            .ok()?;
+        Some(CFHeadersMessage {
+            filter_type,
+            stop_hash,
+            previous_filter_header,
+            filter_hashes: hashes_from_bytes(&slice[1 + 2 * HASH_SIZE..])?,
+        })
+    }
+}
+
+impl MessageTrait for GetCFCheckptMessage {
+    type MessageType = GetCFCheckptMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingGetCFCheckptMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = vec![self.filter_type];
+        bytes_vector.extend(self.stop_hash);
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        if slice.len() != 1 + HASH_SIZE {
+            return Err(MessageError::ErrorCreatingGetCFCheckptMessage);
+        }
+        let stop_hash: [u8; 32] = slice[1..]
+            .try_into()
+            .map_err(|_| MessageError::ErrorCreatingGetCFCheckptMessage)?;
+        Ok(GetCFCheckptMessage {
+            filter_type: slice[0],
+            stop_hash,
+        })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("getcfcheckpt", &self.to_bytes())
+    }
+}
+
+impl MessageTrait for CFCheckptMessage {
+    type MessageType = CFCheckptMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingCFCheckptMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = vec![self.filter_type];
+        bytes_vector.extend(self.stop_hash);
+        bytes_vector.extend(hashes_to_bytes(&self.filter_headers));
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        match Self::_from_bytes(slice) {
+            Some(cfcheckpt_msg) => Ok(cfcheckpt_msg),
+            None => Err(MessageError::ErrorCreatingCFCheckptMessage),
+        }
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("cfcheckpt\0\0\0", &self.to_bytes())
+    }
+}
+
+impl CFCheckptMessage {
+    fn _from_bytes(slice: &[u8]) -> Option<CFCheckptMessage> {
+        let filter_type = *slice.first()?;
+        let stop_hash: [u8; 32] = slice.get(1..1 + HASH_SIZE)?.try_into().ok()?;
+        Some(CFCheckptMessage {
+            filter_type,
+            stop_hash,
+            filter_headers: hashes_from_bytes(&slice[1 + HASH_SIZE..])?,
+        })
+    }
+}
+
+/// Returns the bytes of the getcfilters and getcfheaders messages, which share their fields.
+fn range_request_to_bytes(filter_type: u8, start_height: u32, stop_hash: &[u8; 32]) -> Vec<u8> {
+    let mut bytes_vector = vec![filter_type];
+    bytes_vector.extend(start_height.to_le_bytes());
+    bytes_vector.extend(stop_hash);
+    bytes_vector
+}
+
+/// Returns the filter type, start height and stop hash of a getcfilters or getcfheaders message.
+fn range_request_from_bytes(slice: &[u8]) -> Option<(u8, u32, [u8; 32])> {
+    if slice.len() != 1 + HEIGHT_SIZE + HASH_SIZE {
+        return None;
+    }
+    let start_height: [u8; HEIGHT_SIZE] = slice[1..1 + HEIGHT_SIZE].try_into().ok()?;
+    let stop_hash: [u8; 32] = slice[1 + HEIGHT_SIZE..].try_into().ok()?;
+    Some((slice[0], u32::from_le_bytes(start_height), stop_hash))
+}
+
+/// Returns the hashes preceded by their amount.
+fn hashes_to_bytes(hashes: &[[u8; 32]]) -> Vec<u8> {
+    let mut bytes_vector = VarLenInt::new(hashes.len()).to_bytes();
+    for hash in hashes {
+        bytes_vector.extend(hash);
+    }
+    bytes_vector
+}
+
+/// Returns the hashes of the slice, which must be exactly the amount it starts with.
+fn hashes_from_bytes(slice: &[u8]) -> Option<Vec<[u8; 32]>> {
+    let hashes_count = VarLenInt::from_bytes(slice)?;
+    let slice = &slice[hashes_count.amount_of_bytes()..];
+    if slice.len() != hashes_count.to_usize() * HASH_SIZE {
+        return None;
+    }
+    slice
+        .chunks_exact(HASH_SIZE)
+        .map(|hash| hash.try_into().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn compact_filter_messages_test_1_range_requests_to_and_from_bytes() -> Result<(), MessageError>
+    {
+        let get_cfilters_msg = GetCFiltersMessage {
+            filter_type: 0,
+            start_height: 2_000_000,
+            stop_hash: [7; 32],
+        };
+
+        let bytes = get_cfilters_msg.to_bytes();
+
+        assert_eq!(bytes.len(), 37);
+        assert_eq!(GetCFiltersMessage::from_bytes(&bytes)?, get_cfilters_msg);
+        assert_eq!(
+            GetCFHeadersMessage::from_bytes(&bytes[1..]),
+            Err(MessageError::ErrorCreatingGetCFHeadersMessage)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn compact_filter_messages_test_2_answers_to_and_from_bytes() -> Result<(), MessageError> {
+        let cfilter_msg = CFilterMessage {
+            filter_type: 0,
+            block_hash: [1; 32],
+            filter: vec![1, 0x9d, 0xfc, 0xa8],
+        };
+        let cfheaders_msg = CFHeadersMessage {
+            filter_type: 0,
+            stop_hash: [2; 32],
+            previous_filter_header: [3; 32],
+            filter_hashes: vec![[4; 32], [5; 32]],
+        };
+        let cfcheckpt_msg = CFCheckptMessage {
+            filter_type: 0,
+            stop_hash: [2; 32],
+            filter_headers: vec![[6; 32]],
+        };
+
+        assert_eq!(
+            CFilterMessage::from_bytes(&cfilter_msg.to_bytes())?,
+            cfilter_msg
+        );
+        assert_eq!(
+            CFHeadersMessage::from_bytes(&cfheaders_msg.to_bytes())?,
+            cfheaders_msg
+        );
+        assert_eq!(
+            CFCheckptMessage::from_bytes(&cfcheckpt_msg.to_bytes())?,
+            cfcheckpt_msg
+        );
+        let mut truncated = cfheaders_msg.to_bytes();
+        truncated.pop();
+        assert_eq!(
+            CFHeadersMessage::from_bytes(&truncated),
+            Err(MessageError::ErrorCreatingCFHeadersMessage)
+        );
+        Ok(())
+    }
+}
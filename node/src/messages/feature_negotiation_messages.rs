@@ -0,0 +1,215 @@
+use super::message_trait::*;
+
+const FEE_FILTER_SIZE: usize = 8;
+const SEND_CMPCT_SIZE: usize = 9;
+
+/// Message by which a peer asks to be announced new blocks with headers messages instead of inv messages (BIP130).
+#[derive(Debug, PartialEq, Default)]
+pub struct SendHeadersMessage {}
+
+/// Message by which a peer asks not to be announced transactions whose fee rate, in satoshis
+/// per kilobyte, is lower than the given one (BIP133).
+#[derive(Debug, PartialEq)]
+pub struct FeeFilterMessage {
+    fee_rate: u64,
+}
+
+/// Message by which a peer announces it supports compact blocks of the given version, and whether
+/// it wants new blocks to be announced directly as compact blocks (BIP152).
+#[derive(Debug, PartialEq)]
+pub struct SendCmpctMessage {
+    announce: bool,
+    version: u64,
+}
+
+/// Message by which a peer announces it relays transactions by their wtxid (BIP339).
+#[derive(Debug, PartialEq, Default)]
+pub struct WtxidRelayMessage {}
+
+impl MessageTrait for SendHeadersMessage {
+    type MessageType = SendHeadersMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingSendHeadersMessage;
+
+    /// Returns an empty vector of bytes, since the SendHeadersMessage has no payload.
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Returns a SendHeadersMessage if the slice of bytes is empty, otherwise returns a MessageError.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        if !slice.is_empty() {
+            return Err(MessageError::ErrorCreatingSendHeadersMessage);
+        }
+        Ok(SendHeadersMessage {})
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("sendheaders\0", &self.to_bytes())
+    }
+}
+
+impl SendHeadersMessage {
+    pub fn new() -> SendHeadersMessage {
+        SendHeadersMessage {}
+    }
+}
+
+impl MessageTrait for FeeFilterMessage {
+    type MessageType = FeeFilterMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingFeeFilterMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        self.fee_rate.to_le_bytes().to_vec()
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        let fee_rate: [u8; FEE_FILTER_SIZE] = slice
+            .try_into()
+            .map_err(|_| MessageError::ErrorCreatingFeeFilterMessage)?;
+        Ok(FeeFilterMessage {
+            fee_rate: u64::from_le_bytes(fee_rate),
+        })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("feefilter\0\0\0", &self.to_bytes())
+    }
+}
+
+impl FeeFilterMessage {
+    pub fn new(fee_rate: u64) -> FeeFilterMessage {
+        FeeFilterMessage { fee_rate }
+    }
+
+    /// Returns the minimum fee rate, in satoshis per kilobyte, of the transactions the peer wants.
+    pub fn get_fee_rate(&self) -> u64 {
+        self.fee_rate
+    }
+}
+
+impl MessageTrait for SendCmpctMessage {
+    type MessageType = SendCmpctMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingSendCmpctMessage;
+
+    /// Transforms the message to bytes, usig the p2p bitcoin protocol
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes_vector = vec![self.announce as u8];
+        bytes_vector.extend(self.version.to_le_bytes());
+        bytes_vector
+    }
+
+    /// Creates the coresponding message, using a slice of bytes, wich must be of the correct size, otherwise an error will be returned.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        if slice.len() != SEND_CMPCT_SIZE || slice[0] > 1 {
+            return Err(MessageError::ErrorCreatingSendCmpctMessage);
+        }
+        let version: [u8; 8] = slice[1..]
+            .try_into()
+            .map_err(|_| MessageError::ErrorCreatingSendCmpctMessage)?;
+        Ok(SendCmpctMessage {
+            announce: slice[0] == 1,
+            version: u64::from_le_bytes(version),
+        })
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("sendcmpct\0\0\0", &self.to_bytes())
+    }
+}
+
+impl SendCmpctMessage {
+    pub fn new(announce: bool, version: u64) -> SendCmpctMessage {
+        SendCmpctMessage { announce, version }
+    }
+
+    /// Returns true if the peer wants new blocks to be announced as compact blocks.
+    pub fn get_announce(&self) -> bool {
+        self.announce
+    }
+
+    /// Returns the version of compact blocks the peer supports.
+    pub fn get_version(&self) -> u64 {
+        self.version
+    }
+}
+
+impl MessageTrait for WtxidRelayMessage {
+    type MessageType = WtxidRelayMessage;
+    const SENDING_ERROR: MessageError = MessageError::ErrorSendingWtxidRelayMessage;
+
+    /// Returns an empty vector of bytes, since the WtxidRelayMessage has no payload.
+    fn to_bytes(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Returns a WtxidRelayMessage if the slice of bytes is empty, otherwise returns a MessageError.
+    fn from_bytes(slice: &[u8]) -> Result<Self::MessageType, MessageError> {
+        if !slice.is_empty() {
+            return Err(MessageError::ErrorCreatingWtxidRelayMessage);
+        }
+        Ok(WtxidRelayMessage {})
+    }
+
+    /// Gets the header message corresponding to the corresponding message
+    fn get_header_message(&self) -> Result<HeaderMessage, MessageError> {
+        HeaderMessage::new("wtxidrelay\0\0", &self.to_bytes())
+    }
+}
+
+impl WtxidRelayMessage {
+    pub fn new() -> WtxidRelayMessage {
+        WtxidRelayMessage {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn feature_negotiation_test_1_fee_filter_from_bytes() -> Result<(), MessageError> {
+        let fee_filter = FeeFilterMessage::from_bytes(&[0xe8, 0x03, 0, 0, 0, 0, 0, 0])?;
+
+        assert_eq!(fee_filter.get_fee_rate(), 1000);
+        assert_eq!(
+            FeeFilterMessage::from_bytes(&[0xe8, 0x03]),
+            Err(MessageError::ErrorCreatingFeeFilterMessage)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn feature_negotiation_test_2_send_cmpct_to_and_from_bytes() -> Result<(), MessageError> {
+        let send_cmpct = SendCmpctMessage::new(true, 2);
+
+        let bytes = send_cmpct.to_bytes();
+
+        assert_eq!(bytes, vec![1, 2, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(SendCmpctMessage::from_bytes(&bytes)?, send_cmpct);
+        assert_eq!(
+            SendCmpctMessage::from_bytes(&[2, 1, 0, 0, 0, 0, 0, 0, 0]),
+            Err(MessageError::ErrorCreatingSendCmpctMessage)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn feature_negotiation_test_3_empty_messages_reject_payloads() {
+        assert_eq!(
+            SendHeadersMessage::from_bytes(&[]),
+            Ok(SendHeadersMessage::new())
+        );
+        assert_eq!(
+            WtxidRelayMessage::from_bytes(&[1]),
+            Err(MessageError::ErrorCreatingWtxidRelayMessage)
+        );
+    }
+}
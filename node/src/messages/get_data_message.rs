@@ -46,7 +46,31 @@ impl GetDataMessage {
         }
     }
 
+    /// Creates a new GetDataMessage asking for the given blocks as compact blocks.
+    pub fn create_message_inventory_compact_block_type(
+        inventory_entries: Vec<[u8; 32]>,
+    ) -> GetDataMessage {
+        GetDataMessage {
+            inv: InvMessage::create_message_inventory_compact_block_type(inventory_entries),
+        }
+    }
+
+    /// Creates a new GetDataMessage asking for the given blocks as merkleblock messages, filtered with
+    /// the bloom filter loaded in the peer.
+    pub fn create_message_inventory_filtered_block_type(
+        inventory_entries: Vec<[u8; 32]>,
+    ) -> GetDataMessage {
+        GetDataMessage {
+            inv: InvMessage::create_message_inventory_filtered_block_type(inventory_entries),
+        }
+    }
+
     pub fn get_block_hashes(&self) -> Vec<[u8; 32]> {
         self.inv.get_block_hashes()
     }
+
+    /// Returns the hashes of the blocks asked for as compact blocks.
+    pub fn get_compact_block_hashes(&self) -> Vec<[u8; 32]> {
+        self.inv.get_compact_block_hashes()
+    }
 }
@@ -2,7 +2,7 @@ use super::message_trait::*;
 use bitcoin_hashes::{sha256d, Hash};
 
 const MESAGE_HEADER_SIZE: usize = 24;
-const START_STRING_TEST_NET: [u8; 4] = [0x0b, 0x11, 0x09, 0x07];
+pub const START_STRING_TEST_NET: [u8; 4] = [0x0b, 0x11, 0x09, 0x07];
 const COMMAND_NAME_ERROR: &str = "\0\0\0\0\0\0\0\0\0\0\0\0";
 const COMMAND_NAME_SIZE: usize = 12;
 
@@ -3,6 +3,8 @@ use crate::utils::variable_length_integer::VarLenInt;
 
 const BLOCK_IDENTIFIER: [u8; 4] = [0x02, 0x00, 0x00, 0x00];
 const TRANSACTION_IDENTIFIER: [u8; 4] = [0x01, 0x00, 0x00, 0x00];
+const FILTERED_BLOCK_IDENTIFIER: [u8; 4] = [0x03, 0x00, 0x00, 0x00];
+const COMPACT_BLOCK_IDENTIFIER: [u8; 4] = [0x04, 0x00, 0x00, 0x00];
 const INVENTORY_ENTRY_SIZE: usize = 36;
 
 /// Struct that represents an element of the inventory.
@@ -107,6 +109,37 @@ impl InvMessage {
         block_hashes
     }
 
+    /// Creates a new InvMessage asking for the given blocks as compact blocks, only valid inside a GetDataMessage.
+    pub fn create_message_inventory_compact_block_type(
+        inventory_entries: Vec<[u8; 32]>,
+    ) -> InvMessage {
+        let inventory = inventory_entries
+            .into_iter()
+            .map(Entry::as_compact_block_entry)
+            .collect();
+        Self::new(inventory)
+    }
+
+    /// Creates a new InvMessage asking for the given blocks as merkleblock messages, only valid inside a GetDataMessage.
+    pub fn create_message_inventory_filtered_block_type(
+        inventory_entries: Vec<[u8; 32]>,
+    ) -> InvMessage {
+        let inventory = inventory_entries
+            .into_iter()
+            .map(Entry::as_filtered_block_entry)
+            .collect();
+        Self::new(inventory)
+    }
+
+    /// Returns the hashes of the blocks asked for as compact blocks.
+    pub fn get_compact_block_hashes(&self) -> Vec<[u8; 32]> {
+        self.inventory
+            .iter()
+            .filter(|entry| entry.inv_type == COMPACT_BLOCK_IDENTIFIER)
+            .map(|entry| entry.hash)
+            .collect()
+    }
+
     /// Returns the block hashes of the inventory.
     pub fn get_transaction_hashes(&self) -> Vec<[u8; 32]> {
         let mut transaction_hashes: Vec<[u8; 32]> = Vec::new();
@@ -136,6 +169,22 @@ impl Entry {
         }
     }
 
+    /// Returns a new entry with a filtered block identifier and the given hash.
+    fn as_filtered_block_entry(hash: [u8; 32]) -> Entry {
+        Entry {
+            inv_type: FILTERED_BLOCK_IDENTIFIER,
+            hash,
+        }
+    }
+
+    /// Returns a new entry with a compact block identifier and the given hash.
+    fn as_compact_block_entry(hash: [u8; 32]) -> Entry {
+        Entry {
+            inv_type: COMPACT_BLOCK_IDENTIFIER,
+            hash,
+        }
+    }
+
     /// Returns the bytes of the entry.
     fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::from(self.inv_type);
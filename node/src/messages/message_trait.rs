@@ -31,9 +31,12 @@ pub trait MessageTrait {
 }
 
 pub enum Message {
+    Addr(AddrMessage),
+    AddrV2(AddrV2Message),
     BlockHeaders(BlockHeadersMessage),
     Block(BlockMessage),
     GetBlockHeaders(GetBlockHeadersMessage),
+    GetAddr(GetAddrMessage),
     GetData(GetDataMessage),
     Header(HeaderMessage),
     Inv(InvMessage),
@@ -43,17 +46,36 @@ pub enum Message {
     Version(VersionMessage),
     Ping(PingMessage),
     Pong(PongMessage),
+    SendAddrV2(SendAddrV2Message),
+    SendHeaders(SendHeadersMessage),
+    FeeFilter(FeeFilterMessage),
+    SendCmpct(SendCmpctMessage),
+    WtxidRelay(WtxidRelayMessage),
+    CmpctBlock(CmpctBlockMessage),
+    GetBlockTxn(GetBlockTxnMessage),
+    BlockTxn(BlockTxnMessage),
+    GetCFilters(GetCFiltersMessage),
+    CFilter(CFilterMessage),
+    GetCFHeaders(GetCFHeadersMessage),
+    CFHeaders(CFHeadersMessage),
+    GetCFCheckpt(GetCFCheckptMessage),
+    CFCheckpt(CFCheckptMessage),
+    FilterLoad(FilterLoadMessage),
+    MerkleBlock(MerkleBlockMessage),
     UnknownMessage,
 }
 
 impl Message {
     pub fn from_bytes(bytes: Vec<u8>, command_name: String) -> Result<Message, MessageError> {
         let mensaje = match command_name.as_str() {
+            "addr\0\0\0\0\0\0\0\0" => Message::Addr(AddrMessage::from_bytes(&bytes)?),
+            "addrv2\0\0\0\0\0\0" => Message::AddrV2(AddrV2Message::from_bytes(&bytes)?),
             "headers\0\0\0\0\0" => Message::BlockHeaders(BlockHeadersMessage::from_bytes(&bytes)?),
             "block\0\0\0\0\0\0\0" => Message::Block(BlockMessage::from_bytes(&bytes)?),
             "getheaders\0\0" => {
                 Message::GetBlockHeaders(GetBlockHeadersMessage::from_bytes(&bytes)?)
             }
+            "getaddr\0\0\0\0\0" => Message::GetAddr(GetAddrMessage::from_bytes(&bytes)?),
             "getdata\0\0\0\0\0" => Message::GetData(GetDataMessage::from_bytes(&bytes)?),
             "header\0\0\0\0\0\0" => Message::Header(HeaderMessage::from_bytes(&bytes)?),
             "inv\0\0\0\0\0\0\0\0\0" => Message::Inv(InvMessage::from_bytes(&bytes)?),
@@ -63,6 +85,22 @@ impl Message {
             "version\0\0\0\0\0" => Message::Version(VersionMessage::from_bytes(&bytes)?),
             "ping\0\0\0\0\0\0\0\0" => Message::Ping(PingMessage::from_bytes(&bytes)?),
             "pong\0\0\0\0\0\0\0\0" => Message::Pong(PongMessage::from_bytes(&bytes)?),
+            "sendaddrv2\0\0" => Message::SendAddrV2(SendAddrV2Message::from_bytes(&bytes)?),
+            "sendheaders\0" => Message::SendHeaders(SendHeadersMessage::from_bytes(&bytes)?),
+            "feefilter\0\0\0" => Message::FeeFilter(FeeFilterMessage::from_bytes(&bytes)?),
+            "sendcmpct\0\0\0" => Message::SendCmpct(SendCmpctMessage::from_bytes(&bytes)?),
+            "wtxidrelay\0\0" => Message::WtxidRelay(WtxidRelayMessage::from_bytes(&bytes)?),
+            "cmpctblock\0\0" => Message::CmpctBlock(CmpctBlockMessage::from_bytes(&bytes)?),
+            "getblocktxn\0" => Message::GetBlockTxn(GetBlockTxnMessage::from_bytes(&bytes)?),
+            "blocktxn\0\0\0\0" => Message::BlockTxn(BlockTxnMessage::from_bytes(&bytes)?),
+            "getcfilters\0" => Message::GetCFilters(GetCFiltersMessage::from_bytes(&bytes)?),
+            "cfilter\0\0\0\0\0" => Message::CFilter(CFilterMessage::from_bytes(&bytes)?),
+            "getcfheaders" => Message::GetCFHeaders(GetCFHeadersMessage::from_bytes(&bytes)?),
+            "cfheaders\0\0\0" => Message::CFHeaders(CFHeadersMessage::from_bytes(&bytes)?),
+            "getcfcheckpt" => Message::GetCFCheckpt(GetCFCheckptMessage::from_bytes(&bytes)?),
+            "cfcheckpt\0\0\0" => Message::CFCheckpt(CFCheckptMessage::from_bytes(&bytes)?),
+            "filterload\0\0" => Message::FilterLoad(FilterLoadMessage::from_bytes(&bytes)?),
+            "merkleblock\0" => Message::MerkleBlock(MerkleBlockMessage::from_bytes(&bytes)?),
             _ => Message::UnknownMessage,
         };
         Ok(mensaje)
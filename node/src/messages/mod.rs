@@ -1,5 +1,11 @@
+pub mod addr_message;
+pub mod addrv2_message;
 pub mod block_headers_message;
 pub mod block_message;
+pub mod bloom_filter_messages;
+pub mod compact_block_messages;
+pub mod compact_filter_messages;
+pub mod feature_negotiation_messages;
 pub mod get_block_headers_message;
 pub mod get_data_message;
 pub mod header_message;
@@ -11,8 +17,21 @@ pub mod tx_message;
 pub mod verack_message;
 pub mod version_message;
 
+pub use addr_message::{AddrMessage, GetAddrMessage, NetworkAddress};
+pub use addrv2_message::{AddrV2Message, NetworkAddressV2, SendAddrV2Message};
 pub use block_headers_message::BlockHeadersMessage;
 pub use block_message::BlockMessage;
+pub use bloom_filter_messages::{FilterLoadMessage, MerkleBlockMessage};
+pub use compact_block_messages::{
+    BlockTxnMessage, CmpctBlockMessage, GetBlockTxnMessage, COMPACT_BLOCKS_VERSION,
+};
+pub use compact_filter_messages::{
+    CFCheckptMessage, CFHeadersMessage, CFilterMessage, GetCFCheckptMessage, GetCFHeadersMessage,
+    GetCFiltersMessage,
+};
+pub use feature_negotiation_messages::{
+    FeeFilterMessage, SendCmpctMessage, SendHeadersMessage, WtxidRelayMessage,
+};
 pub use get_block_headers_message::GetBlockHeadersMessage;
 pub use get_data_message::GetDataMessage;
 pub use header_message::HeaderMessage;
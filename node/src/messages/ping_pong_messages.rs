@@ -1,5 +1,3 @@
-use std::net::TcpStream;
-
 use super::message_trait::*;
 
 /// Represents the NotFoundMessage as an InvMessage because it is equally implemented
@@ -31,7 +29,17 @@ impl MessageTrait for PingMessage {
 }
 
 impl PingMessage {
-    pub fn reply_pong(&self, stream: &mut TcpStream) -> Result<(), MessageError> {
+    pub fn new(nonce: u64) -> PingMessage {
+        PingMessage {
+            nonce: nonce.to_le_bytes(),
+        }
+    }
+
+    pub fn get_nonce(&self) -> u64 {
+        u64::from_le_bytes(self.nonce)
+    }
+
+    pub fn reply_pong<T: Read + Write>(&self, stream: &mut T) -> Result<(), MessageError> {
         PongMessage::from(self.nonce).send_to(stream)
     }
 }
@@ -67,4 +75,32 @@ impl PongMessage {
     fn from(nonce: [u8; 8]) -> PongMessage {
         PongMessage { nonce }
     }
+
+    pub fn get_nonce(&self) -> u64 {
+        u64::from_le_bytes(self.nonce)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::mock_tcp_stream::MockTcpStream;
+
+    const HEADER_SIZE: usize = 24;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn ping_pong_test_1_pong_replies_with_the_ping_nonce() -> Result<(), MessageError> {
+        let mut stream = MockTcpStream::new();
+        let ping = PingMessage::from_bytes(&PingMessage::new(0x0102030405060708).to_bytes())?;
+
+        ping.reply_pong(&mut stream)?;
+        let pong = PongMessage::from_bytes(&stream.write_buffer[HEADER_SIZE..])?;
+
+        assert_eq!(ping.get_nonce(), 0x0102030405060708);
+        assert_eq!(pong.get_nonce(), ping.get_nonce());
+        Ok(())
+    }
 }
@@ -5,6 +5,16 @@ use rand::prelude::*;
 use std::net::{IpAddr, SocketAddr};
 
 const NODE_NETWORK: u64 = 0x01;
+/// Service bit of the peers that serve bloom filters (BIP111).
+pub const NODE_BLOOM: u64 = 0x04;
+/// Service bit of the peers that serve compact block filters (BIP157).
+pub const NODE_COMPACT_FILTERS: u64 = 0x40;
+/// Service bit of the peers that only serve the last 288 blocks (BIP159).
+pub const NODE_NETWORK_LIMITED: u64 = 0x0400;
+/// Service bit of the peers that support the v2 transport (BIP324).
+pub const NODE_P2P_V2: u64 = 0x0800;
+/// Services every node offers: it serves the full blocks it stores.
+const NODE_SERVICES: u64 = NODE_NETWORK;
 const MINIMAL_VERSION_MESSAGE_SIZE: usize = 86;
 
 /// Contains all necessary fields, for sending a version message needed for doing a handshake among nodes
@@ -87,7 +97,7 @@ impl VersionMessage {
         let user_agent_length = VarLenInt::new(0);
         let version_msg = VersionMessage {
             version,
-            services: NODE_NETWORK,
+            services: NODE_SERVICES,
             timestamp: Utc::now().timestamp(),
             addr_recv_services: 0, //Como no sabemos que servicios admite el nodo asumimos que no admite ningun servicio
             receiver_address: {
@@ -97,7 +107,7 @@ impl VersionMessage {
                 }
             },
             receiver_port: receiver_address.port(),
-            addr_sender_services: NODE_NETWORK,
+            addr_sender_services: NODE_SERVICES,
             sender_address: {
                 match sender_address.ip() {
                     IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped().octets(),
@@ -115,6 +125,39 @@ impl VersionMessage {
         Ok(version_msg)
     }
 
+    /// Returns the protocol version announced by the peer.
+    pub fn get_version(&self) -> i32 {
+        self.version
+    }
+
+    /// Returns the services announced by the peer.
+    pub fn get_services(&self) -> u64 {
+        self.services
+    }
+
+    /// Adds the services to the ones announced by the node.
+    pub fn add_services(&mut self, services: u64) {
+        self.services |= services;
+        self.addr_sender_services |= services;
+    }
+
+    /// Announces NODE_NETWORK_LIMITED instead of NODE_NETWORK, since a pruned node only serves its recent blocks.
+    pub fn limit_to_recent_blocks(&mut self) {
+        self.services = (self.services & !NODE_NETWORK) | NODE_NETWORK_LIMITED;
+        self.addr_sender_services =
+            (self.addr_sender_services & !NODE_NETWORK) | NODE_NETWORK_LIMITED;
+    }
+
+    /// Returns the user agent of the peer, replacing any byte that is not valid UTF-8.
+    pub fn get_user_agent(&self) -> String {
+        String::from_utf8_lossy(&self.user_agent).to_string()
+    }
+
+    /// Returns the height of the last block the peer had when the connection started.
+    pub fn get_start_height(&self) -> i32 {
+        self.start_height
+    }
+
     /// Implementation of the trait _from for VersionMessage. Recieves a slice of bytes and
     /// returns an Option with either a VersionMessage if everything went Ok or None if any step
     /// in the middle of the conversion from bytes to VersionMessage fields failed.
@@ -171,12 +214,12 @@ mod tests {
     fn version_message_without_user_agent_expected_bytes(timestamp: i64, rand: u64) -> Vec<u8> {
         let mut bytes_vector = Vec::new();
         bytes_vector.extend_from_slice(&(70015 as i32).to_le_bytes());
-        bytes_vector.extend_from_slice(&NODE_NETWORK.to_le_bytes());
+        bytes_vector.extend_from_slice(&NODE_SERVICES.to_le_bytes());
         bytes_vector.extend_from_slice(&timestamp.to_le_bytes());
         bytes_vector.extend_from_slice(&(0 as u64).to_le_bytes());
         bytes_vector.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 2]);
         bytes_vector.extend_from_slice(&(8080 as u16).to_be_bytes());
-        bytes_vector.extend_from_slice(&(NODE_NETWORK as u64).to_le_bytes());
+        bytes_vector.extend_from_slice(&NODE_SERVICES.to_le_bytes());
         bytes_vector.extend_from_slice(&Ipv4Addr::from(LOCAL_HOST).to_ipv6_mapped().octets());
         bytes_vector.extend_from_slice(&LOCAL_PORT.to_be_bytes());
         bytes_vector.extend_from_slice(&rand.to_le_bytes());
@@ -190,12 +233,12 @@ mod tests {
         let rand: u64 = rand::thread_rng().gen();
         let mut bytes_vector = Vec::new();
         bytes_vector.extend_from_slice(&(70015 as i32).to_le_bytes());
-        bytes_vector.extend_from_slice(&NODE_NETWORK.to_le_bytes());
+        bytes_vector.extend_from_slice(&NODE_SERVICES.to_le_bytes());
         bytes_vector.extend_from_slice(&(Utc::now().timestamp() as u64).to_le_bytes());
         bytes_vector.extend_from_slice(&(0 as u64).to_le_bytes());
         bytes_vector.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 2]);
         bytes_vector.extend_from_slice(&(8080 as u16).to_be_bytes());
-        bytes_vector.extend_from_slice(&(NODE_NETWORK as u64).to_le_bytes());
+        bytes_vector.extend_from_slice(&NODE_SERVICES.to_le_bytes());
         bytes_vector.extend_from_slice(&Ipv4Addr::from(LOCAL_HOST).to_ipv6_mapped().octets());
         bytes_vector.extend_from_slice(&LOCAL_PORT.to_be_bytes());
         bytes_vector.extend_from_slice(&rand.to_le_bytes());
@@ -282,6 +325,25 @@ mod tests {
             VersionMessage::from_bytes(&mut expected_version_msg.to_bytes().as_mut_slice())?;
 
         assert_eq!(version_msg, expected_version_msg);
+        assert_eq!(version_msg.get_user_agent(), "test");
+        Ok(())
+    }
+
+    #[test]
+    fn version_message_test_6_pruned_node_announces_limited_services() -> Result<(), MessageError> {
+        let (receiver_socket, sender_socket) = create_socket();
+        let mut version_msg = VersionMessage::new(70015, receiver_socket, sender_socket)?;
+        assert_eq!(version_msg.get_services() & NODE_NETWORK, NODE_NETWORK);
+
+        version_msg.limit_to_recent_blocks();
+
+        let version_msg = VersionMessage::from_bytes(&version_msg.to_bytes())?;
+        assert_eq!(version_msg.get_services() & NODE_NETWORK, 0);
+        assert_eq!(
+            version_msg.get_services() & NODE_NETWORK_LIMITED,
+            NODE_NETWORK_LIMITED
+        );
+        assert_eq!(version_msg.addr_sender_services & NODE_NETWORK, 0);
         Ok(())
     }
 }
@@ -1,44 +1,81 @@
+pub mod address_book;
+pub mod address_index;
+pub mod ban_list;
+pub mod block_filter_index;
+pub mod block_store;
+pub mod block_validation;
+pub mod checkpointer;
 pub mod data_handler;
 pub mod handle_messages;
 pub mod handshake;
+pub mod header_sync;
 pub mod initial_block_download;
+pub mod light_client;
+pub mod maintenance;
 pub mod peer_comunication;
+pub mod peer_table;
 pub mod safe_node_structure;
+#[cfg(test)]
+pub mod test_chain;
+pub mod timelocks;
+pub mod tx_index;
 pub mod utxo_set;
+pub mod v2_transport;
 pub mod wallet_communication;
 
 use self::{
-    data_handler::NodeDataHandler, handle_messages::*, handshake::*, peer_comunication::*,
-    peer_comunicator::PeerComunicator, safe_node_structure::NodeSharedInformation,
+    address_book::{AddressBook, SafeAddressBook},
+    address_index::{AddressIndex, SafeAddressIndex},
+    ban_list::{BanList, SafeBanList, BAN_DURATION},
+    block_filter_index::{serves_compact_filters, BlockFilterIndex, SafeBlockFilterIndex},
+    block_store::BlockStore,
+    checkpointer::*,
+    data_handler::{NodeDataHandler, SafeNodeDataHandler},
+    handle_messages::*,
+    handshake::*,
+    light_client::LightClient,
+    maintenance::MaintenanceOptions,
+    peer_comunication::{outbound_connector::ConnectionSettings, *},
+    peer_comunicator::PeerComunicator,
+    peer_table::{PeerTable, PeerVersion, SafePeerTable},
+    safe_node_structure::NodeSharedInformation,
+    tx_index::{SafeTxIndex, TxIndex},
+    v2_transport::PeerStream,
 };
 use crate::{
     blocks::{blockchain::*, proof::*, transaction::TxOut, Outpoint, Transaction},
     messages::{message_trait::MessageError, *},
-    utils::{btc_errors::NodeError, config::*, log::*, LoadingScreenInfo, UIResponse},
+    utils::{btc_errors::NodeError, config::*, log::*, LoadingScreenInfo, PeerInfo, UIResponse},
 };
+use chrono::Utc;
 use glib::Sender as GlibSender;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     io::{ErrorKind::WouldBlock, Read, Write},
-    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
-    sync::{Arc, Mutex, MutexGuard},
+    net::{SocketAddr, TcpListener, ToSocketAddrs},
+    sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard},
     thread::sleep,
     time::Duration,
 };
 
 const MESSAGE_HEADER_SIZE: usize = 24;
+const MAX_MESSAGE_SIZE: usize = 4_000_000;
+const ADDRESS_BOOK_CANDIDATES_AT_STARTUP: usize = 8;
+const BYTES_PER_MB: u64 = 1024 * 1024;
 
-pub type SafeBlockChain = Arc<Mutex<HashMap<[u8; 32], Block>>>;
-pub type SafeVecHeader = Arc<Mutex<Vec<BlockHeader>>>;
+pub type SafeBlockChain = Arc<Mutex<BlockStore>>;
+pub type SafeVecHeader = Arc<RwLock<Vec<BlockHeader>>>;
 pub type SafePendingTx = Arc<Mutex<HashMap<[u8; 32], Transaction>>>;
-pub type SafeHeaderIndex = Arc<Mutex<HashMap<[u8; 32], usize>>>;
+pub type SafeHeaderIndex = Arc<RwLock<HashMap<[u8; 32], usize>>>;
 
 /// Struct that represents the bitcoin node
 pub struct Node {
     version: i32,
     address: SocketAddr,
-    pub initial_peers: Vec<TcpStream>,
-    data_handler: NodeDataHandler,
+    pub initial_peers: Vec<PeerStream>,
+    data_handler: SafeNodeDataHandler,
+    checkpointer: Option<Checkpointer>,
+    maintenance_options: MaintenanceOptions,
     block_headers: SafeVecHeader,
     headers_index: SafeHeaderIndex,
     starting_block_time: u32,
@@ -47,9 +84,18 @@ pub struct Node {
     pub peer_comunicator: Option<PeerComunicator>,
     pub balance: i64,
     pub pending_tx: SafePendingTx,
+    held_tx: HashMap<[u8; 32], Transaction>,
+    address_book: SafeAddressBook,
+    ban_list: SafeBanList,
+    block_filter_index: SafeBlockFilterIndex,
+    tx_index: SafeTxIndex,
+    address_index: SafeAddressIndex,
+    peer_table: SafePeerTable,
+    light_client: Option<LightClient>,
+    connection_settings: ConnectionSettings,
     last_proccesed_block: usize,
+    utxo_set_path: String,
     wallet_pk_hash: [u8; 20],
-    headers_in_disk: usize,
     pub logger: Logger,
     pub sender_to_ui: GlibSender<UIResponse>,
 }
@@ -68,18 +114,29 @@ impl Node {
             version,
             address: SocketAddr::from(local_address),
             initial_peers: Vec::new(),
-            block_headers: Arc::new(Mutex::from(Vec::new())),
-            headers_index: Arc::new(Mutex::from(HashMap::new())),
+            block_headers: Arc::new(RwLock::from(Vec::new())),
+            headers_index: Arc::new(RwLock::from(HashMap::new())),
             starting_block_time,
-            blockchain: Arc::new(Mutex::from(HashMap::new())),
+            blockchain: Arc::new(Mutex::from(BlockStore::new())),
             utxo_set: HashMap::new(),
             peer_comunicator: None,
-            data_handler,
+            data_handler: Arc::new(Mutex::from(data_handler)),
+            checkpointer: None,
+            maintenance_options: MaintenanceOptions::default(),
             pending_tx: Arc::new(Mutex::from(HashMap::new())),
+            held_tx: HashMap::new(),
+            address_book: Arc::new(Mutex::from(AddressBook::new())),
+            ban_list: Arc::new(Mutex::from(BanList::new())),
+            block_filter_index: Arc::new(Mutex::from(BlockFilterIndex::new())),
+            tx_index: Arc::new(Mutex::from(TxIndex::new())),
+            address_index: Arc::new(Mutex::from(AddressIndex::new())),
+            peer_table: Arc::new(Mutex::from(PeerTable::new())),
+            light_client: None,
+            connection_settings: ConnectionSettings::default(),
             balance: 0,
             last_proccesed_block: 0,
+            utxo_set_path: String::new(),
             wallet_pk_hash: [0; 20],
-            headers_in_disk: 0,
             logger,
             sender_to_ui,
         }
@@ -92,7 +149,7 @@ impl Node {
         let logger = Logger::from_path(config.log_path.as_str())
             .map_err(|_| NodeError::ErrorCreatingNode)?;
 
-        let data_handler = NodeDataHandler::new(&config.headers_path, &config.blocks_path)
+        let data_handler = NodeDataHandler::new(&config.headers_path)
             .map_err(|_| NodeError::ErrorCreatingNode)?;
 
         let mut node = Node::_new(
@@ -104,20 +161,84 @@ impl Node {
             sender_to_ui,
         );
 
+        let address_book = AddressBook::from_path(&config.peers_path)
+            .map_err(|_| NodeError::ErrorCreatingNode)?;
+        node.address_book = Arc::new(Mutex::from(address_book));
+        let mut ban_list =
+            BanList::from_path(&config.banlist_path).map_err(|_| NodeError::ErrorCreatingNode)?;
+        ban_list.remove_expired(Utc::now().timestamp() as u32);
+        node.ban_list = Arc::new(Mutex::from(ban_list));
+        if serves_compact_filters(&config) {
+            let block_filter_index = BlockFilterIndex::from_path(&config.filter_headers_path)
+                .map_err(|_| NodeError::ErrorCreatingNode)?;
+            node.block_filter_index = Arc::new(Mutex::from(block_filter_index));
+        }
+        if config.tx_index {
+            let tx_index = TxIndex::from_path(&config.tx_index_path)
+                .map_err(|_| NodeError::ErrorCreatingNode)?;
+            node.tx_index = Arc::new(Mutex::from(tx_index));
+        }
+        if config.address_index {
+            let address_index = AddressIndex::from_path(&config.address_index_path)
+                .map_err(|_| NodeError::ErrorCreatingNode)?;
+            node.address_index = Arc::new(Mutex::from(address_index));
+        }
+        let mut block_store = BlockStore::from_path(&config.blocks_path, &config.block_index_path)
+            .map_err(|_| NodeError::ErrorCreatingNode)?;
+        if config.prune_target_mb > 0 {
+            block_store.set_prune_target(Some(config.prune_target_mb * BYTES_PER_MB));
+        }
+        node.blockchain = Arc::new(Mutex::from(block_store));
+        node.utxo_set_path = config.utxo_set_path.clone();
+        node.load_held_transactions()
+            .map_err(|_| NodeError::ErrorCreatingNode)?;
+        if config.light_client {
+            node.light_client = Some(LightClient::new());
+        }
+
+        node.connection_settings = ConnectionSettings {
+            target_outbound: config.outbound_peers,
+            dns_seeds: config.dns.clone(),
+            ipv6_enabled: config.ipv6_enabled,
+            v2_transport: config.v2_transport,
+            pruned: config.prune_target_mb > 0,
+            compact_filters: serves_compact_filters(&config),
+        };
+
         let mut address_vector = node.peer_discovery(config.dns, config.ipv6_enabled);
         address_vector.extend(node.add_external_addresses(config.external_addresses));
+        address_vector.extend(node.get_address_book_candidates(&address_vector)?);
+        node.remove_banned_addresses(&mut address_vector)?;
 
         address_vector.reverse(); // Generally the first nodes are slow, so we reverse the vector to connect to the fastest nodes first
 
         for addr in address_vector {
-            match outgoing_handshake(node.version, addr, node.address, &node.logger) {
-                Ok(tcp_stream) => {
+            if node.initial_peers.len() >= node.connection_settings.target_outbound {
+                break;
+            }
+            let settings = &node.connection_settings;
+            match outgoing_handshake(
+                node.version,
+                addr,
+                node.address,
+                settings.v2_transport,
+                settings.pruned,
+                settings.compact_filters,
+                &node.logger,
+            ) {
+                Ok((tcp_stream, peer_version)) => {
+                    let now = Utc::now().timestamp() as u32;
+                    node.get_address_book()?.mark_success(addr, now);
+                    node.get_peer_table()?.add_peer(addr, peer_version, true, now);
                     node.initial_peers.push(tcp_stream);
                     let progress =
                         format!("Amount of peers conected = {}", node.initial_peers.len());
                     node.log_and_send_to_ui(&progress);
                 }
-                Err(error) => node.logger.log_error(&error),
+                Err(error) => {
+                    node.get_address_book()?.mark_failure(addr);
+                    node.logger.log_error(&error)
+                }
             }
         }
 
@@ -147,19 +268,7 @@ impl Node {
         dns_vector: Vec<(String, u16)>,
         ipv6_enabled: bool,
     ) -> Vec<SocketAddr> {
-        let mut socket_address_vector = Vec::new();
-
-        for dns in dns_vector {
-            if let Ok(address_iter) = dns.to_socket_addrs() {
-                for address in address_iter {
-                    if address.is_ipv4() || ipv6_enabled {
-                        socket_address_vector.push(address);
-                    }
-                }
-            }
-        }
-
-        socket_address_vector
+        dns_lookup(&dns_vector, ipv6_enabled)
     }
 
     /// Receives a vector of addresses as a ([u8; 4], u16) each and returns a Vector
@@ -177,16 +286,119 @@ impl Node {
         socket_address_vector
     }
 
-    /// Returns a MutexGuard to the blockchain HashMap.
-    pub fn get_blockchain(&self) -> Result<MutexGuard<HashMap<[u8; 32], Block>>, NodeError> {
+    /// Returns the best known addresses of the address book that are not already in the vector,
+    /// these are the peers that the node could connect to in a previous execution.
+    fn get_address_book_candidates(
+        &self,
+        address_vector: &[SocketAddr],
+    ) -> Result<Vec<SocketAddr>, NodeError> {
+        let excluded: HashSet<SocketAddr> = address_vector.iter().copied().collect();
+        Ok(self
+            .get_address_book()?
+            .select_candidates(ADDRESS_BOOK_CANDIDATES_AT_STARTUP, &excluded))
+    }
+
+    /// Removes the addresses whose ip is banned.
+    fn remove_banned_addresses(
+        &self,
+        address_vector: &mut Vec<SocketAddr>,
+    ) -> Result<(), NodeError> {
+        let now = Utc::now().timestamp() as u32;
+        let ban_list = self.get_ban_list()?;
+        address_vector.retain(|address| !ban_list.is_banned(&address.ip(), now));
+        Ok(())
+    }
+
+    /// Returns a MutexGuard to the ban list.
+    pub fn get_ban_list(&self) -> Result<MutexGuard<BanList>, NodeError> {
+        self.ban_list
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a MutexGuard to the table of connected peers.
+    pub fn get_peer_table(&self) -> Result<MutexGuard<PeerTable>, NodeError> {
+        self.peer_table
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns the information of every connected peer.
+    pub fn get_peer_info(&self) -> Result<Vec<PeerInfo>, NodeError> {
+        Ok(self.get_peer_table()?.get_peers())
+    }
+
+    /// Asks the peer communicator to close the connection with the peer.
+    /// Returns ErrorPeerNotConnected if the node is not connected to it.
+    pub fn disconnect_peer(&self, peer_address: SocketAddr) -> Result<(), NodeError> {
+        if !self.get_peer_table()?.request_disconnect(peer_address) {
+            return Err(NodeError::ErrorPeerNotConnected);
+        }
+        Ok(())
+    }
+
+    /// Bans the ip of the peer for BAN_DURATION seconds, disconnecting every peer that uses it.
+    pub fn ban_peer(&self, peer_address: SocketAddr) -> Result<(), NodeError> {
+        let ip = peer_address.ip();
+        {
+            let mut ban_list = self.get_ban_list()?;
+            ban_list.ban(ip, Utc::now().timestamp() as u32, BAN_DURATION);
+            ban_list.save()?;
+        }
+        let mut peer_table = self.get_peer_table()?;
+        for peer in peer_table.get_peers() {
+            if peer.address.ip() == ip {
+                peer_table.request_disconnect(peer.address);
+            }
+        }
+        self.logger.log(format!("Banned peer {} by user request", ip));
+        Ok(())
+    }
+
+    /// Returns a MutexGuard to the address book.
+    pub fn get_block_filter_index(&self) -> Result<MutexGuard<BlockFilterIndex>, NodeError> {
+        self.block_filter_index
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a MutexGuard to the transaction index.
+    pub fn get_tx_index(&self) -> Result<MutexGuard<TxIndex>, NodeError> {
+        self.tx_index
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a MutexGuard to the address index.
+    pub fn get_address_index(&self) -> Result<MutexGuard<AddressIndex>, NodeError> {
+        self.address_index
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    pub fn get_address_book(&self) -> Result<MutexGuard<AddressBook>, NodeError> {
+        self.address_book
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a MutexGuard to the block store.
+    pub fn get_blockchain(&self) -> Result<MutexGuard<BlockStore>, NodeError> {
         self.blockchain
             .lock()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
 
-    /// Returns a MutexGuard to the blockchain HashMap.
-    pub fn get_block_headers(&self) -> Result<MutexGuard<Vec<BlockHeader>>, NodeError> {
+    /// Returns a read guard to the block headers.
+    pub fn get_block_headers(&self) -> Result<RwLockReadGuard<Vec<BlockHeader>>, NodeError> {
         self.block_headers
+            .read()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a MutexGuard to the handler of the headers file.
+    pub fn get_data_handler(&self) -> Result<MutexGuard<NodeDataHandler>, NodeError> {
+        self.data_handler
             .lock()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
@@ -198,9 +410,29 @@ impl Node {
             .map_err(|_| NodeError::ErrorSharingReference)
     }
 
-    pub fn get_header_index(&self) -> Result<MutexGuard<HashMap<[u8; 32], usize>>, NodeError> {
+    /// Returns a read guard to the index of the position of each header.
+    pub fn get_header_index(
+        &self,
+    ) -> Result<RwLockReadGuard<HashMap<[u8; 32], usize>>, NodeError> {
         self.headers_index
-            .lock()
+            .read()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a write guard to the block headers. Only used to truncate them before the
+    /// threads that talk to peers start, as they are append-only afterwards.
+    pub fn write_block_headers(&self) -> Result<RwLockWriteGuard<Vec<BlockHeader>>, NodeError> {
+        self.block_headers
+            .write()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    /// Returns a write guard to the index of the position of each header.
+    pub fn write_header_index(
+        &self,
+    ) -> Result<RwLockWriteGuard<HashMap<[u8; 32], usize>>, NodeError> {
+        self.headers_index
+            .write()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
 
@@ -210,28 +442,29 @@ impl Node {
             self.version,
             self.address,
             &self.initial_peers,
+            self.connection_settings.clone(),
             self.get_safe_node_info(),
+            self.sender_to_ui.clone(),
             &self.logger,
         ));
     }
 
-    /// Actual receive_message wrapper. Encapsulates node's parameteres.
-    fn receive_message(
-        &mut self,
-        stream_index: usize,
-        downloading_headers: bool,
-    ) -> Result<String, NodeError> {
-        let safe_node_info = self.get_safe_node_info();
-        let stream = &mut self.initial_peers[stream_index];
-        recieve_and_handle(stream, &safe_node_info, &self.logger, downloading_headers)
-    }
-
     fn get_safe_node_info(&self) -> NodeSharedInformation {
         NodeSharedInformation::from(
             &self.blockchain,
             &self.block_headers,
             &self.headers_index,
             &self.pending_tx,
+            &self.address_book,
+            &self.ban_list,
+            &self.block_filter_index,
+            &self.tx_index,
+            &self.address_index,
+            &self.peer_table,
+            self.is_light_client(),
+            self.connection_settings.v2_transport,
+            self.connection_settings.pruned,
+            self.connection_settings.compact_filters,
         )
     }
 }
@@ -245,9 +478,56 @@ impl Drop for Node {
             }
         }
 
+        if let Some(checkpointer) = self.checkpointer.take() {
+            if let Err(error) = checkpointer.stop() {
+                self.logger.log_error(&error);
+            }
+        }
+
         //Saving data.
         self.logger.log("Saving received data".to_string());
 
+        match self.get_address_book() {
+            Ok(address_book) => {
+                if let Err(error) = address_book.save() {
+                    self.logger.log_error(&error);
+                }
+            }
+            Err(error) => self.logger.log_error(&error),
+        }
+        match self.get_ban_list() {
+            Ok(ban_list) => {
+                if let Err(error) = ban_list.save() {
+                    self.logger.log_error(&error);
+                }
+            }
+            Err(error) => self.logger.log_error(&error),
+        }
+        match self.get_block_filter_index() {
+            Ok(mut block_filter_index) => {
+                if let Err(error) = block_filter_index.save() {
+                    self.logger.log_error(&error);
+                }
+            }
+            Err(error) => self.logger.log_error(&error),
+        }
+        match self.get_tx_index() {
+            Ok(tx_index) => {
+                if let Err(error) = tx_index.save() {
+                    self.logger.log_error(&error);
+                }
+            }
+            Err(error) => self.logger.log_error(&error),
+        }
+        match self.get_address_index() {
+            Ok(address_index) => {
+                if let Err(error) = address_index.save() {
+                    self.logger.log_error(&error);
+                }
+            }
+            Err(error) => self.logger.log_error(&error),
+        }
+
         if self.store_blocks_in_disk().is_err() {
             return self.logger.log_error(&NodeError::ErrorSavingDataToDisk);
         };
@@ -256,6 +536,25 @@ impl Drop for Node {
     }
 }
 
+/// Returns the addresses of the peers the dns seeds know, leaving out the IPv6 ones
+/// unless they are enabled.
+/// Seeds that cannot be resolved are skipped.
+pub fn dns_lookup(dns_vector: &[(String, u16)], ipv6_enabled: bool) -> Vec<SocketAddr> {
+    let mut socket_address_vector = Vec::new();
+
+    for dns in dns_vector {
+        if let Ok(address_iter) = dns.to_socket_addrs() {
+            for address in address_iter {
+                if address.is_ipv4() || ipv6_enabled {
+                    socket_address_vector.push(address);
+                }
+            }
+        }
+    }
+
+    socket_address_vector
+}
+
 /// Reads from the stream MESAGE_HEADER_SIZE bytes and returns a HeaderMessage interpreting those bytes acording to bitcoin protocol.
 /// On error returns ErrorReceivingMessage
 pub fn receive_message_header<T: Read + Write>(stream: &mut T) -> Result<HeaderMessage, NodeError> {
@@ -275,8 +574,8 @@ pub fn receive_message_header<T: Read + Write>(stream: &mut T) -> Result<HeaderM
     }
 }
 
-fn receive_message(
-    stream: &mut TcpStream,
+fn receive_message<T: Read + Write>(
+    stream: &mut T,
     logger: &Logger,
 ) -> Result<(Message, String), NodeError> {
     let block_headers_msg_h = receive_message_header(stream)?;
@@ -286,6 +585,9 @@ fn receive_message(
         block_headers_msg_h.get_command_name()
     ));
 
+    if block_headers_msg_h.get_payload_size() as usize > MAX_MESSAGE_SIZE {
+        return Err(NodeError::ErrorOversizedMessage);
+    }
     let mut msg_bytes = vec![0; block_headers_msg_h.get_payload_size() as usize];
 
     stream.read_exact(&mut msg_bytes).map_err(|err| {
@@ -302,7 +604,7 @@ fn receive_message(
 }
 
 pub fn recieve_and_handle(
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     safe_node_info: &NodeSharedInformation,
     logger: &Logger,
     downloading_headers: bool,
@@ -312,16 +614,18 @@ pub fn recieve_and_handle(
     Ok(command_name)
 }
 
+/// Appends the headers to the chain and adds their positions to the index. Both locks are taken
+/// in the order of safe_node_structure, so readers never see a header that is not indexed yet.
 pub fn insert_new_headers(
     headers: Vec<BlockHeader>,
     safe_block_headers: &SafeVecHeader,
     safe_headers_index: &SafeHeaderIndex,
 ) -> Result<(), NodeError> {
-    let mut block_headers = safe_block_headers
-        .lock()
-        .map_err(|_| NodeError::ErrorSharingReference)?;
     let mut headers_index = safe_headers_index
-        .lock()
+        .write()
+        .map_err(|_| NodeError::ErrorSharingReference)?;
+    let mut block_headers = safe_block_headers
+        .write()
         .map_err(|_| NodeError::ErrorSharingReference)?;
 
     for header in headers {
@@ -344,12 +648,11 @@ mod tests {
     const STARTING_BLOCK_TIME: u32 = 1681084800;
     const LOG_FILE_PATH: &str = "tests_txt/test_log.txt";
     const HEADERS_FILE_PATH: &str = "tests_txt/headers.bin";
-    const BLOCKS_FILE_PATH: &str = "tests_txt/blocks.bin";
 
     #[test]
     fn peer_discovery_test_1_fails_when_receiving_invalid_dns_address() {
         let logger = Logger::from_path(LOG_FILE_PATH).unwrap();
-        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH, BLOCKS_FILE_PATH).unwrap();
+        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH).unwrap();
         let (sx, _rx) = glib::MainContext::channel::<UIResponse>(glib::PRIORITY_DEFAULT);
         let node = Node::_new(
             VERSION,
@@ -370,7 +673,7 @@ mod tests {
     #[test]
     fn peer_discovery_test_2_returns_ip_vector_when_receiving_valid_dns() {
         let logger = Logger::from_path(LOG_FILE_PATH).unwrap();
-        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH, BLOCKS_FILE_PATH).unwrap();
+        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH).unwrap();
         let (sx, _rx) = glib::MainContext::channel::<UIResponse>(glib::PRIORITY_DEFAULT);
         let node = Node::_new(
             VERSION,
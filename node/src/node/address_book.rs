@@ -0,0 +1,335 @@
+use crate::{messages::NetworkAddress, utils::btc_errors::NodeError};
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+const MAX_ADDRESSES: usize = 20000;
+const MAX_FAILURES_OVER_SUCCESSES: u32 = 10;
+const MAX_TIME_IN_THE_FUTURE: u32 = 10 * 60;
+const FIELD_DELIMETER: char = ',';
+const FIELD_AMOUNT: usize = 6;
+
+pub type SafeAddressBook = Arc<Mutex<AddressBook>>;
+
+/// What the node knows about a peer address: the services it announced, when it was
+/// last seen or connected to, and how many connection attempts succeeded or failed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AddressInfo {
+    pub services: u64,
+    pub last_seen: u32,
+    pub last_success: u32,
+    pub successes: u32,
+    pub failures: u32,
+}
+
+/// Known peer addresses, learned from DNS seeds, the configuration and addr messages.
+/// It is stored in disk so the node can connect to known peers in the next execution.
+#[derive(Debug, Default)]
+pub struct AddressBook {
+    entries: HashMap<SocketAddr, AddressInfo>,
+    file_path: Option<String>,
+}
+
+impl AddressInfo {
+    fn new(services: u64, last_seen: u32) -> AddressInfo {
+        AddressInfo {
+            services,
+            last_seen,
+            last_success: 0,
+            successes: 0,
+            failures: 0,
+        }
+    }
+
+    /// Addresses that failed many more times than they succeeded are not worth keeping.
+    fn is_terrible(&self) -> bool {
+        self.failures > self.successes + MAX_FAILURES_OVER_SUCCESSES
+    }
+
+    /// Higher is better: addresses that worked before come first, then the most recently seen.
+    fn score(&self) -> (i64, u32, u32) {
+        (
+            self.successes as i64 - self.failures as i64,
+            self.last_success,
+            self.last_seen,
+        )
+    }
+
+    fn to_line(self, address: &SocketAddr) -> String {
+        format!(
+            "{address}{d}{}{d}{}{d}{}{d}{}{d}{}",
+            self.services,
+            self.last_seen,
+            self.last_success,
+            self.successes,
+            self.failures,
+            d = FIELD_DELIMETER
+        )
+    }
+
+    fn from_line(line: &str) -> Option<(SocketAddr, AddressInfo)> {
+        let fields: Vec<&str> = line.trim().split(FIELD_DELIMETER).collect();
+        if fields.len() != FIELD_AMOUNT {
+            return None;
+        }
+        let address = fields[0].parse::<SocketAddr>().ok()?;
+        let info = AddressInfo {
+            services: fields[1].parse().ok()?,
+            last_seen: fields[2].parse().ok()?,
+            last_success: fields[3].parse().ok()?,
+            successes: fields[4].parse().ok()?,
+            failures: fields[5].parse().ok()?,
+        };
+        Some((address, info))
+    }
+}
+
+impl AddressBook {
+    /// Creates an empty address book that is not stored in disk.
+    pub fn new() -> AddressBook {
+        AddressBook::default()
+    }
+
+    /// Loads the address book stored in the file. If the file does not exist yet the book starts
+    /// empty, and lines that cannot be parsed are skipped.
+    pub fn from_path(file_path: &str) -> Result<AddressBook, NodeError> {
+        let mut address_book = AddressBook {
+            entries: HashMap::new(),
+            file_path: Some(file_path.to_string()),
+        };
+        let file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(address_book),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+            if let Some((address, info)) = AddressInfo::from_line(&line) {
+                address_book.entries.insert(address, info);
+            }
+        }
+        Ok(address_book)
+    }
+
+    /// Writes the whole address book in its file, replacing the previous one.
+    pub fn save(&self) -> Result<(), NodeError> {
+        let file_path = match &self.file_path {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let temporary_path = format!("{}.tmp", file_path);
+        let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let mut writer = BufWriter::new(file);
+        for (address, info) in &self.entries {
+            writeln!(writer, "{}", info.to_line(address))
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        writer
+            .flush()
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        fs::rename(temporary_path, file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, address: &SocketAddr) -> Option<&AddressInfo> {
+        self.entries.get(address)
+    }
+
+    /// Adds an announced address, or updates when it was last seen. Times in the future are
+    /// replaced by the current time. When the book is full the worst address is dropped.
+    pub fn add_address(&mut self, network_address: &NetworkAddress, now: u32) {
+        let address = network_address.socket_address();
+        if address.port() == 0 || address.ip().is_unspecified() {
+            return;
+        }
+        let mut last_seen = network_address.time;
+        if last_seen > now + MAX_TIME_IN_THE_FUTURE {
+            last_seen = now;
+        }
+
+        if let Some(info) = self.entries.get_mut(&address) {
+            info.last_seen = info.last_seen.max(last_seen);
+            info.services = network_address.services;
+            return;
+        }
+        if self.entries.len() >= MAX_ADDRESSES {
+            self.remove_worst_address();
+        }
+        self.entries.insert(
+            address,
+            AddressInfo::new(network_address.services, last_seen),
+        );
+    }
+
+    /// Adds every announced address.
+    pub fn add_addresses(&mut self, network_addresses: &[NetworkAddress], now: u32) {
+        for network_address in network_addresses {
+            self.add_address(network_address, now);
+        }
+    }
+
+    /// Registers a successful connection to the address, adding it if it was unknown.
+    pub fn mark_success(&mut self, address: SocketAddr, now: u32) {
+        let info = self
+            .entries
+            .entry(address)
+            .or_insert_with(|| AddressInfo::new(0, now));
+        info.last_seen = now;
+        info.last_success = now;
+        info.successes += 1;
+    }
+
+    /// Registers a failed connection attempt, forgetting the address if it keeps failing.
+    pub fn mark_failure(&mut self, address: SocketAddr) {
+        let terrible = match self.entries.get_mut(&address) {
+            Some(info) => {
+                info.failures += 1;
+                info.is_terrible()
+            }
+            None => return,
+        };
+        if terrible {
+            self.entries.remove(&address);
+        }
+    }
+
+    /// Returns up to amount addresses to connect to, the best ones first, skipping the excluded ones.
+    pub fn select_candidates(
+        &self,
+        amount: usize,
+        excluded: &HashSet<SocketAddr>,
+    ) -> Vec<SocketAddr> {
+        let mut candidates: Vec<(&SocketAddr, &AddressInfo)> = self
+            .entries
+            .iter()
+            .filter(|(address, _)| !excluded.contains(address))
+            .collect();
+        candidates.sort_by_key(|(_, info)| Reverse(info.score()));
+        candidates
+            .into_iter()
+            .take(amount)
+            .map(|(address, _)| *address)
+            .collect()
+    }
+
+    /// Returns up to amount of the most recently seen addresses, to be shared in an addr message.
+    pub fn get_addresses(&self, amount: usize) -> Vec<NetworkAddress> {
+        let mut addresses: Vec<(&SocketAddr, &AddressInfo)> = self.entries.iter().collect();
+        addresses.sort_by_key(|(_, info)| Reverse(info.last_seen));
+        addresses
+            .into_iter()
+            .take(amount)
+            .map(|(address, info)| NetworkAddress::new(info.last_seen, info.services, *address))
+            .collect()
+    }
+
+    fn remove_worst_address(&mut self) {
+        let worst = self
+            .entries
+            .iter()
+            .min_by(|(_, a), (_, b)| a.score().cmp(&b.score()))
+            .map(|(address, _)| *address);
+        if let Some(worst) = worst {
+            self.entries.remove(&worst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u32 = 1681084800;
+    const FILE_PATH: &str = "tests_txt/address_book_test.txt";
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn address(last_byte: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last_byte], 18333))
+    }
+
+    fn network_address(last_byte: u8, time: u32) -> NetworkAddress {
+        NetworkAddress::new(time, 1, address(last_byte))
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn address_book_test_1_announced_addresses_are_added_once() {
+        let mut address_book = AddressBook::new();
+
+        address_book.add_addresses(
+            &[
+                network_address(1, NOW - 100),
+                network_address(1, NOW),
+                network_address(2, NOW + 1_000_000),
+            ],
+            NOW,
+        );
+
+        assert_eq!(address_book.len(), 2);
+        assert_eq!(address_book.get(&address(1)).unwrap().last_seen, NOW);
+        assert_eq!(address_book.get(&address(2)).unwrap().last_seen, NOW);
+    }
+
+    #[test]
+    fn address_book_test_2_candidates_prefer_successful_addresses() {
+        let mut address_book = AddressBook::new();
+        address_book.add_addresses(
+            &[
+                network_address(1, NOW),
+                network_address(2, NOW - 10),
+                network_address(3, NOW - 20),
+            ],
+            NOW,
+        );
+        address_book.mark_success(address(3), NOW);
+        address_book.mark_failure(address(1));
+
+        let excluded = HashSet::from([address(2)]);
+        let candidates = address_book.select_candidates(5, &excluded);
+
+        assert_eq!(candidates, vec![address(3), address(1)]);
+    }
+
+    #[test]
+    fn address_book_test_3_addresses_that_keep_failing_are_forgotten() {
+        let mut address_book = AddressBook::new();
+        address_book.add_address(&network_address(1, NOW), NOW);
+
+        for _ in 0..=MAX_FAILURES_OVER_SUCCESSES {
+            address_book.mark_failure(address(1));
+        }
+
+        assert!(address_book.is_empty());
+    }
+
+    #[test]
+    fn address_book_test_4_book_is_persisted() {
+        let mut address_book = AddressBook::from_path(FILE_PATH).unwrap();
+        address_book.add_address(&network_address(1, NOW), NOW);
+        address_book.mark_success(address(2), NOW);
+        address_book.mark_failure(address(1));
+        address_book.save().unwrap();
+
+        let loaded_book = AddressBook::from_path(FILE_PATH).unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+
+        assert_eq!(loaded_book.len(), 2);
+        assert_eq!(loaded_book.get(&address(1)), address_book.get(&address(1)));
+        assert_eq!(loaded_book.get(&address(2)), address_book.get(&address(2)));
+    }
+}
@@ -0,0 +1,427 @@
+use crate::{
+    blocks::{blockchain::*, transaction::get_pk_script, Outpoint},
+    node::{block_store::BlockStore, tx_index::first_unindexed_position, Node},
+    utils::{btc_errors::NodeError, AddressInfo},
+};
+use bitcoin_hashes::{sha256, Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+const HASH_SIZE: usize = 32;
+const OUTPOINT_SIZE: usize = 36;
+const COUNT_SIZE: usize = 4;
+const VALUE_SIZE: usize = 8;
+
+pub type SafeAddressIndex = Arc<Mutex<AddressIndex>>;
+
+/// Returns the hash that identifies a pk_script in the index, which is its sha256.
+pub fn script_hash(pk_script: &[u8]) -> [u8; 32] {
+    sha256::Hash::hash(pk_script).to_byte_array()
+}
+
+/// Returns the script hash of the p2pkh pk_script of the pk hash.
+pub fn p2pkh_script_hash(pk_hash: [u8; 20]) -> [u8; 32] {
+    script_hash(&get_pk_script(pk_hash))
+}
+
+/// Unspent outputs of a script, with their values, and the transactions that fund or spend it, in the
+/// order of the chain.
+#[derive(Debug, Default)]
+struct ScriptHistory {
+    unspent: HashMap<Outpoint, i64>,
+    transactions: Vec<[u8; 32]>,
+}
+
+impl ScriptHistory {
+    /// Adds the transaction to the history, unless it is already the last one, which happens when a
+    /// transaction spends and funds the same script.
+    fn add_transaction(&mut self, txid: [u8; 32]) {
+        if self.transactions.last() != Some(&txid) {
+            self.transactions.push(txid);
+        }
+    }
+}
+
+/// Optional index of the outputs of the stored blocks by the hash of their pk_script, which keeps the
+/// unspent outputs and the history of every script, so the balance, utxos and transactions of any
+/// address are found without scanning the utxo set. Blocks are indexed in the order of the chain, like the
+/// transaction index, and the index is stored in disk. An index that is not loaded from a file is
+/// disabled, and updating it does nothing.
+#[derive(Debug, Default)]
+pub struct AddressIndex {
+    scripts: HashMap<[u8; 32], ScriptHistory>,
+    unspent_scripts: HashMap<Outpoint, [u8; 32]>,
+    indexed_blocks: HashSet<[u8; 32]>,
+    next_position: Option<usize>,
+    file_path: Option<String>,
+}
+
+/// Reads the next amount of bytes of the slice, moving its start past them.
+fn take_bytes<'a>(bytes: &mut &'a [u8], amount: usize) -> Result<&'a [u8], NodeError> {
+    if bytes.len() < amount {
+        return Err(NodeError::ErrorLoadingDataFromDisk);
+    }
+    let (taken, rest) = bytes.split_at(amount);
+    *bytes = rest;
+    Ok(taken)
+}
+
+fn take_hash(bytes: &mut &[u8]) -> Result<[u8; 32], NodeError> {
+    take_bytes(bytes, HASH_SIZE)?
+        .try_into()
+        .map_err(|_| NodeError::ErrorLoadingDataFromDisk)
+}
+
+fn take_count(bytes: &mut &[u8]) -> Result<usize, NodeError> {
+    let count = take_bytes(bytes, COUNT_SIZE)?
+        .try_into()
+        .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+    Ok(u32::from_le_bytes(count) as usize)
+}
+
+impl AddressIndex {
+    /// Creates a disabled index.
+    pub fn new() -> AddressIndex {
+        AddressIndex::default()
+    }
+
+    /// Loads the index stored in the file. If the file does not exist yet the index starts empty.
+    /// The file has the amount of indexed blocks and their hashes, followed by every script hash
+    /// with the amount of its transactions, their txids, the amount of its unspent outputs and
+    /// each outpoint with its value.
+    pub fn from_path(file_path: &str) -> Result<AddressIndex, NodeError> {
+        let mut index = AddressIndex {
+            file_path: Some(file_path.to_string()),
+            ..AddressIndex::default()
+        };
+        let mut file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(index),
+        };
+        let mut file_bytes = Vec::new();
+        file.read_to_end(&mut file_bytes)
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+
+        let mut bytes = file_bytes.as_slice();
+        for _ in 0..take_count(&mut bytes)? {
+            index.indexed_blocks.insert(take_hash(&mut bytes)?);
+        }
+        while !bytes.is_empty() {
+            let script_hash = take_hash(&mut bytes)?;
+            let mut history = ScriptHistory::default();
+            for _ in 0..take_count(&mut bytes)? {
+                history.transactions.push(take_hash(&mut bytes)?);
+            }
+            for _ in 0..take_count(&mut bytes)? {
+                let outpoint = Outpoint::from_bytes(take_bytes(&mut bytes, OUTPOINT_SIZE)?)
+                    .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+                let value = take_bytes(&mut bytes, VALUE_SIZE)?
+                    .try_into()
+                    .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+                history.unspent.insert(outpoint, i64::from_le_bytes(value));
+                index.unspent_scripts.insert(outpoint, script_hash);
+            }
+            index.scripts.insert(script_hash, history);
+        }
+        Ok(index)
+    }
+
+    /// Returns true if the index was loaded from a file, which means the node was configured to keep it.
+    pub fn is_enabled(&self) -> bool {
+        self.file_path.is_some()
+    }
+
+    /// Writes the whole index in its file, replacing the previous one.
+    pub fn save(&self) -> Result<(), NodeError> {
+        let file_path = match &self.file_path {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let mut bytes = Vec::new();
+        bytes.extend((self.indexed_blocks.len() as u32).to_le_bytes());
+        for block_hash in &self.indexed_blocks {
+            bytes.extend(block_hash);
+        }
+        for (script_hash, history) in &self.scripts {
+            bytes.extend(script_hash);
+            bytes.extend((history.transactions.len() as u32).to_le_bytes());
+            for txid in &history.transactions {
+                bytes.extend(txid);
+            }
+            bytes.extend((history.unspent.len() as u32).to_le_bytes());
+            for (outpoint, value) in &history.unspent {
+                bytes.extend(outpoint.to_bytes());
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+
+        let temporary_path = format!("{}.tmp", file_path);
+        let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let mut writer = BufWriter::new(file);
+        writer
+            .write_all(&bytes)
+            .and_then(|_| writer.flush())
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        fs::rename(temporary_path, file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    /// Indexes the stored blocks that were not indexed yet, from the first one in the chain. Until it is
+    /// called, updating the index does nothing, since blocks may be stored out of order while they are
+    /// being downloaded. Returns the amount of blocks indexed.
+    pub fn build(&mut self, blockchain: &mut BlockStore, block_headers: &[BlockHeader]) -> usize {
+        if !self.is_enabled() {
+            return 0;
+        }
+        self.next_position =
+            first_unindexed_position(blockchain, block_headers, &self.indexed_blocks);
+        self.update(blockchain, block_headers)
+    }
+
+    /// Indexes the stored blocks that follow the last indexed one in the chain, stopping at the first
+    /// block that is not stored yet. Returns the amount of blocks indexed.
+    pub fn update(&mut self, blockchain: &mut BlockStore, block_headers: &[BlockHeader]) -> usize {
+        let mut position = match self.next_position {
+            Some(position) => position,
+            None => return 0,
+        };
+        let first_position = position;
+        while let Some(block) = block_headers
+            .get(position)
+            .and_then(|header| blockchain.get(&header.hash()))
+        {
+            self.add_block(block);
+            position += 1;
+        }
+        self.next_position = Some(position);
+        position - first_position
+    }
+
+    /// Removes the outputs the block spends from their scripts and adds the ones it creates, adding
+    /// each transaction to the history of the scripts it involves.
+    fn add_block(&mut self, block: &Block) {
+        for tx in block.get_transactions() {
+            let txid = tx.hash();
+            for tx_in in &tx.tx_in {
+                let outpoint = tx_in.previous_output;
+                if let Some(history) = self
+                    .unspent_scripts
+                    .remove(&outpoint)
+                    .and_then(|script_hash| self.scripts.get_mut(&script_hash))
+                {
+                    history.unspent.remove(&outpoint);
+                    history.add_transaction(txid);
+                }
+            }
+            for (index, tx_out) in tx.tx_out.iter().enumerate() {
+                let outpoint = Outpoint::new(txid, index as u32);
+                let script_hash = script_hash(&tx_out.pk_script);
+                let history = self.scripts.entry(script_hash).or_default();
+                history.unspent.insert(outpoint, tx_out.value);
+                history.add_transaction(txid);
+                self.unspent_scripts.insert(outpoint, script_hash);
+            }
+        }
+        self.indexed_blocks.insert(block.header_hash());
+    }
+
+    /// Returns the position of the headers the index reaches, that is, the amount of headers whose
+    /// blocks were indexed or come before the first stored block. It is None until the index is built.
+    pub fn indexed_position(&self) -> Option<usize> {
+        self.next_position
+    }
+
+    /// Returns the unspent outputs of the script, with their values.
+    pub fn get_unspent(&self, script_hash: &[u8; 32]) -> HashMap<Outpoint, i64> {
+        self.scripts
+            .get(script_hash)
+            .map(|history| history.unspent.clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the txids of the transactions that fund or spend the script, in the order of the chain.
+    pub fn get_history(&self, script_hash: &[u8; 32]) -> Vec<[u8; 32]> {
+        self.scripts
+            .get(script_hash)
+            .map(|history| history.transactions.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Node {
+    /// Indexes the outputs of the stored blocks if the index is enabled, after which it is kept up to date
+    /// as new blocks arrive.
+    pub fn build_address_index(&mut self) -> Result<(), NodeError> {
+        if !self.get_address_index()?.is_enabled() {
+            return Ok(());
+        }
+        self.log_and_send_to_ui("Building address index");
+        let indexed_blocks = {
+            let mut blockchain = self.get_blockchain()?;
+            let block_headers = self.get_block_headers()?;
+            self.get_address_index()?
+                .build(&mut blockchain, &block_headers)
+        };
+        self.logger
+            .log(format!("Address index built for {indexed_blocks} blocks"));
+        Ok(())
+    }
+
+    /// Returns the unspent p2pkh outputs of the pk hash from the address index, if it is enabled and
+    /// has indexed at least every block in the utxo set. Outputs created after the last processed block
+    /// are left out, so they are added when the blocks are processed.
+    pub fn get_indexed_utxos(
+        &self,
+        pk_hash: [u8; 20],
+    ) -> Result<Option<HashMap<Outpoint, i64>>, NodeError> {
+        let address_index = self.get_address_index()?;
+        match address_index.indexed_position() {
+            Some(position) if position >= self.last_proccesed_block => {}
+            _ => return Ok(None),
+        }
+        let mut utxos = address_index.get_unspent(&p2pkh_script_hash(pk_hash));
+        utxos.retain(|outpoint, _| self.utxo_set.contains_key(outpoint));
+        Ok(Some(utxos))
+    }
+
+    /// Returns the balance, unspent outputs and history of the p2pkh address of the pk hash, according
+    /// to the indexed blocks. Returns ErrorAddressIndexDisabled if the node does not keep the index.
+    pub fn get_address_info(&self, pk_hash: [u8; 20]) -> Result<AddressInfo, NodeError> {
+        let address_index = self.get_address_index()?;
+        if !address_index.is_enabled() {
+            return Err(NodeError::ErrorAddressIndexDisabled);
+        }
+        let script_hash = p2pkh_script_hash(pk_hash);
+        let utxos: Vec<(Outpoint, i64)> = address_index
+            .get_unspent(&script_hash)
+            .into_iter()
+            .collect();
+        let balance = utxos.iter().map(|(_, value)| value).sum();
+
+        Ok(AddressInfo::new(
+            balance,
+            utxos,
+            address_index.get_history(&script_hash),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{transaction::TxIn, Transaction, TxOut};
+    use std::fs::remove_file;
+
+    const FIRST_PK_HASH: [u8; 20] = [1; 20];
+    const SECOND_PK_HASH: [u8; 20] = [2; 20];
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn block_with(prev_hash: [u8; 32], transactions: Vec<Transaction>) -> Block {
+        let header = BlockHeader::new(1, prev_hash, transactions[0].hash(), 0x1d00ffff);
+        Block::new(header, transactions)
+    }
+
+    /// Returns a chain where the first block pays 50 to the first pk hash, and the second one spends
+    /// it paying 30 to the second pk hash and 20 back to the first one.
+    fn chain() -> (Vec<Block>, Vec<BlockHeader>, [u8; 32], [u8; 32]) {
+        let funding_tx = Transaction::new(
+            1,
+            Vec::new(),
+            vec![TxOut::new(50, get_pk_script(FIRST_PK_HASH).to_vec())],
+            0,
+        );
+        let funding_txid = funding_tx.hash();
+        let spending_tx = Transaction::new(
+            1,
+            vec![TxIn::new(
+                Outpoint::new(funding_txid, 0),
+                Vec::new(),
+                u32::MAX,
+            )],
+            vec![
+                TxOut::new(30, get_pk_script(SECOND_PK_HASH).to_vec()),
+                TxOut::new(20, get_pk_script(FIRST_PK_HASH).to_vec()),
+            ],
+            0,
+        );
+        let spending_txid = spending_tx.hash();
+
+        let first_block = block_with([0; 32], vec![funding_tx]);
+        let second_block = block_with(first_block.header_hash(), vec![spending_tx]);
+        let block_headers = vec![first_block.get_header(), second_block.get_header()];
+        (
+            vec![first_block, second_block],
+            block_headers,
+            funding_txid,
+            spending_txid,
+        )
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn address_index_test_1_tracks_balance_and_history_of_scripts() -> Result<(), NodeError> {
+        let (blocks, block_headers, funding_txid, spending_txid) = chain();
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let mut index = AddressIndex::from_path("tests_txt/address_index_test_1.bin")?;
+        assert_eq!(index.build(&mut blockchain, &block_headers), 2);
+
+        let first_script_hash = p2pkh_script_hash(FIRST_PK_HASH);
+        let second_script_hash = p2pkh_script_hash(SECOND_PK_HASH);
+        assert_eq!(
+            index.get_unspent(&first_script_hash),
+            HashMap::from([(Outpoint::new(spending_txid, 1), 20)])
+        );
+        assert_eq!(
+            index.get_unspent(&second_script_hash),
+            HashMap::from([(Outpoint::new(spending_txid, 0), 30)])
+        );
+        assert_eq!(
+            index.get_history(&first_script_hash),
+            vec![funding_txid, spending_txid]
+        );
+        assert_eq!(index.get_history(&second_script_hash), vec![spending_txid]);
+        Ok(())
+    }
+
+    #[test]
+    fn address_index_test_2_saved_index_is_loaded_and_continues() -> Result<(), NodeError> {
+        let file_path = "tests_txt/address_index_test_2.bin";
+        let (mut blocks, block_headers, funding_txid, spending_txid) = chain();
+        let second_block = blocks.remove(1);
+        let mut blockchain = BlockStore::new();
+        blockchain.insert(blocks.remove(0), 0)?;
+        let mut index = AddressIndex::from_path(file_path)?;
+        assert_eq!(index.build(&mut blockchain, &block_headers), 1);
+        index.save()?;
+
+        blockchain.insert(second_block, 0)?;
+        let mut loaded_index = AddressIndex::from_path(file_path)?;
+        remove_file(file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+
+        let first_script_hash = p2pkh_script_hash(FIRST_PK_HASH);
+        assert_eq!(
+            loaded_index.get_unspent(&first_script_hash),
+            HashMap::from([(Outpoint::new(funding_txid, 0), 50)])
+        );
+        assert_eq!(loaded_index.build(&mut blockchain, &block_headers), 1);
+        assert_eq!(
+            loaded_index.get_unspent(&first_script_hash),
+            HashMap::from([(Outpoint::new(spending_txid, 1), 20)])
+        );
+        assert_eq!(
+            loaded_index.get_history(&first_script_hash),
+            vec![funding_txid, spending_txid]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+use crate::utils::btc_errors::NodeError;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+pub const BAN_DURATION: u32 = 24 * 60 * 60;
+const FIELD_DELIMETER: char = ',';
+
+pub type SafeBanList = Arc<Mutex<BanList>>;
+
+/// IP addresses of misbehaving peers, alongside the time until which they are banned.
+/// It is stored in disk so bans last between executions.
+#[derive(Debug, Default)]
+pub struct BanList {
+    banned: HashMap<IpAddr, u32>,
+    file_path: Option<String>,
+}
+
+impl BanList {
+    /// Creates an empty ban list that is not stored in disk.
+    pub fn new() -> BanList {
+        BanList::default()
+    }
+
+    /// Loads the ban list stored in the file. If the file does not exist yet the list starts
+    /// empty, and lines that cannot be parsed are skipped.
+    pub fn from_path(file_path: &str) -> Result<BanList, NodeError> {
+        let mut ban_list = BanList {
+            banned: HashMap::new(),
+            file_path: Some(file_path.to_string()),
+        };
+        let file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(ban_list),
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+            if let Some((ip, banned_until)) = line.trim().split_once(FIELD_DELIMETER) {
+                if let (Ok(ip), Ok(banned_until)) = (ip.parse(), banned_until.parse()) {
+                    ban_list.banned.insert(ip, banned_until);
+                }
+            }
+        }
+        Ok(ban_list)
+    }
+
+    /// Writes the whole ban list in its file, replacing the previous one.
+    pub fn save(&self) -> Result<(), NodeError> {
+        let file_path = match &self.file_path {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let temporary_path = format!("{}.tmp", file_path);
+        let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let mut writer = BufWriter::new(file);
+        for (ip, banned_until) in &self.banned {
+            writeln!(writer, "{ip}{FIELD_DELIMETER}{banned_until}")
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        writer
+            .flush()
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        fs::rename(temporary_path, file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    /// Bans the ip until now plus the duration, extending the ban if it was already banned for less time.
+    pub fn ban(&mut self, ip: IpAddr, now: u32, duration: u32) {
+        let banned_until = now.saturating_add(duration);
+        let entry = self.banned.entry(ip).or_insert(banned_until);
+        *entry = (*entry).max(banned_until);
+    }
+
+    /// Removes the ban of the ip, returning true if it was banned.
+    pub fn unban(&mut self, ip: &IpAddr) -> bool {
+        self.banned.remove(ip).is_some()
+    }
+
+    /// Returns true if the ip is banned at the given time.
+    pub fn is_banned(&self, ip: &IpAddr, now: u32) -> bool {
+        match self.banned.get(ip) {
+            Some(banned_until) => *banned_until > now,
+            None => false,
+        }
+    }
+
+    /// Forgets the bans that already expired.
+    pub fn remove_expired(&mut self, now: u32) {
+        self.banned.retain(|_, banned_until| *banned_until > now);
+    }
+
+    /// Returns the banned ips with the time until which they are banned.
+    pub fn get_banned(&self) -> Vec<(IpAddr, u32)> {
+        self.banned
+            .iter()
+            .map(|(ip, banned_until)| (*ip, *banned_until))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u32 = 1681084800;
+    const FILE_PATH: &str = "tests_txt/ban_list_test.txt";
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn ip(last_byte: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last_byte])
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn ban_list_test_1_bans_expire() {
+        let mut ban_list = BanList::new();
+
+        ban_list.ban(ip(1), NOW, BAN_DURATION);
+
+        assert!(ban_list.is_banned(&ip(1), NOW));
+        assert!(ban_list.is_banned(&ip(1), NOW + BAN_DURATION - 1));
+        assert!(!ban_list.is_banned(&ip(1), NOW + BAN_DURATION));
+        assert!(!ban_list.is_banned(&ip(2), NOW));
+
+        ban_list.remove_expired(NOW + BAN_DURATION);
+        assert!(ban_list.get_banned().is_empty());
+    }
+
+    #[test]
+    fn ban_list_test_2_longer_ban_is_kept() {
+        let mut ban_list = BanList::new();
+
+        ban_list.ban(ip(1), NOW, BAN_DURATION);
+        ban_list.ban(ip(1), NOW, 10);
+
+        assert_eq!(ban_list.get_banned(), vec![(ip(1), NOW + BAN_DURATION)]);
+        assert!(ban_list.unban(&ip(1)));
+        assert!(!ban_list.is_banned(&ip(1), NOW));
+    }
+
+    #[test]
+    fn ban_list_test_3_ban_list_is_persisted() {
+        let mut ban_list = BanList::from_path(FILE_PATH).unwrap();
+        ban_list.ban(ip(1), NOW, BAN_DURATION);
+        ban_list.ban("2001:db8::1".parse().unwrap(), NOW, 10);
+        ban_list.save().unwrap();
+
+        let loaded_list = BanList::from_path(FILE_PATH).unwrap();
+        fs::remove_file(FILE_PATH).unwrap();
+
+        let mut banned = loaded_list.get_banned();
+        banned.sort();
+        let mut expected = ban_list.get_banned();
+        expected.sort();
+        assert_eq!(banned, expected);
+    }
+}
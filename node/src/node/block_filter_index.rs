@@ -0,0 +1,490 @@
+use crate::{
+    blocks::{
+        block_filter::{BlockFilter, GENESIS_BLOCK_FILTER},
+        blockchain::*,
+        Outpoint,
+    },
+    node::{
+        block_store::BlockStore,
+        initial_block_download::{GENESIS_BLOCK_TIME, HASHEDGENESISBLOCK},
+        tx_index::TxIndex,
+        Node,
+    },
+    utils::{btc_errors::NodeError, config::Config},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    sync::{Arc, Mutex},
+};
+
+const HASH_SIZE: usize = 32;
+const ENTRY_HEADER_SIZE: usize = 2 * HASH_SIZE + 4;
+
+pub type SafeBlockFilterIndex = Arc<Mutex<BlockFilterIndex>>;
+
+/// Where the filter of a block is stored, alongside its filter header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FilterLocation {
+    filter_header: [u8; 32],
+    offset: u64,
+    length: u32,
+}
+
+/// Basic filters (BIP158) of the blocks of the chain, alongside their filter headers. Blocks are filtered
+/// in the order of the chain from the first block after the genesis one, since a filter has the scripts of
+/// the outputs its block spends, which are found through the transaction index, and a filter header
+/// commits to the previous one. Each filter is appended to a file after the hash of its block, its filter
+/// header and its length, so only the filter headers and where the filters are stored are kept in memory.
+/// An index that is not loaded from a file is disabled, and updating it does nothing.
+#[derive(Debug, Default)]
+pub struct BlockFilterIndex {
+    locations: HashMap<[u8; 32], FilterLocation>,
+    writer: Option<BufWriter<File>>,
+    file_size: u64,
+    next_position: Option<usize>,
+    file_path: Option<String>,
+}
+
+/// Returns true if the node is configured to keep every block of the chain and the transaction index,
+/// which the filters need so they have the scripts of every output the blocks spend.
+pub fn serves_compact_filters(config: &Config) -> bool {
+    config.tx_index
+        && config.prune_target_mb == 0
+        && !config.light_client
+        && config.begin_time <= GENESIS_BLOCK_TIME
+}
+
+impl BlockFilterIndex {
+    /// Creates a disabled index.
+    pub fn new() -> BlockFilterIndex {
+        BlockFilterIndex::default()
+    }
+
+    /// Loads the filter headers stored in the file, dropping an incomplete entry at its end, and opens
+    /// it to append the next filters. If the file does not exist yet the index starts empty.
+    pub fn from_path(file_path: &str) -> Result<BlockFilterIndex, NodeError> {
+        let mut index = BlockFilterIndex {
+            file_path: Some(file_path.to_string()),
+            ..BlockFilterIndex::default()
+        };
+        if let Ok(file) = File::open(file_path) {
+            let file_length = file
+                .metadata()
+                .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?
+                .len();
+            let mut reader = BufReader::new(file);
+            let mut entry = [0; ENTRY_HEADER_SIZE];
+            while reader.read_exact(&mut entry).is_ok() {
+                let (block_hash, rest) = entry.split_at(HASH_SIZE);
+                let (filter_header, length) = rest.split_at(HASH_SIZE);
+                let (Ok(block_hash), Ok(filter_header), Ok(length)) = (
+                    block_hash.try_into(),
+                    filter_header.try_into(),
+                    length.try_into(),
+                ) else {
+                    break;
+                };
+                let length = u32::from_le_bytes(length);
+                let offset = index.file_size + ENTRY_HEADER_SIZE as u64;
+                if offset + length as u64 > file_length
+                    || reader.seek_relative(length as i64).is_err()
+                {
+                    break;
+                }
+                let location = FilterLocation {
+                    filter_header,
+                    offset,
+                    length,
+                };
+                index.locations.insert(block_hash, location);
+                index.file_size = offset + length as u64;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(file_path)
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+        file.set_len(index.file_size)
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+        index.writer = Some(BufWriter::new(file));
+        Ok(index)
+    }
+
+    /// Returns true if the index was loaded from a file, which means the node serves compact filters.
+    pub fn is_enabled(&self) -> bool {
+        self.file_path.is_some()
+    }
+
+    /// Makes sure every filter appended to the file is written in disk.
+    pub fn save(&mut self) -> Result<(), NodeError> {
+        if let Some(writer) = &mut self.writer {
+            writer
+                .flush()
+                .and_then(|_| writer.get_ref().sync_all())
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        Ok(())
+    }
+
+    /// Filters the stored blocks that were not filtered yet, from the first block after the genesis one.
+    /// Until it is called, updating the index does nothing, since blocks may be stored out of order while
+    /// they are being downloaded. Returns the amount of blocks filtered.
+    pub fn build(
+        &mut self,
+        blockchain: &mut BlockStore,
+        block_headers: &[BlockHeader],
+        tx_index: &TxIndex,
+    ) -> usize {
+        if !self.is_enabled() {
+            return 0;
+        }
+        let filtered_blocks = block_headers
+            .iter()
+            .take_while(|header| self.locations.contains_key(&header.hash()))
+            .count();
+        self.next_position = Some(filtered_blocks);
+        self.update(blockchain, block_headers, tx_index)
+    }
+
+    /// Filters the stored blocks that follow the last filtered one in the chain, stopping at the first
+    /// block that is not stored yet or spends an output that is not in the transaction index.
+    /// Returns the amount of blocks filtered.
+    pub fn update(
+        &mut self,
+        blockchain: &mut BlockStore,
+        block_headers: &[BlockHeader],
+        tx_index: &TxIndex,
+    ) -> usize {
+        let mut position = match self.next_position {
+            Some(position) => position,
+            None => return 0,
+        };
+        let first_position = position;
+        while let Some(header) = block_headers.get(position) {
+            if self.add_block(blockchain, header, tx_index).is_err() {
+                break;
+            }
+            position += 1;
+        }
+        self.next_position = Some(position);
+        position - first_position
+    }
+
+    /// Creates the filter of the block and its filter header, which follows the one of the previous
+    /// block, and appends them to the file.
+    fn add_block(
+        &mut self,
+        blockchain: &mut BlockStore,
+        header: &BlockHeader,
+        tx_index: &TxIndex,
+    ) -> Result<(), NodeError> {
+        let block_hash = header.hash();
+        let previous_filter_header = self
+            .previous_filter_header(header)
+            .ok_or(NodeError::ErrorFindingBlock)?;
+        let (outputs_in_block, earlier_outputs) = blockchain
+            .get(&block_hash)
+            .map(spent_outputs)
+            .ok_or(NodeError::ErrorFindingBlock)?;
+
+        let mut spent_scripts = Vec::new();
+        for outpoint in &earlier_outputs {
+            let script = find_script(blockchain, tx_index, outpoint)
+                .ok_or(NodeError::ErrorFindingTransaction)?;
+            spent_scripts.push(script);
+        }
+        let block = blockchain
+            .get(&block_hash)
+            .ok_or(NodeError::ErrorFindingBlock)?;
+        for outpoint in &outputs_in_block {
+            let script =
+                script_in_block(block, outpoint).ok_or(NodeError::ErrorFindingTransaction)?;
+            spent_scripts.push(script);
+        }
+
+        let filter = BlockFilter::new(block, &spent_scripts);
+        let filter_header = filter.header(&previous_filter_header);
+        self.append(block_hash, filter_header, &filter.to_bytes())
+    }
+
+    fn append(
+        &mut self,
+        block_hash: [u8; 32],
+        filter_header: [u8; 32],
+        filter: &[u8],
+    ) -> Result<(), NodeError> {
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or(NodeError::ErrorSavingDataToDisk)?;
+        let length = filter.len() as u32;
+        writer
+            .write_all(&block_hash)
+            .and_then(|_| writer.write_all(&filter_header))
+            .and_then(|_| writer.write_all(&length.to_le_bytes()))
+            .and_then(|_| writer.write_all(filter))
+            .and_then(|_| writer.flush())
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let location = FilterLocation {
+            filter_header,
+            offset: self.file_size + ENTRY_HEADER_SIZE as u64,
+            length,
+        };
+        self.locations.insert(block_hash, location);
+        self.file_size = location.offset + length as u64;
+        Ok(())
+    }
+
+    /// Returns the filter of the block, reading it from disk, if it was filtered.
+    pub fn get_filter(&self, block_hash: &[u8; 32]) -> Option<BlockFilter> {
+        if !self.is_enabled() {
+            return None;
+        }
+        if *block_hash == HASHEDGENESISBLOCK {
+            return BlockFilter::from_bytes(&GENESIS_BLOCK_FILTER).ok();
+        }
+        let location = self.locations.get(block_hash)?;
+        let mut file = File::open(self.file_path.as_ref()?).ok()?;
+        file.seek(SeekFrom::Start(location.offset)).ok()?;
+        let mut bytes = vec![0; location.length as usize];
+        file.read_exact(&mut bytes).ok()?;
+        BlockFilter::from_bytes(&bytes).ok()
+    }
+
+    /// Returns the filter header of the block, if it was filtered.
+    pub fn get_filter_header(&self, block_hash: &[u8; 32]) -> Option<[u8; 32]> {
+        if !self.is_enabled() {
+            return None;
+        }
+        if *block_hash == HASHEDGENESISBLOCK {
+            let genesis_filter = BlockFilter::from_bytes(&GENESIS_BLOCK_FILTER).ok()?;
+            return Some(genesis_filter.header(&[0; HASH_SIZE]));
+        }
+        self.locations
+            .get(block_hash)
+            .map(|location| location.filter_header)
+    }
+
+    /// Returns the filter header of the block before the one of the header, if it was filtered.
+    pub fn previous_filter_header(&self, header: &BlockHeader) -> Option<[u8; 32]> {
+        self.get_filter_header(&header.prev_hash)
+    }
+}
+
+/// Returns the outputs the inputs of the block spend, leaving out the one of the coinbase transaction.
+/// The outputs created by the block itself are returned apart from the ones created by earlier blocks.
+fn spent_outputs(block: &Block) -> (Vec<Outpoint>, Vec<Outpoint>) {
+    let transactions = block.get_transactions();
+    let txids: HashSet<[u8; 32]> = transactions.iter().map(|tx| tx.hash()).collect();
+    transactions
+        .iter()
+        .skip(1)
+        .flat_map(|tx| tx.tx_in.iter().map(|tx_in| tx_in.previous_output))
+        .partition(|outpoint| txids.contains(&outpoint.hash))
+}
+
+/// Returns the script of an output created by an earlier block, finding its block in the transaction index.
+fn find_script(
+    blockchain: &mut BlockStore,
+    tx_index: &TxIndex,
+    outpoint: &Outpoint,
+) -> Option<Vec<u8>> {
+    let location = tx_index.get(&outpoint.hash)?;
+    let tx = blockchain
+        .get(&location.block_hash)?
+        .get_transactions()
+        .get(location.position as usize)?;
+    let tx_out = tx.tx_out.get(outpoint.index as usize)?;
+    Some(tx_out.pk_script.clone())
+}
+
+/// Returns the script of an output created by the block itself, which the transaction index may not have yet.
+fn script_in_block(block: &Block, outpoint: &Outpoint) -> Option<Vec<u8>> {
+    let tx = block
+        .get_transactions()
+        .iter()
+        .find(|tx| tx.hash() == outpoint.hash)?;
+    let tx_out = tx.tx_out.get(outpoint.index as usize)?;
+    Some(tx_out.pk_script.clone())
+}
+
+impl Node {
+    /// Builds the filters of the stored blocks if the node serves compact filters, after which they are
+    /// kept up to date as new blocks arrive.
+    pub fn build_block_filters(&mut self) -> Result<(), NodeError> {
+        if !self.get_block_filter_index()?.is_enabled() {
+            return Ok(());
+        }
+        self.log_and_send_to_ui("Building block filters");
+        let filtered_blocks = {
+            let mut blockchain = self.get_blockchain()?;
+            let block_headers = self.get_block_headers()?;
+            let mut block_filter_index = self.get_block_filter_index()?;
+            let tx_index = self.get_tx_index()?;
+            block_filter_index.build(&mut blockchain, &block_headers, &tx_index)
+        };
+        self.logger
+            .log(format!("Block filters built for {filtered_blocks} blocks"));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{Transaction, TxIn, TxOut},
+        node::test_chain,
+    };
+    use std::fs;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn chain(transactions: Vec<Transaction>) -> (Vec<Block>, Vec<BlockHeader>) {
+        let transactions = transactions
+            .into_iter()
+            .enumerate()
+            .map(|(i, tx)| vec![Transaction::new(1, Vec::new(), Vec::new(), i as u32), tx])
+            .collect();
+        test_chain::chain(HASHEDGENESISBLOCK, 0x1d00ffff, transactions)
+    }
+
+    fn transaction(spent_output: Option<Outpoint>, pk_script: Vec<u8>) -> Transaction {
+        let tx_in = spent_output
+            .map(|outpoint| vec![TxIn::new(outpoint, Vec::new(), u32::MAX)])
+            .unwrap_or_default();
+        Transaction::new(1, tx_in, vec![TxOut::new(1000, pk_script)], 0)
+    }
+
+    fn indexes(name: &str) -> Result<(BlockFilterIndex, TxIndex), NodeError> {
+        let filters_path = format!("tests_txt/block_filter_index_{name}_filters.bin");
+        let tx_index_path = format!("tests_txt/block_filter_index_{name}_tx_index.bin");
+        _ = fs::remove_file(&filters_path);
+        _ = fs::remove_file(&tx_index_path);
+        Ok((
+            BlockFilterIndex::from_path(&filters_path)?,
+            TxIndex::from_path(&tx_index_path)?,
+        ))
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn block_filter_index_test_1_filters_in_chain_order_once_built() -> Result<(), NodeError> {
+        let transactions = (0..4).map(|i| transaction(None, vec![0x51, i])).collect();
+        let (mut blocks, block_headers) = chain(transactions);
+        let missing = blocks.remove(2);
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let (mut index, mut tx_index) = indexes("test_1")?;
+        tx_index.build(&mut blockchain, &block_headers);
+
+        assert_eq!(index.update(&mut blockchain, &block_headers, &tx_index), 0);
+        assert_eq!(index.build(&mut blockchain, &block_headers, &tx_index), 2);
+
+        blockchain.insert(missing, 0)?;
+        tx_index.update(&mut blockchain, &block_headers);
+        assert_eq!(index.update(&mut blockchain, &block_headers, &tx_index), 2);
+
+        let first_header = index.get_filter_header(&block_headers[0].hash()).unwrap();
+        let second_filter = index.get_filter(&block_headers[1].hash()).unwrap();
+        assert_eq!(
+            index.get_filter_header(&block_headers[1].hash()),
+            Some(second_filter.header(&first_header))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_filter_index_test_2_first_filter_header_follows_the_genesis_one(
+    ) -> Result<(), NodeError> {
+        let (blocks, block_headers) = chain(vec![transaction(None, vec![0x51])]);
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let (mut index, tx_index) = indexes("test_2")?;
+        index.build(&mut blockchain, &block_headers, &tx_index);
+
+        let genesis_filter = BlockFilter::from_bytes(&GENESIS_BLOCK_FILTER).unwrap();
+        let genesis_header = genesis_filter.header(&[0; 32]);
+        let first_filter = index.get_filter(&block_headers[0].hash()).unwrap();
+        assert_eq!(
+            index.get_filter_header(&HASHEDGENESISBLOCK),
+            Some(genesis_header)
+        );
+        assert_eq!(
+            index.get_filter_header(&block_headers[0].hash()),
+            Some(first_filter.header(&genesis_header))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_filter_index_test_3_filters_have_the_scripts_spent_from_earlier_blocks(
+    ) -> Result<(), NodeError> {
+        let spent_script = vec![0x76, 0xa9, 0x14];
+        let first_tx = transaction(None, spent_script.clone());
+        let spent_output = Outpoint::new(first_tx.hash(), 0);
+        let (blocks, block_headers) =
+            chain(vec![first_tx, transaction(Some(spent_output), vec![0x51])]);
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let (mut index, mut tx_index) = indexes("test_3")?;
+        tx_index.build(&mut blockchain, &block_headers);
+
+        assert_eq!(index.build(&mut blockchain, &block_headers, &tx_index), 2);
+
+        let block_hash = block_headers[1].hash();
+        let filter = index.get_filter(&block_hash).unwrap();
+        assert!(filter.matches_any(&block_hash, &[spent_script]));
+        Ok(())
+    }
+
+    #[test]
+    fn block_filter_index_test_4_filters_are_found_after_reopening_the_index(
+    ) -> Result<(), NodeError> {
+        let (blocks, block_headers) = chain(vec![transaction(None, vec![0x51])]);
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let (mut index, tx_index) = indexes("test_4")?;
+        index.build(&mut blockchain, &block_headers, &tx_index);
+        index.save()?;
+        let block_hash = block_headers[0].hash();
+        let filter = index.get_filter(&block_hash);
+
+        let index = BlockFilterIndex::from_path("tests_txt/block_filter_index_test_4_filters.bin")?;
+        assert!(filter.is_some());
+        assert_eq!(index.get_filter(&block_hash), filter);
+        Ok(())
+    }
+
+    #[test]
+    fn block_filter_index_test_5_disabled_index_serves_nothing() -> Result<(), NodeError> {
+        let (blocks, block_headers) = chain(vec![transaction(None, vec![0x51])]);
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let mut index = BlockFilterIndex::new();
+
+        assert_eq!(
+            index.build(&mut blockchain, &block_headers, &TxIndex::new()),
+            0
+        );
+        assert!(index.get_filter_header(&HASHEDGENESISBLOCK).is_none());
+        assert!(index.get_filter(&block_headers[0].hash()).is_none());
+        Ok(())
+    }
+}
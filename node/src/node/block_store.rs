@@ -0,0 +1,847 @@
+use crate::{
+    blocks::{blockchain::*, proof::validate_block_proof_of_inclusion},
+    utils::btc_errors::NodeError,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+const HASH_SIZE: usize = 32;
+const INDEX_ENTRY_SIZE: usize = HASH_SIZE + 4 + 8 + 4 + 4;
+const BLOCK_CACHE_CAPACITY: usize = 256;
+const MAX_BLOCK_FILE_SIZE: u64 = 128 * 1024 * 1024;
+const SCAN_CHUNK_SIZE: usize = 1024 * 1024;
+const PRUNED_LENGTH: u32 = 0;
+/// Amount of the last blocks that are never pruned, which is what peers expect from a node that announces
+/// NODE_NETWORK_LIMITED (BIP159).
+pub const MIN_BLOCKS_TO_KEEP: usize = 288;
+
+/// Position of a stored block: the number of the block file, where the block starts in it and its size,
+/// alongside its height, which is 0 if its header was not known when it was stored. The blocks of a pruned
+/// file keep their location in the index with a length of 0, which marks their height as pruned.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlockLocation {
+    pub file: u32,
+    pub offset: u64,
+    pub length: u32,
+    pub height: u32,
+}
+
+impl BlockLocation {
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend(self.file.to_le_bytes());
+        bytes.extend(self.offset.to_le_bytes());
+        bytes.extend(self.length.to_le_bytes());
+        bytes.extend(self.height.to_le_bytes());
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<BlockLocation> {
+        Some(BlockLocation {
+            file: u32::from_le_bytes(bytes.get(0..4)?.try_into().ok()?),
+            offset: u64::from_le_bytes(bytes.get(4..12)?.try_into().ok()?),
+            length: u32::from_le_bytes(bytes.get(12..16)?.try_into().ok()?),
+            height: u32::from_le_bytes(bytes.get(16..20)?.try_into().ok()?),
+        })
+    }
+}
+
+/// Block files the store appends to, alongside the file of its index.
+#[derive(Debug)]
+struct BlockFiles {
+    blocks_path: String,
+    index_path: String,
+    writer: BufWriter<File>,
+    current_file: u32,
+    current_size: u64,
+    max_file_size: u64,
+}
+
+/// Blocks of the node. Stored blocks are appended to the block files as they arrive, and an index maps
+/// their hashes to where they are, so they are read from disk only when they are needed. The last read or
+/// stored blocks are kept in a bounded cache. The index is kept in memory and written in its own file.
+/// If a prune target is set, the oldest block files can be deleted once the block files exceed it.
+#[derive(Debug, Default)]
+pub struct BlockStore {
+    locations: HashMap<[u8; 32], BlockLocation>,
+    hashes_by_height: HashMap<u32, [u8; 32]>,
+    cache: HashMap<[u8; 32], Block>,
+    cache_order: VecDeque<[u8; 32]>,
+    cache_capacity: Option<usize>,
+    files: Option<BlockFiles>,
+    pruned: HashMap<[u8; 32], BlockLocation>,
+    prune_target: Option<u64>,
+}
+
+/// Path of the block file with the given number. The first one is the configured path, so blocks stored
+/// in a single file by previous versions are found.
+fn block_file_path(blocks_path: &str, file: u32) -> String {
+    match file {
+        0 => blocks_path.to_string(),
+        _ => format!("{}.{}", blocks_path, file),
+    }
+}
+
+/// Returns the numbers of the block files that exist, in order.
+fn existing_block_files(blocks_path: &str) -> Vec<u32> {
+    let path = Path::new(blocks_path);
+    let file_name = match path.file_name().and_then(|file_name| file_name.to_str()) {
+        Some(file_name) => file_name,
+        None => return Vec::new(),
+    };
+    let directory = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut files: Vec<u32> = entries
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|entry_name| match entry_name.strip_prefix(file_name)? {
+            "" => Some(0),
+            suffix => suffix.strip_prefix('.')?.parse().ok(),
+        })
+        .collect();
+    files.sort_unstable();
+    files
+}
+
+/// Opens the block file to append blocks to it.
+fn open_block_file(blocks_path: &str, file: u32) -> Result<File, NodeError> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(block_file_path(blocks_path, file))
+        .map_err(|_| NodeError::ErrorLoadingDataFromDisk)
+}
+
+/// Reads the blocks of the file in order from the given offset, calling visit with each of them and where
+/// it starts. Returns where the last block that could be read ends, which is before the end of the file if
+/// it ends with an incomplete block. If the file does not exist it returns the given offset.
+fn scan_block_file(
+    file_path: &str,
+    mut offset: u64,
+    mut visit: impl FnMut(&Block, u64, u32),
+) -> Result<u64, NodeError> {
+    let mut file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(_) => return Ok(offset),
+    };
+    file.seek(SeekFrom::Start(offset))
+        .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+    let mut buffer = Vec::new();
+    let mut start = 0;
+    let mut end_of_file = false;
+    loop {
+        match Block::from_bytes(&buffer[start..]) {
+            Ok(block) => {
+                let length = block.amount_of_bytes();
+                visit(&block, offset, length as u32);
+                start += length;
+                offset += length as u64;
+            }
+            Err(_) if end_of_file => return Ok(offset),
+            Err(_) => {
+                buffer.drain(..start);
+                start = 0;
+                let mut chunk = vec![0; SCAN_CHUNK_SIZE];
+                let read = file
+                    .read(&mut chunk)
+                    .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+                buffer.extend_from_slice(&chunk[..read]);
+                end_of_file = read == 0;
+            }
+        }
+    }
+}
+
+impl BlockStore {
+    /// Creates an empty store that is not stored in disk, which keeps every block in memory.
+    pub fn new() -> BlockStore {
+        BlockStore::default()
+    }
+
+    /// Loads the index stored in its file and opens the last block file to append the new blocks.
+    /// If the index does not exist yet the store starts empty, and its blocks can be found by rebuilding it.
+    pub fn from_path(blocks_path: &str, index_path: &str) -> Result<BlockStore, NodeError> {
+        let mut store = BlockStore {
+            cache_capacity: Some(BLOCK_CACHE_CAPACITY),
+            ..BlockStore::default()
+        };
+        if let Ok(mut file) = File::open(index_path) {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)
+                .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+            for entry in bytes.chunks_exact(INDEX_ENTRY_SIZE) {
+                let (block_hash, location) = entry.split_at(HASH_SIZE);
+                match (block_hash.try_into(), BlockLocation::from_bytes(location)) {
+                    (Ok(block_hash), Some(location)) if location.length == PRUNED_LENGTH => {
+                        store.pruned.insert(block_hash, location);
+                    }
+                    (Ok(block_hash), Some(location)) => store.add_location(block_hash, location),
+                    _ => {}
+                }
+            }
+        }
+
+        let current_file = existing_block_files(blocks_path)
+            .last()
+            .copied()
+            .unwrap_or(0);
+        let file = open_block_file(blocks_path, current_file)?;
+        let current_size = file
+            .metadata()
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?
+            .len();
+        store.files = Some(BlockFiles {
+            blocks_path: blocks_path.to_string(),
+            index_path: index_path.to_string(),
+            writer: BufWriter::new(file),
+            current_file,
+            current_size,
+            max_file_size: MAX_BLOCK_FILE_SIZE,
+        });
+        Ok(store)
+    }
+
+    /// Sets the size in bytes the block files can take before the oldest ones are pruned,
+    /// or None to keep every block.
+    pub fn set_prune_target(&mut self, prune_target: Option<u64>) {
+        self.prune_target = prune_target;
+    }
+
+    /// Returns true if a prune target is set.
+    pub fn is_pruning(&self) -> bool {
+        self.prune_target.is_some()
+    }
+
+    /// Returns true if the block was stored and then pruned.
+    pub fn is_pruned(&self, block_hash: &[u8; 32]) -> bool {
+        self.pruned.contains_key(block_hash)
+    }
+
+    /// Returns the height of the last pruned block, or 0 if no block was pruned.
+    pub fn pruned_height(&self) -> u32 {
+        self.pruned
+            .values()
+            .map(|location| location.height)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns true if the block is stored.
+    pub fn contains_key(&self, block_hash: &[u8; 32]) -> bool {
+        self.locations.contains_key(block_hash) || self.cache.contains_key(block_hash)
+    }
+
+    /// Returns the amount of headers that can be kept without leaving a missing block after a stored or
+    /// pruned one, so the download continues from the first missing block. The headers before the given
+    /// position are already kept, so a missing block right after them is not skipped either.
+    pub fn headers_of_stored_blocks(&self, block_headers: &[BlockHeader], from: usize) -> usize {
+        let has_block = |header: &BlockHeader| {
+            let hash = header.hash();
+            self.contains_key(&hash) || self.is_pruned(&hash)
+        };
+        let mut should_have_block = from
+            .checked_sub(1)
+            .and_then(|position| block_headers.get(position))
+            .is_some_and(has_block);
+        for (position, header) in block_headers.iter().enumerate().skip(from) {
+            if has_block(header) {
+                should_have_block = true;
+            } else if should_have_block {
+                return position;
+            }
+        }
+        block_headers.len()
+    }
+
+    /// Returns the amount of stored blocks.
+    pub fn len(&self) -> usize {
+        match self.files {
+            Some(_) => self.locations.len(),
+            None => self.cache.len(),
+        }
+    }
+
+    /// Returns true if there are no stored blocks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns where the block is stored in disk, if it is.
+    pub fn get_location(&self, block_hash: &[u8; 32]) -> Option<BlockLocation> {
+        self.locations.get(block_hash).copied()
+    }
+
+    /// Returns the block, reading it from disk if it is not in the cache.
+    /// Returns None if it is not stored or it could not be read.
+    pub fn get(&mut self, block_hash: &[u8; 32]) -> Option<&Block> {
+        if !self.cache.contains_key(block_hash) {
+            let block = self.read_block(block_hash)?;
+            self.add_to_cache(block);
+        }
+        self.cache.get(block_hash)
+    }
+
+    /// Returns the stored block of the given height, where the first block after the genesis one has height 1.
+    pub fn get_by_height(&mut self, height: u32) -> Option<&Block> {
+        let block_hash = *self.hashes_by_height.get(&height)?;
+        self.get(&block_hash)
+    }
+
+    /// Stores the block, appending it to the last block file, or starting a new one if it is full.
+    /// The height is 0 if the header of the block is not known.
+    pub fn insert(&mut self, block: Block, height: u32) -> Result<(), NodeError> {
+        let block_hash = block.header_hash();
+        if self.contains_key(&block_hash) || self.is_pruned(&block_hash) {
+            return Ok(());
+        }
+        if let Some(files) = &mut self.files {
+            let bytes = block.to_bytes();
+            if files.current_size > 0
+                && files.current_size + bytes.len() as u64 > files.max_file_size
+            {
+                let file = open_block_file(&files.blocks_path, files.current_file + 1)
+                    .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+                files
+                    .writer
+                    .flush()
+                    .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+                files.writer = BufWriter::new(file);
+                files.current_file += 1;
+                files.current_size = 0;
+            }
+            files
+                .writer
+                .write_all(&bytes)
+                .and_then(|_| files.writer.flush())
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+            let location = BlockLocation {
+                file: files.current_file,
+                offset: files.current_size,
+                length: bytes.len() as u32,
+                height,
+            };
+            files.current_size += bytes.len() as u64;
+            self.add_location(block_hash, location);
+        } else if height > 0 {
+            self.hashes_by_height.insert(height, block_hash);
+        }
+        self.add_to_cache(block);
+        Ok(())
+    }
+
+    /// Writes the blocks that are still buffered and waits until the block file is synced to disk.
+    pub fn sync(&mut self) -> Result<(), NodeError> {
+        let files = match &mut self.files {
+            Some(files) => files,
+            None => return Ok(()),
+        };
+        files
+            .writer
+            .flush()
+            .and_then(|_| files.writer.get_ref().sync_data())
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    /// Writes the whole index in its file, replacing the previous one. The new index is synced to disk
+    /// before it replaces the previous one, so an interrupted write leaves the previous one intact.
+    pub fn save_index(&self) -> Result<(), NodeError> {
+        let index_path = match &self.files {
+            Some(files) => &files.index_path,
+            None => return Ok(()),
+        };
+        let temporary_path = format!("{}.tmp", index_path);
+        let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let mut writer = BufWriter::new(file);
+        for (block_hash, location) in self.locations.iter().chain(&self.pruned) {
+            writer
+                .write_all(block_hash)
+                .and_then(|_| writer.write_all(&location.to_bytes()))
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        writer
+            .flush()
+            .and_then(|_| writer.get_ref().sync_all())
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        fs::rename(temporary_path, index_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    /// Replaces the index with one built by reading every block file, taking the heights from the
+    /// position of the headers. Blocks whose header is not known or whose transactions do not match
+    /// its merkle root are left out, and so is an incomplete block at the end of a file.
+    /// Returns the amount of indexed blocks.
+    pub fn rebuild_index(
+        &mut self,
+        headers_index: &HashMap<[u8; 32], usize>,
+    ) -> Result<usize, NodeError> {
+        let blocks_path = match &self.files {
+            Some(files) => files.blocks_path.clone(),
+            None => return Ok(self.len()),
+        };
+        let mut locations = Vec::new();
+        for file in existing_block_files(&blocks_path) {
+            scan_block_file(
+                &block_file_path(&blocks_path, file),
+                0,
+                |block, offset, length| {
+                    if !validate_block_proof_of_inclusion(block) {
+                        return;
+                    }
+                    if let Some(position) = headers_index.get(&block.header_hash()) {
+                        let location = BlockLocation {
+                            file,
+                            offset,
+                            length,
+                            height: (*position + 1) as u32,
+                        };
+                        locations.push((block.header_hash(), location));
+                    }
+                },
+            )?;
+        }
+
+        self.locations.clear();
+        self.hashes_by_height.clear();
+        for (block_hash, location) in locations {
+            self.add_location(block_hash, location);
+        }
+        Ok(self.locations.len())
+    }
+
+    /// Repairs the block files after the node stopped without saving the index. Every file from the last
+    /// one the index points to is scanned from its indexed end, as blocks may have been appended to it before
+    /// the store moved on to a new file. The blocks written after the indexed ones are added to the index if
+    /// their header is known, indexed blocks that are not in their file anymore are removed from it, and so
+    /// is an incomplete block at the end of a file, left by an interrupted write.
+    /// Returns the amount of recovered blocks and removed bytes.
+    pub fn recover(
+        &mut self,
+        headers_index: &HashMap<[u8; 32], usize>,
+    ) -> Result<(usize, u64), NodeError> {
+        let blocks_path = match &self.files {
+            Some(files) => files.blocks_path.clone(),
+            None => return Ok((0, 0)),
+        };
+        let last_indexed_file = self
+            .locations
+            .values()
+            .chain(self.pruned.values())
+            .map(|location| location.file)
+            .max()
+            .unwrap_or(0);
+
+        let (mut recovered_blocks, mut removed_bytes) = (0, 0);
+        for file in existing_block_files(&blocks_path) {
+            if file < last_indexed_file {
+                continue;
+            }
+            let (recovered, removed) = self.recover_file(&blocks_path, file, headers_index)?;
+            recovered_blocks += recovered;
+            removed_bytes += removed;
+        }
+        Ok((recovered_blocks, removed_bytes))
+    }
+
+    /// Repairs a single block file, as recover describes. Returns the amount of recovered blocks and
+    /// removed bytes.
+    fn recover_file(
+        &mut self,
+        blocks_path: &str,
+        file: u32,
+        headers_index: &HashMap<[u8; 32], usize>,
+    ) -> Result<(usize, u64), NodeError> {
+        let file_path = block_file_path(blocks_path, file);
+        let file_size = fs::metadata(&file_path)
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?
+            .len();
+        let lost_blocks: Vec<[u8; 32]> = self
+            .locations
+            .iter()
+            .filter(|(_, location)| {
+                location.file == file && location.offset + location.length as u64 > file_size
+            })
+            .map(|(block_hash, _)| *block_hash)
+            .collect();
+        for block_hash in lost_blocks {
+            self.remove_location(&block_hash);
+        }
+
+        let indexed_end = self
+            .locations
+            .values()
+            .filter(|location| location.file == file)
+            .map(|location| location.offset + location.length as u64)
+            .max()
+            .unwrap_or(0);
+        let mut recovered = Vec::new();
+        let valid_end = scan_block_file(&file_path, indexed_end, |block, offset, length| {
+            if let Some(position) = headers_index.get(&block.header_hash()) {
+                let location = BlockLocation {
+                    file,
+                    offset,
+                    length,
+                    height: (*position + 1) as u32,
+                };
+                recovered.push((block.header_hash(), location));
+            }
+        })?;
+        let recovered_blocks = recovered.len();
+        for (block_hash, location) in recovered {
+            self.add_location(block_hash, location);
+        }
+
+        if valid_end < file_size {
+            OpenOptions::new()
+                .write(true)
+                .open(&file_path)
+                .and_then(|file| file.set_len(valid_end))
+                .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+            if let Some(files) = &mut self.files {
+                if files.current_file == file {
+                    files.current_size = valid_end;
+                }
+            }
+        }
+        Ok((recovered_blocks, file_size - valid_end))
+    }
+
+    /// Returns the oldest block files that have to be deleted so the block files do not exceed the prune
+    /// target. Only files whose blocks are not above the given height can be deleted, and the file blocks
+    /// are being appended to is never deleted.
+    pub fn files_to_prune(&self, max_height: u32) -> Vec<u32> {
+        let (files, prune_target) = match (&self.files, self.prune_target) {
+            (Some(files), Some(prune_target)) => (files, prune_target),
+            _ => return Vec::new(),
+        };
+        let file_sizes: Vec<(u32, u64)> = existing_block_files(&files.blocks_path)
+            .into_iter()
+            .filter_map(|file| {
+                let metadata = fs::metadata(block_file_path(&files.blocks_path, file)).ok()?;
+                Some((file, metadata.len()))
+            })
+            .collect();
+        let mut total_size: u64 = file_sizes.iter().map(|(_, size)| size).sum();
+
+        let mut files_to_prune = Vec::new();
+        for (file, size) in file_sizes {
+            if total_size <= prune_target || file >= files.current_file {
+                break;
+            }
+            let above_max_height = self
+                .locations
+                .values()
+                .any(|location| location.file == file && location.height > max_height);
+            if above_max_height {
+                break;
+            }
+            files_to_prune.push(file);
+            total_size -= size;
+        }
+        files_to_prune
+    }
+
+    /// Deletes the block files, marking their blocks as pruned. The index is saved before the files are
+    /// deleted, so it never points to a deleted file. Returns the amount of pruned blocks.
+    pub fn prune_files(&mut self, files_to_prune: &[u32]) -> Result<usize, NodeError> {
+        let blocks_path = match &self.files {
+            Some(files) => files.blocks_path.clone(),
+            None => return Ok(0),
+        };
+        let mut pruned_blocks = 0;
+        for file in files_to_prune {
+            let pruned_hashes: Vec<[u8; 32]> = self
+                .locations
+                .iter()
+                .filter(|(_, location)| location.file == *file)
+                .map(|(block_hash, _)| *block_hash)
+                .collect();
+            for block_hash in pruned_hashes {
+                if let Some(mut location) = self.locations.get(&block_hash).copied() {
+                    self.remove_location(&block_hash);
+                    location.length = PRUNED_LENGTH;
+                    self.pruned.insert(block_hash, location);
+                    pruned_blocks += 1;
+                }
+            }
+        }
+        self.save_index()?;
+
+        for file in files_to_prune {
+            fs::remove_file(block_file_path(&blocks_path, *file))
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        Ok(pruned_blocks)
+    }
+
+    fn remove_location(&mut self, block_hash: &[u8; 32]) {
+        if let Some(location) = self.locations.remove(block_hash) {
+            self.hashes_by_height.remove(&location.height);
+        }
+        self.cache.remove(block_hash);
+    }
+
+    fn add_location(&mut self, block_hash: [u8; 32], location: BlockLocation) {
+        if location.height > 0 {
+            self.hashes_by_height.insert(location.height, block_hash);
+        }
+        self.locations.insert(block_hash, location);
+    }
+
+    /// Adds the block to the cache, removing the oldest ones if it is full. Only blocks stored in disk
+    /// are removed, so a store that is not stored in disk keeps every block.
+    fn add_to_cache(&mut self, block: Block) {
+        if let Some(cache_capacity) = self.cache_capacity {
+            while self.cache.len() >= cache_capacity.max(1) {
+                match self.cache_order.pop_front() {
+                    Some(oldest_hash) => _ = self.cache.remove(&oldest_hash),
+                    None => break,
+                }
+            }
+        }
+        let block_hash = block.header_hash();
+        self.cache_order.push_back(block_hash);
+        self.cache.insert(block_hash, block);
+    }
+
+    /// Reads the block from its block file.
+    fn read_block(&self, block_hash: &[u8; 32]) -> Option<Block> {
+        let files = self.files.as_ref()?;
+        let location = self.locations.get(block_hash)?;
+        let mut file = File::open(block_file_path(&files.blocks_path, location.file)).ok()?;
+        file.seek(SeekFrom::Start(location.offset)).ok()?;
+        let mut bytes = vec![0; location.length as usize];
+        file.read_exact(&mut bytes).ok()?;
+        Block::from_bytes(&bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{blocks::Transaction, node::test_chain};
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn chain(length: u32) -> (Vec<Block>, HashMap<[u8; 32], usize>) {
+        let transactions = (0..length)
+            .map(|i| vec![Transaction::new(1, Vec::new(), Vec::new(), i)])
+            .collect();
+        let (blocks, block_headers) = test_chain::chain([0; 32], 0x1d00ffff, transactions);
+        let headers_index = block_headers
+            .iter()
+            .enumerate()
+            .map(|(position, header)| (header.hash(), position))
+            .collect();
+        (blocks, headers_index)
+    }
+
+    fn copy(block: &Block) -> Block {
+        Block::from_bytes(&block.to_bytes()).unwrap()
+    }
+
+    fn bytes_of(block: Option<&Block>) -> Option<Vec<u8>> {
+        block.map(|block| block.to_bytes())
+    }
+
+    fn remove_files(blocks_path: &str, index_path: &str) {
+        _ = fs::remove_file(blocks_path);
+        _ = fs::remove_file(block_file_path(blocks_path, 1));
+        _ = fs::remove_file(index_path);
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn block_store_test_1_blocks_are_found_after_reopening_the_store() -> Result<(), NodeError> {
+        let blocks_path = "tests_txt/block_store_test_1_blocks.bin";
+        let index_path = "tests_txt/block_store_test_1_index.bin";
+        remove_files(blocks_path, index_path);
+        let (blocks, _) = chain(3);
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        for (position, block) in blocks.iter().enumerate() {
+            store.insert(copy(block), position as u32 + 1)?;
+        }
+        store.save_index()?;
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        assert_eq!(store.len(), 3);
+        assert_eq!(
+            bytes_of(store.get(&blocks[1].header_hash())),
+            Some(blocks[1].to_bytes())
+        );
+        assert_eq!(bytes_of(store.get_by_height(3)), Some(blocks[2].to_bytes()));
+        assert_eq!(
+            store.get_location(&blocks[0].header_hash()).unwrap().offset,
+            0
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_store_test_2_evicted_blocks_are_read_from_disk() -> Result<(), NodeError> {
+        let blocks_path = "tests_txt/block_store_test_2_blocks.bin";
+        let index_path = "tests_txt/block_store_test_2_index.bin";
+        remove_files(blocks_path, index_path);
+        let (blocks, _) = chain(4);
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        store.cache_capacity = Some(2);
+        for (position, block) in blocks.iter().enumerate() {
+            store.insert(copy(block), position as u32 + 1)?;
+        }
+
+        assert_eq!(store.cache.len(), 2);
+        assert!(store.contains_key(&blocks[0].header_hash()));
+        assert_eq!(
+            bytes_of(store.get(&blocks[0].header_hash())),
+            Some(blocks[0].to_bytes())
+        );
+        assert_eq!(store.cache.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn block_store_test_3_rebuilding_the_index_skips_an_incomplete_block() -> Result<(), NodeError>
+    {
+        let blocks_path = "tests_txt/block_store_test_3_blocks.bin";
+        let index_path = "tests_txt/block_store_test_3_index.bin";
+        remove_files(blocks_path, index_path);
+        let (blocks, headers_index) = chain(3);
+        let mut bytes = Vec::new();
+        for block in &blocks {
+            bytes.extend(block.to_bytes());
+        }
+        bytes.truncate(bytes.len() - 10);
+        fs::write(blocks_path, bytes).unwrap();
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        assert!(store.is_empty());
+
+        assert_eq!(store.rebuild_index(&headers_index)?, 2);
+        assert_eq!(bytes_of(store.get_by_height(2)), Some(blocks[1].to_bytes()));
+        assert!(!store.contains_key(&blocks[2].header_hash()));
+        Ok(())
+    }
+
+    #[test]
+    fn block_store_test_4_recovers_blocks_stored_after_the_index_was_saved() -> Result<(), NodeError>
+    {
+        let blocks_path = "tests_txt/block_store_test_4_blocks.bin";
+        let index_path = "tests_txt/block_store_test_4_index.bin";
+        remove_files(blocks_path, index_path);
+        let (blocks, headers_index) = chain(3);
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        store.insert(copy(&blocks[0]), 1)?;
+        store.save_index()?;
+        store.insert(copy(&blocks[1]), 2)?;
+        store.insert(copy(&blocks[2]), 3)?;
+        drop(store);
+        let file_size = fs::metadata(blocks_path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(blocks_path)
+            .unwrap()
+            .set_len(file_size - 10)
+            .unwrap();
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        let removed_bytes = blocks[2].to_bytes().len() as u64 - 10;
+        assert_eq!(store.recover(&headers_index)?, (1, removed_bytes));
+        assert_eq!(store.len(), 2);
+        assert_eq!(bytes_of(store.get_by_height(2)), Some(blocks[1].to_bytes()));
+
+        store.insert(copy(&blocks[2]), 3)?;
+        assert_eq!(bytes_of(store.get_by_height(3)), Some(blocks[2].to_bytes()));
+        Ok(())
+    }
+
+    #[test]
+    fn block_store_test_5_pruning_deletes_the_oldest_files_up_to_the_height(
+    ) -> Result<(), NodeError> {
+        let blocks_path = "tests_txt/block_store_test_5_blocks.bin";
+        let index_path = "tests_txt/block_store_test_5_index.bin";
+        for file in existing_block_files(blocks_path) {
+            _ = fs::remove_file(block_file_path(blocks_path, file));
+        }
+        remove_files(blocks_path, index_path);
+        let (blocks, _) = chain(5);
+        let block_size = blocks[0].to_bytes().len() as u64;
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        if let Some(files) = &mut store.files {
+            files.max_file_size = block_size;
+        }
+        for (position, block) in blocks.iter().take(4).enumerate() {
+            store.insert(copy(block), position as u32 + 1)?;
+        }
+        assert!(store.files_to_prune(4).is_empty());
+        store.set_prune_target(Some(2 * block_size));
+
+        assert_eq!(store.files_to_prune(1), vec![0]);
+        assert_eq!(store.files_to_prune(4), vec![0, 1]);
+        assert_eq!(store.prune_files(&store.files_to_prune(4))?, 2);
+        drop(store);
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        assert_eq!(existing_block_files(blocks_path), vec![2, 3]);
+        assert!(store.is_pruned(&blocks[1].header_hash()));
+        assert!(!store.contains_key(&blocks[1].header_hash()));
+        assert_eq!(store.pruned_height(), 2);
+        assert_eq!(bytes_of(store.get_by_height(3)), Some(blocks[2].to_bytes()));
+
+        store.insert(copy(&blocks[0]), 1)?;
+        assert!(!store.contains_key(&blocks[0].header_hash()));
+        store.insert(copy(&blocks[4]), 5)?;
+        assert_eq!(
+            store.get_location(&blocks[4].header_hash()).unwrap().file,
+            3
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn block_store_test_6_recovers_blocks_stored_in_every_file_after_a_rollover(
+    ) -> Result<(), NodeError> {
+        let blocks_path = "tests_txt/block_store_test_6_blocks.bin";
+        let index_path = "tests_txt/block_store_test_6_index.bin";
+        remove_files(blocks_path, index_path);
+        let (blocks, headers_index) = chain(4);
+        let block_size = blocks[0].to_bytes().len() as u64;
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        if let Some(files) = &mut store.files {
+            files.max_file_size = 2 * block_size;
+        }
+        store.insert(copy(&blocks[0]), 1)?;
+        store.save_index()?;
+        for (position, block) in blocks.iter().enumerate().skip(1) {
+            store.insert(copy(block), position as u32 + 1)?;
+        }
+        assert_eq!(existing_block_files(blocks_path), vec![0, 1]);
+        drop(store);
+
+        let mut store = BlockStore::from_path(blocks_path, index_path)?;
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.recover(&headers_index)?, (3, 0));
+        assert_eq!(store.len(), 4);
+        assert_eq!(bytes_of(store.get_by_height(2)), Some(blocks[1].to_bytes()));
+        assert_eq!(
+            store.get_location(&blocks[1].header_hash()).unwrap().file,
+            0
+        );
+        assert_eq!(bytes_of(store.get_by_height(4)), Some(blocks[3].to_bytes()));
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use crate::node::*;
+use std::thread;
+
+/// Checks the p2pkh signatures of the inputs of the block that spend the available outputs, which
+/// are removed as they are spent. The outputs of the block are made available for the next ones.
+/// Returns the amount of checked scripts.
+pub fn verify_block_scripts(
+    block: &Block,
+    available_outputs: &mut HashMap<Outpoint, TxOut>,
+) -> Result<usize, NodeError> {
+    let mut checked_scripts = 0;
+    for tx in block.get_transactions() {
+        for (input_index, tx_in) in tx.tx_in.iter().enumerate() {
+            let previous_output = match available_outputs.remove(&tx_in.previous_output) {
+                Some(previous_output) => previous_output,
+                None => continue,
+            };
+            match tx.verify_p2pkh_input(input_index, &previous_output) {
+                Some(true) => checked_scripts += 1,
+                Some(false) => return Err(NodeError::ErrorInvalidScript),
+                None => {}
+            }
+        }
+        let tx_hash = tx.hash();
+        for (index, tx_out) in tx.tx_out.iter().enumerate() {
+            available_outputs.insert(Outpoint::new(tx_hash, index as u32), tx_out.clone());
+        }
+    }
+    Ok(checked_scripts)
+}
+
+/// Checks the proof of work and the merkle root of the block, and the p2pkh signatures of the inputs
+/// that spend outputs of previous transactions of the same block. It does not use any shared data, so
+/// blocks can be validated in parallel and the blockchain only needs to be locked to store them.
+/// Returns the amount of checked scripts.
+pub fn validate_block_contents(block: &Block) -> Result<usize, NodeError> {
+    if !validate_proof_of_work(&block.get_header()) {
+        return Err(NodeError::ErrorInvalidProofOfWork);
+    }
+    if !validate_block_proof_of_inclusion(block) {
+        return Err(NodeError::ErrorInvalidMerkleRoot);
+    }
+    verify_block_scripts(block, &mut HashMap::new())
+}
+
+/// Validates the contents of the blocks, splitting them between the given amount of threads.
+/// Returns the amount of checked scripts, or the error of the first invalid block found.
+pub fn validate_blocks_in_parallel(blocks: &[Block], threads: usize) -> Result<usize, NodeError> {
+    if blocks.is_empty() {
+        return Ok(0);
+    }
+    let chunk_size = blocks.len().div_ceil(threads.max(1));
+    thread::scope(|scope| {
+        let handles: Vec<_> = blocks
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(validate_block_contents).sum()))
+            .collect();
+
+        let mut checked_scripts = 0;
+        for handle in handles {
+            let chunk_checked_scripts: Result<usize, NodeError> =
+                handle.join().map_err(|_| NodeError::ErrorJoiningThread)?;
+            checked_scripts += chunk_checked_scripts?;
+        }
+        Ok(checked_scripts)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::transaction::get_pk_script_from_pubkey;
+    use secp256k1::{Secp256k1, SecretKey};
+
+    const EASY_N_BITS: u32 = 0xffffff20;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn block(transactions: Vec<Transaction>) -> Block {
+        let tx_hashes = transactions.iter().map(|tx| tx.hash()).collect();
+        let header = BlockHeader::new(1, [0; 32], calculate_merkle_root(tx_hashes), EASY_N_BITS);
+        Block::new(header, transactions)
+    }
+
+    /// Returns a block with a transaction that pays to a key and one that spends it.
+    fn block_spending_its_own_output(spent_value: i64) -> Block {
+        let priv_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        let pub_key = priv_key.public_key(&Secp256k1::new());
+        let funding_output = TxOut::new(50000, Vec::from(get_pk_script_from_pubkey(pub_key)));
+        let funding_tx = Transaction::new(1, Vec::new(), vec![funding_output], 0);
+        let mut address: [u8; 25] = [0; 25];
+        address[1..21].copy_from_slice(&[3; 20]);
+        let outpoint = Outpoint::new(funding_tx.hash(), 0);
+        let mut spending_tx =
+            Transaction::create_sweep(1000, vec![outpoint], 50000, pub_key, priv_key, address)
+                .unwrap();
+        spending_tx.tx_out[0].value = spent_value;
+        block(vec![funding_tx, spending_tx])
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn block_validation_test_1_checks_the_scripts_that_spend_outputs_of_the_block() {
+        let valid_block = block_spending_its_own_output(49000);
+        let tampered_block = block_spending_its_own_output(49999);
+
+        assert_eq!(validate_block_contents(&valid_block).unwrap(), 1);
+        assert!(matches!(
+            validate_block_contents(&tampered_block),
+            Err(NodeError::ErrorInvalidScript)
+        ));
+    }
+
+    #[test]
+    fn block_validation_test_2_rejects_a_block_that_does_not_match_its_merkle_root() {
+        let tx = Transaction::new(1, Vec::new(), Vec::new(), 0);
+        let header = BlockHeader::new(1, [0; 32], [2; 32], EASY_N_BITS);
+
+        assert!(matches!(
+            validate_block_contents(&Block::new(header, vec![tx])),
+            Err(NodeError::ErrorInvalidMerkleRoot)
+        ));
+    }
+
+    #[test]
+    fn block_validation_test_3_parallel_validation_finds_every_invalid_block() {
+        let mut blocks: Vec<Block> = (0..10)
+            .map(|_| block_spending_its_own_output(49000))
+            .collect();
+
+        assert_eq!(validate_blocks_in_parallel(&blocks, 4).unwrap(), 10);
+        assert_eq!(validate_blocks_in_parallel(&blocks, 20).unwrap(), 10);
+        assert_eq!(validate_blocks_in_parallel(&[], 4).unwrap(), 0);
+
+        blocks[7] = block_spending_its_own_output(49999);
+        assert!(matches!(
+            validate_blocks_in_parallel(&blocks, 3),
+            Err(NodeError::ErrorInvalidScript)
+        ));
+    }
+}
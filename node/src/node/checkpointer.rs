@@ -0,0 +1,176 @@
+use crate::node::{data_handler::SafeNodeDataHandler, *};
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread::{self, JoinHandle},
+};
+
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Writes a checkpoint of the downloaded data. The blocks are synced to disk first, then the index that
+/// points to them replaces the previous one, and last the headers of the stored blocks are appended to the
+/// headers file. Each step only refers to data written by the previous ones, so the files can be recovered
+/// if the node stops at any point. Returns the amount of headers in disk.
+pub fn write_checkpoint(
+    safe_blockchain: &SafeBlockChain,
+    safe_block_headers: &SafeVecHeader,
+    safe_data_handler: &SafeNodeDataHandler,
+) -> Result<usize, NodeError> {
+    let mut data_handler = safe_data_handler
+        .lock()
+        .map_err(|_| NodeError::ErrorSharingReference)?;
+    let mut blockchain = safe_blockchain
+        .lock()
+        .map_err(|_| NodeError::ErrorSharingReference)?;
+    let block_headers = safe_block_headers
+        .read()
+        .map_err(|_| NodeError::ErrorSharingReference)?;
+
+    blockchain.sync()?;
+    blockchain.save_index()?;
+    data_handler
+        .save_headers_of_stored_blocks(&blockchain, &block_headers)
+        .map_err(|_| NodeError::ErrorSavingDataToDisk)
+}
+
+/// Thread that writes a checkpoint every CHECKPOINT_INTERVAL, so a crash only loses the data received
+/// since the last one.
+#[derive(Debug)]
+pub struct Checkpointer {
+    thread: JoinHandle<()>,
+    stop_sender: mpsc::Sender<()>,
+}
+
+impl Checkpointer {
+    /// Starts the thread, which runs until the checkpointer is stopped.
+    pub fn new(
+        safe_blockchain: SafeBlockChain,
+        safe_block_headers: SafeVecHeader,
+        safe_data_handler: SafeNodeDataHandler,
+        logger: Logger,
+    ) -> Checkpointer {
+        let (stop_sender, stop_receiver) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) =
+                stop_receiver.recv_timeout(CHECKPOINT_INTERVAL)
+            {
+                match write_checkpoint(&safe_blockchain, &safe_block_headers, &safe_data_handler) {
+                    Ok(saved_headers) => {
+                        logger.log(format!("Checkpoint written with {saved_headers} headers"))
+                    }
+                    Err(error) => logger.log_error(&error),
+                }
+            }
+        });
+
+        Checkpointer {
+            thread,
+            stop_sender,
+        }
+    }
+
+    /// Stops the thread, waiting for the checkpoint it may be writing.
+    pub fn stop(self) -> Result<(), NodeError> {
+        _ = self.stop_sender.send(());
+        self.thread
+            .join()
+            .map_err(|_| NodeError::ErrorJoiningThread)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::data_handler::NodeDataHandler;
+    use std::fs;
+
+    const HEADERS_FILE_PATH: &str = "tests_txt/checkpointer_test_headers.bin";
+    const BLOCKS_FILE_PATH: &str = "tests_txt/checkpointer_test_blocks.bin";
+    const INDEX_FILE_PATH: &str = "tests_txt/checkpointer_test_index.bin";
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn block(prev_hash: [u8; 32], lock_time: u32) -> Block {
+        let tx = Transaction::new(1, Vec::new(), Vec::new(), lock_time);
+        let header = BlockHeader::new(1, prev_hash, tx.hash(), 0x1d00ffff);
+        Block::new(header, vec![tx])
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn checkpointer_test_1_saves_headers_up_to_the_first_missing_block() -> Result<(), NodeError> {
+        for file_path in [HEADERS_FILE_PATH, BLOCKS_FILE_PATH, INDEX_FILE_PATH] {
+            _ = fs::remove_file(file_path);
+        }
+        let first_block = block([0; 32], 0);
+        let second_block = block(first_block.header_hash(), 1);
+        let third_block = block(second_block.header_hash(), 2);
+        let block_headers = vec![
+            first_block.get_header(),
+            second_block.get_header(),
+            third_block.get_header(),
+        ];
+
+        let mut blockchain = BlockStore::from_path(BLOCKS_FILE_PATH, INDEX_FILE_PATH)?;
+        blockchain.insert(first_block, 1)?;
+        blockchain.insert(third_block, 3)?;
+        let safe_blockchain = Arc::new(Mutex::from(blockchain));
+        let safe_block_headers = Arc::new(RwLock::from(block_headers));
+        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH).unwrap();
+        let safe_data_handler = Arc::new(Mutex::from(data_handler));
+
+        let saved_headers =
+            write_checkpoint(&safe_blockchain, &safe_block_headers, &safe_data_handler)?;
+
+        assert_eq!(saved_headers, 1);
+        let mut data_handler = NodeDataHandler::new(HEADERS_FILE_PATH).unwrap();
+        assert_eq!(data_handler.get_all_headers().unwrap().len(), 1);
+        let blockchain = BlockStore::from_path(BLOCKS_FILE_PATH, INDEX_FILE_PATH)?;
+        assert_eq!(blockchain.len(), 2);
+        Ok(())
+    }
+    #[test]
+    fn checkpointer_test_2_missing_block_after_the_saved_headers_stays_missing(
+    ) -> Result<(), NodeError> {
+        let headers_file_path = "tests_txt/checkpointer_test_2_headers.bin";
+        let blocks_file_path = "tests_txt/checkpointer_test_2_blocks.bin";
+        let index_file_path = "tests_txt/checkpointer_test_2_index.bin";
+        for file_path in [headers_file_path, blocks_file_path, index_file_path] {
+            _ = fs::remove_file(file_path);
+        }
+        let first_block = block([0; 32], 0);
+        let second_block = block(first_block.header_hash(), 1);
+        let third_block = block(second_block.header_hash(), 2);
+        let block_headers = vec![
+            first_block.get_header(),
+            second_block.get_header(),
+            third_block.get_header(),
+        ];
+
+        let mut blockchain = BlockStore::from_path(blocks_file_path, index_file_path)?;
+        blockchain.insert(first_block, 1)?;
+        blockchain.insert(third_block, 3)?;
+        let safe_blockchain = Arc::new(Mutex::from(blockchain));
+        let safe_block_headers = Arc::new(RwLock::from(block_headers));
+        let data_handler = NodeDataHandler::new(headers_file_path).unwrap();
+        let safe_data_handler = Arc::new(Mutex::from(data_handler));
+
+        let first_checkpoint =
+            write_checkpoint(&safe_blockchain, &safe_block_headers, &safe_data_handler)?;
+        let second_checkpoint =
+            write_checkpoint(&safe_blockchain, &safe_block_headers, &safe_data_handler)?;
+        safe_blockchain
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)?
+            .insert(second_block, 2)?;
+        let third_checkpoint =
+            write_checkpoint(&safe_blockchain, &safe_block_headers, &safe_data_handler)?;
+
+        assert_eq!(first_checkpoint, 1);
+        assert_eq!(second_checkpoint, 1);
+        assert_eq!(third_checkpoint, 3);
+        Ok(())
+    }
+}
@@ -2,19 +2,20 @@ use crate::{node::*, utils::btc_errors::NodeDataHandlerError};
 use std::{
     fs::{File, OpenOptions},
     io::{BufReader, BufWriter},
+    sync::{Arc, Mutex},
 };
 
 const BLOCKHEADER_SIZE: usize = 80;
 
-/// Struct that handles the data persistance of the node.  It has two readers and two writers, one for each file.
-/// The headers reader and writer are used to read and write the headers file, and the blocks
-/// reader and writer are used to read and write the blocks file.
+pub type SafeNodeDataHandler = Arc<Mutex<NodeDataHandler>>;
+
+/// Struct that handles the persistance of the headers of the node, with a reader and a writer of the
+/// headers file, and the amount of headers that are already in it. Blocks are stored by the BlockStore.
 #[derive(Debug)]
 pub struct NodeDataHandler {
     headers_reader: BufReader<File>,
-    blocks_reader: BufReader<File>,
     headers_writer: BufWriter<File>,
-    blocks_writer: BufWriter<File>,
+    saved_headers: usize,
 }
 
 /// Opens the file in the given path with the given permissions of reading and appending
@@ -62,28 +63,54 @@ fn get_bytes_from_file(reader: &mut BufReader<File>) -> Result<Vec<u8>, NodeData
 
 impl NodeDataHandler {
     /// Creates a new NodeDataHandler.
-    pub fn new(
-        headers_file_path: &str,
-        blocks_file_path: &str,
-    ) -> Result<NodeDataHandler, NodeDataHandlerError> {
+    pub fn new(headers_file_path: &str) -> Result<NodeDataHandler, NodeDataHandlerError> {
         let read_headers_file = open_file(headers_file_path, true, false)?;
         let write_headers_file = open_file(headers_file_path, false, true)?;
-        let read_blocks_file = open_file(blocks_file_path, true, false)?;
-        let write_blocks_file = open_file(blocks_file_path, false, true)?;
 
         let headers_writer = BufWriter::new(write_headers_file);
-        let blocks_writer = BufWriter::new(write_blocks_file);
         let headers_reader = BufReader::new(read_headers_file);
-        let blocks_reader = BufReader::new(read_blocks_file);
 
         Ok(NodeDataHandler {
             headers_reader,
-            blocks_reader,
             headers_writer,
-            blocks_writer,
+            saved_headers: 0,
         })
     }
 
+    /// Returns the amount of headers in the headers file.
+    pub fn saved_headers(&self) -> usize {
+        self.saved_headers
+    }
+
+    /// Removes the incomplete header an interrupted write may have left at the end of the headers file,
+    /// so the next ones are appended after the last complete one. Returns the amount of removed bytes.
+    pub fn recover_headers_file(&mut self) -> Result<u64, NodeDataHandlerError> {
+        let headers_file = self.headers_writer.get_ref();
+        let file_size = headers_file
+            .metadata()
+            .map_err(|_| NodeDataHandlerError::ErrorReadingHeaders)?
+            .len();
+        let removed_bytes = file_size % BLOCKHEADER_SIZE as u64;
+        if removed_bytes > 0 {
+            headers_file
+                .set_len(file_size - removed_bytes)
+                .map_err(|_| NodeDataHandlerError::ErrorWritingInFile)?;
+        }
+        Ok(removed_bytes)
+    }
+
+    /// Removes the headers after the given amount from the headers file, so they are downloaded again.
+    pub fn truncate_headers(&mut self, amount: usize) -> Result<(), NodeDataHandlerError> {
+        flush_writer(&mut self.headers_writer)?;
+        self.headers_writer
+            .get_ref()
+            .set_len((amount * BLOCKHEADER_SIZE) as u64)
+            .and_then(|_| self.headers_writer.get_ref().sync_data())
+            .map_err(|_| NodeDataHandlerError::ErrorWritingInFile)?;
+        self.saved_headers = self.saved_headers.min(amount);
+        Ok(())
+    }
+
     pub fn get_all_headers(&mut self) -> Result<Vec<BlockHeader>, NodeDataHandlerError> {
         let mut headers: Vec<BlockHeader> = Vec::new();
         let reader_reference = &mut self.headers_reader;
@@ -98,31 +125,11 @@ impl NodeDataHandler {
                 Err(_) => return Err(NodeDataHandlerError::ErrorReadingHeaders),
             }
         }
+        self.saved_headers = headers.len();
         Ok(headers)
     }
 
-    /// Returns a vector with all the blocks stored in the blocks file.
-    /// This function is only called once when the node starts, since dealing with
-    /// reading and writing on the same file at the same time can produce
-    /// unexpected results. On error returns NodeDataHandlerError
-    pub fn get_all_blocks(&mut self) -> Result<Vec<Block>, NodeDataHandlerError> {
-        let mut blocks: Vec<Block> = Vec::new();
-        let reader_reference = &mut self.blocks_reader;
-        let bytes_from_file = get_bytes_from_file(reader_reference)?;
-        let mut block_bytes = bytes_from_file.as_slice();
-
-        while !block_bytes.is_empty() {
-            let block = match Block::from_bytes(block_bytes) {
-                Ok(block) => block,
-                Err(_) => return Err(NodeDataHandlerError::ErrorReadingBlocks),
-            };
-            (_, block_bytes) = block_bytes.split_at(block.amount_of_bytes());
-            blocks.push(block);
-        }
-        Ok(blocks)
-    }
-
-    /// Saves the block (as bytes) passed by parameter in the blocks file.
+    /// Saves the header (as bytes) passed by parameter in the headers file.
     /// On error returns NodeDataHandlerError
     pub fn save_header(&mut self, header: &BlockHeader) -> Result<(), NodeDataHandlerError> {
         let header_bytes = header.to_bytes();
@@ -130,56 +137,45 @@ impl NodeDataHandler {
         Ok(())
     }
 
-    /// Saves the headers starting form start (as bytes) passed by parameter in the headers file.
+    /// Saves the headers that are not in the headers file yet (as bytes) in the headers file.
     /// On error returns NodeDataHandlerError
     pub fn save_headers_to_disk(
         &mut self,
         safe_headers: &SafeVecHeader,
-        start: usize,
     ) -> Result<(), NodeDataHandlerError> {
         let block_headers = safe_headers
-            .lock()
+            .read()
             .map_err(|_| NodeDataHandlerError::ErrorSharingData)?;
 
-        for header in block_headers.iter().skip(start) {
+        for header in block_headers.iter().skip(self.saved_headers) {
             self.save_header(header)?;
+            self.saved_headers += 1;
         }
         Ok(())
     }
 
-    /// Saves the block (as bytes) passed by parameter in the blocks file.
-    /// On error returns NodeDataHandlerError
-    pub fn save_block(&mut self, block: &Block) -> Result<(), NodeDataHandlerError> {
-        let block_bytes = block.to_bytes();
-        write_to_file(&mut self.blocks_writer, &block_bytes)?;
-        Ok(())
-    }
-
-    /// Saves the blocks starting form start (as bytes) passed by parameter in the headers file.
-    /// On error returns NodeDataHandlerError
-    pub fn save_blocks_to_disk(
+    /// Saves the headers that are not in the headers file yet, stopping at the first one whose block is
+    /// missing after a stored one, so the download continues from there. The file is synced to disk
+    /// afterwards. Returns the amount of headers in the file. On error returns NodeDataHandlerError
+    pub fn save_headers_of_stored_blocks(
         &mut self,
-        safe_blockchain: &SafeBlockChain,
-        safe_headers: &SafeVecHeader,
-        start: usize,
+        blockchain: &BlockStore,
+        block_headers: &[BlockHeader],
     ) -> Result<usize, NodeDataHandlerError> {
-        let block_headers = safe_headers
-            .lock()
-            .map_err(|_| NodeDataHandlerError::ErrorSharingData)?;
-        let blockchain = safe_blockchain
-            .lock()
-            .map_err(|_| NodeDataHandlerError::ErrorSharingData)?;
-        let mut should_have_block = false;
-        for (i, header) in block_headers.iter().skip(start).enumerate() {
-            if let Some(block) = blockchain.get(&header.hash()) {
-                self.save_block(block)?;
-                should_have_block = true;
-            } else if should_have_block {
-                return Ok(i);
-            }
+        let kept_headers = blockchain.headers_of_stored_blocks(block_headers, self.saved_headers);
+        for header in block_headers
+            .iter()
+            .take(kept_headers)
+            .skip(self.saved_headers)
+        {
             self.save_header(header)?;
+            self.saved_headers += 1;
         }
 
-        Ok(block_headers.len())
+        self.headers_writer
+            .get_ref()
+            .sync_data()
+            .map_err(|_| NodeDataHandlerError::ErrorFlushingWriter)?;
+        Ok(self.saved_headers)
     }
 }
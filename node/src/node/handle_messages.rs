@@ -1,25 +1,30 @@
+use crate::blocks::block_filter::BASIC_FILTER_TYPE;
+use crate::messages::addr_message::MAX_ADDR_ENTRIES;
+use crate::messages::compact_filter_messages::{
+    CFCHECKPT_INTERVAL, MAX_CFHEADERS_REQUEST, MAX_CFILTERS_REQUEST,
+};
 use crate::node::get_block_headers_message::MAX_QUANTITY_FOR_GET_HEADERS;
 use crate::node::initial_block_download::HASHEDGENESISBLOCK;
 use crate::node::*;
 
+use super::block_validation::validate_block_contents;
 use super::peer_comunication::block_downloader::send_get_data_message_for_blocks;
 use super::safe_node_structure::NodeSharedInformation;
+use super::timelocks::{block_lock_state, is_final_for_next_block};
 
-pub fn handle_message(
+pub fn handle_message<T: Read + Write>(
     message: Message,
-    stream: &mut TcpStream,
+    stream: &mut T,
     safe_node_info: &NodeSharedInformation,
     logger: &Logger,
     downloading_headers: bool,
 ) -> Result<(), NodeError> {
     match message {
         Message::BlockHeaders(msg) => {
-            if downloading_headers {
-                handle_block_headers_message(
-                    msg,
-                    &safe_node_info.safe_block_headers,
-                    &safe_node_info.safe_headers_index,
-                )?;
+            // While downloading headers they are only accepted from the header sync peer, which
+            // validates them before adding them to the chain.
+            if !downloading_headers && !safe_node_info.light_client {
+                handle_headers_announcement(stream, msg, &safe_node_info.safe_blockchain)?;
             }
         }
         Message::Block(msg) => {
@@ -46,9 +51,37 @@ pub fn handle_message(
                 )?;
             }
         }
+        Message::GetBlockTxn(msg) => {
+            if !downloading_headers {
+                handle_get_block_txn(stream, msg, &safe_node_info.safe_blockchain)?;
+            }
+        }
+        Message::GetCFilters(msg) => {
+            if !downloading_headers {
+                handle_get_cfilters(stream, msg, safe_node_info)?;
+            }
+        }
+        Message::GetCFHeaders(msg) => {
+            if !downloading_headers {
+                handle_get_cfheaders(stream, msg, safe_node_info)?;
+            }
+        }
+        Message::GetCFCheckpt(msg) => {
+            if !downloading_headers {
+                handle_get_cfcheckpt(stream, msg, safe_node_info)?;
+            }
+        }
         Message::Header(_) => return Err(NodeError::DoubleHeader),
         Message::Inv(msg) => {
             if !downloading_headers {
+                // A light client gets new blocks from its filtering peer, alongside their headers.
+                let msg = if safe_node_info.light_client {
+                    InvMessage::create_message_inventory_transaction_type(
+                        msg.get_transaction_hashes(),
+                    )
+                } else {
+                    msg
+                };
                 handle_inv_message(
                     stream,
                     msg,
@@ -57,32 +90,50 @@ pub fn handle_message(
                 )?;
             }
         }
-        Message::Tx(msg) => handle_tx_message(msg, &safe_node_info.safe_pending_tx)?,
+        Message::Tx(msg) => handle_tx_message(msg, safe_node_info, logger)?,
         Message::Ping(msg) => handle_ping_message(stream, msg)?,
+        Message::Addr(msg) => handle_addr_message(&msg.addresses, safe_node_info)?,
+        Message::AddrV2(msg) => handle_addr_message(&msg.get_ip_addresses(), safe_node_info)?,
+        Message::GetAddr(_) => {
+            if !downloading_headers {
+                handle_get_addr_message(stream, safe_node_info)?;
+            }
+        }
         _ => {}
     };
     Ok(())
 }
 
-///Handles the headers message by hashing the last received header and asking for more headers.
-pub fn handle_block_headers_message(
+/// Handles the headers message a peer that was sent a sendheaders message uses to announce new blocks,
+/// by asking for the blocks that are not in the blockchain. Their headers are added once the blocks arrive.
+/// Returns ErrorInvalidHeaders if any of the headers does not have a valid proof of work.
+pub fn handle_headers_announcement<T: Read + Write>(
+    stream: &mut T,
     block_headers_msg: BlockHeadersMessage,
-    safe_block_headers: &SafeVecHeader,
-    safe_headers_index: &SafeHeaderIndex,
+    safe_blockchain: &SafeBlockChain,
 ) -> Result<(), NodeError> {
-    let received_block_headers = block_headers_msg.headers;
-
-    insert_new_headers(
-        received_block_headers,
-        safe_block_headers,
-        safe_headers_index,
-    )?;
+    if !block_headers_msg.headers.iter().all(validate_proof_of_work) {
+        return Err(NodeError::ErrorInvalidHeaders);
+    }
 
+    let request_block_hashes: Vec<[u8; 32]> = match safe_blockchain.lock() {
+        Ok(blockchain) => block_headers_msg
+            .headers
+            .iter()
+            .map(|header| header.hash())
+            .filter(|hash| !blockchain.contains_key(hash))
+            .collect(),
+        Err(_) => return Err(NodeError::ErrorSharingReference),
+    };
+    if !request_block_hashes.is_empty() {
+        send_get_data_message_for_blocks(request_block_hashes, stream)
+            .map_err(|_| NodeError::ErrorDownloadingBlockBundle)?;
+    }
     Ok(())
 }
 
-/// Handles the block message by validating the proof of work and the proof of inclusion and the saving it.
-/// If the block is already in the blockchain, it is not saved.
+/// Handles the block message by validating the proof of work, the proof of inclusion and the scripts it can
+/// check, and then saving it. If the block is already in the blockchain, it is not saved.
 pub fn handle_block_message(
     block_msg: BlockMessage,
     safe_node_info: &NodeSharedInformation,
@@ -90,24 +141,40 @@ pub fn handle_block_message(
     downloading_headers: bool,
 ) -> Result<(), NodeError> {
     let block = block_msg.block;
+    let block_header = block.get_header();
 
-    let mut blockchain = safe_node_info.lock_blockchain()?;
+    if safe_node_info
+        .lock_blockchain()?
+        .contains_key(&block_header.hash())
+    {
+        return Ok(());
+    }
 
-    let block_header = block.get_header();
+    // The block is validated without holding the lock of the blockchain, so the workers that
+    // download blocks validate them in parallel and only storing them is serialized.
+    if let Err(error) = validate_block_contents(&block) {
+        logger.log(format!("Validation failed for a block: {:?}", error));
+        return Err(error);
+    }
+    if let Some((height, median_time_past)) =
+        block_lock_state(&block_header.prev_hash, safe_node_info)?
+    {
+        if !block
+            .get_transactions()
+            .iter()
+            .all(|tx| tx.is_final(height, median_time_past))
+        {
+            logger.log(String::from("A block contains non final transactions"));
+            return Err(NodeError::ErrorValidatingBlock);
+        }
+    }
 
+    let mut blockchain = safe_node_info.lock_blockchain()?;
+    // Another worker may have stored the block while it was being validated.
     if blockchain.contains_key(&block_header.hash()) {
         return Ok(());
     }
 
-    if !validate_proof_of_work(&block_header) {
-        logger.log(String::from("Proof of work failed for a block"));
-        return Err(NodeError::ErrorValidatingBlock);
-    };
-    if !validate_block_proof_of_inclusion(&block) {
-        logger.log(String::from("Proof of inclusion failed for a block"));
-        return Err(NodeError::ErrorValidatingBlock);
-    };
-
     match safe_node_info.lock_safe_pending_tx() {
         Ok(mut pending_tx) => {
             for tx in block.get_transactions() {
@@ -121,7 +188,7 @@ pub fn handle_block_message(
 
     if !downloading_headers
         && !safe_node_info
-            .lock_headers_index()?
+            .read_headers_index()?
             .contains_key(&block_header.hash())
     {
         insert_new_headers(
@@ -131,15 +198,30 @@ pub fn handle_block_message(
         )?;
     }
 
-    blockchain.insert(block.header_hash(), block);
+    let height = safe_node_info
+        .read_headers_index()?
+        .get(&block.header_hash())
+        .map_or(0, |position| *position as u32 + 1);
+    blockchain.insert(block, height)?;
+
+    if !downloading_headers {
+        let block_headers = safe_node_info.read_block_headers()?;
+        let mut block_filter_index = safe_node_info.lock_block_filter_index()?;
+        let mut tx_index = safe_node_info.lock_tx_index()?;
+        tx_index.update(&mut blockchain, &block_headers);
+        block_filter_index.update(&mut blockchain, &block_headers, &tx_index);
+        safe_node_info
+            .lock_address_index()?
+            .update(&mut blockchain, &block_headers);
+    }
 
     Ok(())
 }
 
 ///Handles the inv message by asking for the blocks that are not in the blockchain.
 ///If the block is already in the blockchain, it is not saved.
-pub fn handle_inv_message(
-    stream: &mut TcpStream,
+pub fn handle_inv_message<T: Read + Write>(
+    stream: &mut T,
     inv_msg: InvMessage,
     safe_blockchain: &SafeBlockChain,
     safe_pending_tx: &SafePendingTx,
@@ -182,20 +264,53 @@ pub fn handle_inv_message(
 }
 
 ///Handles the ping message by sending a pong message.
-pub fn handle_ping_message(stream: &mut TcpStream, ping_msg: PingMessage) -> Result<(), NodeError> {
+pub fn handle_ping_message<T: Read + Write>(
+    stream: &mut T,
+    ping_msg: PingMessage,
+) -> Result<(), NodeError> {
     ping_msg.reply_pong(stream).map_err(NodeError::ErrorMessage)
 }
 
-///Handles the block message by validating the proof of work and the proof of inclusion and the saving it.
-/// If the block is already in the blockchain, it is not saved.
+/// Handles the addr and addrv2 messages by adding the announced addresses to the address book.
+pub fn handle_addr_message(
+    addresses: &[NetworkAddress],
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    let now = Utc::now().timestamp() as u32;
+    safe_node_info
+        .lock_address_book()?
+        .add_addresses(addresses, now);
+    Ok(())
+}
+
+/// Handles the getaddr message by answering with the most recently seen addresses of the address book.
+pub fn handle_get_addr_message<T: Read + Write>(
+    stream: &mut T,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    let addresses = safe_node_info
+        .lock_address_book()?
+        .get_addresses(MAX_ADDR_ENTRIES);
+    AddrMessage::new(addresses)
+        .send_to(stream)
+        .map_err(NodeError::ErrorMessage)
+}
+
+///Handles the tx message by saving the transaction in pending, unless its absolute or relative
+/// locks do not allow it to be included in the next block, in which case it is ignored.
 pub fn handle_tx_message(
     tx_msg: TxMessage,
-    safe_pending_tx: &SafePendingTx,
+    safe_node_info: &NodeSharedInformation,
+    logger: &Logger,
 ) -> Result<(), NodeError> {
     let tx = tx_msg.tx;
-    let mut pending_tx = safe_pending_tx
-        .lock()
-        .map_err(|_| NodeError::ErrorSharingReference)?;
+    if !is_final_for_next_block(&tx, safe_node_info)? {
+        logger.log(String::from(
+            "Se ignoro una transaccion que todavia no es final",
+        ));
+        return Ok(());
+    }
+    let mut pending_tx = safe_node_info.lock_safe_pending_tx()?;
     pending_tx.insert(tx.hash(), tx);
     Ok(())
 }
@@ -206,8 +321,8 @@ pub fn handle_tx_message(
 /// -The stopping_hash is found
 /// -The end of the blockchain is reached
 /// -The len of the vector reaches MAX_QUANTITY_FOR_GET_HEADERS
-pub fn handle_get_headers_message(
-    stream: &mut TcpStream,
+pub fn handle_get_headers_message<T: Read + Write>(
+    stream: &mut T,
     get_headers_msg: GetBlockHeadersMessage,
     safe_block_headers: &SafeVecHeader,
     safe_headers_index: &SafeHeaderIndex,
@@ -249,7 +364,7 @@ fn get_headers_to_send(
 ) -> Result<Vec<BlockHeader>, NodeError> {
     let mut headers_to_send: Vec<BlockHeader> = Vec::new();
 
-    match safe_block_headers.lock() {
+    match safe_block_headers.read() {
         Ok(headers) => {
             let header_iter = headers.iter().skip(starting_header_position);
 
@@ -273,7 +388,7 @@ fn get_starting_header_position(
     get_headers_msg: &GetBlockHeadersMessage,
     safe_headers_index: &SafeHeaderIndex,
 ) -> Result<Option<usize>, NodeError> {
-    match safe_headers_index.lock() {
+    match safe_headers_index.read() {
         Ok(header_index) => {
             for header_hash in &get_headers_msg.block_header_hashes {
                 if let Some(starting_header_position) = header_index.get(header_hash) {
@@ -291,19 +406,21 @@ fn get_starting_header_position(
 
 /// Handles get data message. If it receives block hashes it looks for them in the block chain and responds
 /// with Block messages, each block hash not found in the blocks is then returned through a NotFoundMessage
-pub fn handle_get_data(
-    stream: &mut TcpStream,
+pub fn handle_get_data<T: Read + Write>(
+    stream: &mut T,
     get_data_msg: GetDataMessage,
     safe_blockchain: &SafeBlockChain,
     safe_pending: &SafePendingTx,
 ) -> Result<(), NodeError> {
     let block_hashes = get_data_msg.get_block_hashes();
+    let compact_block_hashes = get_data_msg.get_compact_block_hashes();
     let tx_hashes = get_data_msg.get_block_hashes();
 
     let mut block_messages = Vec::new();
+    let mut compact_block_messages = Vec::new();
     let mut not_found_blocks = Vec::new();
     match safe_blockchain.lock() {
-        Ok(blockchain) => {
+        Ok(mut blockchain) => {
             for hash in block_hashes {
                 match blockchain.get(&hash) {
                     Some(block) => {
@@ -316,6 +433,15 @@ pub fn handle_get_data(
                     }
                 };
             }
+            for hash in compact_block_hashes {
+                match blockchain.get(&hash) {
+                    Some(block) => compact_block_messages.push(
+                        CmpctBlockMessage::from_block(block, rand::random())
+                            .map_err(NodeError::ErrorMessage)?,
+                    ),
+                    None => not_found_blocks.push(hash),
+                };
+            }
         }
         Err(_) => return Err(NodeError::ErrorSharingReference),
     }
@@ -338,6 +464,10 @@ pub fn handle_get_data(
         message.send_to(stream).map_err(NodeError::ErrorMessage)?;
     }
 
+    for message in compact_block_messages {
+        message.send_to(stream).map_err(NodeError::ErrorMessage)?;
+    }
+
     for message in tx_messages {
         message.send_to(stream).map_err(NodeError::ErrorMessage)?;
     }
@@ -350,11 +480,242 @@ pub fn handle_get_data(
     Ok(())
 }
 
+/// Handles a getblocktxn message by sending the requested transactions of the block through a blocktxn
+/// message. Returns an error if the block is not in the blockchain or any of the indexes is out of range.
+pub fn handle_get_block_txn<T: Read + Write>(
+    stream: &mut T,
+    get_block_txn_msg: GetBlockTxnMessage,
+    safe_blockchain: &SafeBlockChain,
+) -> Result<(), NodeError> {
+    let mut transactions = Vec::new();
+    {
+        let mut blockchain = safe_blockchain
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)?;
+        let block =
+            blockchain
+                .get(&get_block_txn_msg.block_hash)
+                .ok_or(NodeError::ErrorMessage(
+                    MessageError::ErrorCreatingBlockTxnMessage,
+                ))?;
+        let block_transactions = block.get_transactions();
+        for index in get_block_txn_msg.indexes {
+            let tx = block_transactions
+                .get(index)
+                .ok_or(NodeError::ErrorMessage(
+                    MessageError::ErrorCreatingBlockTxnMessage,
+                ))?;
+            transactions.push(Transaction::from_bytes(&tx.to_bytes()).map_err(|_| {
+                NodeError::ErrorMessage(MessageError::ErrorCreatingBlockTxnMessage)
+            })?);
+        }
+    }
+    BlockTxnMessage::new(get_block_txn_msg.block_hash, transactions)
+        .send_to(stream)
+        .map_err(NodeError::ErrorMessage)
+}
+
+/// Handles a getcfilters message by sending a cfilter message with the basic filter of each of the blocks
+/// asked for. The request is ignored if it is not valid, and answered with a notfound message for the stop
+/// hash if the node does not serve compact filters or any of the filters is not known.
+pub fn handle_get_cfilters<T: Read + Write>(
+    stream: &mut T,
+    get_cfilters_msg: GetCFiltersMessage,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    if !safe_node_info.compact_filters {
+        return send_filters_not_found(stream, get_cfilters_msg.stop_hash);
+    }
+    if get_cfilters_msg.filter_type != BASIC_FILTER_TYPE {
+        return Ok(());
+    }
+    let block_hashes = match block_hashes_in_range(
+        get_cfilters_msg.start_height,
+        &get_cfilters_msg.stop_hash,
+        MAX_CFILTERS_REQUEST,
+        safe_node_info,
+    )? {
+        Some(block_hashes) => block_hashes,
+        None => return Ok(()),
+    };
+    let mut cfilter_messages = Vec::new();
+    {
+        let block_filter_index = safe_node_info.lock_block_filter_index()?;
+        for block_hash in block_hashes {
+            let filter = match block_filter_index.get_filter(&block_hash) {
+                Some(filter) => filter.to_bytes(),
+                None => return send_filters_not_found(stream, get_cfilters_msg.stop_hash),
+            };
+            cfilter_messages.push(CFilterMessage {
+                filter_type: BASIC_FILTER_TYPE,
+                block_hash,
+                filter,
+            });
+        }
+    }
+    for cfilter_msg in cfilter_messages {
+        cfilter_msg
+            .send_to(stream)
+            .map_err(NodeError::ErrorMessage)?;
+    }
+    Ok(())
+}
+
+/// Handles a getcfheaders message by sending a cfheaders message with the hashes of the basic filters of
+/// the blocks asked for. The request is ignored if it is not valid, and answered with a notfound message
+/// for the stop hash if the node does not serve compact filters or any of the filters is not known.
+pub fn handle_get_cfheaders<T: Read + Write>(
+    stream: &mut T,
+    get_cfheaders_msg: GetCFHeadersMessage,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    if !safe_node_info.compact_filters {
+        return send_filters_not_found(stream, get_cfheaders_msg.stop_hash);
+    }
+    if get_cfheaders_msg.filter_type != BASIC_FILTER_TYPE {
+        return Ok(());
+    }
+    let block_hashes = match block_hashes_in_range(
+        get_cfheaders_msg.start_height,
+        &get_cfheaders_msg.stop_hash,
+        MAX_CFHEADERS_REQUEST,
+        safe_node_info,
+    )? {
+        Some(block_hashes) => block_hashes,
+        None => return Ok(()),
+    };
+    let first_header = match block_hashes.first() {
+        Some(first_hash) => {
+            let headers_index = safe_node_info.read_headers_index()?;
+            let block_headers = safe_node_info.read_block_headers()?;
+            headers_index
+                .get(first_hash)
+                .and_then(|position| block_headers.get(*position))
+                .cloned()
+        }
+        None => None,
+    };
+    let cfheaders_msg = {
+        let block_filter_index = safe_node_info.lock_block_filter_index()?;
+        let previous_filter_header = if block_hashes.first() == Some(&HASHEDGENESISBLOCK) {
+            Some([0; 32])
+        } else {
+            first_header.and_then(|header| block_filter_index.previous_filter_header(&header))
+        };
+        let previous_filter_header = match previous_filter_header {
+            Some(previous_filter_header) => previous_filter_header,
+            None => return send_filters_not_found(stream, get_cfheaders_msg.stop_hash),
+        };
+        let mut filter_hashes = Vec::new();
+        for block_hash in &block_hashes {
+            match block_filter_index.get_filter(block_hash) {
+                Some(filter) => filter_hashes.push(filter.hash()),
+                None => return send_filters_not_found(stream, get_cfheaders_msg.stop_hash),
+            }
+        }
+        CFHeadersMessage {
+            filter_type: BASIC_FILTER_TYPE,
+            stop_hash: get_cfheaders_msg.stop_hash,
+            previous_filter_header,
+            filter_hashes,
+        }
+    };
+    cfheaders_msg
+        .send_to(stream)
+        .map_err(NodeError::ErrorMessage)
+}
+
+/// Handles a getcfcheckpt message by sending a cfcheckpt message with the basic filter headers every
+/// CFCHECKPT_INTERVAL blocks up to the stop hash. The request is answered with a notfound message for
+/// the stop hash if the node does not serve compact filters or any of the filter headers is not known.
+pub fn handle_get_cfcheckpt<T: Read + Write>(
+    stream: &mut T,
+    get_cfcheckpt_msg: GetCFCheckptMessage,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    if !safe_node_info.compact_filters {
+        return send_filters_not_found(stream, get_cfcheckpt_msg.stop_hash);
+    }
+    if get_cfcheckpt_msg.filter_type != BASIC_FILTER_TYPE {
+        return Ok(());
+    }
+    let checkpoint_hashes: Vec<[u8; 32]> = {
+        let headers_index = safe_node_info.read_headers_index()?;
+        let block_headers = safe_node_info.read_block_headers()?;
+        let stop_height = match headers_index.get(&get_cfcheckpt_msg.stop_hash) {
+            Some(position) => position + 1,
+            None => return Ok(()),
+        };
+        (CFCHECKPT_INTERVAL..=stop_height)
+            .step_by(CFCHECKPT_INTERVAL)
+            .filter_map(|height| block_headers.get(height - 1))
+            .map(|header| header.hash())
+            .collect()
+    };
+    let mut filter_headers = Vec::new();
+    {
+        let block_filter_index = safe_node_info.lock_block_filter_index()?;
+        for block_hash in checkpoint_hashes {
+            match block_filter_index.get_filter_header(&block_hash) {
+                Some(filter_header) => filter_headers.push(filter_header),
+                None => return send_filters_not_found(stream, get_cfcheckpt_msg.stop_hash),
+            }
+        }
+    }
+    CFCheckptMessage {
+        filter_type: BASIC_FILTER_TYPE,
+        stop_hash: get_cfcheckpt_msg.stop_hash,
+        filter_headers,
+    }
+    .send_to(stream)
+    .map_err(NodeError::ErrorMessage)
+}
+
+/// Sends a notfound message with the stop hash of a compact filters request that can not be answered.
+fn send_filters_not_found<T: Read + Write>(
+    stream: &mut T,
+    stop_hash: [u8; 32],
+) -> Result<(), NodeError> {
+    NotFoundMessage::from_block_hashes(vec![stop_hash])
+        .send_to(stream)
+        .map_err(|_| NodeError::ErrorMessage(MessageError::ErrorSendingNotFoundMessage))
+}
+
+/// Returns the hashes of the blocks from the start height up to the block with the stop hash, or None if
+/// the stop hash is not known, the start height is after it or there are more than max_amount blocks.
+/// The height of the genesis block is 0, so the first stored header has height 1.
+fn block_hashes_in_range(
+    start_height: u32,
+    stop_hash: &[u8; 32],
+    max_amount: u32,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Option<Vec<[u8; 32]>>, NodeError> {
+    let headers_index = safe_node_info.read_headers_index()?;
+    let block_headers = safe_node_info.read_block_headers()?;
+    let stop_height = match headers_index.get(stop_hash) {
+        Some(position) => position + 1,
+        None if *stop_hash == HASHEDGENESISBLOCK => 0,
+        None => return Ok(None),
+    };
+    let start_height = start_height as usize;
+    if start_height > stop_height || stop_height - start_height >= max_amount as usize {
+        return Ok(None);
+    }
+    Ok(Some(
+        (start_height..=stop_height)
+            .filter_map(|height| match height {
+                0 => Some(HASHEDGENESISBLOCK),
+                _ => block_headers.get(height - 1).map(|header| header.hash()),
+            })
+            .collect(),
+    ))
+}
+
 /// Sends a getdata message to the stream, requesting the blocks with the specified hashes.
 /// Returns an error if it was not possible to send the message.
-fn send_get_data_message_for_transactions(
+fn send_get_data_message_for_transactions<T: Read + Write>(
     hashes: Vec<[u8; 32]>,
-    stream: &mut TcpStream,
+    stream: &mut T,
 ) -> Result<(), NodeError> {
     let get_data_message = GetDataMessage::create_message_inventory_transaction_type(hashes);
 
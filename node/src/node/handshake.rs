@@ -1,48 +1,116 @@
-use std::time::Duration;
+use std::{net::TcpStream, time::Duration};
 
-use crate::node::*;
+use crate::{
+    messages::version_message::{NODE_COMPACT_FILTERS, NODE_P2P_V2},
+    node::{v2_transport::*, *},
+};
 
 pub const PEER_TIMEOUT: Duration = Duration::from_secs(15);
+const SKIPPED_HANDSHAKE_COMMANDS: [&str; 2] = ["sendaddrv2\0\0", "wtxidrelay\0\0"];
 
 /// Attemps to start and do the peer conection protocol acording to the bitcoin network. Sends a VersionMessage,
 /// and it receives a VersionMessage and a VerAckMessage, not in any order on particular.
-/// If everything works well returns a tcpstream, which lets us communicate with the peer._err
+/// If v2_transport is set, the connection is first attempted over the v2 transport (BIP324), falling back to
+/// the v1 transport if the peer does not support it. If pruned is set, only the recent blocks are announced,
+/// and if compact_filters is set, the compact filters are announced as well.
+/// If everything works well returns a stream, which lets us communicate with the peer, alongside
+/// what the peer announced in its VersionMessage.
 pub fn outgoing_handshake(
     version: i32,
     peer_address: SocketAddr,
     node_address: SocketAddr,
+    v2_transport: bool,
+    pruned: bool,
+    compact_filters: bool,
     logger: &Logger,
-) -> Result<TcpStream, NodeError> {
-    let mut tcp_stream = connect_to_peer(peer_address)?;
-
-    handshake_send_version_message(version, peer_address, node_address, &mut tcp_stream)?;
-
-    let first_msg_name = handshake_receive_verack_or_version_message(&mut tcp_stream, logger)?;
-    let second_msg_name = handshake_receive_verack_or_version_message(&mut tcp_stream, logger)?;
-
-    if first_msg_name == second_msg_name {
-        return Err(NodeError::ErrorReceivingMessageInHandshake);
-    }
+) -> Result<(PeerStream, PeerVersion), NodeError> {
+    let mut stream = connect_with_transport(peer_address, v2_transport, logger)?;
+
+    handshake_send_version_message(
+        version,
+        peer_address,
+        node_address,
+        v2_transport,
+        pruned,
+        compact_filters,
+        &mut stream,
+    )?;
+
+    let first_msg = handshake_receive_verack_or_version_message(&mut stream, logger)?;
+    let second_msg = handshake_receive_verack_or_version_message(&mut stream, logger)?;
+
+    let version_message = match (first_msg, second_msg) {
+        (Some(version_message), None) | (None, Some(version_message)) => version_message,
+        _ => return Err(NodeError::ErrorReceivingMessageInHandshake),
+    };
 
-    handshake_send_verack_message(&mut tcp_stream)?;
+    handshake_send_sendaddrv2_message(&mut stream)?;
+    handshake_send_verack_message(&mut stream)?;
 
-    Ok(tcp_stream)
+    Ok((stream, PeerVersion::from(&version_message)))
 }
 
 /// Attemps to do the peer conection protocol acording to the bitcoin network with a requesting peer. Sends a VersionMessage,
 /// and it receives a VersionMessage and a VerAckMessage, not in any order on particular.
-/// If everything works well returns a tcpstream, which lets us communicate with the peer._err
+/// If v2_transport is set, peers that do not start with a v1 VersionMessage are answered over the v2 transport (BIP324).
+/// If pruned is set, only the recent blocks are announced, and if compact_filters is set, the compact filters are
+/// announced as well.
+/// If everything works well returns the stream alongside what the peer announced in its VersionMessage.
+#[allow(clippy::too_many_arguments)]
 pub fn incoming_handshake(
     version: i32,
     peer_address: SocketAddr,
     node_address: SocketAddr,
-    new_peer_conection: &mut TcpStream,
+    new_peer_conection: TcpStream,
+    v2_transport: bool,
+    pruned: bool,
+    compact_filters: bool,
     logger: &Logger,
-) -> Result<(), NodeError> {
-    handshake_receive_version_message(new_peer_conection, logger)?;
-    handshake_send_version_message(version, peer_address, node_address, new_peer_conection)?;
-    handshake_send_verack_message(new_peer_conection)?;
-    handshake_receive_verack_message(new_peer_conection, logger)
+) -> Result<(PeerStream, PeerVersion), NodeError> {
+    new_peer_conection
+        .set_read_timeout(Some(PEER_TIMEOUT))
+        .map_err(|_| NodeError::ErrorReceivingMessageInHandshake)?;
+    let mut stream = if v2_transport && !starts_with_v1_version_message(&new_peer_conection)? {
+        respond_v2_transport(new_peer_conection)?
+    } else {
+        PeerStream::v1(new_peer_conection)
+    };
+
+    let version_message = handshake_receive_version_message(&mut stream, logger)?;
+    handshake_send_version_message(
+        version,
+        peer_address,
+        node_address,
+        v2_transport,
+        pruned,
+        compact_filters,
+        &mut stream,
+    )?;
+    handshake_send_verack_message(&mut stream)?;
+    handshake_receive_verack_message(&mut stream, logger)?;
+    Ok((stream, PeerVersion::from(&version_message)))
+}
+
+/// Connects to the peer over the v2 transport if it is enabled and the peer supports it, otherwise over the v1 transport.
+fn connect_with_transport(
+    peer_address: SocketAddr,
+    v2_transport: bool,
+    logger: &Logger,
+) -> Result<PeerStream, NodeError> {
+    if v2_transport {
+        match initiate_v2_transport(connect_to_peer(peer_address)?) {
+            Ok(stream) => {
+                logger.log(format!(
+                    "Connected to peer {peer_address} over the v2 transport"
+                ));
+                return Ok(stream);
+            }
+            Err(_) => logger.log(format!(
+                "Peer {peer_address} does not support the v2 transport, falling back to v1"
+            )),
+        }
+    }
+    Ok(PeerStream::v1(connect_to_peer(peer_address)?))
 }
 
 /// Returns a tcp stream representing the conection with the peer, if this fails returns ErrorConnectingToPeer
@@ -61,17 +129,32 @@ fn connect_to_peer(receiving_addrs: SocketAddr) -> Result<TcpStream, NodeError>
     }
 }
 
-/// Sends the version message as bytes to the stream according to bitcoin protocol. On error returns ErrorSendingMessageInHandshake
+/// Sends the version message as bytes to the stream according to bitcoin protocol. The NODE_P2P_V2 service is
+/// announced if the v2 transport is enabled, and NODE_NETWORK_LIMITED instead of NODE_NETWORK if the node is
+/// pruned. NODE_COMPACT_FILTERS is announced if the node serves the compact filters of the whole chain.
+/// On error returns ErrorSendingMessageInHandshake
 fn handshake_send_version_message<T: Read + Write>(
     version: i32,
     receiving_addrs: SocketAddr,
     sending_address: SocketAddr,
+    v2_transport: bool,
+    pruned: bool,
+    compact_filters: bool,
     stream: &mut T,
 ) -> Result<(), NodeError> {
-    let vm = match VersionMessage::new(version, receiving_addrs, sending_address) {
+    let mut vm = match VersionMessage::new(version, receiving_addrs, sending_address) {
         Ok(version_message) => version_message,
         Err(_) => return Err(NodeError::ErrorSendingMessageInHandshake),
     };
+    if v2_transport {
+        vm.add_services(NODE_P2P_V2);
+    }
+    if pruned {
+        vm.limit_to_recent_blocks();
+    }
+    if compact_filters {
+        vm.add_services(NODE_COMPACT_FILTERS);
+    }
 
     match vm.send_to(stream) {
         Ok(_) => Ok(()),
@@ -92,30 +175,46 @@ fn handshake_send_verack_message<T: Read + Write>(stream: &mut T) -> Result<(),
     }
 }
 
-/// Receives a message, if it is any other than VersionMessage or VerackMessage it returns ErrorReceivingMessageInHandshake
+/// Sends the sendaddrv2 message to the stream, to signal that addrv2 messages are preferred (BIP155).
+/// On error returns ErrorSendingMessageInHandshake
+fn handshake_send_sendaddrv2_message<T: Read + Write>(stream: &mut T) -> Result<(), NodeError> {
+    match SendAddrV2Message::new().send_to(stream) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(NodeError::ErrorSendingMessageInHandshake),
+    }
+}
+
+/// Receives a message, if it is any other than VersionMessage or VerackMessage it returns ErrorReceivingMessageInHandshake.
+/// Returns the VersionMessage, or None if a VerackMessage was received.
+/// SendAddrV2 and WtxidRelay messages, which peers may send before their verack, are skipped.
 fn handshake_receive_verack_or_version_message<T: Read + Write>(
     stream: &mut T,
     logger: &Logger,
-) -> Result<String, NodeError> {
-    let hm = receive_message_header(stream)?;
-
-    let mut received_vm_bytes = vec![0; hm.get_payload_size() as usize];
-    match stream.read_exact(&mut received_vm_bytes) {
-        Ok(_) => {}
-        Err(_) => return Err(NodeError::ErrorReceivingMessageInHandshake),
+) -> Result<Option<VersionMessage>, NodeError> {
+    let (hm, received_vm_bytes) = loop {
+        let hm = receive_message_header(stream)?;
+
+        let mut received_vm_bytes = vec![0; hm.get_payload_size() as usize];
+        match stream.read_exact(&mut received_vm_bytes) {
+            Ok(_) => {}
+            Err(_) => return Err(NodeError::ErrorReceivingMessageInHandshake),
+        };
+
+        logger.log(format!("Received message: {}", hm.get_command_name()));
+        if !SKIPPED_HANDSHAKE_COMMANDS.contains(&hm.get_command_name().as_str()) {
+            break (hm, received_vm_bytes);
+        }
     };
-
-    logger.log(format!("Received message: {}", hm.get_command_name()));
     let cmd_name = hm.get_command_name();
 
     match cmd_name.as_str() {
         "version\0\0\0\0\0" => match VersionMessage::from_bytes(&received_vm_bytes) {
-            Ok(_) => Ok(cmd_name),
+            Ok(version_message) => Ok(Some(version_message)),
             Err(_) => Err(NodeError::ErrorReceivingMessageInHandshake),
         },
         "verack\0\0\0\0\0\0" => {
             if hm.get_payload_size() == 0 {
-                Ok(cmd_name)
+                Ok(None)
             } else {
                 Err(NodeError::ErrorReceivingMessageInHandshake)
             }
@@ -126,25 +225,23 @@ fn handshake_receive_verack_or_version_message<T: Read + Write>(
 
 /// Receives a message, if it is any other than VersionMessage it returns ErrorReceivingMessageInHandshake
 fn handshake_receive_version_message(
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     logger: &Logger,
-) -> Result<(), NodeError> {
-    let msg_received = handshake_receive_verack_or_version_message(stream, logger)?;
-    match msg_received.as_str() {
-        "version\0\0\0\0\0" => Ok(()),
-        _ => Err(NodeError::ErrorReceivingMessageInHandshake),
+) -> Result<VersionMessage, NodeError> {
+    match handshake_receive_verack_or_version_message(stream, logger)? {
+        Some(version_message) => Ok(version_message),
+        None => Err(NodeError::ErrorReceivingMessageInHandshake),
     }
 }
 
 /// Receives a message, if it is any other than VerackMessage it returns ErrorReceivingMessageInHandshake
 fn handshake_receive_verack_message(
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     logger: &Logger,
 ) -> Result<(), NodeError> {
-    let msg_received = handshake_receive_verack_or_version_message(stream, logger)?;
-    match msg_received.as_str() {
-        "verack\0\0\0\0\0\0" => Ok(()),
-        _ => Err(NodeError::ErrorReceivingMessageInHandshake),
+    match handshake_receive_verack_or_version_message(stream, logger)? {
+        Some(_) => Err(NodeError::ErrorReceivingMessageInHandshake),
+        None => Ok(()),
     }
 }
 
@@ -158,7 +255,6 @@ mod tests {
     const LOCAL_ADDRESS: ([u8; 4], u16) = ([127, 0, 0, 1], 1001);
     const STARTING_BLOCK_TIME: u32 = 1681084800;
     const HEADERS_FILE_PATH: &str = "data/headers.bin";
-    const BLOCKS_FILE_PATH: &str = "data/blocks.bin";
 
     // Auxiliar functions
     //=================================================================
@@ -166,7 +262,7 @@ mod tests {
     fn initiate(log_file_path: &str) -> (MockTcpStream, Node) {
         let stream = MockTcpStream::new();
         let logger = Logger::from_path(log_file_path).unwrap();
-        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH, BLOCKS_FILE_PATH).unwrap();
+        let data_handler = NodeDataHandler::new(HEADERS_FILE_PATH).unwrap();
         let (sx, _rx) = glib::MainContext::channel::<UIResponse>(glib::PRIORITY_DEFAULT);
         let node = Node::_new(
             VERSION,
@@ -190,7 +286,15 @@ mod tests {
         let receiver_socket = SocketAddr::from(([127, 0, 0, 2], 8080));
         let expected_vm = VersionMessage::new(node.version, receiver_socket, node.address).unwrap();
 
-        handshake_send_version_message(node.version, receiver_socket, node.address, &mut stream)?;
+        handshake_send_version_message(
+            node.version,
+            receiver_socket,
+            node.address,
+            false,
+            false,
+            false,
+            &mut stream,
+        )?;
         let write_buffer_len = stream.write_buffer.len();
 
         //reemplaza el valor erroneo de checksum por el esperado debido al diferente random
@@ -232,7 +336,7 @@ mod tests {
 
         let received_mg = handshake_receive_verack_or_version_message(&mut stream, &node.logger)?;
 
-        assert_eq!(received_mg, "version\0\0\0\0\0");
+        assert_eq!(received_mg, Some(expected_vm));
         Ok(())
     }
 
@@ -259,7 +363,21 @@ mod tests {
         stream.read_buffer = verack_hm.to_bytes();
 
         let received_msg = handshake_receive_verack_or_version_message(&mut stream, &node.logger)?;
-        assert_eq!(received_msg, "verack\0\0\0\0\0\0");
+        assert_eq!(received_msg, None);
+        Ok(())
+    }
+
+    #[test]
+    fn handshake_test_5_sendaddrv2_message_is_skipped() -> Result<(), NodeError> {
+        let (mut stream, node) = initiate("tests_txt/handshake_test_5_log.txt");
+
+        let sendaddrv2_hm = SendAddrV2Message::new().get_header_message().unwrap();
+        let verack_hm = VerACKMessage::new().unwrap().get_header_message().unwrap();
+        stream.read_buffer = sendaddrv2_hm.to_bytes();
+        stream.read_buffer.extend(verack_hm.to_bytes());
+
+        let received_msg = handshake_receive_verack_or_version_message(&mut stream, &node.logger)?;
+        assert_eq!(received_msg, None);
         Ok(())
     }
 }
@@ -0,0 +1,253 @@
+use crate::node::*;
+use std::{
+    sync::mpsc,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+pub const HEADER_SYNC_CANDIDATES: usize = 4;
+
+/// Headers a peer answered with while choosing the peer to sync headers from.
+#[derive(Debug)]
+pub struct HeaderSyncCandidate {
+    pub peer_index: usize,
+    pub work: u128,
+    pub headers: Vec<BlockHeader>,
+}
+
+/// Checks that every header has a valid proof of work and follows the previous one, the first one
+/// following the header with prev_hash. If prev_hash is None the first header starts the chain.
+/// Returns the work the headers add to the chain.
+pub fn validate_headers(
+    prev_hash: Option<[u8; 32]>,
+    headers: &[BlockHeader],
+) -> Result<u128, NodeError> {
+    let mut prev_hash = prev_hash;
+    let mut work: u128 = 0;
+    for header in headers {
+        if !validate_proof_of_work(header) {
+            return Err(NodeError::ErrorInvalidProofOfWork);
+        }
+        if let Some(prev_hash) = prev_hash {
+            if header.prev_hash != prev_hash {
+                return Err(NodeError::ErrorDisconectedFromBlockchain);
+            }
+        }
+        work = work.saturating_add(header_work(header));
+        prev_hash = Some(header.hash());
+    }
+    Ok(work)
+}
+
+/// Returns the candidate whose headers add the most work to the chain. On a tie the first one wins.
+pub fn select_most_work(candidates: Vec<HeaderSyncCandidate>) -> Option<HeaderSyncCandidate> {
+    let mut best_candidate: Option<HeaderSyncCandidate> = None;
+    for candidate in candidates {
+        match &best_candidate {
+            Some(best) if best.work >= candidate.work => {}
+            _ => best_candidate = Some(candidate),
+        }
+    }
+    best_candidate
+}
+
+/// Receives messages from the peer until it sends a headers message, handling the other ones as
+/// usual, and returns its headers. Returns ErrorReceivingHeadersMessageInIBD if the headers do not
+/// arrive within the timeout.
+pub fn receive_headers(
+    stream: &mut PeerStream,
+    safe_node_info: &NodeSharedInformation,
+    timeout: Duration,
+    logger: &Logger,
+) -> Result<Vec<BlockHeader>, NodeError> {
+    let start_time = Instant::now();
+    while start_time.elapsed() < timeout {
+        let (message, _) = receive_message(stream, logger)?;
+        match message {
+            Message::BlockHeaders(headers_message) => return Ok(headers_message.headers),
+            message => handle_message(message, stream, safe_node_info, logger, true)?,
+        }
+    }
+    Err(NodeError::ErrorReceivingHeadersMessageInIBD)
+}
+
+/// Thread that validates the batches of headers received during the header sync and adds them to
+/// the chain, so the next batch can be requested while the previous one is being validated. It
+/// stops at the first invalid batch, discarding it and the ones after it.
+#[derive(Debug)]
+pub struct HeaderValidator {
+    thread: JoinHandle<Result<(), NodeError>>,
+    batch_sender: mpsc::Sender<Vec<BlockHeader>>,
+}
+
+impl HeaderValidator {
+    /// Starts the thread. The first batch must follow the header with prev_hash, or start the
+    /// chain if it is None.
+    pub fn new(
+        prev_hash: Option<[u8; 32]>,
+        safe_block_headers: SafeVecHeader,
+        safe_headers_index: SafeHeaderIndex,
+        logger: Logger,
+    ) -> HeaderValidator {
+        let (batch_sender, batch_receiver) = mpsc::channel::<Vec<BlockHeader>>();
+        let thread = thread::spawn(move || {
+            let mut prev_hash = prev_hash;
+            for batch in batch_receiver {
+                if let Err(error) = validate_headers(prev_hash, &batch) {
+                    logger.log(format!("Received invalid headers: {:?}", error));
+                    return Err(error);
+                }
+                if let Some(last_header) = batch.last() {
+                    prev_hash = Some(last_header.hash());
+                }
+                insert_new_headers(batch, &safe_block_headers, &safe_headers_index)?;
+            }
+            Ok(())
+        });
+
+        HeaderValidator {
+            thread,
+            batch_sender,
+        }
+    }
+
+    /// Queues the batch to be validated. Returns ErrorInvalidHeaders if the thread already stopped
+    /// because of an invalid batch.
+    pub fn validate(&self, batch: Vec<BlockHeader>) -> Result<(), NodeError> {
+        self.batch_sender
+            .send(batch)
+            .map_err(|_| NodeError::ErrorInvalidHeaders)
+    }
+
+    /// Waits for the queued batches to be validated, returning the error of the first invalid one.
+    pub fn finish(self) -> Result<(), NodeError> {
+        drop(self.batch_sender);
+        self.thread
+            .join()
+            .map_err(|_| NodeError::ErrorJoiningThread)?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::initial_block_download::HASHEDGENESISBLOCK;
+
+    const EASIEST_N_BITS: u32 = 0xffff7f20;
+    const HEADER_SIZE: usize = 80;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn mine_header(prev_hash: [u8; 32], time: u32) -> BlockHeader {
+        let mut header = BlockHeader::new(70015, prev_hash, [0u8; 32], EASIEST_N_BITS);
+        header.time = time;
+        while !validate_proof_of_work(&header) {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    fn mine_headers(prev_hash: [u8; 32], amount: u32) -> Vec<BlockHeader> {
+        let mut headers: Vec<BlockHeader> = Vec::new();
+        for time in 0..amount {
+            let prev_hash = match headers.last() {
+                Some(header) => header.hash(),
+                None => prev_hash,
+            };
+            headers.push(mine_header(prev_hash, time));
+        }
+        headers
+    }
+
+    fn candidate(peer_index: usize, work: u128) -> HeaderSyncCandidate {
+        HeaderSyncCandidate {
+            peer_index,
+            work,
+            headers: Vec::new(),
+        }
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn header_sync_test_1_linked_headers_add_their_work() -> Result<(), NodeError> {
+        let headers = mine_headers([1; 32], 5);
+
+        assert_eq!(validate_headers(Some([1; 32]), &headers)?, 10);
+        assert_eq!(validate_headers(None, &headers[2..])?, 6);
+        assert_eq!(validate_headers(Some([1; 32]), &[])?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn header_sync_test_2_disconnected_or_unmined_headers_are_rejected() {
+        let mut headers = mine_headers([1; 32], 3);
+
+        assert!(matches!(
+            validate_headers(Some([2; 32]), &headers),
+            Err(NodeError::ErrorDisconectedFromBlockchain)
+        ));
+
+        headers[1] = BlockHeader::new(70015, headers[0].hash(), [0u8; 32], 0x01000003);
+        assert!(matches!(
+            validate_headers(Some([1; 32]), &headers),
+            Err(NodeError::ErrorInvalidProofOfWork)
+        ));
+    }
+
+    #[test]
+    fn header_sync_test_3_the_candidate_with_most_work_is_selected() {
+        let candidates = vec![candidate(3, 10), candidate(4, 30), candidate(5, 30)];
+
+        let best_candidate = select_most_work(candidates).unwrap();
+
+        assert_eq!(best_candidate.peer_index, 4);
+        assert!(select_most_work(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn header_sync_test_4_validator_stops_at_the_first_invalid_batch() {
+        let logger = Logger::from_path("tests_txt/header_sync_test_4_log.txt").unwrap();
+        let safe_block_headers = Arc::new(RwLock::new(Vec::new()));
+        let safe_headers_index = Arc::new(RwLock::new(HashMap::new()));
+        let first_batch = mine_headers([1; 32], 3);
+        let second_batch = mine_headers(first_batch[2].hash(), 2);
+        let unlinked_batch = mine_headers([7; 32], 2);
+
+        let validator = HeaderValidator::new(
+            Some([1; 32]),
+            safe_block_headers.clone(),
+            safe_headers_index.clone(),
+            logger,
+        );
+        validator.validate(first_batch).unwrap();
+        validator.validate(second_batch).unwrap();
+        validator.validate(unlinked_batch).unwrap();
+
+        assert!(matches!(
+            validator.finish(),
+            Err(NodeError::ErrorDisconectedFromBlockchain)
+        ));
+        assert_eq!(safe_block_headers.read().unwrap().len(), 5);
+        assert_eq!(safe_headers_index.read().unwrap().len(), 5);
+    }
+
+    #[test]
+    fn header_sync_test_5_the_first_headers_must_follow_the_genesis_block() -> Result<(), NodeError>
+    {
+        let bytes = std::fs::read("tests_txt/ibd_test_headers.bin").unwrap();
+        let first_headers: Vec<BlockHeader> = bytes[..HEADER_SIZE * 3]
+            .chunks(HEADER_SIZE)
+            .map(|header_bytes| BlockHeader::from_bytes(header_bytes).unwrap())
+            .collect();
+
+        assert!(validate_headers(Some(HASHEDGENESISBLOCK), &first_headers)? > 0);
+        assert!(matches!(
+            validate_headers(Some(HASHEDGENESISBLOCK), &mine_headers([1; 32], 3)),
+            Err(NodeError::ErrorDisconectedFromBlockchain)
+        ));
+        Ok(())
+    }
+}
@@ -1,6 +1,9 @@
 use crate::utils::ui_communication_protocol::UIResponse;
 use crate::{node::*, utils::LoadingScreenInfo};
 use block_downloader::*;
+use download_scheduler::{format_download_rates, SafeDownloadScheduler};
+use get_block_headers_message::MAX_QUANTITY_FOR_GET_HEADERS;
+use header_sync::*;
 use std::thread;
 use std::{
     thread::JoinHandle,
@@ -9,20 +12,49 @@ use std::{
 
 use super::peer_comunication::workers::FinishedIndicator;
 
+/// Hash of the testnet genesis block, in the byte order of the prev_hash of the headers.
 pub const HASHEDGENESISBLOCK: [u8; 32] = [
-    0x00, 0x00, 0x00, 0x00, 0x00, 0x19, 0xd6, 0x68, 0x9c, 0x08, 0x5a, 0xe1, 0x65, 0x83, 0x1e, 0x93,
-    0x4f, 0xf7, 0x63, 0xae, 0x46, 0xa2, 0xa6, 0xc1, 0x72, 0xb3, 0xf1, 0xb6, 0x0a, 0x8c, 0xe2, 0x6f,
+    0x43, 0x49, 0x7f, 0xd7, 0xf8, 0x26, 0x95, 0x71, 0x08, 0xf4, 0xa3, 0x0f, 0xd9, 0xce, 0xc3, 0xae,
+    0xba, 0x79, 0x97, 0x20, 0x84, 0xe9, 0x0e, 0xad, 0x01, 0xea, 0x33, 0x09, 0x00, 0x00, 0x00, 0x00,
 ];
-const MAX_BLOCK_BUNDLE: usize = 16;
+/// Timestamp of the testnet genesis block.
+pub const GENESIS_BLOCK_TIME: u32 = 1296688602;
 const MAXIMUM_PEER_TIME_OUT: u64 = 10;
 const REFRESH_BLOCK_DOWNLOAD_PROGRESS_FOR_UI: Duration = Duration::from_secs(1);
+const LOG_DOWNLOAD_RATES_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Sends the download rates of the peers to the UI, and logs them every LOG_DOWNLOAD_RATES_INTERVAL.
+fn report_download_rates(
+    scheduler: &SafeDownloadScheduler,
+    sender_to_ui: &GlibSender<UIResponse>,
+    last_log: &mut Instant,
+    logger: &Logger,
+) {
+    let rates = match scheduler.lock() {
+        Ok(scheduler) => scheduler.download_rates(Instant::now()),
+        Err(_) => return,
+    };
+    if rates.is_empty() {
+        return;
+    }
+    let rates_str = format!("Download rates: {}", format_download_rates(&rates));
+    if last_log.elapsed() >= LOG_DOWNLOAD_RATES_INTERVAL {
+        logger.log(rates_str.clone());
+        *last_log = Instant::now();
+    }
+    _ = sender_to_ui.send(UIResponse::LoadingScreenUpdate(
+        LoadingScreenInfo::UpdateLabel(rates_str),
+    ));
+}
 
 fn send_ibd_information_to_ui(
     sender_to_ui: GlibSender<UIResponse>,
-    blockchain: Arc<Mutex<HashMap<[u8; 32], Block>>>,
+    blockchain: SafeBlockChain,
+    scheduler: SafeDownloadScheduler,
     total_blocks: usize,
     starting_block_count: usize,
     finish: FinishedIndicator,
+    logger: Logger,
 ) -> Result<JoinHandle<()>, NodeError> {
     let message_to_ui = LoadingScreenInfo::StartedBlockDownload(total_blocks);
     let started_downloading = "Started downloading blocks";
@@ -35,6 +67,7 @@ fn send_ibd_information_to_ui(
         ))
         .expect("Error sending to UI thread");
     let sender_clone = sender_to_ui.clone();
+    let mut last_log = Instant::now();
 
     let join_handle = thread::spawn(move || loop {
         match finish.lock() {
@@ -58,6 +91,7 @@ fn send_ibd_information_to_ui(
                 .send(UIResponse::LoadingScreenUpdate(message_to_ui))
                 .expect("Error sending to UI thread");
         }
+        report_download_rates(&scheduler, &sender_clone, &mut last_log, &logger);
     });
     Ok(join_handle)
 }
@@ -74,15 +108,15 @@ impl Node {
 
     /// Creates and sends a GetBlockHeadersMessage to the stream, always asking for the maximum amount of headers. On error returns ErrorSendingMessageInIBD
     pub fn ibd_send_get_block_headers_message(
-        &self,
+        &mut self,
         last_hash: [u8; 32],
         sync_node_index: usize,
     ) -> Result<(), NodeError> {
         let get_block_headers_msg = self.create_get_block_header_message(last_hash);
 
-        let mut stream = &self.initial_peers[sync_node_index];
+        let stream = &mut self.initial_peers[sync_node_index];
 
-        match get_block_headers_msg.send_to(&mut stream) {
+        match get_block_headers_msg.send_to(stream) {
             Ok(_) => Ok(()),
             Err(_) => Err(NodeError::ErrorSendingMessageInIBD),
         }
@@ -105,81 +139,181 @@ impl Node {
         }
     }
 
-    /// Receives messages from a given peer till it receives a headersMessage or 30 seconds have passed
+    /// Receives messages from a given peer till it receives a headers message or peer_timeout seconds
+    /// have passed, and returns the received headers without adding them to the chain.
     fn receive_headers_message(
         &mut self,
         sync_node_index: usize,
         peer_timeout: u64,
-    ) -> Result<(), NodeError> {
-        let mut start_time = Instant::now();
-        let target_duration = Duration::from_secs(peer_timeout);
-        while self.receive_message(sync_node_index, true)? != "headers\0\0\0\0\0" {
-            if Instant::now() - start_time > target_duration {
-                self.logger.log(format!(
-                    "Peer {} timed_out switching peers",
-                    sync_node_index
-                ));
-                return Err(NodeError::ErrorReceivingHeadersMessageInIBD);
-            }
-            start_time = Instant::now();
-        }
-        if Instant::now() - start_time > target_duration {
+    ) -> Result<Vec<BlockHeader>, NodeError> {
+        let safe_node_info = self.get_safe_node_info();
+        let stream = &mut self.initial_peers[sync_node_index];
+        let headers = receive_headers(
+            stream,
+            &safe_node_info,
+            Duration::from_secs(peer_timeout),
+            &self.logger,
+        );
+        if headers.is_err() {
             self.logger.log(format!(
                 "Peer {} timed_out switching peers",
                 sync_node_index
             ));
-            return Err(NodeError::ErrorReceivingHeadersMessageInIBD);
         }
+        headers
+    }
+
+    /// Returns the hash of the last validated header, which the next headers must follow. If there
+    /// are no headers yet they must follow the genesis block.
+    fn last_validated_header_hash(&self) -> Result<[u8; 32], NodeError> {
+        match self.get_block_headers()?.last() {
+            Some(header) => Ok(header.hash()),
+            None => Ok(HASHEDGENESISBLOCK),
+        }
+    }
+
+    /// Asks up to HEADER_SYNC_CANDIDATES peers, starting from first_candidate, for the headers that
+    /// follow the last validated one, and returns the peer whose headers add the most work to the chain
+    /// along with them. Peers that do not answer in time or send invalid headers are skipped.
+    fn select_header_sync_peer(
+        &mut self,
+        first_candidate: usize,
+        peer_timeout: u64,
+    ) -> Result<HeaderSyncCandidate, NodeError> {
+        let last_hash = self.last_validated_header_hash()?;
+        let amount_of_peers = self.initial_peers.len();
+        let mut queried_peers = Vec::new();
+        for offset in 0..HEADER_SYNC_CANDIDATES.min(amount_of_peers) {
+            let peer_index = (first_candidate + offset) % amount_of_peers;
+            match self.ibd_send_get_block_headers_message(last_hash, peer_index) {
+                Ok(()) => queried_peers.push(peer_index),
+                Err(error) => self.logger.log_error(&error),
+            }
+        }
+
+        let mut candidates = Vec::new();
+        for peer_index in queried_peers {
+            let headers = match self.receive_headers_message(peer_index, peer_timeout) {
+                Ok(headers) => headers,
+                Err(_) => continue,
+            };
+            match validate_headers(Some(last_hash), &headers) {
+                Ok(work) => candidates.push(HeaderSyncCandidate {
+                    peer_index,
+                    work,
+                    headers,
+                }),
+                Err(error) => self.logger.log(format!(
+                    "Peer {peer_index} sent invalid headers: {:?}",
+                    error
+                )),
+            }
+        }
+
+        let candidate =
+            select_most_work(candidates).ok_or(NodeError::ErrorReceivingHeadersMessageInIBD)?;
+        self.logger.log(format!(
+            "Syncing headers from peer {}, which sent {} headers",
+            candidate.peer_index,
+            candidate.headers.len()
+        ));
+        Ok(candidate)
+    }
+
+    /// Requests the blocks of the validated headers that were not requested yet.
+    fn request_validated_blocks(
+        &self,
+        block_downloader: &BlockDownloader,
+        requested_headers: &mut usize,
+        total_amount_of_blocks: &mut usize,
+        first_downloaded_block_index: &mut i32,
+    ) -> Result<(), NodeError> {
+        let block_headers = self.get_block_headers()?;
+        if !self.is_light_client() {
+            request_blocks(
+                *requested_headers,
+                &block_headers,
+                block_downloader,
+                total_amount_of_blocks,
+                self.starting_block_time,
+                first_downloaded_block_index,
+            )?;
+        }
+        *requested_headers = block_headers.len();
         Ok(())
     }
 
-    /// Downloads the blocks from the node, starting from the given block hash. It ignores the messages that
-    /// are not block messages, and only downloads blocks that are after the given time. On error returns NodeError
+    /// Downloads the headers from the sync peer, starting with the batch it sent when it was selected.
+    /// Each batch is validated while the next one is requested, and the blocks of the validated headers
+    /// that are after the starting time are requested to the block downloader. If the peer fails, the
+    /// headers validated until then are kept. On error returns NodeError
+    #[allow(clippy::too_many_arguments)]
     fn download_headers_and_blocks(
         &mut self,
         block_downloader: &BlockDownloader,
-        sync_node_index: usize,
+        sync_candidate: HeaderSyncCandidate,
         peer_timeout: u64,
         first_downloaded_block_index: &mut i32,
         starting_block_count: usize,
         finish: &FinishedIndicator,
     ) -> Result<JoinHandle<()>, NodeError> {
-        let mut headers_received = self.get_block_headers()?.len();
-        let mut last_hash = HASHEDGENESISBLOCK;
-        if !self.get_block_headers()?.is_empty() {
-            last_hash = self.get_block_headers()?[headers_received - 1].hash();
-        }
-        let mut request_block_hashes_bundle: Vec<[u8; 32]> = Vec::new();
-        let mut total_amount_of_blocks = self.get_block_headers()?.len();
-        while headers_received == self.get_block_headers()?.len() {
-            self.ibd_send_get_block_headers_message(last_hash, sync_node_index)?;
-
-            self.receive_headers_message(sync_node_index, peer_timeout)?;
-
-            let i = headers_received;
-            headers_received += 2000;
-            let block_headers = self.get_block_headers()?;
-            last_hash = block_headers[block_headers.len() - 1].hash();
+        let sync_node_index = sync_candidate.peer_index;
+        let prev_hash = self.last_validated_header_hash()?;
+        let mut requested_headers = self.get_block_headers()?.len();
+        let mut total_amount_of_blocks = requested_headers;
+        let validator = HeaderValidator::new(
+            Some(prev_hash),
+            self.block_headers.clone(),
+            self.headers_index.clone(),
+            self.logger.clone(),
+        );
 
-            if i == block_headers.len() {
-                break;
+        let mut batch = sync_candidate.headers;
+        let mut received_headers = requested_headers;
+        let sync_result = loop {
+            let last_hash = match batch.last() {
+                Some(header) => header.hash(),
+                None => break Ok(()),
+            };
+            let full_batch = batch.len() == MAX_QUANTITY_FOR_GET_HEADERS;
+            received_headers += batch.len();
+            if let Err(error) = validator.validate(batch) {
+                break Err(error);
             }
-
-            request_block_hashes_bundle = request_blocks(
-                i,
-                &block_headers,
-                request_block_hashes_bundle,
+            self.request_validated_blocks(
                 block_downloader,
+                &mut requested_headers,
                 &mut total_amount_of_blocks,
-                self.starting_block_time,
                 first_downloaded_block_index,
             )?;
             let headers_amount_string = format!(
                 "Current amount of downloaded headers = {}",
-                headers_received
+                received_headers
             );
             self.log_and_send_to_ui(&headers_amount_string);
-        }
+            if !full_batch {
+                break Ok(());
+            }
+
+            if let Err(error) = self.ibd_send_get_block_headers_message(last_hash, sync_node_index)
+            {
+                break Err(error);
+            }
+            batch = match self.receive_headers_message(sync_node_index, peer_timeout) {
+                Ok(batch) => batch,
+                Err(error) => break Err(error),
+            };
+        };
+        let validation_result = validator.finish();
+        self.request_validated_blocks(
+            block_downloader,
+            &mut requested_headers,
+            &mut total_amount_of_blocks,
+            first_downloaded_block_index,
+        )?;
+        validation_result?;
+        sync_result?;
+
         let total_block_headers = self.get_block_headers()?.len();
         let mut amount_of_blocks_to_download = 0;
         if *first_downloaded_block_index != -1 {
@@ -194,106 +328,140 @@ impl Node {
         let thread_join = send_ibd_information_to_ui(
             self.sender_to_ui.clone(),
             self.blockchain.clone(),
+            block_downloader.get_scheduler(),
             amount_of_blocks_to_download,
             starting_block_count,
             finish.clone(),
+            self.logger.clone(),
         )?;
 
-        if !request_block_hashes_bundle.is_empty()
-            && block_downloader
-                .download_block_bundle(request_block_hashes_bundle)
-                .is_err()
-        {
-            return Err(NodeError::ErrorDownloadingBlockBundle);
-        }
         Ok(thread_join)
     }
 
     /// Writes the necessary headers into disk, to be able to continue the IBD from the last point.
-    /// On error returns NodeError. Written starting from the given positions.
+    /// On error returns NodeError. Written starting from the last header in disk.
     pub fn store_headers_in_disk(&mut self) -> Result<(), NodeError> {
-        self.data_handler
-            .save_headers_to_disk(&self.block_headers, self.headers_in_disk)
+        self.get_data_handler()?
+            .save_headers_to_disk(&self.block_headers)
             .map_err(|_| NodeError::ErrorSavingDataToDisk)
     }
 
-    /// Writes the necessary blocks into disk, to be able to continue the IBD from the last point.
-    /// On error returns NodeError. Written starting from the given positions.
+    /// Writes a checkpoint of the stored blocks and the necessary headers into disk, to be able to
+    /// continue the IBD from the last point. Returns the amount of headers in disk.
+    /// On error returns NodeError.
     pub fn store_blocks_in_disk(&mut self) -> Result<usize, NodeError> {
-        self.data_handler
-            .save_blocks_to_disk(&self.blockchain, &self.block_headers, self.headers_in_disk)
-            .map_err(|_| NodeError::ErrorSavingDataToDisk)
+        write_checkpoint(&self.blockchain, &self.block_headers, &self.data_handler)
     }
 
-    /// Loads the blocks and headers from disk. On error returns NodeError
+    /// Loads the headers from disk. Blocks are read from disk when they are needed, but if their index
+    /// is empty it is rebuilt from the block files, which were written without one by previous versions.
+    /// The files are repaired first in case the node stopped after the last checkpoint.
+    /// On error returns NodeError
     pub fn load_blocks_and_headers(&mut self) -> Result<(), NodeError> {
-        let headers = match self.data_handler.get_all_headers() {
-            Ok(headers) => headers,
-            Err(_) => return Err(NodeError::ErrorLoadingDataFromDisk),
+        let headers = {
+            let mut data_handler = self.get_data_handler()?;
+            let removed_bytes = data_handler
+                .recover_headers_file()
+                .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+            if removed_bytes > 0 {
+                self.logger.log(format!(
+                    "Removed {removed_bytes} bytes of an incomplete header from the headers file"
+                ));
+            }
+            match data_handler.get_all_headers() {
+                Ok(headers) => headers,
+                Err(_) => return Err(NodeError::ErrorLoadingDataFromDisk),
+            }
         };
 
-        let blocks = match self.data_handler.get_all_blocks() {
-            Ok(blocks) => blocks,
-            Err(_) => return Err(NodeError::ErrorLoadingDataFromDisk),
-        };
+        insert_new_headers(headers, &self.block_headers, &self.headers_index)?;
 
-        for block in blocks {
-            _ = self
-                .get_blockchain()?
-                .insert(block.get_header().hash(), block);
+        let mut blockchain = self.get_blockchain()?;
+        let headers_index = self.get_header_index()?;
+        if blockchain.is_empty() {
+            let indexed_blocks = blockchain.rebuild_index(&headers_index)?;
+            if indexed_blocks > 0 {
+                self.logger
+                    .log(format!("Block index rebuilt with {indexed_blocks} blocks"));
+            }
+        }
+        let (recovered_blocks, removed_bytes) = blockchain.recover(&headers_index)?;
+        if recovered_blocks > 0 || removed_bytes > 0 {
+            self.logger.log(format!(
+                "Recovered {recovered_blocks} blocks stored after the last checkpoint and removed {removed_bytes} bytes of an incomplete block"
+            ));
         }
-
-        insert_new_headers(headers, &self.block_headers, &self.headers_index)?;
 
         Ok(())
     }
 
-    /// Downloads block and headers from a given peer.If a problem occurs while downloading headers it continues asking to another peer.
+    /// Returns the peer to ask after the given one, reducing the time standards each time every peer
+    /// was asked.
+    fn next_header_sync_candidate(&self, peer_index: usize, peer_time_out: &mut u64) -> usize {
+        if peer_index + 1 < self.initial_peers.len() {
+            return peer_index + 1;
+        }
+        *peer_time_out += 1;
+        self.logger.log(format!(
+            "Reducing time standards, new peer_time_out = {} seconds",
+            peer_time_out
+        ));
+        0
+    }
+
+    /// Downloads block and headers, syncing the headers from the peer that sends the ones with the most work.
+    /// If a problem occurs while downloading headers it continues from the last validated header with another peer.
     fn start_downloading(
         &mut self,
         starting_block_count: usize,
         finish: &FinishedIndicator,
     ) -> Result<(BlockDownloader, Option<JoinHandle<()>>), NodeError> {
-        let mut i = 0;
-        let mut block_downloader = self.create_block_downloader(i)?;
+        if self.initial_peers.is_empty() {
+            return Err(NodeError::ErrorCreatingBlockDownloader);
+        }
+        let mut first_candidate = 0;
         let mut first_downloaded_block_index: i32 = -1;
         let mut peer_time_out = 1;
-        let mut thread_join: Option<JoinHandle<()>> = None;
         while peer_time_out < MAXIMUM_PEER_TIME_OUT {
-            println!("\n{i}\n");
+            let sync_candidate = match self.select_header_sync_peer(first_candidate, peer_time_out)
+            {
+                Ok(sync_candidate) => sync_candidate,
+                Err(_) => {
+                    let last_candidate = first_candidate + HEADER_SYNC_CANDIDATES - 1;
+                    first_candidate = self.next_header_sync_candidate(
+                        last_candidate.min(self.initial_peers.len() - 1),
+                        &mut peer_time_out,
+                    );
+                    continue;
+                }
+            };
+            let sync_node_index = sync_candidate.peer_index;
+            let mut block_downloader = self.create_block_downloader(sync_node_index)?;
             match self.download_headers_and_blocks(
                 &block_downloader,
-                i,
+                sync_candidate,
                 peer_time_out,
                 &mut first_downloaded_block_index,
                 starting_block_count,
                 finish,
             ) {
-                Ok(join) => {
-                    thread_join = Some(join);
-                    break;
-                }
+                Ok(join) => return Ok((block_downloader, Some(join))),
                 Err(error) => {
                     if let NodeError::ErrorDownloadingBlockBundle = error {
                         return Err(error);
                     }
+                    self.logger.log(format!(
+                        "Header sync with peer {sync_node_index} failed: {:?}",
+                        error
+                    ));
                 }
             };
-            i += 1;
-            if i >= self.initial_peers.len() {
-                i = 0;
-                peer_time_out += 1;
-                self.logger.log(format!(
-                    "Reducing time standards, new peer_time_out = {} seconds",
-                    peer_time_out
-                ));
-            }
             if let Err(error) = block_downloader.finish_downloading() {
                 self.logger.log_error(&error);
             }
-            block_downloader = self.create_block_downloader(i)?;
+            first_candidate = self.next_header_sync_candidate(sync_node_index, &mut peer_time_out);
         }
-        Ok((block_downloader, thread_join))
+        Ok((self.create_block_downloader(first_candidate)?, None))
     }
 
     /// Asks the node for the block headers starting from the given block hash,
@@ -307,8 +475,14 @@ impl Node {
         progress_str = "Finished loading data from disk";
         self.log_and_send_to_ui(progress_str);
 
-        let aux_len = self.get_block_headers()?.len();
-        self.headers_in_disk = aux_len;
+        self.run_maintenance()?;
+
+        self.checkpointer = Some(Checkpointer::new(
+            self.blockchain.clone(),
+            self.block_headers.clone(),
+            self.data_handler.clone(),
+            self.logger.clone(),
+        ));
         let starting_block_count = self.get_blockchain()?.len();
 
         progress_str = "Started downloading headers";
@@ -326,6 +500,18 @@ impl Node {
         block_downloader
             .finish_downloading()
             .map_err(|_| NodeError::ErrorDownloadingBlockBundle)?;
+        if let Ok(rates) = block_downloader.download_rates() {
+            for rate in rates {
+                self.logger.log(format!(
+                    "Peer {} downloaded {} blocks at {:.1} blocks/s, final bundle size {}, {} stalls",
+                    rate.peer_id,
+                    rate.downloaded_blocks,
+                    rate.blocks_per_second,
+                    rate.bundle_size,
+                    rate.stalls
+                ));
+            }
+        }
 
         if let Ok(mut finished) = finish.lock() {
             *finished = true;
@@ -338,7 +524,7 @@ impl Node {
         progress_str = "Started storing headers to disk";
         self.log_and_send_to_ui(progress_str);
 
-        self.headers_in_disk = self.store_blocks_in_disk()?;
+        self.last_proccesed_block = self.store_blocks_in_disk()?;
         progress_str = "Finished storing headers to disk";
         self.log_and_send_to_ui(progress_str);
 
@@ -356,44 +542,35 @@ impl Node {
             ))
             .map_err(|_| NodeError::ErrorSendingThroughChannel)?;
 
-        self.last_proccesed_block = self.headers_in_disk;
         Ok(())
     }
 }
 
-/// Requests block_downloader to download block bundles (16 blocks each),
-/// that were created after the starting_block_time.
-/// If at the end we do not have enough to form a full block bundle, then then unrequested block hashes are returned
+/// Requests block_downloader to download the blocks that were created after the starting_block_time.
+/// The block downloader decides how many of them each peer downloads at a time.
 fn request_blocks(
     mut i: usize,
     block_headers: &Vec<BlockHeader>,
-    mut request_block_hashes_bundle: Vec<[u8; 32]>,
     block_downloader: &BlockDownloader,
     total_amount_of_blocks: &mut usize,
     starting_block_time: u32,
     first_downloaded_block_index: &mut i32,
-) -> Result<Vec<[u8; 32]>, NodeError> {
+) -> Result<(), NodeError> {
+    let mut request_block_hashes: Vec<[u8; 32]> = Vec::new();
     while i < block_headers.len() {
         if block_headers[i].time > starting_block_time {
             if *first_downloaded_block_index == -1 {
                 *first_downloaded_block_index = i as i32;
             }
             *total_amount_of_blocks += 1;
-            request_block_hashes_bundle.push(block_headers[i].hash());
-            if request_block_hashes_bundle.len() == MAX_BLOCK_BUNDLE {
-                if block_downloader
-                    .download_block_bundle(request_block_hashes_bundle)
-                    .is_err()
-                {
-                    return Err(NodeError::ErrorDownloadingBlockBundle);
-                }
-                request_block_hashes_bundle = Vec::new();
-            }
+            request_block_hashes.push(block_headers[i].hash());
         }
         i += 1;
     }
 
-    Ok(request_block_hashes_bundle)
+    block_downloader
+        .download_block_bundle(request_block_hashes)
+        .map_err(|_| NodeError::ErrorDownloadingBlockBundle)
 }
 
 #[cfg(test)]
@@ -405,6 +582,14 @@ mod tests {
     const STARTING_BLOCK_TIME: u32 = 1681084800;
     const HEADERS_FILE_PATH: &str = "tests_txt/ibd_test_headers.bin";
     const BLOCKS_FILE_PATH: &str = "tests_txt/ibd_test_blocks.bin";
+    const BLOCK_INDEX_FILE_PATH: &str = "tests_txt/ibd_test_block_index.bin";
+    const PEERS_FILE_PATH: &str = "tests_txt/ibd_test_peers.txt";
+    const BANLIST_FILE_PATH: &str = "tests_txt/ibd_test_banlist.txt";
+    const FILTER_HEADERS_FILE_PATH: &str = "tests_txt/ibd_test_filter_headers.bin";
+    const UTXO_SET_FILE_PATH: &str = "tests_txt/ibd_test_utxo_set.bin";
+    const TX_INDEX_FILE_PATH: &str = "tests_txt/ibd_test_tx_index.bin";
+    const ADDRESS_INDEX_FILE_PATH: &str = "tests_txt/ibd_test_address_index.bin";
+    const OUTBOUND_PEERS: usize = 8;
     const DNS_HOST: &str = "seed.testnet.bitcoin.sprovoost.nl";
     const DNS_PORT: u16 = 18333;
 
@@ -419,7 +604,20 @@ mod tests {
             begin_time: STARTING_BLOCK_TIME,
             headers_path: String::from(HEADERS_FILE_PATH),
             blocks_path: String::from(BLOCKS_FILE_PATH),
+            block_index_path: String::from(BLOCK_INDEX_FILE_PATH),
+            peers_path: String::from(PEERS_FILE_PATH),
+            banlist_path: String::from(BANLIST_FILE_PATH),
+            filter_headers_path: String::from(FILTER_HEADERS_FILE_PATH),
+            utxo_set_path: String::from(UTXO_SET_FILE_PATH),
+            tx_index_path: String::from(TX_INDEX_FILE_PATH),
+            address_index_path: String::from(ADDRESS_INDEX_FILE_PATH),
+            outbound_peers: OUTBOUND_PEERS,
             ipv6_enabled: false,
+            light_client: false,
+            v2_transport: false,
+            prune_target_mb: 0,
+            tx_index: false,
+            address_index: false,
             dns: vec![(DNS_HOST.to_string(), DNS_PORT)],
             external_addresses: vec![],
         }
@@ -434,14 +632,9 @@ mod tests {
 
         let (sx, _rx) = glib::MainContext::channel::<UIResponse>(glib::PRIORITY_DEFAULT);
         let mut node = Node::new(config, sx)?;
-        let mut i = 0;
-        node.ibd_send_get_block_headers_message(HASHEDGENESISBLOCK, i)?;
-        while let Err(_) = node.receive_headers_message(i, 15) {
-            i += 1;
-            node.ibd_send_get_block_headers_message(HASHEDGENESISBLOCK, i)?;
-        }
+        let sync_candidate = node.select_header_sync_peer(0, 15)?;
 
-        assert!(node.get_block_headers()?.len() == 2000);
+        assert!(sync_candidate.headers.len() == 2000);
         Ok(())
     }
 
@@ -451,21 +644,20 @@ mod tests {
 
         let (sx, _rx) = glib::MainContext::channel::<UIResponse>(glib::PRIORITY_DEFAULT);
         let mut node = Node::new(config, sx)?;
+        let sync_candidate = node.select_header_sync_peer(0, 15)?;
+        insert_new_headers(
+            sync_candidate.headers,
+            &node.block_headers,
+            &node.headers_index,
+        )?;
         let mut block_downloader = BlockDownloader::from(
             &node.initial_peers,
-            0,
+            sync_candidate.peer_index,
             node.get_safe_node_info(),
             &node.logger.clone(),
         )
         .unwrap();
 
-        let mut sync_node_index = 0;
-        node.ibd_send_get_block_headers_message(HASHEDGENESISBLOCK, sync_node_index)?;
-        while let Err(_) = node.receive_headers_message(sync_node_index, 15) {
-            sync_node_index += 1;
-            node.ibd_send_get_block_headers_message(HASHEDGENESISBLOCK, sync_node_index)?;
-        }
-
         for j in 0..125 {
             let mut block_hashes_bundle: Vec<[u8; 32]> = Vec::new();
             for i in 0..16 {
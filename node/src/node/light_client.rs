@@ -0,0 +1,301 @@
+use crate::{
+    blocks::bloom_filter::{BloomFilter, BLOOM_UPDATE_ALL},
+    messages::version_message::NODE_BLOOM,
+    node::{initial_block_download::HASHEDGENESISBLOCK, *},
+    wallet::Wallet,
+};
+use std::time::{Duration, Instant};
+
+const FILTER_FALSE_POSITIVE_RATE: f64 = 0.0001;
+const FILTERED_BLOCKS_PER_REQUEST: usize = 500;
+const MAX_HEADERS_PER_MESSAGE: usize = 2000;
+const FILTERING_PEER_CANDIDATES: usize = 16;
+const FILTERED_BLOCKS_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// State of the light client mode, in which the node downloads headers only. Instead of full blocks, it
+/// loads a bloom filter (BIP37) with the scripts of the wallet in a peer, and asks it for the blocks as
+/// merkleblock messages, which carry the matched transactions alongside a proof of their inclusion.
+/// Only the matched transactions are kept, so the blocks cannot be served to other peers.
+#[derive(Debug, Default)]
+pub struct LightClient {
+    stream: Option<PeerStream>,
+    filter: Option<BloomFilter>,
+    next_position: Option<usize>,
+}
+
+impl LightClient {
+    pub fn new() -> LightClient {
+        LightClient::default()
+    }
+}
+
+impl Node {
+    /// Returns true if the node runs as a light client.
+    pub fn is_light_client(&self) -> bool {
+        self.light_client.is_some()
+    }
+
+    /// Replaces the bloom filter with one that matches the outputs paying to the wallet and the inputs
+    /// it signs, so the blocks after the starting time are filtered again on the next sync.
+    pub fn load_light_client_filter(&mut self, wallet: &Wallet) {
+        let light_client = match &mut self.light_client {
+            Some(light_client) => light_client,
+            None => return,
+        };
+        let mut filter = BloomFilter::new(
+            2,
+            FILTER_FALSE_POSITIVE_RATE,
+            rand::random(),
+            BLOOM_UPDATE_ALL,
+        );
+        filter.insert(&wallet.get_pk_hash());
+        filter.insert(&wallet.pub_key.serialize());
+
+        light_client.filter = Some(filter);
+        light_client.next_position = None;
+        light_client.stream = None;
+    }
+
+    /// Downloads the new headers and the transactions of the wallet in the blocks that were not
+    /// filtered yet, updating the utxo set with them. Problems with the filtering peer are logged,
+    /// and another peer is looked for on the next sync.
+    pub fn sync_light_client(
+        &mut self,
+        wallet_utxos: &mut HashMap<Outpoint, i64>,
+    ) -> Result<(), NodeError> {
+        let mut light_client = match self.light_client.take() {
+            Some(light_client) => light_client,
+            None => return Ok(()),
+        };
+        let result = self.sync_with_filtering_peer(&mut light_client, wallet_utxos);
+        if let Err(error) = &result {
+            self.logger.log_error(error);
+            light_client.stream = None;
+        }
+        self.light_client = Some(light_client);
+
+        match result {
+            Err(NodeError::ErrorSharingReference) => Err(NodeError::ErrorSharingReference),
+            _ => Ok(()),
+        }
+    }
+
+    fn sync_with_filtering_peer(
+        &mut self,
+        light_client: &mut LightClient,
+        wallet_utxos: &mut HashMap<Outpoint, i64>,
+    ) -> Result<(), NodeError> {
+        let filter = match &light_client.filter {
+            Some(filter) => filter,
+            None => return Ok(()),
+        };
+        if light_client.stream.is_none() {
+            light_client.stream = Some(self.connect_to_filtering_peer(filter)?);
+        }
+        let stream = match &mut light_client.stream {
+            Some(stream) => stream,
+            None => return Err(NodeError::ErrorPeerNotConnected),
+        };
+
+        self.download_light_client_headers(stream)?;
+
+        let mut position = match light_client.next_position {
+            Some(position) => position,
+            None => self.first_position_after_starting_time()?,
+        };
+        loop {
+            let block_hashes: Vec<[u8; 32]> = self
+                .get_block_headers()?
+                .iter()
+                .skip(position)
+                .take(FILTERED_BLOCKS_PER_REQUEST)
+                .map(|header| header.hash())
+                .collect();
+            if block_hashes.is_empty() {
+                break;
+            }
+
+            for block in self.receive_filtered_blocks(stream, &block_hashes)? {
+                self.update_utxo_with_filtered_block(&block, wallet_utxos);
+            }
+            position += block_hashes.len();
+            light_client.next_position = Some(position);
+            self.logger.log(format!(
+                "Light client filtered blocks up to number {position}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Position of the first header of a block created after the starting time, the same blocks a
+    /// full node downloads.
+    fn first_position_after_starting_time(&self) -> Result<usize, NodeError> {
+        let block_headers = self.get_block_headers()?;
+        Ok(block_headers
+            .iter()
+            .position(|header| header.time > self.starting_block_time)
+            .unwrap_or(block_headers.len()))
+    }
+
+    /// Connects to a peer that serves bloom filters, preferring the ones the node is already connected
+    /// to, and loads the filter in it.
+    fn connect_to_filtering_peer(&self, filter: &BloomFilter) -> Result<PeerStream, NodeError> {
+        let mut candidates: Vec<SocketAddr> = self
+            .get_peer_table()?
+            .get_peers()
+            .iter()
+            .filter(|peer| peer.services & NODE_BLOOM != 0)
+            .map(|peer| peer.address)
+            .collect();
+        let connected: HashSet<SocketAddr> = candidates.iter().copied().collect();
+        candidates.extend(
+            self.get_address_book()?
+                .select_candidates(FILTERING_PEER_CANDIDATES, &connected),
+        );
+        self.remove_banned_addresses(&mut candidates)?;
+
+        for address in candidates {
+            let (mut stream, peer_version) = match outgoing_handshake(
+                self.version,
+                address,
+                self.address,
+                self.connection_settings.v2_transport,
+                self.connection_settings.pruned,
+                self.connection_settings.compact_filters,
+                &self.logger,
+            ) {
+                Ok(connection) => connection,
+                Err(_) => continue,
+            };
+            if peer_version.services & NODE_BLOOM == 0 {
+                continue;
+            }
+            let filter_load_msg = FilterLoadMessage {
+                filter: filter.clone(),
+            };
+            if filter_load_msg.send_to(&mut stream).is_ok() {
+                self.logger
+                    .log(format!("Light client filtering blocks with peer {address}"));
+                return Ok(stream);
+            }
+        }
+        Err(NodeError::ErrorConnectingToPeer)
+    }
+
+    /// Asks the peer for the headers that follow the last one, until it has no more. Headers that do
+    /// not follow the last one or lack a valid proof of work are rejected.
+    fn download_light_client_headers(&self, stream: &mut PeerStream) -> Result<(), NodeError> {
+        loop {
+            let last_hash = match self.get_block_headers()?.last() {
+                Some(header) => header.hash(),
+                None => HASHEDGENESISBLOCK,
+            };
+            GetBlockHeadersMessage::new(self.version as u32, vec![last_hash], [0; 32])
+                .send_to(stream)
+                .map_err(NodeError::ErrorMessage)?;
+
+            let headers = loop {
+                match super::receive_message(stream, &self.logger)?.0 {
+                    Message::BlockHeaders(block_headers_msg) => break block_headers_msg.headers,
+                    Message::Ping(ping_msg) => handle_ping_message(stream, ping_msg)?,
+                    _ => {}
+                }
+            };
+            let amount_of_headers = headers.len();
+            let mut previous_hash = last_hash;
+            for header in &headers {
+                if header.prev_hash != previous_hash || !validate_proof_of_work(header) {
+                    return Err(NodeError::ErrorInvalidHeaders);
+                }
+                previous_hash = header.hash();
+            }
+            insert_new_headers(headers, &self.block_headers, &self.headers_index)?;
+
+            if amount_of_headers < MAX_HEADERS_PER_MESSAGE {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Asks the peer for the blocks as merkleblock messages, and returns them with the matched
+    /// transactions only, in the order they were asked for. A ping is sent after the request, since
+    /// its pong arrives after every transaction the peer sends.
+    fn receive_filtered_blocks(
+        &self,
+        stream: &mut PeerStream,
+        block_hashes: &[[u8; 32]],
+    ) -> Result<Vec<Block>, NodeError> {
+        GetDataMessage::create_message_inventory_filtered_block_type(block_hashes.to_vec())
+            .send_to(stream)
+            .map_err(NodeError::ErrorMessage)?;
+        let nonce = rand::random();
+        PingMessage::new(nonce)
+            .send_to(stream)
+            .map_err(NodeError::ErrorMessage)?;
+
+        let start_time = Instant::now();
+        let mut filtered_blocks = Vec::new();
+        let mut transactions = HashMap::new();
+        loop {
+            if start_time.elapsed() > FILTERED_BLOCKS_TIMEOUT {
+                return Err(NodeError::ErrorPeerTimeout);
+            }
+            match super::receive_message(stream, &self.logger)?.0 {
+                Message::MerkleBlock(merkle_block_msg) => {
+                    filtered_blocks.push(validate_merkle_block(merkle_block_msg)?)
+                }
+                Message::Tx(tx_msg) => {
+                    transactions.insert(tx_msg.tx.hash(), tx_msg.tx);
+                }
+                Message::NotFound(_) => return Err(NodeError::ErrorFindingBlock),
+                Message::Ping(ping_msg) => handle_ping_message(stream, ping_msg)?,
+                Message::Pong(pong_msg) if pong_msg.get_nonce() == nonce => break,
+                _ => {}
+            }
+        }
+
+        if filtered_blocks.len() != block_hashes.len()
+            || filtered_blocks
+                .iter()
+                .zip(block_hashes)
+                .any(|((header, _), hash)| header.hash() != *hash)
+        {
+            return Err(NodeError::ErrorFindingBlock);
+        }
+
+        let mut blocks = Vec::new();
+        for (header, matched_hashes) in filtered_blocks {
+            let mut matched_transactions = Vec::new();
+            for hash in matched_hashes {
+                match transactions.remove(&hash) {
+                    Some(tx) => matched_transactions.push(tx),
+                    None => self.logger.log(String::from(
+                        "A matched transaction was not sent by the peer",
+                    )),
+                }
+            }
+            blocks.push(Block::new(header, matched_transactions));
+        }
+        Ok(blocks)
+    }
+}
+
+/// Validates the proof of work of the header of a merkleblock message, and its partial merkle tree
+/// against the merkle root. Returns the header alongside the hashes of the matched transactions.
+fn validate_merkle_block(
+    merkle_block_msg: MerkleBlockMessage,
+) -> Result<(BlockHeader, Vec<[u8; 32]>), NodeError> {
+    let header = merkle_block_msg.header;
+    if !validate_proof_of_work(&header) {
+        return Err(NodeError::ErrorInvalidProofOfWork);
+    }
+    match validate_partial_merkle_tree(
+        &header,
+        merkle_block_msg.total_transactions,
+        &merkle_block_msg.hashes,
+        &merkle_block_msg.flags,
+    ) {
+        Some(matched_hashes) => Ok((header, matched_hashes)),
+        None => Err(NodeError::ErrorInvalidMerkleRoot),
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    blocks::proof::*,
+    node::{block_store::BlockStore, block_validation::verify_block_scripts, *},
+};
+
+const REINDEX_ARGUMENT: &str = "--reindex";
+const VERIFY_CHAIN_ARGUMENT: &str = "--verify-chain";
+const VERIFICATION_PROGRESS_INTERVAL: usize = 100;
+
+/// Maintenance tasks requested through the arguments of the node binary. They run after the data is
+/// loaded from disk and before the download starts.
+#[derive(Debug, Default, PartialEq)]
+pub struct MaintenanceOptions {
+    pub reindex: bool,
+    pub verify_chain: Option<usize>,
+}
+
+impl MaintenanceOptions {
+    /// Parses the arguments that follow the path of the config file, which are `--reindex` and
+    /// `--verify-chain N`. Returns None if one of them is not valid.
+    pub fn from_args(args: &[String]) -> Option<MaintenanceOptions> {
+        let mut options = MaintenanceOptions::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                REINDEX_ARGUMENT => options.reindex = true,
+                VERIFY_CHAIN_ARGUMENT => options.verify_chain = Some(args.next()?.parse().ok()?),
+                _ => return None,
+            }
+        }
+        Some(options)
+    }
+}
+
+/// Returns the position of the first header that does not follow the previous one or does not have a
+/// valid proof of work, or the amount of headers if all of them are valid.
+fn first_invalid_header(block_headers: &[BlockHeader]) -> usize {
+    for (position, header) in block_headers.iter().enumerate() {
+        if position > 0 && header.prev_hash != block_headers[position - 1].hash() {
+            return position;
+        }
+        if !validate_proof_of_work(header) {
+            return position;
+        }
+    }
+    block_headers.len()
+}
+
+/// Removes the headers after the given amount.
+fn truncate_headers(
+    block_headers: &mut Vec<BlockHeader>,
+    headers_index: &mut HashMap<[u8; 32], usize>,
+    amount: usize,
+) {
+    for header in block_headers.drain(amount..) {
+        headers_index.remove(&header.hash());
+    }
+}
+
+/// Checks the proof of work of the block in the given position of the headers, that it follows the
+/// previous one, that its stored transactions match the merkle root and the p2pkh signatures of the
+/// inputs that spend available outputs. The outputs of the block are made available for the next ones.
+/// Returns the amount of checked scripts.
+fn verify_block(
+    blockchain: &mut BlockStore,
+    block_headers: &[BlockHeader],
+    position: usize,
+    available_outputs: &mut HashMap<Outpoint, TxOut>,
+) -> Result<usize, NodeError> {
+    let header = &block_headers[position];
+    if !validate_proof_of_work(header) {
+        return Err(NodeError::ErrorInvalidProofOfWork);
+    }
+    if position > 0 && header.prev_hash != block_headers[position - 1].hash() {
+        return Err(NodeError::ErrorDisconectedFromBlockchain);
+    }
+    let block = blockchain
+        .get(&header.hash())
+        .ok_or(NodeError::ErrorFindingBlock)?;
+    if block.header_hash() != header.hash() {
+        return Err(NodeError::ErrorInvalidHeaders);
+    }
+    if !validate_block_proof_of_inclusion(block) {
+        return Err(NodeError::ErrorInvalidMerkleRoot);
+    }
+
+    verify_block_scripts(block, available_outputs)
+}
+
+impl Node {
+    /// Sets the maintenance tasks that run before the download starts.
+    pub fn set_maintenance_options(&mut self, maintenance_options: MaintenanceOptions) {
+        self.maintenance_options = maintenance_options;
+    }
+
+    /// Runs the requested maintenance tasks. The reindex runs first, so the verification checks the
+    /// rebuilt data.
+    pub fn run_maintenance(&mut self) -> Result<(), NodeError> {
+        if self.maintenance_options.reindex {
+            self.reindex()?;
+        }
+        if let Some(amount) = self.maintenance_options.verify_chain {
+            self.verify_chain(amount)?;
+        }
+        Ok(())
+    }
+
+    /// Rebuilds the block index from the block files, leaving out the blocks that can not be read or do not
+    /// match their merkle root. The headers that are not valid are removed, and so are the ones after the
+    /// first missing block, so they are downloaded again. The UTXO set is built from the indexed blocks
+    /// afterwards, as on every start.
+    pub fn reindex(&mut self) -> Result<(), NodeError> {
+        self.log_and_send_to_ui("Started reindexing blocks from disk");
+        let mut data_handler = self.get_data_handler()?;
+        let mut blockchain = self.get_blockchain()?;
+        let mut headers_index = self.write_header_index()?;
+        let mut block_headers = self.write_block_headers()?;
+
+        let valid_headers = first_invalid_header(&block_headers);
+        if valid_headers < block_headers.len() {
+            self.logger.log(format!(
+                "Header at height {} is not valid, removing it and the {} headers after it",
+                valid_headers + 1,
+                block_headers.len() - valid_headers - 1
+            ));
+        }
+        truncate_headers(&mut block_headers, &mut headers_index, valid_headers);
+
+        let indexed_blocks = blockchain.rebuild_index(&headers_index)?;
+        let kept_headers = blockchain.headers_of_stored_blocks(&block_headers, 0);
+        truncate_headers(&mut block_headers, &mut headers_index, kept_headers);
+        data_handler
+            .truncate_headers(kept_headers)
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        blockchain.save_index()?;
+
+        self.logger.log(format!(
+            "Reindexed {indexed_blocks} blocks, keeping {kept_headers} headers"
+        ));
+        self.log_and_send_to_ui("Finished reindexing blocks from disk");
+        Ok(())
+    }
+
+    /// Checks again the last amount of stored blocks: their proof of work, that each one follows the
+    /// previous header, that their transactions match the merkle root, and the scripts that can be
+    /// validated. Since the UTXO set is not built yet, only p2pkh inputs that spend outputs of the verified
+    /// blocks are checked. The progress is shown in the loading screen. Returns the error of the first block
+    /// that is not valid.
+    pub fn verify_chain(&mut self, amount: usize) -> Result<(), NodeError> {
+        let mut blockchain = self.get_blockchain()?;
+        let block_headers = self.get_block_headers()?;
+        let mut positions: Vec<usize> = (0..block_headers.len())
+            .rev()
+            .filter(|position| blockchain.contains_key(&block_headers[*position].hash()))
+            .take(amount)
+            .collect();
+        positions.reverse();
+
+        self.log_and_send_to_ui(&format!(
+            "Started verifying the last {} blocks",
+            positions.len()
+        ));
+        self.send_loading_screen_update(LoadingScreenInfo::StartedBlockDownload(positions.len()))?;
+
+        let mut available_outputs = HashMap::new();
+        let mut checked_scripts = 0;
+        for (verified_blocks, position) in positions.iter().enumerate() {
+            match verify_block(
+                &mut blockchain,
+                &block_headers,
+                *position,
+                &mut available_outputs,
+            ) {
+                Ok(block_checked_scripts) => checked_scripts += block_checked_scripts,
+                Err(error) => {
+                    self.logger.log(format!(
+                        "Block at height {} failed verification with {:?}, the data files may be corrupt, start the node with --reindex to rebuild them",
+                        position + 1,
+                        error
+                    ));
+                    self.log_and_send_to_ui(&format!(
+                        "Verification failed at height {}",
+                        position + 1
+                    ));
+                    self.send_loading_screen_update(LoadingScreenInfo::FinishedBlockDownload)?;
+                    return Err(error);
+                }
+            }
+            if (verified_blocks + 1) % VERIFICATION_PROGRESS_INTERVAL == 0 {
+                self.send_loading_screen_update(LoadingScreenInfo::DownloadedBlocks(
+                    verified_blocks + 1,
+                ))?;
+            }
+        }
+
+        self.send_loading_screen_update(LoadingScreenInfo::FinishedBlockDownload)?;
+        self.logger.log(format!(
+            "Verified {} blocks and {checked_scripts} scripts",
+            positions.len()
+        ));
+        self.log_and_send_to_ui("Finished verifying blocks");
+        Ok(())
+    }
+
+    /// Sends the update to the loading screen.
+    fn send_loading_screen_update(&self, update: LoadingScreenInfo) -> Result<(), NodeError> {
+        self.sender_to_ui
+            .send(UIResponse::LoadingScreenUpdate(update))
+            .map_err(|_| NodeError::ErrorSendingThroughChannel)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::test_chain;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn chain(length: u32) -> (Vec<BlockHeader>, BlockStore) {
+        let transactions = (0..length)
+            .map(|lock_time| vec![Transaction::new(1, Vec::new(), Vec::new(), lock_time)])
+            .collect();
+        let (blocks, block_headers) = test_chain::chain([0; 32], 0xffffff20, transactions);
+        let mut blockchain = BlockStore::new();
+        for (height, block) in (1..).zip(blocks) {
+            blockchain.insert(block, height).unwrap();
+        }
+        (block_headers, blockchain)
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn maintenance_test_1_options_are_parsed_from_the_args() {
+        assert_eq!(
+            MaintenanceOptions::from_args(&args(&[])),
+            Some(MaintenanceOptions::default())
+        );
+        assert_eq!(
+            MaintenanceOptions::from_args(&args(&["--verify-chain", "10", "--reindex"])),
+            Some(MaintenanceOptions {
+                reindex: true,
+                verify_chain: Some(10),
+            })
+        );
+        assert_eq!(
+            MaintenanceOptions::from_args(&args(&["--verify-chain"])),
+            None
+        );
+        assert_eq!(
+            MaintenanceOptions::from_args(&args(&["--verify-chain", "a"])),
+            None
+        );
+        assert_eq!(MaintenanceOptions::from_args(&args(&["--other"])), None);
+    }
+
+    #[test]
+    fn maintenance_test_2_finds_the_first_header_that_does_not_follow_the_previous_one() {
+        let (mut block_headers, _) = chain(4);
+        assert_eq!(first_invalid_header(&block_headers), 4);
+
+        block_headers[2].prev_hash = [1; 32];
+
+        assert_eq!(first_invalid_header(&block_headers), 2);
+    }
+
+    #[test]
+    fn maintenance_test_3_verification_detects_a_block_that_does_not_match_its_header() {
+        let (block_headers, mut blockchain) = chain(3);
+        let mut available_outputs = HashMap::new();
+        assert!(verify_block(&mut blockchain, &block_headers, 2, &mut available_outputs).is_ok());
+
+        let mut wrong_headers = block_headers.clone();
+        let tx = Transaction::new(1, Vec::new(), Vec::new(), 7);
+        wrong_headers[1] = BlockHeader::new(1, block_headers[0].hash(), [2; 32], 0xffffff20);
+        blockchain
+            .insert(Block::new(wrong_headers[1].clone(), vec![tx]), 2)
+            .unwrap();
+
+        assert!(matches!(
+            verify_block(&mut blockchain, &wrong_headers, 1, &mut available_outputs),
+            Err(NodeError::ErrorInvalidMerkleRoot)
+        ));
+        assert!(matches!(
+            verify_block(&mut blockchain, &wrong_headers, 2, &mut available_outputs),
+            Err(NodeError::ErrorDisconectedFromBlockchain)
+        ));
+    }
+}
@@ -5,82 +5,102 @@ use crate::{
 };
 
 use std::{
-    net::TcpStream,
-    sync::{mpsc, Arc, Mutex},
+    sync::{Arc, Mutex},
+    thread,
     time::Instant,
 };
 use workers::*;
 
-/// Gets a bundle from the shared reference and logs the errors
-fn get_bundle(id: usize, receiver: &SafeReceiver, logger: &Logger) -> Option<Bundle> {
-    let bundle = match receiver.lock() {
-        Ok(rec_lock) => match rec_lock.recv() {
-            Ok(bundle) => bundle,
-            Err(error) => {
-                logger.log(format!("Worker {id} failed: {:?}", error));
-                return None;
-            }
-        },
+use download_scheduler::{DownloadScheduler, PeerDownloadRate, SafeDownloadScheduler};
+
+const IDLE_WORKER_WAIT: Duration = Duration::from_millis(100);
+
+/// Gets the next bundle of the worker from the scheduler, first taking the blocks of the peers
+/// that stalled so they can be downloaded by the others. Returns Ok(None) if there is nothing to
+/// download right now, or the stop of the worker if there is nothing left to download.
+fn get_bundle(
+    id: usize,
+    scheduler: &SafeDownloadScheduler,
+    logger: &Logger,
+) -> Result<Option<Bundle>, Stops> {
+    let mut scheduler = match scheduler.lock() {
+        Ok(scheduler) => scheduler,
         Err(error) => {
             logger.log(format!("Worker {id} failed: {:?}", error));
-            return None;
+            return Err(Stops::UngracefullStop);
         }
     };
-    Some(bundle)
+    let now = Instant::now();
+    for stalled_peer in scheduler.reassign_stalled(now) {
+        logger.log(format!(
+            "Worker {stalled_peer} stalled, its blocks will be downloaded by other peers"
+        ));
+    }
+
+    if let Some(bundle) = scheduler.next_bundle(id, now) {
+        return Ok(Some(bundle));
+    }
+    if scheduler.can_stop(id) {
+        return Err(Stops::GracefullStop);
+    }
+    Ok(None)
 }
 
-/// Main loop that worker's thread executes. It gets a bundle from the shared channel,
+/// Main loop that worker's thread executes. It gets a bundle from the download scheduler,
 /// gets the blocks from it's peer, and saves them to the shared reference block vector.
 /// If anything fails along the way it loggs acordingly, as well as other things like
-/// received messages.
+/// received messages, and gives the blocks of the bundle back to the scheduler.
 /// It returns a type of stop, depending on wheather the worker finished, gracefully, ungracefully,
 /// or must continue
 pub fn block_downloader_thread_loop(
     id: usize,
-    receiver: &SafeReceiver,
-    stream: &mut TcpStream,
+    scheduler: &SafeDownloadScheduler,
+    stream: &mut PeerStream,
     safe_node_info: &NodeSharedInformation,
-    missed_bundles_sender: &mpsc::Sender<Bundle>,
     downloading_headers: &FinishedIndicator,
     logger: &Logger,
 ) -> Stops {
-    let bundle = match get_bundle(id, receiver, logger) {
-        Some(bundle) => bundle,
-        None => return Stops::UngracefullStop,
+    let bundle = match get_bundle(id, scheduler, logger) {
+        Ok(Some(bundle)) => bundle,
+        Ok(None) => {
+            thread::sleep(IDLE_WORKER_WAIT);
+            return Stops::Continue;
+        }
+        Err(stop) => return stop,
     };
 
-    //si se recibe un end_of_channel
-    if bundle.is_empty() {
-        return Stops::GracefullStop;
-    }
     let downloading_headers = match downloading_headers.lock() {
         Ok(downloading_headers) => *downloading_headers,
         Err(_) => return Stops::UngracefullStop,
     };
 
-    let aux_bundle = bundle.clone();
     logger.log(format!("sigo vivo {id}"));
-    match get_blocks_from_bundle(bundle, stream, safe_node_info, downloading_headers, logger) {
-        Ok(blocks) => blocks,
-        Err(error) => {
-            if let Err(error) = missed_bundles_sender.send(aux_bundle) {
-                logger.log(format!(
-                    "Worker {id} failed sending missed bundle: {:?}",
-                    error
-                ));
-            }
-
-            if let BlockDownloaderError::BundleNotFound = error {
-                logger.log(format!("Worker {id} did not find bundle"));
-                return Stops::Continue;
-            } else {
-                logger.log(format!("Worker {id} failed: {:?}", error));
-                return Stops::UngracefullStop;
-            }
-        }
+    let error = match get_blocks_from_bundle(
+        id,
+        bundle,
+        stream,
+        safe_node_info,
+        scheduler,
+        downloading_headers,
+        logger,
+    ) {
+        Ok(()) => return Stops::Continue,
+        Err(error) => error,
     };
 
-    Stops::Continue
+    let mut scheduler = match scheduler.lock() {
+        Ok(scheduler) => scheduler,
+        Err(_) => return Stops::UngracefullStop,
+    };
+    if let BlockDownloaderError::BundleNotFound = error {
+        logger.log(format!("Worker {id} did not find bundle"));
+        scheduler.bundle_not_found(id);
+        Stops::Continue
+    } else {
+        logger.log(format!("Worker {id} failed: {:?}", error));
+        scheduler.peer_failed(id);
+        Stops::UngracefullStop
+    }
 }
 
 //=====================================================================================
@@ -88,29 +108,19 @@ pub fn block_downloader_thread_loop(
 #[derive(Debug)]
 pub struct BlockDownloader {
     workers: Vec<Worker>,
-    sender: mpsc::Sender<Bundle>,
-    receiver: SafeReceiver,
-    missed_bundles_sender: mpsc::Sender<Bundle>,
-    missed_bundles_receiver: mpsc::Receiver<Bundle>,
+    scheduler: SafeDownloadScheduler,
     safe_node_info: NodeSharedInformation,
-    downloading_headers_peer: Option<(TcpStream, usize)>,
+    downloading_headers_peer: Option<(PeerStream, usize)>,
     pub downloading_headers: FinishedIndicator,
     logger: Logger,
 }
 
 impl BlockDownloader {
     fn new(safe_node_info: NodeSharedInformation, logger: &Logger) -> BlockDownloader {
-        let (sender, receiver) = mpsc::channel();
-        let (missed_bundles_sender, missed_bundles_receiver) = mpsc::channel();
-
-        let receiver = Arc::new(Mutex::new(receiver));
         let downloading_headers = Arc::new(Mutex::from(true));
         BlockDownloader {
             workers: Vec::new(),
-            sender,
-            receiver,
-            missed_bundles_sender,
-            missed_bundles_receiver,
+            scheduler: Arc::new(Mutex::new(DownloadScheduler::new())),
             safe_node_info,
             downloading_headers_peer: None,
             downloading_headers,
@@ -120,7 +130,7 @@ impl BlockDownloader {
 
     /// Creates a new thread pool with the specified size, it must be greater than zero.
     pub fn from(
-        outbound_connections: &Vec<TcpStream>,
+        outbound_connections: &Vec<PeerStream>,
         header_stream_index: usize,
         safe_node_info: NodeSharedInformation,
         logger: &Logger,
@@ -156,13 +166,12 @@ impl BlockDownloader {
         Ok(block_downloader)
     }
 
-    fn add_worker(&mut self, stream: TcpStream, id: usize) {
+    fn add_worker(&mut self, stream: PeerStream, id: usize) {
         let worker = Worker::new_block_downloader_worker(
             id,
-            self.receiver.clone(),
+            self.scheduler.clone(),
             stream,
             self.safe_node_info.clone(),
-            self.missed_bundles_sender.clone(),
             self.downloading_headers.clone(),
             self.logger.clone(),
         );
@@ -170,74 +179,80 @@ impl BlockDownloader {
         self.workers.push(worker);
     }
 
-    /// Receives a function or closure that receives no parameters and executes them in a diferent thread using workers.x
+    /// Adds the blocks to the ones the workers must download. They are downloaded in the order
+    /// they were added. On error, it returns BlockDownloaderError.
     pub fn download_block_bundle(&self, bundle: Vec<[u8; 32]>) -> Result<(), BlockDownloaderError> {
         if bundle.is_empty() {
             return Ok(());
         }
 
-        match self.sender.send(bundle) {
-            Ok(_) => Ok(()),
-            Err(_err) => Err(BlockDownloaderError::ErrorSendingToThread),
-        }
+        self.scheduler
+            .lock()
+            .map_err(|_| BlockDownloaderError::ErrorSharingReference)?
+            .add_blocks(bundle);
+        Ok(())
+    }
+
+    /// Returns the shared reference to the scheduler that assigns the blocks to the workers.
+    pub fn get_scheduler(&self) -> SafeDownloadScheduler {
+        self.scheduler.clone()
     }
 
-    /// Writes an empty vector to the channel of the workers, so they can finish their execution. It works as
-    /// a way to stop the threads execution. On error, it returns BlockDownloaderError.
+    /// Returns the download statistics of each peer that was given blocks.
+    pub fn download_rates(&self) -> Result<Vec<PeerDownloadRate>, BlockDownloaderError> {
+        Ok(self
+            .scheduler
+            .lock()
+            .map_err(|_| BlockDownloaderError::ErrorSharingReference)?
+            .download_rates(Instant::now()))
+    }
+
+    /// Tells the workers that no more blocks will be added, so they finish once every block was
+    /// downloaded, and waits for them. The blocks that no worker could download are asked to the
+    /// peer of a worker that finished gracefully. On error, it returns BlockDownloaderError.
     pub fn finish_downloading(&mut self) -> Result<(), BlockDownloaderError> {
         if let Some((header_peer_stream, worker_id)) = self.downloading_headers_peer.take() {
             self.add_worker(header_peer_stream, worker_id);
         }
+        self.scheduler
+            .lock()
+            .map_err(|_| BlockDownloaderError::ErrorSharingReference)?
+            .finish_adding();
         let working_peer_conection = self.join_workers()?;
 
-        let mut stream = match working_peer_conection {
-            Some(stream) => stream,
-            None => return Err(BlockDownloaderError::ErrorAllWorkersFailed),
-        };
-
-        while let Ok(bundle) = self.missed_bundles_receiver.try_recv() {
-            get_blocks_from_bundle(
-                bundle,
-                &mut stream,
-                &self.safe_node_info,
-                false,
-                &self.logger,
-            )?;
+        let remaining_blocks = self
+            .scheduler
+            .lock()
+            .map_err(|_| BlockDownloaderError::ErrorSharingReference)?
+            .remaining_blocks();
+        if remaining_blocks.is_empty() {
+            return Ok(());
         }
 
-        Ok(())
+        let (mut stream, id) = match working_peer_conection {
+            Some(working_peer_conection) => working_peer_conection,
+            None => return Err(BlockDownloaderError::ErrorAllWorkersFailed),
+        };
+        get_blocks_from_bundle(
+            id,
+            remaining_blocks,
+            &mut stream,
+            &self.safe_node_info,
+            &self.scheduler,
+            false,
+            &self.logger,
+        )
     }
 
-    fn join_workers(&mut self) -> Result<Option<TcpStream>, BlockDownloaderError> {
-        let cantidad_workers = self.workers.len();
+    fn join_workers(&mut self) -> Result<Option<(PeerStream, usize)>, BlockDownloaderError> {
         let mut working_peer_conection = None;
-        for _ in 0..cantidad_workers {
-            let end_of_channel: Vec<[u8; 32]> = Vec::new();
-            if self.sender.send(end_of_channel).is_err() {
-                self.logger
-                    .log(String::from("Falló en el envio al end of channel"));
-                return Err(BlockDownloaderError::ErrorSendingToThread);
-            }
-
-            while let Ok(bundle) = self.missed_bundles_receiver.try_recv() {
-                self.download_block_bundle(bundle)?;
-            }
-
-            let mut joined_a_worker = false;
-
-            while !joined_a_worker {
-                let worker = self.workers.remove(0);
-                if worker.is_finished() {
-                    let stream_op = worker
-                        .join_thread()
-                        .map_err(|_| BlockDownloaderError::ErrorWorkerPanicked)?;
-                    if working_peer_conection.is_none() {
-                        working_peer_conection = stream_op;
-                    };
-                    joined_a_worker = true;
-                } else {
-                    self.workers.push(worker);
-                }
+        for worker in self.workers.drain(..) {
+            let id = worker._id;
+            let stream_op = worker
+                .join_thread()
+                .map_err(|_| BlockDownloaderError::ErrorWorkerPanicked)?;
+            if working_peer_conection.is_none() {
+                working_peer_conection = stream_op.map(|stream| (stream, id));
             }
         }
         Ok(working_peer_conection)
@@ -246,7 +261,7 @@ impl BlockDownloader {
 
 /// Receives messages until it receives either block or not found
 fn receive_block(
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     safe_node_info: &NodeSharedInformation,
     downloading_headers: bool,
     logger: &Logger,
@@ -265,7 +280,10 @@ fn receive_block(
                 NodeError::ErrorDownloadingBlockBundle => {
                     return Err(BlockDownloaderError::BundleNotFound)
                 }
-                NodeError::ErrorValidatingBlock => {
+                NodeError::ErrorValidatingBlock
+                | NodeError::ErrorInvalidProofOfWork
+                | NodeError::ErrorInvalidMerkleRoot
+                | NodeError::ErrorInvalidScript => {
                     return Err(BlockDownloaderError::ErrorValidatingBlock)
                 }
                 _ => return Err(BlockDownloaderError::ErrorReceivingBlockMessage),
@@ -277,9 +295,9 @@ fn receive_block(
 
 /// Sends a getdata message to the stream, requesting the blocks with the specified hashes.
 /// Returns an error if it was not possible to send the message.
-pub fn send_get_data_message_for_blocks(
+pub fn send_get_data_message_for_blocks<T: Read + Write>(
     hashes: Vec<[u8; 32]>,
-    stream: &mut TcpStream,
+    stream: &mut T,
 ) -> Result<(), BlockDownloaderError> {
     let get_data_message = GetDataMessage::create_message_inventory_block_type(hashes);
 
@@ -289,11 +307,43 @@ pub fn send_get_data_message_for_blocks(
     }
 }
 
-/// Receives a vector of block hashes and a TcpStream, and returns a vector of blocks that were requested to the stream
+/// Tells the scheduler which of the requested blocks are already stored, removing them from the
+/// pending ones.
+fn report_received_blocks(
+    id: usize,
+    pending_block_hashes: &mut Vec<[u8; 32]>,
+    safe_node_info: &NodeSharedInformation,
+    scheduler: &SafeDownloadScheduler,
+) -> Result<(), BlockDownloaderError> {
+    let received_block_hashes: Vec<[u8; 32]> = {
+        let blockchain = safe_node_info
+            .lock_blockchain()
+            .map_err(|_| BlockDownloaderError::ErrorSharingReference)?;
+        let (received, pending) = pending_block_hashes
+            .iter()
+            .partition(|hash| blockchain.contains_key(hash) || blockchain.is_pruned(hash));
+        *pending_block_hashes = pending;
+        received
+    };
+
+    let mut scheduler = scheduler
+        .lock()
+        .map_err(|_| BlockDownloaderError::ErrorSharingReference)?;
+    let now = Instant::now();
+    for block_hash in &received_block_hashes {
+        scheduler.block_received(id, block_hash, now);
+    }
+    Ok(())
+}
+
+/// Requests the blocks with the specified hashes to the stream and receives them, reporting each
+/// one to the scheduler as it arrives.
 fn get_blocks_from_bundle(
+    id: usize,
     requested_block_hashes: Vec<[u8; 32]>,
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     safe_node_info: &NodeSharedInformation,
+    scheduler: &SafeDownloadScheduler,
     downloading_headers: bool,
     logger: &Logger,
 ) -> Result<(), BlockDownloaderError> {
@@ -301,9 +351,11 @@ fn get_blocks_from_bundle(
         return Ok(());
     }
     let amount_of_hashes = requested_block_hashes.len();
+    let mut pending_block_hashes = requested_block_hashes.clone();
     send_get_data_message_for_blocks(requested_block_hashes, stream)?;
     for _ in 0..amount_of_hashes {
         receive_block(stream, safe_node_info, downloading_headers, logger)?;
+        report_received_blocks(id, &mut pending_block_hashes, safe_node_info, scheduler)?;
     }
 
     Ok(())
@@ -0,0 +1,333 @@
+use crate::node::*;
+
+use std::collections::HashMap;
+
+use compact_block_messages::short_id;
+
+const MAX_PENDING_COMPACT_BLOCKS: usize = 8;
+
+/// A block being reconstructed from a compact block. Each position of the block holds its
+/// transaction once it is known.
+#[derive(Debug)]
+pub struct PartialBlock {
+    header: BlockHeader,
+    transactions: Vec<Option<Transaction>>,
+}
+
+impl PartialBlock {
+    /// Places the prefilled transactions of the compact block, and the pending transactions whose short id
+    /// matches one of the block. Short ids matched by more than one pending transaction are left missing.
+    /// Returns None if the compact block is inconsistent or two of its short ids collide.
+    pub fn from(
+        cmpct_block_msg: CmpctBlockMessage,
+        pending_tx: &HashMap<[u8; 32], Transaction>,
+    ) -> Option<PartialBlock> {
+        let keys = cmpct_block_msg.short_id_keys();
+        let mut transactions: Vec<Option<Transaction>> = (0..cmpct_block_msg.transaction_count())
+            .map(|_| None)
+            .collect();
+        for prefilled in cmpct_block_msg.prefilled_txs {
+            let position = transactions.get_mut(prefilled.index)?;
+            if position.is_some() {
+                return None;
+            }
+            *position = Some(prefilled.tx);
+        }
+
+        let mut short_ids = cmpct_block_msg.short_ids.iter();
+        let mut positions_by_short_id = HashMap::new();
+        for (position, tx) in transactions.iter().enumerate() {
+            if tx.is_none() {
+                let short_id = *short_ids.next()?;
+                if positions_by_short_id.insert(short_id, position).is_some() {
+                    return None;
+                }
+            }
+        }
+
+        let mut matches: HashMap<usize, Option<&Transaction>> = HashMap::new();
+        for (txid, tx) in pending_tx {
+            if let Some(position) = positions_by_short_id.get(&short_id(keys, txid)) {
+                matches
+                    .entry(*position)
+                    .and_modify(|matched| *matched = None)
+                    .or_insert(Some(tx));
+            }
+        }
+        for (position, matched) in matches {
+            if let Some(tx) = matched {
+                transactions[position] = Transaction::from_bytes(&tx.to_bytes()).ok();
+            }
+        }
+
+        Some(PartialBlock {
+            header: cmpct_block_msg.header,
+            transactions,
+        })
+    }
+
+    pub fn block_hash(&self) -> [u8; 32] {
+        self.header.hash()
+    }
+
+    /// Returns the positions of the transactions that are still missing.
+    pub fn missing_indexes(&self) -> Vec<usize> {
+        self.transactions
+            .iter()
+            .enumerate()
+            .filter(|(_, tx)| tx.is_none())
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    /// Places the missing transactions, in the order of their positions. Returns false if the amount
+    /// of transactions does not match the amount missing.
+    pub fn fill(&mut self, transactions: Vec<Transaction>) -> bool {
+        let missing_indexes = self.missing_indexes();
+        if missing_indexes.len() != transactions.len() {
+            return false;
+        }
+        for (position, tx) in missing_indexes.into_iter().zip(transactions) {
+            self.transactions[position] = Some(tx);
+        }
+        true
+    }
+
+    /// Returns the block, or None if any of its transactions is still missing.
+    pub fn into_block(self) -> Option<Block> {
+        let transactions = self.transactions.into_iter().collect::<Option<Vec<_>>>()?;
+        Some(Block::new(self.header, transactions))
+    }
+}
+
+/// Receives the compact blocks of a peer (BIP152), reconstructing them from the pending transactions
+/// and asking the peer for the missing ones. If the peer supports compact blocks, new blocks it announces
+/// are asked for as compact blocks. Whenever a block cannot be reconstructed it is asked for in full.
+#[derive(Debug, Default)]
+pub struct CompactBlockReceiver {
+    peer_supports_compact_blocks: bool,
+    pending: HashMap<[u8; 32], PartialBlock>,
+}
+
+impl CompactBlockReceiver {
+    pub fn new() -> CompactBlockReceiver {
+        CompactBlockReceiver::default()
+    }
+
+    /// Processes the message if it has to do with compact blocks, returning the message to handle in its
+    /// place. Once a block is reconstructed, it is returned as a block message.
+    pub fn process<T: Read + Write>(
+        &mut self,
+        message: Message,
+        stream: &mut T,
+        safe_node_info: &NodeSharedInformation,
+    ) -> Result<Option<Message>, NodeError> {
+        match message {
+            Message::SendCmpct(send_cmpct) => {
+                if send_cmpct.get_version() == COMPACT_BLOCKS_VERSION
+                    && !safe_node_info.light_client
+                {
+                    self.peer_supports_compact_blocks = true;
+                }
+                Ok(Some(Message::SendCmpct(send_cmpct)))
+            }
+            Message::Inv(inv_msg) if self.peer_supports_compact_blocks => {
+                let block_hashes = inv_msg.get_block_hashes();
+                if block_hashes.is_empty() {
+                    return Ok(Some(Message::Inv(inv_msg)));
+                }
+                request_compact_blocks(block_hashes, stream, safe_node_info)?;
+                Ok(Some(Message::Inv(
+                    InvMessage::create_message_inventory_transaction_type(
+                        inv_msg.get_transaction_hashes(),
+                    ),
+                )))
+            }
+            Message::BlockHeaders(headers_msg) if self.peer_supports_compact_blocks => {
+                if !headers_msg.headers.iter().all(validate_proof_of_work) {
+                    return Err(NodeError::ErrorInvalidHeaders);
+                }
+                let block_hashes = headers_msg
+                    .headers
+                    .iter()
+                    .map(|header| header.hash())
+                    .collect();
+                request_compact_blocks(block_hashes, stream, safe_node_info)?;
+                Ok(None)
+            }
+            Message::CmpctBlock(cmpct_block_msg) => {
+                self.receive_compact_block(cmpct_block_msg, stream, safe_node_info)
+            }
+            Message::BlockTxn(block_txn_msg) => {
+                let mut partial_block = match self.pending.remove(&block_txn_msg.block_hash) {
+                    Some(partial_block) => partial_block,
+                    None => return Ok(None),
+                };
+                if !partial_block.fill(block_txn_msg.transactions) {
+                    request_full_block(block_txn_msg.block_hash, stream)?;
+                    return Ok(None);
+                }
+                self.complete_or_request_missing(partial_block, stream)
+            }
+            _ => Ok(Some(message)),
+        }
+    }
+
+    /// Starts reconstructing the block of the compact block, unless it is already in the blockchain.
+    fn receive_compact_block<T: Read + Write>(
+        &mut self,
+        cmpct_block_msg: CmpctBlockMessage,
+        stream: &mut T,
+        safe_node_info: &NodeSharedInformation,
+    ) -> Result<Option<Message>, NodeError> {
+        if !validate_proof_of_work(&cmpct_block_msg.header) {
+            return Err(NodeError::ErrorInvalidProofOfWork);
+        }
+        let block_hash = cmpct_block_msg.header.hash();
+        if safe_node_info.lock_blockchain()?.contains_key(&block_hash)
+            || self.pending.contains_key(&block_hash)
+        {
+            return Ok(None);
+        }
+        let partial_block = {
+            let pending_tx = safe_node_info.lock_safe_pending_tx()?;
+            PartialBlock::from(cmpct_block_msg, &pending_tx)
+        };
+        match partial_block {
+            Some(partial_block) => self.complete_or_request_missing(partial_block, stream),
+            None => {
+                request_full_block(block_hash, stream)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the block if every transaction is known and its merkle root matches. Otherwise asks the
+    /// peer for the missing transactions or, if there are too many blocks being reconstructed or the merkle
+    /// root does not match due to a short id collision, for the full block.
+    fn complete_or_request_missing<T: Read + Write>(
+        &mut self,
+        partial_block: PartialBlock,
+        stream: &mut T,
+    ) -> Result<Option<Message>, NodeError> {
+        let block_hash = partial_block.block_hash();
+        let missing_indexes = partial_block.missing_indexes();
+        if !missing_indexes.is_empty() {
+            if self.pending.len() >= MAX_PENDING_COMPACT_BLOCKS {
+                request_full_block(block_hash, stream)?;
+                return Ok(None);
+            }
+            GetBlockTxnMessage::new(block_hash, missing_indexes)
+                .send_to(stream)
+                .map_err(NodeError::ErrorMessage)?;
+            self.pending.insert(block_hash, partial_block);
+            return Ok(None);
+        }
+        match partial_block.into_block() {
+            Some(block) if validate_block_proof_of_inclusion(&block) => {
+                Ok(Some(Message::Block(BlockMessage { block })))
+            }
+            _ => {
+                request_full_block(block_hash, stream)?;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// Asks for the blocks that are not in the blockchain as compact blocks.
+fn request_compact_blocks<T: Read + Write>(
+    block_hashes: Vec<[u8; 32]>,
+    stream: &mut T,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    let request_block_hashes: Vec<[u8; 32]> = {
+        let blockchain = safe_node_info.lock_blockchain()?;
+        block_hashes
+            .into_iter()
+            .filter(|hash| !blockchain.contains_key(hash))
+            .collect()
+    };
+    if request_block_hashes.is_empty() {
+        return Ok(());
+    }
+    GetDataMessage::create_message_inventory_compact_block_type(request_block_hashes)
+        .send_to(stream)
+        .map_err(NodeError::ErrorMessage)
+}
+
+fn request_full_block<T: Read + Write>(
+    block_hash: [u8; 32],
+    stream: &mut T,
+) -> Result<(), NodeError> {
+    GetDataMessage::create_message_inventory_block_type(vec![block_hash])
+        .send_to(stream)
+        .map_err(NodeError::ErrorMessage)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn block() -> Block {
+        let transactions = (0..4)
+            .map(|lock_time| Transaction::new(1, Vec::new(), Vec::new(), lock_time))
+            .collect();
+        Block::new(
+            BlockHeader::new(70015, [1; 32], [2; 32], 0x1d00ffff),
+            transactions,
+        )
+    }
+
+    fn pending_tx(block: &Block, positions: &[usize]) -> HashMap<[u8; 32], Transaction> {
+        positions
+            .iter()
+            .map(|position| {
+                let tx = &block.get_transactions()[*position];
+                (tx.hash(), Transaction::from_bytes(&tx.to_bytes()).unwrap())
+            })
+            .collect()
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn compact_blocks_test_1_block_is_reconstructed_from_pending_transactions() {
+        let block = block();
+        let cmpct_block_msg = CmpctBlockMessage::from_block(&block, 1).unwrap();
+
+        let partial_block =
+            PartialBlock::from(cmpct_block_msg, &pending_tx(&block, &[1, 2, 3])).unwrap();
+
+        assert!(partial_block.missing_indexes().is_empty());
+        assert_eq!(
+            partial_block.into_block().unwrap().to_bytes(),
+            block.to_bytes()
+        );
+    }
+
+    #[test]
+    fn compact_blocks_test_2_missing_transactions_are_filled_in_order() {
+        let block = block();
+        let cmpct_block_msg = CmpctBlockMessage::from_block(&block, 2).unwrap();
+        let mut partial_block =
+            PartialBlock::from(cmpct_block_msg, &pending_tx(&block, &[2])).unwrap();
+
+        assert_eq!(partial_block.missing_indexes(), vec![1, 3]);
+        assert!(!partial_block.fill(pending_tx(&block, &[1]).into_values().collect()));
+
+        let missing = vec![
+            Transaction::from_bytes(&block.get_transactions()[1].to_bytes()).unwrap(),
+            Transaction::from_bytes(&block.get_transactions()[3].to_bytes()).unwrap(),
+        ];
+        assert!(partial_block.fill(missing));
+        assert_eq!(
+            partial_block.into_block().unwrap().to_bytes(),
+            block.to_bytes()
+        );
+    }
+}
@@ -0,0 +1,72 @@
+use std::io::{Read, Result, Write};
+
+/// Wraps a stream, counting the bytes written to it and read from it.
+#[derive(Debug)]
+pub struct CountingStream<'a, T: Read + Write> {
+    stream: &'a mut T,
+    bytes_sent: u64,
+    bytes_received: u64,
+}
+
+impl<'a, T: Read + Write> CountingStream<'a, T> {
+    pub fn new(stream: &'a mut T) -> CountingStream<'a, T> {
+        CountingStream {
+            stream,
+            bytes_sent: 0,
+            bytes_received: 0,
+        }
+    }
+
+    /// Returns the wrapped stream.
+    pub fn get_ref(&self) -> &T {
+        self.stream
+    }
+
+    /// Returns the bytes sent and received since the stream was wrapped.
+    pub fn get_traffic(&self) -> (u64, u64) {
+        (self.bytes_sent, self.bytes_received)
+    }
+}
+
+impl<T: Read + Write> Read for CountingStream<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let read = self.stream.read(buf)?;
+        self.bytes_received += read as u64;
+        Ok(read)
+    }
+}
+
+impl<T: Read + Write> Write for CountingStream<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.stream.write(buf)?;
+        self.bytes_sent += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::mock_tcp_stream::MockTcpStream;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn counting_stream_test_1_counts_bytes_in_both_directions() {
+        let mut mock = MockTcpStream::new();
+        mock.read_buffer = vec![1, 2, 3, 4];
+        let mut stream = CountingStream::new(&mut mock);
+
+        stream.write_all(&[9; 10]).unwrap();
+        let mut buffer = [0; 3];
+        stream.read_exact(&mut buffer).unwrap();
+
+        assert_eq!(stream.get_traffic(), (10, 3));
+        assert_eq!(mock.write_buffer, vec![9; 10]);
+    }
+}
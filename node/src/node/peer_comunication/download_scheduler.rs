@@ -0,0 +1,504 @@
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub const INITIAL_BUNDLE_SIZE: usize = 16;
+pub const MIN_BUNDLE_SIZE: usize = 1;
+pub const MAX_BUNDLE_SIZE: usize = 128;
+pub const DOWNLOAD_WINDOW: usize = 1024;
+pub const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+const FAST_BUNDLE_TIME: Duration = Duration::from_secs(2);
+const SLOW_BUNDLE_TIME: Duration = Duration::from_secs(8);
+
+pub type SafeDownloadScheduler = Arc<Mutex<DownloadScheduler>>;
+
+/// Download statistics of a peer, as reported to the logs and the UI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerDownloadRate {
+    pub peer_id: usize,
+    pub downloaded_blocks: usize,
+    pub blocks_per_second: f64,
+    pub bundle_size: usize,
+    pub stalls: usize,
+}
+
+/// State of the downloads assigned to a single peer.
+#[derive(Debug)]
+struct PeerDownloadState {
+    bundle_size: usize,
+    in_flight: HashSet<usize>,
+    bundle_start: Option<(Instant, usize)>,
+    last_progress: Instant,
+    downloaded_blocks: usize,
+    downloading_time: Duration,
+    not_found: HashSet<usize>,
+    stalls: usize,
+    failed: bool,
+}
+
+impl PeerDownloadState {
+    fn new(now: Instant) -> PeerDownloadState {
+        PeerDownloadState {
+            bundle_size: INITIAL_BUNDLE_SIZE,
+            in_flight: HashSet::new(),
+            bundle_start: None,
+            last_progress: now,
+            downloaded_blocks: 0,
+            downloading_time: Duration::ZERO,
+            not_found: HashSet::new(),
+            stalls: 0,
+            failed: false,
+        }
+    }
+
+    /// Adapts the bundle size to the time the peer took to deliver its last bundle.
+    fn finish_bundle(&mut self, now: Instant) {
+        let (start, bundle_len) = match self.bundle_start.take() {
+            Some(bundle_start) => bundle_start,
+            None => return,
+        };
+        let elapsed = now.saturating_duration_since(start);
+        self.downloading_time += elapsed;
+        if elapsed < FAST_BUNDLE_TIME && bundle_len == self.bundle_size {
+            self.bundle_size = (self.bundle_size * 2).min(MAX_BUNDLE_SIZE);
+        } else if elapsed > SLOW_BUNDLE_TIME {
+            self.bundle_size = (self.bundle_size / 2).max(MIN_BUNDLE_SIZE);
+        }
+    }
+}
+
+/// Decides which blocks each peer downloads. Blocks are handed out in chain order, but only
+/// those within DOWNLOAD_WINDOW blocks of the oldest block not yet received, so a slow peer can
+/// not leave a gap behind the rest. Peers that make no progress for STALL_TIMEOUT lose their
+/// blocks to the others, and the size of the bundle each peer gets follows its throughput.
+/// Blocks that none of the connected peers has are given up, so the window moves past them.
+#[derive(Debug, Default)]
+pub struct DownloadScheduler {
+    hashes: Vec<[u8; 32]>,
+    positions: HashMap<[u8; 32], usize>,
+    unassigned: BTreeSet<usize>,
+    assigned_to: HashMap<usize, usize>,
+    received: HashSet<usize>,
+    given_up: BTreeSet<usize>,
+    window_start: usize,
+    peers: HashMap<usize, PeerDownloadState>,
+    finished_adding: bool,
+}
+
+impl DownloadScheduler {
+    pub fn new() -> DownloadScheduler {
+        DownloadScheduler::default()
+    }
+
+    /// Adds the blocks to the end of the download queue, ignoring the ones already added.
+    pub fn add_blocks(&mut self, block_hashes: Vec<[u8; 32]>) {
+        for block_hash in block_hashes {
+            if self.positions.contains_key(&block_hash) {
+                continue;
+            }
+            let position = self.hashes.len();
+            self.hashes.push(block_hash);
+            self.positions.insert(block_hash, position);
+            self.unassigned.insert(position);
+        }
+    }
+
+    /// Indicates that no more blocks will be added, so peers without work can stop.
+    pub fn finish_adding(&mut self) {
+        self.finished_adding = true;
+    }
+
+    /// Returns the next bundle of blocks the peer must request, or None if there is nothing
+    /// it can download right now. Blocks the peer still had in flight go back to the queue.
+    pub fn next_bundle(&mut self, peer_id: usize, now: Instant) -> Option<Vec<[u8; 32]>> {
+        self.release_blocks(peer_id, false);
+        let window_end = self.window_start + DOWNLOAD_WINDOW;
+        let peer = self
+            .peers
+            .entry(peer_id)
+            .or_insert_with(|| PeerDownloadState::new(now));
+
+        let positions: Vec<usize> = self
+            .unassigned
+            .range(..window_end)
+            .filter(|position| !peer.not_found.contains(position))
+            .take(peer.bundle_size)
+            .copied()
+            .collect();
+        if positions.is_empty() {
+            return None;
+        }
+
+        for position in &positions {
+            self.unassigned.remove(position);
+            self.assigned_to.insert(*position, peer_id);
+            peer.in_flight.insert(*position);
+        }
+        peer.bundle_start = Some((now, positions.len()));
+        peer.last_progress = now;
+        Some(
+            positions
+                .iter()
+                .map(|position| self.hashes[*position])
+                .collect(),
+        )
+    }
+
+    /// Registers that the peer delivered the block. Blocks that were not requested or were
+    /// already delivered by another peer are ignored.
+    pub fn block_received(&mut self, peer_id: usize, block_hash: &[u8; 32], now: Instant) {
+        let position = match self.positions.get(block_hash) {
+            Some(position) => *position,
+            None => return,
+        };
+        if self.given_up.remove(&position) {
+            if position >= self.window_start {
+                self.received.insert(position);
+            }
+        } else if position < self.window_start || !self.received.insert(position) {
+            return;
+        }
+        self.unassigned.remove(&position);
+        if let Some(owner) = self.assigned_to.remove(&position) {
+            if let Some(owner) = self.peers.get_mut(&owner) {
+                owner.in_flight.remove(&position);
+            }
+        }
+        self.advance_window();
+
+        let peer = self
+            .peers
+            .entry(peer_id)
+            .or_insert_with(|| PeerDownloadState::new(now));
+        peer.downloaded_blocks += 1;
+        peer.last_progress = now;
+        if peer.in_flight.is_empty() {
+            peer.finish_bundle(now);
+        }
+    }
+
+    /// Returns the blocks in flight of the peer to the queue, marking them as blocks the peer
+    /// does not have so they are asked to someone else.
+    pub fn bundle_not_found(&mut self, peer_id: usize) {
+        self.release_blocks(peer_id, true);
+        self.give_up_missing_blocks();
+    }
+
+    /// Returns the blocks in flight of a peer that disconnected to the queue.
+    pub fn peer_failed(&mut self, peer_id: usize) {
+        self.release_blocks(peer_id, false);
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.failed = true;
+        }
+        self.give_up_missing_blocks();
+    }
+
+    /// Moves the start of the window past the blocks that were received or given up.
+    fn advance_window(&mut self) {
+        while self.received.remove(&self.window_start) || self.given_up.contains(&self.window_start)
+        {
+            self.window_start += 1;
+        }
+    }
+
+    /// Gives up the blocks in the window that every connected peer reported as not found, so the
+    /// window does not wait for them. They stay in the queue for the peers that connect later, and
+    /// are asked for once the download finishes.
+    fn give_up_missing_blocks(&mut self) {
+        let connected_peers: Vec<&PeerDownloadState> =
+            self.peers.values().filter(|peer| !peer.failed).collect();
+        if connected_peers.is_empty() {
+            return;
+        }
+        let window_end = self.window_start + DOWNLOAD_WINDOW;
+        let missing_blocks: Vec<usize> = self
+            .unassigned
+            .range(..window_end)
+            .filter(|position| {
+                connected_peers
+                    .iter()
+                    .all(|peer| peer.not_found.contains(position))
+            })
+            .copied()
+            .collect();
+        self.given_up.extend(missing_blocks);
+        self.advance_window();
+    }
+
+    fn release_blocks(&mut self, peer_id: usize, not_found: bool) {
+        let peer = match self.peers.get_mut(&peer_id) {
+            Some(peer) => peer,
+            None => return,
+        };
+        for position in peer.in_flight.drain() {
+            self.assigned_to.remove(&position);
+            self.unassigned.insert(position);
+            if not_found {
+                peer.not_found.insert(position);
+            }
+        }
+        peer.bundle_start = None;
+    }
+
+    /// Takes the blocks from the peers that did not deliver any block for STALL_TIMEOUT, so
+    /// that other peers download them, and halves the bundle size of those peers.
+    /// Returns the ids of the stalled peers.
+    pub fn reassign_stalled(&mut self, now: Instant) -> Vec<usize> {
+        let mut stalled_peers: Vec<usize> = self
+            .peers
+            .iter()
+            .filter(|(_, peer)| {
+                !peer.in_flight.is_empty()
+                    && now.saturating_duration_since(peer.last_progress) > STALL_TIMEOUT
+            })
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        stalled_peers.sort_unstable();
+
+        for peer_id in &stalled_peers {
+            self.release_blocks(*peer_id, false);
+            if let Some(peer) = self.peers.get_mut(peer_id) {
+                peer.stalls += 1;
+                peer.bundle_size = (peer.bundle_size / 2).max(MIN_BUNDLE_SIZE);
+            }
+        }
+        stalled_peers
+    }
+
+    /// Returns true if the peer has nothing left to do: no more blocks will be added, none is
+    /// in flight and the peer does not have any of the blocks left in the queue.
+    pub fn can_stop(&self, peer_id: usize) -> bool {
+        if !self.finished_adding || !self.assigned_to.is_empty() {
+            return false;
+        }
+        match self.peers.get(&peer_id) {
+            Some(peer) => self
+                .unassigned
+                .iter()
+                .all(|position| peer.not_found.contains(position)),
+            None => self.unassigned.is_empty(),
+        }
+    }
+
+    /// Returns true if every block added was received.
+    pub fn is_finished(&self) -> bool {
+        self.unassigned.is_empty() && self.assigned_to.is_empty()
+    }
+
+    /// Returns the hashes of the blocks not received yet, in chain order.
+    pub fn remaining_blocks(&self) -> Vec<[u8; 32]> {
+        let mut positions: Vec<usize> = self
+            .unassigned
+            .iter()
+            .chain(self.assigned_to.keys())
+            .copied()
+            .collect();
+        positions.sort_unstable();
+        positions
+            .iter()
+            .map(|position| self.hashes[*position])
+            .collect()
+    }
+
+    /// Returns the download statistics of every peer that was given blocks, ordered by id.
+    pub fn download_rates(&self, now: Instant) -> Vec<PeerDownloadRate> {
+        let mut rates: Vec<PeerDownloadRate> = self
+            .peers
+            .iter()
+            .map(|(peer_id, peer)| {
+                let mut downloading_time = peer.downloading_time;
+                if let Some((start, _)) = peer.bundle_start {
+                    downloading_time += now.saturating_duration_since(start);
+                }
+                let blocks_per_second = match downloading_time.as_secs_f64() {
+                    seconds if seconds > 0.0 => peer.downloaded_blocks as f64 / seconds,
+                    _ => 0.0,
+                };
+                PeerDownloadRate {
+                    peer_id: *peer_id,
+                    downloaded_blocks: peer.downloaded_blocks,
+                    blocks_per_second,
+                    bundle_size: peer.bundle_size,
+                    stalls: peer.stalls,
+                }
+            })
+            .collect();
+        rates.sort_by_key(|rate| rate.peer_id);
+        rates
+    }
+}
+
+/// Formats the download rates as a single line, e.g. "peer 0: 12.5 blocks/s, peer 1: 3.0 blocks/s".
+pub fn format_download_rates(rates: &[PeerDownloadRate]) -> String {
+    rates
+        .iter()
+        .map(|rate| {
+            format!(
+                "peer {}: {:.1} blocks/s",
+                rate.peer_id, rate.blocks_per_second
+            )
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn block_hashes(amount: usize) -> Vec<[u8; 32]> {
+        (0..amount as u32)
+            .map(|i| {
+                let mut hash = [1; 32];
+                hash[..4].copy_from_slice(&i.to_le_bytes());
+                hash
+            })
+            .collect()
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn download_scheduler_test_1_blocks_are_assigned_in_order_to_each_peer() {
+        let now = Instant::now();
+        let hashes = block_hashes(40);
+        let mut scheduler = DownloadScheduler::new();
+        scheduler.add_blocks(hashes.clone());
+
+        let first_bundle = scheduler.next_bundle(0, now).unwrap();
+        let second_bundle = scheduler.next_bundle(1, now).unwrap();
+        let third_bundle = scheduler.next_bundle(2, now).unwrap();
+
+        assert_eq!(first_bundle, hashes[..16].to_vec());
+        assert_eq!(second_bundle, hashes[16..32].to_vec());
+        assert_eq!(third_bundle, hashes[32..].to_vec());
+        assert_eq!(scheduler.next_bundle(3, now), None);
+    }
+
+    #[test]
+    fn download_scheduler_test_2_stalled_peer_loses_its_blocks() {
+        let now = Instant::now();
+        let hashes = block_hashes(16);
+        let mut scheduler = DownloadScheduler::new();
+        scheduler.add_blocks(hashes.clone());
+        scheduler.next_bundle(0, now).unwrap();
+        scheduler.block_received(0, &hashes[0], now);
+
+        assert!(scheduler.reassign_stalled(now + STALL_TIMEOUT).is_empty());
+        assert_eq!(scheduler.next_bundle(1, now), None);
+
+        let later = now + STALL_TIMEOUT + Duration::from_secs(1);
+        assert_eq!(scheduler.reassign_stalled(later), vec![0]);
+        assert_eq!(
+            scheduler.next_bundle(1, later).unwrap(),
+            hashes[1..].to_vec()
+        );
+
+        let rates = scheduler.download_rates(later);
+        assert_eq!(rates[0].stalls, 1);
+        assert_eq!(rates[0].bundle_size, INITIAL_BUNDLE_SIZE / 2);
+    }
+
+    #[test]
+    fn download_scheduler_test_3_bundle_size_follows_the_throughput() {
+        let now = Instant::now();
+        let hashes = block_hashes(200);
+        let mut scheduler = DownloadScheduler::new();
+        scheduler.add_blocks(hashes);
+
+        let fast_bundle = scheduler.next_bundle(0, now).unwrap();
+        for hash in &fast_bundle {
+            scheduler.block_received(0, hash, now + Duration::from_millis(500));
+        }
+        let slow_bundle = scheduler.next_bundle(1, now).unwrap();
+        let slow_end = now + SLOW_BUNDLE_TIME + Duration::from_secs(1);
+        for hash in &slow_bundle {
+            scheduler.block_received(1, hash, slow_end);
+        }
+
+        let rates = scheduler.download_rates(slow_end);
+        assert_eq!(rates[0].downloaded_blocks, 16);
+        assert_eq!(rates[0].blocks_per_second, 32.0);
+        assert_eq!(scheduler.next_bundle(0, now).unwrap().len(), 32);
+        assert_eq!(scheduler.next_bundle(1, slow_end).unwrap().len(), 8);
+    }
+
+    #[test]
+    fn download_scheduler_test_4_blocks_outside_the_window_wait() {
+        let now = Instant::now();
+        let hashes = block_hashes(DOWNLOAD_WINDOW + 10);
+        let mut scheduler = DownloadScheduler::new();
+        scheduler.add_blocks(hashes.clone());
+        scheduler.next_bundle(0, now).unwrap();
+        let mut greedy_peer = PeerDownloadState::new(now);
+        greedy_peer.bundle_size = 2 * DOWNLOAD_WINDOW;
+        scheduler.peers.insert(1, greedy_peer);
+
+        let bundle = scheduler.next_bundle(1, now).unwrap();
+        assert_eq!(bundle.len(), DOWNLOAD_WINDOW - INITIAL_BUNDLE_SIZE);
+        assert_eq!(scheduler.next_bundle(2, now), None);
+
+        scheduler.block_received(0, &hashes[0], now);
+
+        let bundle = scheduler.next_bundle(2, now).unwrap();
+        assert_eq!(bundle, vec![hashes[DOWNLOAD_WINDOW]]);
+    }
+
+    #[test]
+    fn download_scheduler_test_5_peers_stop_when_everything_was_received() {
+        let now = Instant::now();
+        let hashes = block_hashes(2);
+        let mut scheduler = DownloadScheduler::new();
+        scheduler.add_blocks(hashes.clone());
+        scheduler.next_bundle(0, now).unwrap();
+        scheduler.bundle_not_found(0);
+
+        assert!(!scheduler.can_stop(0));
+        scheduler.finish_adding();
+        assert!(scheduler.can_stop(0));
+        assert!(!scheduler.can_stop(1));
+        assert_eq!(scheduler.remaining_blocks(), hashes);
+
+        scheduler.next_bundle(1, now).unwrap();
+        assert!(!scheduler.can_stop(0));
+        scheduler.block_received(1, &hashes[0], now);
+        scheduler.block_received(1, &hashes[1], now);
+
+        assert!(scheduler.is_finished());
+        assert!(scheduler.can_stop(1));
+    }
+
+    #[test]
+    fn download_scheduler_test_6_block_no_peer_has_is_given_up() {
+        let now = Instant::now();
+        let hashes = block_hashes(DOWNLOAD_WINDOW + 1);
+        let mut scheduler = DownloadScheduler::new();
+        scheduler.add_blocks(hashes.clone());
+        scheduler.finish_adding();
+        scheduler.next_bundle(0, now).unwrap();
+        scheduler.bundle_not_found(0);
+
+        let bundle = scheduler.next_bundle(1, now).unwrap();
+        assert_eq!(bundle, hashes[..INITIAL_BUNDLE_SIZE].to_vec());
+        for hash in &bundle[1..] {
+            scheduler.block_received(1, hash, now);
+        }
+        scheduler.bundle_not_found(1);
+
+        while let Some(bundle) = scheduler.next_bundle(1, now) {
+            for hash in &bundle {
+                scheduler.block_received(1, hash, now);
+            }
+        }
+        assert!(scheduler.can_stop(0));
+        assert!(scheduler.can_stop(1));
+        assert!(!scheduler.is_finished());
+        assert_eq!(scheduler.remaining_blocks(), vec![hashes[0]]);
+    }
+}
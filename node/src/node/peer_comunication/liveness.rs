@@ -0,0 +1,133 @@
+use crate::{messages::Message, node::*};
+
+use std::time::Instant;
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(2 * 60);
+pub const PING_TIMEOUT: Duration = Duration::from_secs(20 * 60);
+pub const STALE_TIP_TIMEOUT: u32 = 20 * 60;
+pub const HEADERS_RESPONSE_TIMEOUT: u32 = 2 * 60;
+
+/// Keeps track of the pings sent to a peer. Only one ping is in flight at a time, and a new
+/// one is sent PING_INTERVAL after the previous one was sent.
+#[derive(Debug, Default)]
+pub struct PingTracker {
+    pending: Option<(u64, Instant)>,
+    last_ping: Option<Instant>,
+}
+
+impl PingTracker {
+    pub fn new() -> PingTracker {
+        PingTracker::default()
+    }
+
+    /// Returns true if no ping is waiting for its pong and the last one was sent long enough ago.
+    pub fn should_ping(&self, now: Instant) -> bool {
+        if self.pending.is_some() {
+            return false;
+        }
+        match self.last_ping {
+            Some(last_ping) => now.saturating_duration_since(last_ping) >= PING_INTERVAL,
+            None => true,
+        }
+    }
+
+    /// Registers a ping sent with the given nonce.
+    pub fn ping_sent(&mut self, nonce: u64, now: Instant) {
+        self.pending = Some((nonce, now));
+        self.last_ping = Some(now);
+    }
+
+    /// Returns the round trip time of the ping answered by the pong, or None if the nonce
+    /// does not match the one of the ping in flight.
+    pub fn pong_received(&mut self, nonce: u64, now: Instant) -> Option<Duration> {
+        match self.pending {
+            Some((pending_nonce, sent)) if pending_nonce == nonce => {
+                self.pending = None;
+                Some(now.saturating_duration_since(sent))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the ping in flight was sent more than PING_TIMEOUT ago.
+    pub fn timed_out(&self, now: Instant) -> bool {
+        match self.pending {
+            Some((_, sent)) => now.saturating_duration_since(sent) > PING_TIMEOUT,
+            None => false,
+        }
+    }
+}
+
+/// Returns the hashes of the blocks a message shows the peer has.
+pub fn announced_block_hashes(message: &Message) -> Vec<[u8; 32]> {
+    match message {
+        Message::Inv(inv_msg) => inv_msg.get_block_hashes(),
+        Message::BlockHeaders(headers_msg) => match headers_msg.headers.last() {
+            Some(header) => vec![header.hash()],
+            None => Vec::new(),
+        },
+        Message::Block(block_msg) => vec![block_msg.block.header_hash()],
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the height of the highest of the blocks that is already in the chain of the node.
+pub fn highest_known_height(
+    block_hashes: &[[u8; 32]],
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Option<usize>, NodeError> {
+    let headers_index = safe_node_info.read_headers_index()?;
+    Ok(block_hashes
+        .iter()
+        .filter_map(|hash| headers_index.get(hash))
+        .map(|index| index + 1)
+        .max())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn liveness_test_1_only_the_pending_nonce_is_accepted() {
+        let now = Instant::now();
+        let mut ping_tracker = PingTracker::new();
+        assert!(ping_tracker.should_ping(now));
+
+        ping_tracker.ping_sent(7, now);
+
+        assert!(!ping_tracker.should_ping(now + PING_INTERVAL));
+        assert_eq!(ping_tracker.pong_received(8, now), None);
+        assert_eq!(
+            ping_tracker.pong_received(7, now + Duration::from_millis(150)),
+            Some(Duration::from_millis(150))
+        );
+        assert_eq!(ping_tracker.pong_received(7, now), None);
+    }
+
+    #[test]
+    fn liveness_test_2_next_ping_waits_for_the_interval() {
+        let now = Instant::now();
+        let mut ping_tracker = PingTracker::new();
+
+        ping_tracker.ping_sent(1, now);
+        ping_tracker.pong_received(1, now);
+
+        assert!(!ping_tracker.should_ping(now + Duration::from_secs(1)));
+        assert!(ping_tracker.should_ping(now + PING_INTERVAL));
+    }
+
+    #[test]
+    fn liveness_test_3_unanswered_ping_times_out() {
+        let now = Instant::now();
+        let mut ping_tracker = PingTracker::new();
+
+        ping_tracker.ping_sent(1, now);
+
+        assert!(!ping_tracker.timed_out(now + PING_TIMEOUT));
+        assert!(ping_tracker.timed_out(now + PING_TIMEOUT + Duration::from_secs(1)));
+    }
+}
@@ -0,0 +1,314 @@
+use crate::{
+    node::{
+        ban_list::{SafeBanList, BAN_DURATION},
+        *,
+    },
+    utils::PeerComunicatorError,
+};
+
+use std::{collections::HashMap, net::IpAddr, sync::mpsc, time::Instant};
+
+use workers::Worker;
+
+pub const BAN_THRESHOLD: u32 = 100;
+const UNKNOWN_COMMANDS_WINDOW: Duration = Duration::from_secs(60);
+const MAX_UNKNOWN_COMMANDS_PER_WINDOW: u32 = 50;
+const SCORE_MEMORY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Address of the misbehaving peer and what it did, as the workers report it to the worker manager.
+pub type OffenseReport = (SocketAddr, Offense);
+
+/// Things a peer can do wrong, each one adds its score to the misbehavior score of the peer.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Offense {
+    InvalidProofOfWork,
+    InvalidMerkleRoot,
+    InvalidBlock,
+    InvalidHeaders,
+    OversizedMessage,
+    MalformedMessage,
+    UnknownCommandFlood,
+}
+
+impl Offense {
+    pub fn score(&self) -> u32 {
+        match self {
+            Offense::InvalidProofOfWork
+            | Offense::InvalidMerkleRoot
+            | Offense::InvalidBlock
+            | Offense::InvalidHeaders => 100,
+            Offense::OversizedMessage => 50,
+            Offense::MalformedMessage | Offense::UnknownCommandFlood => 20,
+        }
+    }
+
+    /// Returns the offense committed by a peer that sent a message that could not be received.
+    pub fn from_receiving_error(error: &NodeError) -> Option<Offense> {
+        match error {
+            NodeError::ErrorOversizedMessage => Some(Offense::OversizedMessage),
+            NodeError::ErrorMessage(_) => Some(Offense::MalformedMessage),
+            _ => None,
+        }
+    }
+
+    /// Returns the offense committed by a peer that sent a message that could not be handled.
+    pub fn from_handling_error(error: &NodeError) -> Option<Offense> {
+        match error {
+            NodeError::ErrorInvalidProofOfWork => Some(Offense::InvalidProofOfWork),
+            NodeError::ErrorInvalidMerkleRoot => Some(Offense::InvalidMerkleRoot),
+            NodeError::ErrorValidatingBlock | NodeError::ErrorInvalidScript => {
+                Some(Offense::InvalidBlock)
+            }
+            NodeError::ErrorInvalidHeaders => Some(Offense::InvalidHeaders),
+            _ => None,
+        }
+    }
+}
+
+/// Counts the unknown commands a peer sends, so a flood of them can be told apart from
+/// the few messages of protocol extensions the node does not support.
+#[derive(Debug)]
+pub struct UnknownCommandCounter {
+    window_start: Instant,
+    count: u32,
+}
+
+impl Default for UnknownCommandCounter {
+    fn default() -> Self {
+        UnknownCommandCounter::new()
+    }
+}
+
+impl UnknownCommandCounter {
+    pub fn new() -> UnknownCommandCounter {
+        UnknownCommandCounter {
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    /// Registers an unknown command, returning true if too many were received within the last window.
+    pub fn register(&mut self) -> bool {
+        if self.window_start.elapsed() > UNKNOWN_COMMANDS_WINDOW {
+            self.window_start = Instant::now();
+            self.count = 0;
+        }
+        self.count += 1;
+        if self.count > MAX_UNKNOWN_COMMANDS_PER_WINDOW {
+            self.count = 0;
+            return true;
+        }
+        false
+    }
+}
+
+/// Keeps the misbehavior score of each ip, like the ban list, so a peer can not clear its score by
+/// reconnecting from another port. Once an ip reaches the threshold it gets banned and every worker
+/// connected to it is disconnected.
+#[derive(Debug)]
+pub struct MisbehaviorTracker {
+    scores: HashMap<IpAddr, (u32, Instant)>,
+    offense_sender: mpsc::Sender<OffenseReport>,
+    offense_receiver: mpsc::Receiver<OffenseReport>,
+}
+
+impl Default for MisbehaviorTracker {
+    fn default() -> Self {
+        MisbehaviorTracker::new()
+    }
+}
+
+impl MisbehaviorTracker {
+    pub fn new() -> MisbehaviorTracker {
+        let (offense_sender, offense_receiver) = mpsc::channel();
+        MisbehaviorTracker {
+            scores: HashMap::new(),
+            offense_sender,
+            offense_receiver,
+        }
+    }
+
+    /// Returns a sender through which a worker can report the offenses of its peer.
+    pub fn get_offense_sender(&self) -> mpsc::Sender<OffenseReport> {
+        self.offense_sender.clone()
+    }
+
+    /// Returns the current misbehavior score of the ip of the peer.
+    pub fn get_score(&self, peer_address: &SocketAddr) -> u32 {
+        match self.scores.get(&peer_address.ip()) {
+            Some((score, _)) => *score,
+            None => 0,
+        }
+    }
+
+    /// Adds the score of every reported offense, banning the ips that reached the threshold.
+    /// Scores of ips that are not connected and did not misbehave within SCORE_MEMORY are forgotten.
+    pub fn process_offenses(
+        &mut self,
+        workers: &[Worker],
+        safe_ban_list: &SafeBanList,
+        logger: &Logger,
+    ) -> Result<(), PeerComunicatorError> {
+        let mut offenders = Vec::new();
+        while let Ok((peer_address, offense)) = self.offense_receiver.try_recv() {
+            logger.log(format!("Peer {} misbehaved: {:?}", peer_address, offense));
+            let (score, last_offense) = self
+                .scores
+                .entry(peer_address.ip())
+                .or_insert((0, Instant::now()));
+            *score += offense.score();
+            *last_offense = Instant::now();
+            if *score >= BAN_THRESHOLD && !offenders.contains(&peer_address.ip()) {
+                offenders.push(peer_address.ip());
+            }
+        }
+
+        if !offenders.is_empty() {
+            let now = Utc::now().timestamp() as u32;
+            let mut ban_list = safe_ban_list
+                .lock()
+                .map_err(|_| PeerComunicatorError::ErrorBanningPeer)?;
+            for ip in &offenders {
+                logger.log(format!("Banning peer {}", ip));
+                ban_list.ban(*ip, now, BAN_DURATION);
+            }
+            if let Err(error) = ban_list.save() {
+                logger.log_error(&error);
+            }
+            for worker in workers {
+                if let Some(peer_address) = worker.get_peer_address() {
+                    if offenders.contains(&peer_address.ip()) {
+                        worker.disconnect();
+                    }
+                }
+            }
+        }
+
+        let connected: Vec<IpAddr> = workers
+            .iter()
+            .filter_map(|worker| worker.get_peer_address())
+            .map(|peer_address| peer_address.ip())
+            .collect();
+        self.scores.retain(|ip, (_, last_offense)| {
+            connected.contains(ip) || last_offense.elapsed() < SCORE_MEMORY
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::ban_list::BanList;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn peer_address() -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], 18333))
+    }
+
+    fn reconnected_peer_address() -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], 51234))
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn misbehavior_test_1_invalid_block_bans_the_peer() {
+        let logger = Logger::from_path("tests_txt/misbehavior_test_1_log.txt").unwrap();
+        let safe_ban_list = Arc::new(Mutex::from(BanList::new()));
+        let mut tracker = MisbehaviorTracker::new();
+
+        tracker
+            .get_offense_sender()
+            .send((peer_address(), Offense::InvalidProofOfWork))
+            .unwrap();
+        tracker
+            .process_offenses(&[], &safe_ban_list, &logger)
+            .unwrap();
+
+        let now = Utc::now().timestamp() as u32;
+        assert!(safe_ban_list
+            .lock()
+            .unwrap()
+            .is_banned(&peer_address().ip(), now));
+    }
+
+    #[test]
+    fn misbehavior_test_2_minor_offenses_do_not_ban_the_peer() {
+        let logger = Logger::from_path("tests_txt/misbehavior_test_2_log.txt").unwrap();
+        let safe_ban_list = Arc::new(Mutex::from(BanList::new()));
+        let mut tracker = MisbehaviorTracker::new();
+        let offense_sender = tracker.get_offense_sender();
+
+        offense_sender
+            .send((peer_address(), Offense::MalformedMessage))
+            .unwrap();
+        offense_sender
+            .send((peer_address(), Offense::OversizedMessage))
+            .unwrap();
+        tracker
+            .process_offenses(&[], &safe_ban_list, &logger)
+            .unwrap();
+
+        assert!(safe_ban_list.lock().unwrap().get_banned().is_empty());
+        assert_eq!(tracker.get_score(&peer_address()), 70);
+    }
+
+    #[test]
+    fn misbehavior_test_3_errors_are_classified() {
+        assert_eq!(
+            Offense::from_receiving_error(&NodeError::ErrorOversizedMessage),
+            Some(Offense::OversizedMessage)
+        );
+        assert_eq!(
+            Offense::from_handling_error(&NodeError::ErrorInvalidMerkleRoot),
+            Some(Offense::InvalidMerkleRoot)
+        );
+        assert_eq!(
+            Offense::from_receiving_error(&NodeError::ErrorPeerTimeout),
+            None
+        );
+    }
+
+    #[test]
+    fn misbehavior_test_4_unknown_command_flood_is_detected() {
+        let mut counter = UnknownCommandCounter::new();
+
+        for _ in 0..MAX_UNKNOWN_COMMANDS_PER_WINDOW {
+            assert!(!counter.register());
+        }
+
+        assert!(counter.register());
+    }
+
+    #[test]
+    fn misbehavior_test_5_reconnecting_from_another_port_keeps_the_score() {
+        let logger = Logger::from_path("tests_txt/misbehavior_test_5_log.txt").unwrap();
+        let safe_ban_list = Arc::new(Mutex::from(BanList::new()));
+        let mut tracker = MisbehaviorTracker::new();
+        let offense_sender = tracker.get_offense_sender();
+
+        offense_sender
+            .send((peer_address(), Offense::OversizedMessage))
+            .unwrap();
+        tracker
+            .process_offenses(&[], &safe_ban_list, &logger)
+            .unwrap();
+        offense_sender
+            .send((reconnected_peer_address(), Offense::OversizedMessage))
+            .unwrap();
+        tracker
+            .process_offenses(&[], &safe_ban_list, &logger)
+            .unwrap();
+
+        let now = Utc::now().timestamp() as u32;
+        assert_eq!(tracker.get_score(&reconnected_peer_address()), 100);
+        assert!(safe_ban_list
+            .lock()
+            .unwrap()
+            .is_banned(&peer_address().ip(), now));
+    }
+}
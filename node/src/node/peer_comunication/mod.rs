@@ -1,3 +1,10 @@
 pub mod block_downloader;
+pub mod compact_blocks;
+pub mod counting_stream;
+pub mod download_scheduler;
+pub mod liveness;
+pub mod misbehavior;
+pub mod outbound_connector;
 pub mod peer_comunicator;
+pub mod relay;
 pub mod workers;
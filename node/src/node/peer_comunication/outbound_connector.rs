@@ -0,0 +1,333 @@
+use crate::node::{address_book::SafeAddressBook, *};
+
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    thread::{self, JoinHandle},
+    time::Instant,
+};
+
+use workers::Worker;
+
+pub const DEFAULT_OUTBOUND_PEERS: usize = 8;
+const BASE_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(10 * 60);
+const DNS_LOOKUP_INTERVAL: Duration = Duration::from_secs(60);
+const CANDIDATE_SEARCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many outbound peers the node keeps, and the DNS seeds used to find new ones when
+/// the address book runs out of candidates. Pruned is set if the node only serves its recent blocks,
+/// and compact filters if it serves the compact filters of the whole chain.
+#[derive(Debug, Clone)]
+pub struct ConnectionSettings {
+    pub target_outbound: usize,
+    pub dns_seeds: Vec<(String, u16)>,
+    pub ipv6_enabled: bool,
+    pub v2_transport: bool,
+    pub pruned: bool,
+    pub compact_filters: bool,
+}
+
+impl Default for ConnectionSettings {
+    fn default() -> Self {
+        ConnectionSettings {
+            target_outbound: DEFAULT_OUTBOUND_PEERS,
+            dns_seeds: Vec::new(),
+            ipv6_enabled: false,
+            v2_transport: false,
+            pruned: false,
+            compact_filters: false,
+        }
+    }
+}
+
+/// What the background thread of the connector is doing.
+#[derive(Debug, Clone, Copy)]
+enum DialTask {
+    Peer(SocketAddr),
+    DnsLookup,
+}
+
+/// Failed attempts to connect to an address. Each failure doubles the time to wait before dialing it again.
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    last_attempt: Instant,
+}
+
+impl Backoff {
+    fn delay(&self) -> Duration {
+        let exponent = self.failures.saturating_sub(1).min(16);
+        BASE_BACKOFF.saturating_mul(1 << exponent).min(MAX_BACKOFF)
+    }
+
+    fn allows_retry(&self) -> bool {
+        self.last_attempt.elapsed() >= self.delay()
+    }
+}
+
+/// Keeps the amount of outbound connections of the node, dialing addresses of the address book
+/// whenever an outbound peer disconnects. Only one address is dialed at a time, in a background
+/// thread, so the worker manager is never blocked by a slow peer. Addresses that fail are retried
+/// with an exponential backoff, and at most one outbound peer is chosen from each network group.
+#[derive(Debug)]
+pub struct OutboundConnector {
+    node_version: i32,
+    node_address: SocketAddr,
+    settings: ConnectionSettings,
+    dialing: Option<(DialTask, JoinHandle<Option<PeerStream>>)>,
+    backoffs: HashMap<SocketAddr, Backoff>,
+    last_dns_lookup: Option<Instant>,
+    last_search: Option<Instant>,
+}
+
+impl OutboundConnector {
+    pub fn new(
+        node_version: i32,
+        node_address: SocketAddr,
+        settings: ConnectionSettings,
+    ) -> OutboundConnector {
+        OutboundConnector {
+            node_version,
+            node_address,
+            settings,
+            dialing: None,
+            backoffs: HashMap::new(),
+            last_dns_lookup: None,
+            last_search: None,
+        }
+    }
+
+    /// Returns the protocol version the node announces to its peers.
+    pub fn get_node_version(&self) -> i32 {
+        self.node_version
+    }
+
+    /// Returns the stream of a new outbound peer if a dial finished successfully. Otherwise, if there
+    /// are less outbound workers than the target, starts dialing the best address not connected yet,
+    /// or looks up the DNS seeds if the address book has no address to dial.
+    pub fn poll(
+        &mut self,
+        workers: &[Worker],
+        safe_node_info: &NodeSharedInformation,
+        logger: &Logger,
+    ) -> Option<PeerStream> {
+        if let Some((_, handle)) = &self.dialing {
+            if !handle.is_finished() {
+                return None;
+            }
+        }
+        if let Some((task, handle)) = self.dialing.take() {
+            let new_stream = handle.join().ok().flatten();
+            if let DialTask::Peer(address) = task {
+                match new_stream {
+                    Some(_) => _ = self.backoffs.remove(&address),
+                    None => self.register_failure(address),
+                }
+            }
+            if new_stream.is_some() {
+                return new_stream;
+            }
+        }
+
+        let outbound: Vec<&Worker> = workers
+            .iter()
+            .filter(|worker| worker.is_outbound())
+            .collect();
+        if outbound.len() >= self.settings.target_outbound {
+            return None;
+        }
+        if let Some(last_search) = self.last_search {
+            if last_search.elapsed() < CANDIDATE_SEARCH_INTERVAL {
+                return None;
+            }
+        }
+        self.last_search = Some(Instant::now());
+
+        let connected: HashSet<SocketAddr> = workers
+            .iter()
+            .filter_map(|worker| worker.get_peer_address())
+            .collect();
+        let used_groups: HashSet<Vec<u8>> = outbound
+            .iter()
+            .filter_map(|worker| worker.get_peer_address())
+            .map(|address| network_group(&address.ip()))
+            .collect();
+
+        match self.next_candidate(&connected, &used_groups, safe_node_info) {
+            Ok(Some(candidate)) => self.dial(candidate, safe_node_info, logger),
+            Ok(None) => self.lookup_dns_seeds(safe_node_info, logger),
+            Err(error) => logger.log_error(&error),
+        }
+        None
+    }
+
+    /// Returns the best address of the book that is not connected nor banned, whose backoff already
+    /// passed and whose network group has no outbound peer yet.
+    fn next_candidate(
+        &self,
+        connected: &HashSet<SocketAddr>,
+        used_groups: &HashSet<Vec<u8>>,
+        safe_node_info: &NodeSharedInformation,
+    ) -> Result<Option<SocketAddr>, NodeError> {
+        // The address book is released before locking the ban list, as both are leaves in the lock
+        // order of safe_node_structure.
+        let candidates = {
+            let address_book = safe_node_info.lock_address_book()?;
+            address_book.select_candidates(address_book.len(), connected)
+        };
+        let ban_list = safe_node_info.lock_ban_list()?;
+        let now = Utc::now().timestamp() as u32;
+
+        Ok(candidates.into_iter().find(|address| {
+            let backoff_passed = match self.backoffs.get(address) {
+                Some(backoff) => backoff.allows_retry(),
+                None => true,
+            };
+            backoff_passed
+                && !ban_list.is_banned(&address.ip(), now)
+                && !used_groups.contains(&network_group(&address.ip()))
+        }))
+    }
+
+    fn register_failure(&mut self, address: SocketAddr) {
+        let backoff = self.backoffs.entry(address).or_insert(Backoff {
+            failures: 0,
+            last_attempt: Instant::now(),
+        });
+        backoff.failures += 1;
+        backoff.last_attempt = Instant::now();
+    }
+
+    /// Starts the handshake with the address in a new thread, registering the result in the address book.
+    /// Successfully connected peers are added to the peer table.
+    fn dial(
+        &mut self,
+        address: SocketAddr,
+        safe_node_info: &NodeSharedInformation,
+        logger: &Logger,
+    ) {
+        let node_version = self.node_version;
+        let node_address = self.node_address;
+        let v2_transport = self.settings.v2_transport;
+        let pruned = self.settings.pruned;
+        let compact_filters = self.settings.compact_filters;
+        let safe_node_info = safe_node_info.clone();
+        let logger = logger.clone();
+
+        let handle = thread::spawn(move || {
+            logger.log(format!("Dialing outbound peer {}", address));
+            let result = outgoing_handshake(
+                node_version,
+                address,
+                node_address,
+                v2_transport,
+                pruned,
+                compact_filters,
+                &logger,
+            );
+            let now = Utc::now().timestamp() as u32;
+            if let Ok(mut address_book) = safe_node_info.lock_address_book() {
+                match &result {
+                    Ok(_) => address_book.mark_success(address, now),
+                    Err(_) => address_book.mark_failure(address),
+                }
+            }
+            match result {
+                Ok((stream, peer_version)) => {
+                    if let Ok(mut peer_table) = safe_node_info.lock_peer_table() {
+                        peer_table.add_peer(address, peer_version, true, now);
+                    }
+                    Some(stream)
+                }
+                Err(error) => {
+                    logger.log_error(&error);
+                    None
+                }
+            }
+        });
+        self.dialing = Some((DialTask::Peer(address), handle));
+    }
+
+    /// Looks up the DNS seeds in a new thread, adding the addresses found to the address book.
+    /// Lookups are done at most once every DNS_LOOKUP_INTERVAL.
+    fn lookup_dns_seeds(&mut self, safe_node_info: &NodeSharedInformation, logger: &Logger) {
+        if self.settings.dns_seeds.is_empty() {
+            return;
+        }
+        if let Some(last_dns_lookup) = self.last_dns_lookup {
+            if last_dns_lookup.elapsed() < DNS_LOOKUP_INTERVAL {
+                return;
+            }
+        }
+        self.last_dns_lookup = Some(Instant::now());
+
+        let dns_seeds = self.settings.dns_seeds.clone();
+        let ipv6_enabled = self.settings.ipv6_enabled;
+        let safe_address_book: SafeAddressBook = safe_node_info.safe_address_book.clone();
+        let logger = logger.clone();
+
+        let handle = thread::spawn(move || {
+            let addresses = dns_lookup(&dns_seeds, ipv6_enabled);
+            logger.log(format!("DNS seeds returned {} addresses", addresses.len()));
+            let now = Utc::now().timestamp() as u32;
+            if let Ok(mut address_book) = safe_address_book.lock() {
+                for address in addresses {
+                    address_book.add_address(&NetworkAddress::new(now, 0, address), now);
+                }
+            }
+            None
+        });
+        self.dialing = Some((DialTask::DnsLookup, handle));
+    }
+}
+
+/// Returns the network group of the ip: its /16 for IPv4 and its /32 for IPv6. Peers of the
+/// same group are likely to be run by the same operator.
+pub fn network_group(ip: &IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ipv4) => ipv4.octets()[..2].to_vec(),
+        IpAddr::V6(ipv6) => match ipv6.to_ipv4_mapped() {
+            Some(ipv4) => ipv4.octets()[..2].to_vec(),
+            None => ipv6.octets()[..4].to_vec(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn outbound_connector_test_1_network_groups() {
+        let first: IpAddr = "10.20.1.1".parse().unwrap();
+        let same_group: IpAddr = "10.20.200.3".parse().unwrap();
+        let other_group: IpAddr = "10.21.1.1".parse().unwrap();
+        let mapped: IpAddr = "::ffff:10.20.5.5".parse().unwrap();
+        let ipv6: IpAddr = "2001:db8:1::1".parse().unwrap();
+
+        assert_eq!(network_group(&first), network_group(&same_group));
+        assert_ne!(network_group(&first), network_group(&other_group));
+        assert_eq!(network_group(&first), network_group(&mapped));
+        assert_eq!(network_group(&ipv6), vec![0x20, 0x01, 0x0d, 0xb8]);
+    }
+
+    #[test]
+    fn outbound_connector_test_2_backoff_doubles_until_the_maximum() {
+        let mut backoff = Backoff {
+            failures: 1,
+            last_attempt: Instant::now(),
+        };
+        assert_eq!(backoff.delay(), BASE_BACKOFF);
+        assert!(!backoff.allows_retry());
+
+        backoff.failures = 3;
+        assert_eq!(backoff.delay(), BASE_BACKOFF * 4);
+
+        backoff.failures = 100;
+        assert_eq!(backoff.delay(), MAX_BACKOFF);
+    }
+}
@@ -1,14 +1,26 @@
-use crate::{node::*, utils::btc_errors::PeerComunicatorError};
+use crate::{
+    node::{initial_block_download::HASHEDGENESISBLOCK, *},
+    utils::btc_errors::PeerComunicatorError,
+};
 
 use std::{
-    collections::HashSet,
-    net::TcpStream,
+    collections::{HashMap, HashSet},
     sync::{
         mpsc::{self, RecvTimeoutError},
         Arc, Mutex,
     },
+    time::Instant,
 };
 
+use compact_blocks::CompactBlockReceiver;
+use counting_stream::CountingStream;
+use liveness::{
+    announced_block_hashes, highest_known_height, PingTracker, HEADERS_RESPONSE_TIMEOUT,
+    STALE_TIP_TIMEOUT,
+};
+use misbehavior::{MisbehaviorTracker, Offense, OffenseReport, UnknownCommandCounter};
+use outbound_connector::{ConnectionSettings, OutboundConnector};
+use relay::{message_bytes, Relay, MIN_RELAY_FEE_RATE};
 use workers::*;
 
 pub const NEW_CONECTION_INTERVAL: Duration = Duration::from_secs(5);
@@ -27,14 +39,17 @@ impl PeerComunicator {
     pub fn new(
         node_version: i32,
         node_address: SocketAddr,
-        outbound_connections: &Vec<TcpStream>,
+        outbound_connections: &Vec<PeerStream>,
+        connection_settings: ConnectionSettings,
         safe_node_info: NodeSharedInformation,
+        sender_to_ui: GlibSender<UIResponse>,
         logger: &Logger,
     ) -> PeerComunicator {
         let finished_working_indicator = Arc::new(Mutex::from(false));
         let new_peer_conector = NewPeerConnector::new(
             node_version,
             node_address,
+            safe_node_info.clone(),
             logger.clone(),
             finished_working_indicator.clone(),
         );
@@ -42,10 +57,14 @@ impl PeerComunicator {
             logger.log_error(error)
         }
         let worker_manager = PeerComunicatorWorkerManager::new(
+            node_version,
+            node_address,
             new_peer_conector.ok(),
             outbound_connections,
+            connection_settings,
             safe_node_info,
             finished_working_indicator.clone(),
+            sender_to_ui,
             logger.clone(),
         );
 
@@ -85,10 +104,14 @@ impl PeerComunicator {
 }
 
 ///Main loop for the worker manager, attemps to create a new worker from any new connection the NewPeerConnector
-///might have stablished. Checks if there are any messages to send to the net, and joins any trhead corresponding
-///to a worker that already finished.
+///or the OutboundConnector might have stablished. Checks if there are any messages to send to the net, bans the
+///peers that misbehaved too much, evicts the stale ones, and joins any trhead corresponding to a worker that
+///already finished.
+#[allow(clippy::too_many_arguments)]
 pub fn worker_manager_loop(
     new_peer_connector: &Option<NewPeerConnector>,
+    outbound_connector: &mut OutboundConnector,
+    misbehavior_tracker: &mut MisbehaviorTracker,
     workers: &mut Vec<Worker>,
     safe_node_info: &NodeSharedInformation,
     message_bytes_receiver: &mpsc::Receiver<Vec<u8>>,
@@ -105,21 +128,34 @@ pub fn worker_manager_loop(
         Err(_) => return Stops::UngracefullStop,
     }
 
+    //conectarse a nuevos peers salientes
+    if let Some(new_stream) = outbound_connector.poll(workers, safe_node_info, logger) {
+        let new_worker = Worker::new_peer_comunicator_worker(
+            new_stream,
+            safe_node_info.clone(),
+            propagation_channel.clone(),
+            misbehavior_tracker.get_offense_sender(),
+            logger.clone(),
+            finished.clone(),
+            next_worker_id(workers),
+            true,
+        );
+        workers.push(new_worker);
+    }
+
     //recivir nuevos peers
     if let Some(new_peer_connector) = new_peer_connector {
-        let id = match workers.last() {
-            Some(worker) => worker._id + 1,
-            None => 0,
-        };
         match new_peer_connector.recv_timeout(NEW_CONECTION_INTERVAL) {
             Ok(new_stream) => {
                 let new_worker = Worker::new_peer_comunicator_worker(
                     new_stream,
                     safe_node_info.clone(),
                     propagation_channel.clone(),
+                    misbehavior_tracker.get_offense_sender(),
                     logger.clone(),
                     finished.clone(),
-                    id,
+                    next_worker_id(workers),
+                    false,
                 );
                 workers.push(new_worker);
             }
@@ -131,20 +167,110 @@ pub fn worker_manager_loop(
         }
     }
 
-    if let Err(error) = process_existing_workers(workers, message_bytes_receiver, logger) {
+    if let Err(error) =
+        misbehavior_tracker.process_offenses(workers, &safe_node_info.safe_ban_list, logger)
+    {
+        logger.log_error(&error);
+    }
+    if let Err(error) = disconnect_requested_peers(workers, safe_node_info) {
+        logger.log_error(&error);
+    }
+    let node_version = outbound_connector.get_node_version();
+    if let Err(error) = evict_stale_peers(workers, safe_node_info, node_version, logger) {
+        logger.log_error(&error);
+    }
+    if let Err(error) =
+        process_existing_workers(workers, safe_node_info, message_bytes_receiver, logger)
+    {
         logger.log_error(&error);
         return Stops::UngracefullStop;
     };
-    if workers.is_empty() {
-        return Stops::GracefullStop;
-    }
     Stops::Continue
 }
 
+/// Returns the id for a new worker, which is the next one to the last worker id.
+fn next_worker_id(workers: &[Worker]) -> usize {
+    match workers.iter().map(|worker| worker._id).max() {
+        Some(id) => id + 1,
+        None => 0,
+    }
+}
+
+/// Closes the connection with every peer the user asked to disconnect from.
+fn disconnect_requested_peers(
+    workers: &[Worker],
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    let disconnect_requests = safe_node_info.lock_peer_table()?.take_disconnect_requests();
+    for worker in workers {
+        if let Some(peer_address) = worker.get_peer_address() {
+            if disconnect_requests.contains(&peer_address) {
+                worker.disconnect();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Disconnects the outbound peers that have been behind the tip of the node for too long, so
+/// their slots can be used by peers that keep up with the chain. Before that, the peers are asked
+/// for the headers that follow the parent of the tip, so a peer that has the tip but did not
+/// announce it catches up with its reply and is not evicted.
+fn evict_stale_peers(
+    workers: &[Worker],
+    safe_node_info: &NodeSharedInformation,
+    node_version: i32,
+    logger: &Logger,
+) -> Result<(), NodeError> {
+    let (tip_height, locator_hash) = {
+        let block_headers = safe_node_info.read_block_headers()?;
+        let locator_hash = match block_headers.len().checked_sub(2) {
+            Some(position) => block_headers[position].hash(),
+            None => HASHEDGENESISBLOCK,
+        };
+        (block_headers.len(), locator_hash)
+    };
+    let now = Utc::now().timestamp() as u32;
+    let (peers_to_ask, stale_peers) = safe_node_info.lock_peer_table()?.find_stale_peers(
+        tip_height,
+        now,
+        STALE_TIP_TIMEOUT,
+        HEADERS_RESPONSE_TIMEOUT,
+    );
+    if peers_to_ask.is_empty() && stale_peers.is_empty() {
+        return Ok(());
+    }
+    let get_headers_bytes = message_bytes(&GetBlockHeadersMessage::new(
+        node_version as u32,
+        vec![locator_hash],
+        [0; 32],
+    ))
+    .ok_or(NodeError::ErrorMessage(
+        MessageError::ErrorSendingGetBlockHeadersMessage,
+    ))?;
+    for worker in workers {
+        if let Some(peer_address) = worker.get_peer_address() {
+            if stale_peers.contains(&peer_address) {
+                logger.log(format!("Evicting stale peer {}", peer_address));
+                worker.disconnect();
+            } else if peers_to_ask.contains(&peer_address) {
+                logger.log(format!(
+                    "Asking headers to peer {} behind the tip",
+                    peer_address
+                ));
+                _ = worker.send_message_bytes(get_headers_bytes.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Processes existing workers by removing any that may have ungracefully finished, and sending the message bytes
-/// to each one of themif any message needs to be broadcasted to the hole net.
+/// to each one of themif any message needs to be broadcasted to the hole net, adapted to the preferences its peer
+/// negotiated. The peers of the removed workers are removed from the peer table.
 fn process_existing_workers(
     workers: &mut Vec<Worker>,
+    safe_node_info: &NodeSharedInformation,
     message_bytes_receiver: &mpsc::Receiver<Vec<u8>>,
     logger: &Logger,
 ) -> Result<(), PeerComunicatorError> {
@@ -160,37 +286,72 @@ fn process_existing_workers(
         };
     }
 
+    let mut relays = Vec::new();
+    for message_bytes in messages {
+        relays.push(
+            Relay::from(message_bytes, safe_node_info)
+                .map_err(|_| PeerComunicatorError::ErrorPropagating)?,
+        );
+    }
+    let features = match relays.is_empty() {
+        true => HashMap::new(),
+        false => safe_node_info
+            .lock_peer_table()
+            .map_err(|_| PeerComunicatorError::ErrorPropagating)?
+            .get_features(),
+    };
+
     let mut i = 0;
+    let mut message_wanted = false;
     let mut message_sent = false;
     while i < workers.len() {
         if workers[i].is_finished() {
             let removed_worker = workers.swap_remove(i);
             logger.log("removing_desconected_peer".to_string());
+            if let (Some(peer_address), Ok(mut peer_table)) = (
+                removed_worker.get_peer_address(),
+                safe_node_info.lock_peer_table(),
+            ) {
+                peer_table.remove_peer(&peer_address);
+            }
             if let Err(error) = removed_worker.join_thread() {
                 logger.log_error(&error);
             }
         } else {
-            for message_bytes in &messages {
-                if workers[i].send_message_bytes(message_bytes.clone()).is_ok() {
-                    message_sent = true;
-                };
+            let peer_features = workers[i]
+                .get_peer_address()
+                .and_then(|peer_address| features.get(&peer_address));
+            for relay in &relays {
+                if let Some(message_bytes) = relay.bytes_for(peer_features) {
+                    message_wanted = true;
+                    if workers[i].send_message_bytes(message_bytes).is_ok() {
+                        message_sent = true;
+                    };
+                }
             }
             i += 1;
         }
     }
-    if !messages.is_empty() && !message_sent {
+    if message_wanted && !message_sent {
         return Err(PeerComunicatorError::ErrorSendingMessage);
     }
     Ok(())
 }
 
 /// Main loop for each peer communicator worker, attemps to receive a message form its peer and handles it.
-/// If there is a message to send then it sends it to its peer
+/// If there is a message to send then it sends it to its peer. Any misbehavior of the peer is reported
+/// through the offense_sender, and the bytes exchanged with it are added to the peer table.
+/// The peer is pinged periodically, and disconnected if it does not answer in time.
+#[allow(clippy::too_many_arguments)]
 pub fn peer_comunicator_worker_thread_loop(
-    stream: &mut TcpStream,
+    stream: &mut PeerStream,
     safe_node_info: &NodeSharedInformation,
     message_bytes_receiver: &mpsc::Receiver<Vec<u8>>,
     propagation_channel: &mpsc::Sender<Vec<u8>>,
+    offense_sender: &mpsc::Sender<OffenseReport>,
+    unknown_commands: &mut UnknownCommandCounter,
+    ping_tracker: &mut PingTracker,
+    compact_block_receiver: &mut CompactBlockReceiver,
     logger: &Logger,
     finished: &FinishedIndicator,
     id: usize,
@@ -204,26 +365,108 @@ pub fn peer_comunicator_worker_thread_loop(
         Err(_) => return Stops::UngracefullStop,
     }
 
+    let mut counting_stream = CountingStream::new(stream);
+    let stop = comunicate_with_peer(
+        &mut counting_stream,
+        safe_node_info,
+        message_bytes_receiver,
+        propagation_channel,
+        offense_sender,
+        unknown_commands,
+        ping_tracker,
+        compact_block_receiver,
+        logger,
+        id,
+    );
+    register_traffic(&counting_stream, safe_node_info);
+    stop
+}
+
+/// Pings the peer if it is time to, then receives a message from the peer and handles it, and sends
+/// it the next message to propagate, if any.
+#[allow(clippy::too_many_arguments)]
+fn comunicate_with_peer(
+    stream: &mut CountingStream<PeerStream>,
+    safe_node_info: &NodeSharedInformation,
+    message_bytes_receiver: &mpsc::Receiver<Vec<u8>>,
+    propagation_channel: &mpsc::Sender<Vec<u8>>,
+    offense_sender: &mpsc::Sender<OffenseReport>,
+    unknown_commands: &mut UnknownCommandCounter,
+    ping_tracker: &mut PingTracker,
+    compact_block_receiver: &mut CompactBlockReceiver,
+    logger: &Logger,
+    id: usize,
+) -> Stops {
+    if ping_tracker.timed_out(Instant::now()) {
+        logger.log(format!("Worker {id}: peer did not answer the ping in time"));
+        return Stops::UngracefullStop;
+    }
+    if ping_tracker.should_ping(Instant::now()) {
+        let nonce: u64 = rand::random();
+        if let Err(error) = PingMessage::new(nonce).send_to(stream) {
+            logger.log_error(&error);
+            return Stops::UngracefullStop;
+        }
+        ping_tracker.ping_sent(nonce, Instant::now());
+    }
+
     match receive_message(stream, logger) {
         Ok((msg, _command_name)) => {
-            if propagate_messages(
-                &msg,
-                propagation_channel,
-                &safe_node_info.safe_blockchain,
-                &safe_node_info.safe_pending_tx,
-            )
-            .is_err()
-            {
-                return Stops::UngracefullStop;
+            if let Message::Pong(pong_msg) = &msg {
+                if let Some(ping) = ping_tracker.pong_received(pong_msg.get_nonce(), Instant::now())
+                {
+                    register_ping(stream.get_ref(), safe_node_info, ping);
+                }
+            }
+            register_features(stream.get_ref(), safe_node_info, &msg);
+            if matches!(msg, Message::UnknownMessage) && unknown_commands.register() {
+                report_offense(
+                    stream.get_ref(),
+                    offense_sender,
+                    Offense::UnknownCommandFlood,
+                );
+            }
+            let announced_blocks = announced_block_hashes(&msg);
+            let msg = match compact_block_receiver.process(msg, stream, safe_node_info) {
+                Ok(msg) => msg,
+                Err(error) => {
+                    if let Some(offense) = Offense::from_handling_error(&error) {
+                        report_offense(stream.get_ref(), offense_sender, offense);
+                    }
+                    return Stops::UngracefullStop;
+                }
             };
+            if let Some(msg) = msg {
+                if propagate_messages(
+                    &msg,
+                    propagation_channel,
+                    &safe_node_info.safe_blockchain,
+                    &safe_node_info.safe_pending_tx,
+                )
+                .is_err()
+                {
+                    return Stops::UngracefullStop;
+                };
 
-            if handle_message(msg, stream, safe_node_info, logger, false).is_err() {
-                return Stops::UngracefullStop;
-            };
+                if let Err(error) = handle_message(msg, stream, safe_node_info, logger, false) {
+                    if let Some(offense) = Offense::from_handling_error(&error) {
+                        report_offense(stream.get_ref(), offense_sender, offense);
+                    }
+                    return Stops::UngracefullStop;
+                };
+            }
+            if let Ok(Some(height)) = highest_known_height(&announced_blocks, safe_node_info) {
+                register_best_known_height(stream.get_ref(), safe_node_info, height);
+            }
         }
         Err(error) => match error {
             NodeError::ErrorPeerTimeout => {}
-            _ => return Stops::UngracefullStop,
+            _ => {
+                if let Some(offense) = Offense::from_receiving_error(&error) {
+                    report_offense(stream.get_ref(), offense_sender, offense);
+                }
+                return Stops::UngracefullStop;
+            }
         },
     };
 
@@ -242,10 +485,78 @@ pub fn peer_comunicator_worker_thread_loop(
     Stops::Continue
 }
 
+/// Tells the peer the preferences of the node: new blocks are to be announced with headers, transactions
+/// with a fee rate lower than MIN_RELAY_FEE_RATE are not to be announced, and compact blocks are supported.
+pub fn send_preferences<T: Read + Write>(stream: &mut T) -> Result<(), MessageError> {
+    SendHeadersMessage::new().send_to(stream)?;
+    FeeFilterMessage::new(MIN_RELAY_FEE_RATE).send_to(stream)?;
+    SendCmpctMessage::new(false, COMPACT_BLOCKS_VERSION).send_to(stream)
+}
+
+/// Reports the offense of the peer of the stream to the worker manager.
+fn report_offense(
+    stream: &PeerStream,
+    offense_sender: &mpsc::Sender<OffenseReport>,
+    offense: Offense,
+) {
+    if let Ok(peer_address) = stream.peer_addr() {
+        _ = offense_sender.send((peer_address, offense));
+    }
+}
+
+/// Adds the bytes exchanged through the stream to the totals of its peer in the peer table.
+fn register_traffic(stream: &CountingStream<PeerStream>, safe_node_info: &NodeSharedInformation) {
+    let (bytes_sent, bytes_received) = stream.get_traffic();
+    if bytes_sent == 0 && bytes_received == 0 {
+        return;
+    }
+    if let (Ok(peer_address), Ok(mut peer_table)) = (
+        stream.get_ref().peer_addr(),
+        safe_node_info.lock_peer_table(),
+    ) {
+        peer_table.add_traffic(&peer_address, bytes_sent, bytes_received);
+    }
+}
+
+/// Sets the round trip time of the last ping answered by the peer of the stream in the peer table.
+fn register_ping(stream: &PeerStream, safe_node_info: &NodeSharedInformation, ping: Duration) {
+    if let (Ok(peer_address), Ok(mut peer_table)) =
+        (stream.peer_addr(), safe_node_info.lock_peer_table())
+    {
+        peer_table.set_ping(&peer_address, ping);
+    }
+}
+
+/// Registers in the peer table the preference negotiated by the message, if it is a feature negotiation message.
+fn register_features(
+    stream: &PeerStream,
+    safe_node_info: &NodeSharedInformation,
+    message: &Message,
+) {
+    if let (Ok(peer_address), Ok(mut peer_table)) =
+        (stream.peer_addr(), safe_node_info.lock_peer_table())
+    {
+        peer_table.update_features(&peer_address, message);
+    }
+}
+
+/// Registers in the peer table that the peer of the stream has the block at the given height.
+fn register_best_known_height(
+    stream: &PeerStream,
+    safe_node_info: &NodeSharedInformation,
+    height: usize,
+) {
+    if let (Ok(peer_address), Ok(mut peer_table)) =
+        (stream.peer_addr(), safe_node_info.lock_peer_table())
+    {
+        peer_table.update_best_known_height(&peer_address, height);
+    }
+}
+
 /// Atempts to send any message bytes that may be received trough the message_bytes_receiver to the given stream
-fn try_to_send_message(
+fn try_to_send_message<T: Read + Write>(
     message_bytes_receiver: &mpsc::Receiver<Vec<u8>>,
-    stream: &mut TcpStream,
+    stream: &mut T,
 ) -> Result<bool, PeerComunicatorError> {
     let message_bytes = match message_bytes_receiver.try_recv() {
         Ok(message_bytes) => message_bytes,
@@ -341,13 +652,14 @@ fn propagate_messages(
     Ok(())
 }
 
-/// Checks for new incomming connections, if a successfull handshake is done then it sends the new TcpStream to
+/// Checks for new incomming connections, if a successfull handshake is done then it sends the new PeerStream to
 /// the worker manager in orther to make a new PeerConnectoWorker to communicate with the new peer.
 pub fn new_peer_conector_thread_loop(
     listener: &TcpListener,
     node_version: i32,
     node_address: SocketAddr,
-    worker_sender: &mpsc::Sender<TcpStream>,
+    worker_sender: &mpsc::Sender<PeerStream>,
+    safe_node_info: &NodeSharedInformation,
     logger: &Logger,
     finished: &FinishedIndicator,
 ) -> Stops {
@@ -361,21 +673,39 @@ pub fn new_peer_conector_thread_loop(
     }
 
     match listener.accept() {
-        Ok((mut tcp_stream, peer_address)) => {
+        Ok((tcp_stream, peer_address)) => {
             logger.log("New peer requested conection".to_string());
-            if incoming_handshake(
+            let now = Utc::now().timestamp() as u32;
+            match safe_node_info.lock_ban_list() {
+                Ok(ban_list) => {
+                    if ban_list.is_banned(&peer_address.ip(), now) {
+                        logger.log(format!("Rejected banned peer {}", peer_address));
+                        return Stops::Continue;
+                    }
+                }
+                Err(_) => return Stops::UngracefullStop,
+            }
+            let (stream, peer_version) = match incoming_handshake(
                 node_version,
                 peer_address,
                 node_address,
-                &mut tcp_stream,
+                tcp_stream,
+                safe_node_info.v2_transport,
+                safe_node_info.pruned,
+                safe_node_info.compact_filters,
                 logger,
-            )
-            .is_err()
-            {
-                logger.log("New peer failed handshake".to_string());
-                return Stops::UngracefullStop;
+            ) {
+                Ok(connection) => connection,
+                Err(_) => {
+                    logger.log("New peer failed handshake".to_string());
+                    return Stops::UngracefullStop;
+                }
+            };
+            match safe_node_info.lock_peer_table() {
+                Ok(mut peer_table) => peer_table.add_peer(peer_address, peer_version, false, now),
+                Err(_) => return Stops::UngracefullStop,
             }
-            if worker_sender.send(tcp_stream).is_err() {
+            if worker_sender.send(stream).is_err() {
                 logger.log_error(&PeerComunicatorError::ErrorCantReceiveNewPeerConections);
                 return Stops::UngracefullStop;
             };
@@ -0,0 +1,259 @@
+use crate::{node::*, utils::ui_communication_protocol::PeerFeatures};
+
+/// Fee rate, in satoshis per kilobyte, below which the node asks its peers not to announce transactions.
+pub const MIN_RELAY_FEE_RATE: u64 = 1000;
+const MESSAGE_HEADER_SIZE: usize = 24;
+const BYTES_IN_KB: u64 = 1000;
+
+/// What a message to propagate announces, which decides how it is adapted to each peer.
+#[derive(Debug)]
+enum Announcement {
+    /// New blocks, whose headers are known, alongside the transactions announced with them. If the
+    /// message is the block itself, the bytes of its compact block are kept too.
+    Blocks {
+        headers: Vec<BlockHeader>,
+        transaction_hashes: Vec<[u8; 32]>,
+        compact_block: Option<Vec<u8>>,
+    },
+    /// A transaction, with its fee rate if every output it spends is known.
+    Transaction {
+        fee_rate: Option<u64>,
+    },
+    Other,
+}
+
+/// A message to propagate to the peers. It is parsed once, so it can be adapted to the preferences each
+/// peer negotiated: blocks are sent as compact blocks to the peers that asked for them to be announced that
+/// way, and announced with headers to the peers that sent sendheaders. Transactions are not announced to
+/// the peers whose fee filter is higher than their fee rate.
+#[derive(Debug)]
+pub struct Relay {
+    message_bytes: Vec<u8>,
+    announcement: Announcement,
+}
+
+impl Relay {
+    pub fn from(
+        message_bytes: Vec<u8>,
+        safe_node_info: &NodeSharedInformation,
+    ) -> Result<Relay, NodeError> {
+        let announcement = match parse_message(&message_bytes) {
+            Some(Message::Block(block_msg)) => Announcement::Blocks {
+                headers: vec![block_msg.block.get_header()],
+                transaction_hashes: Vec::new(),
+                compact_block: CmpctBlockMessage::from_block(&block_msg.block, rand::random())
+                    .ok()
+                    .and_then(|cmpct_block_msg| self::message_bytes(&cmpct_block_msg)),
+            },
+            Some(Message::Inv(inv_msg)) => {
+                match known_headers(&inv_msg.get_block_hashes(), safe_node_info)? {
+                    Some(headers) => Announcement::Blocks {
+                        headers,
+                        transaction_hashes: inv_msg.get_transaction_hashes(),
+                        compact_block: None,
+                    },
+                    None => Announcement::Other,
+                }
+            }
+            Some(Message::Tx(tx_msg)) => Announcement::Transaction {
+                fee_rate: transaction_fee_rate(&tx_msg.tx, safe_node_info)?,
+            },
+            _ => Announcement::Other,
+        };
+        Ok(Relay {
+            message_bytes,
+            announcement,
+        })
+    }
+
+    /// Returns the bytes to send to a peer with the given preferences, or None if the peer does not want
+    /// the message. Peers whose preferences are unknown receive the message as it is.
+    pub fn bytes_for(&self, features: Option<&PeerFeatures>) -> Option<Vec<u8>> {
+        let features = match features {
+            Some(features) => features,
+            None => return Some(self.message_bytes.clone()),
+        };
+        match &self.announcement {
+            Announcement::Blocks {
+                compact_block: Some(compact_block),
+                ..
+            } if features.compact_blocks_announce
+                && features.compact_blocks_version == COMPACT_BLOCKS_VERSION =>
+            {
+                Some(compact_block.clone())
+            }
+            Announcement::Blocks {
+                headers,
+                transaction_hashes,
+                ..
+            } if features.send_headers => {
+                let mut bytes = message_bytes(&BlockHeadersMessage::new(headers.clone()))?;
+                if !transaction_hashes.is_empty() {
+                    bytes.extend(message_bytes(
+                        &InvMessage::create_message_inventory_transaction_type(
+                            transaction_hashes.clone(),
+                        ),
+                    )?);
+                }
+                Some(bytes)
+            }
+            Announcement::Transaction {
+                fee_rate: Some(fee_rate),
+            } if *fee_rate < features.fee_filter => None,
+            _ => Some(self.message_bytes.clone()),
+        }
+    }
+}
+
+/// Returns the message of the bytes, which start with its header, or None if they are not a valid message.
+fn parse_message(message_bytes: &[u8]) -> Option<Message> {
+    if message_bytes.len() < MESSAGE_HEADER_SIZE {
+        return None;
+    }
+    let header = HeaderMessage::from_bytes(&message_bytes[..MESSAGE_HEADER_SIZE]).ok()?;
+    Message::from_bytes(
+        message_bytes[MESSAGE_HEADER_SIZE..].to_vec(),
+        header.get_command_name(),
+    )
+    .ok()
+}
+
+/// Returns the bytes of the message, starting with its header.
+pub fn message_bytes<T: MessageTrait>(message: &T) -> Option<Vec<u8>> {
+    let mut bytes = message.get_header_message().ok()?.to_bytes();
+    bytes.extend(message.to_bytes());
+    Some(bytes)
+}
+
+/// Returns the headers of the blocks, in the order of the chain, or None if there are no blocks or
+/// the header of any of them is not known.
+fn known_headers(
+    block_hashes: &[[u8; 32]],
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Option<Vec<BlockHeader>>, NodeError> {
+    if block_hashes.is_empty() {
+        return Ok(None);
+    }
+    let mut positions = Vec::new();
+    {
+        let headers_index = safe_node_info.read_headers_index()?;
+        for hash in block_hashes {
+            match headers_index.get(hash) {
+                Some(position) => positions.push(*position),
+                None => return Ok(None),
+            }
+        }
+    }
+    positions.sort();
+    let block_headers = safe_node_info.read_block_headers()?;
+    Ok(positions
+        .iter()
+        .map(|position| block_headers.get(*position).cloned())
+        .collect())
+}
+
+/// Returns the fee rate of the transaction in satoshis per kilobyte, or None if any of the outputs it
+/// spends is not one of a pending transaction.
+pub fn transaction_fee_rate(
+    tx: &Transaction,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Option<u64>, NodeError> {
+    let pending_tx = safe_node_info.lock_safe_pending_tx()?;
+    let mut input_total = 0;
+    for tx_in in &tx.tx_in {
+        let previous_output = &tx_in.previous_output;
+        match pending_tx
+            .get(&previous_output.hash)
+            .and_then(|parent| parent.tx_out.get(previous_output.index as usize))
+        {
+            Some(spent_output) => input_total += spent_output.value,
+            None => return Ok(None),
+        }
+    }
+    let output_total: i64 = tx.tx_out.iter().map(|tx_out| tx_out.value).sum();
+    let fee = (input_total - output_total).max(0) as u64;
+    let size = tx.to_bytes().len().max(1) as u64;
+    Ok(Some(fee * BYTES_IN_KB / size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn transaction_relay(fee_rate: Option<u64>) -> Relay {
+        Relay {
+            message_bytes: vec![1, 2, 3],
+            announcement: Announcement::Transaction { fee_rate },
+        }
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn relay_test_1_transactions_below_the_fee_filter_are_not_relayed() {
+        let features = PeerFeatures {
+            fee_filter: MIN_RELAY_FEE_RATE,
+            ..PeerFeatures::default()
+        };
+
+        assert_eq!(
+            transaction_relay(Some(999)).bytes_for(Some(&features)),
+            None
+        );
+        assert_eq!(
+            transaction_relay(Some(1000)).bytes_for(Some(&features)),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            transaction_relay(None).bytes_for(Some(&features)),
+            Some(vec![1, 2, 3])
+        );
+        assert_eq!(
+            transaction_relay(Some(1)).bytes_for(None),
+            Some(vec![1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn relay_test_2_blocks_are_announced_with_headers_or_compact_blocks() {
+        let header = BlockHeader::new(1, [1; 32], [2; 32], 0x1d00ffff);
+        let relay = Relay {
+            message_bytes: vec![1, 2, 3],
+            announcement: Announcement::Blocks {
+                headers: vec![header.clone()],
+                transaction_hashes: Vec::new(),
+                compact_block: Some(vec![4, 5, 6]),
+            },
+        };
+        let features = PeerFeatures {
+            send_headers: true,
+            ..PeerFeatures::default()
+        };
+
+        let bytes = relay.bytes_for(Some(&features)).unwrap();
+
+        match parse_message(&bytes) {
+            Some(Message::BlockHeaders(headers_msg)) => {
+                assert_eq!(headers_msg.headers, vec![header])
+            }
+            _ => panic!("expected a headers message"),
+        }
+        let compact_features = PeerFeatures {
+            compact_blocks_announce: true,
+            compact_blocks_version: COMPACT_BLOCKS_VERSION,
+            ..features
+        };
+        assert_eq!(
+            relay.bytes_for(Some(&compact_features)),
+            Some(vec![4, 5, 6])
+        );
+        assert_eq!(
+            relay.bytes_for(Some(&PeerFeatures::default())),
+            Some(vec![1, 2, 3])
+        );
+    }
+}
@@ -4,7 +4,7 @@ use crate::{
 };
 
 use std::{
-    net::TcpStream,
+    net::Shutdown,
     sync::{
         mpsc::{self, RecvTimeoutError},
         Arc, Mutex,
@@ -13,8 +13,14 @@ use std::{
 };
 
 use block_downloader::block_downloader_thread_loop;
+use compact_blocks::CompactBlockReceiver;
+use download_scheduler::SafeDownloadScheduler;
+use liveness::PingTracker;
+use misbehavior::{MisbehaviorTracker, OffenseReport, UnknownCommandCounter};
+use outbound_connector::{ConnectionSettings, OutboundConnector};
 use peer_comunicator::new_peer_conector_thread_loop;
 use peer_comunicator::peer_comunicator_worker_thread_loop;
+use peer_comunicator::send_preferences;
 use peer_comunicator::worker_manager_loop;
 
 pub type FinishedIndicator = Arc<Mutex<bool>>;
@@ -38,22 +44,23 @@ impl Stops {
 /// Struct that represents a worker thread in a thread pool.
 #[derive(Debug)]
 pub struct Worker {
-    thread: thread::JoinHandle<Option<TcpStream>>,
+    thread: thread::JoinHandle<Option<PeerStream>>,
     message_bytes_sender: Option<mpsc::Sender<Vec<u8>>>,
+    stream: Option<PeerStream>,
+    peer_address: Option<SocketAddr>,
+    outbound: bool,
     pub _id: usize,
 }
 
 pub type Bundle = Vec<[u8; 32]>;
-pub type SafeReceiver = Arc<Mutex<mpsc::Receiver<Bundle>>>;
 
 impl Worker {
     ///Creates a worker which attempts to ask for and download blocks to a peer trough the given stream
     pub fn new_block_downloader_worker(
         id: usize,
-        receiver: SafeReceiver,
-        mut stream: TcpStream,
+        scheduler: SafeDownloadScheduler,
+        mut stream: PeerStream,
         safe_node_info: NodeSharedInformation,
-        missed_bundles_sender: mpsc::Sender<Bundle>,
         downloading_headers: FinishedIndicator,
         logger: Logger,
     ) -> Worker {
@@ -69,10 +76,9 @@ impl Worker {
         let thread = thread::spawn(move || loop {
             let stop = block_downloader_thread_loop(
                 id,
-                &receiver,
+                &scheduler,
                 &mut stream,
                 &safe_node_info,
-                &missed_bundles_sender,
                 &downloading_headers,
                 &logger,
             );
@@ -93,18 +99,26 @@ impl Worker {
         Worker {
             thread,
             message_bytes_sender: None,
+            stream: None,
+            peer_address: None,
+            outbound: true,
             _id: id,
         }
     }
 
-    /// Creates a worker responsible for communicating with a peer
+    /// Creates a worker responsible for communicating with a peer. The peer is told the preferences of
+    /// the node and, if the connection is outbound, asked for the addresses it knows. The offenses of the
+    /// peer are reported through the offense_sender.
+    #[allow(clippy::too_many_arguments)]
     pub fn new_peer_comunicator_worker(
-        mut stream: TcpStream,
+        mut stream: PeerStream,
         safe_node_info: NodeSharedInformation,
         propagation_channel: mpsc::Sender<Vec<u8>>,
+        offense_sender: mpsc::Sender<OffenseReport>,
         logger: Logger,
         finished: FinishedIndicator,
         id: usize,
+        outbound: bool,
     ) -> Worker {
         if (stream.set_write_timeout(Some(PEER_TIMEOUT)).is_err())
             || (stream.set_read_timeout(Some(PEER_TIMEOUT)).is_err())
@@ -114,6 +128,19 @@ impl Worker {
                 id
             ));
         }
+        if let Err(error) = send_preferences(&mut stream) {
+            logger.log_error(&error);
+        }
+        if outbound {
+            if let Err(error) = GetAddrMessage::new().send_to(&mut stream) {
+                logger.log_error(&error);
+            }
+        }
+        let peer_address = stream.peer_addr().ok();
+        let stream_clone = stream.try_clone().ok();
+        let mut unknown_commands = UnknownCommandCounter::new();
+        let mut ping_tracker = PingTracker::new();
+        let mut compact_block_receiver = CompactBlockReceiver::new();
 
         let (message_bytes_sender, message_bytes_receiver) = mpsc::channel();
 
@@ -124,6 +151,10 @@ impl Worker {
                 &safe_node_info,
                 &message_bytes_receiver,
                 &propagation_channel,
+                &offense_sender,
+                &mut unknown_commands,
+                &mut ping_tracker,
+                &mut compact_block_receiver,
                 &logger,
                 &finished,
                 id,
@@ -143,12 +174,32 @@ impl Worker {
         Worker {
             thread,
             message_bytes_sender: Some(message_bytes_sender),
+            stream: stream_clone,
+            peer_address,
+            outbound,
             _id: id,
         }
     }
 
+    /// Returns the address of the peer of the worker, if it is known.
+    pub fn get_peer_address(&self) -> Option<SocketAddr> {
+        self.peer_address
+    }
+
+    /// Returns true if the node started the connection with the peer.
+    pub fn is_outbound(&self) -> bool {
+        self.outbound
+    }
+
+    /// Closes the connection with the peer, which makes the worker finish.
+    pub fn disconnect(&self) {
+        if let Some(stream) = &self.stream {
+            _ = stream.shutdown(Shutdown::Both);
+        }
+    }
+
     ///Joins the thread of the worker, returning an error if it was not possible to join it.
-    pub fn join_thread(self) -> Result<Option<TcpStream>, WorkerError> {
+    pub fn join_thread(self) -> Result<Option<PeerStream>, WorkerError> {
         match self.thread.join() {
             Ok(stream) => Ok(stream),
             Err(_) => Err(WorkerError::ErrorWorkerPanicked),
@@ -174,14 +225,15 @@ impl Worker {
 #[derive(Debug)]
 pub struct NewPeerConnector {
     thread: thread::JoinHandle<()>,
-    new_workers_receiver: mpsc::Receiver<TcpStream>,
+    new_workers_receiver: mpsc::Receiver<PeerStream>,
 }
 
 impl NewPeerConnector {
-    /// Creates a worker responsible for receiving incoming connections from new peers
+    /// Creates a worker responsible for receiving incoming connections from new peers, rejecting the banned ones
     pub fn new(
         node_version: i32,
         node_address: SocketAddr,
+        safe_node_info: NodeSharedInformation,
         logger: Logger,
         finished: FinishedIndicator,
     ) -> Result<NewPeerConnector, PeerComunicatorError> {
@@ -199,6 +251,7 @@ impl NewPeerConnector {
                 node_version,
                 node_address,
                 &sender,
+                &safe_node_info,
                 &logger,
                 &finished,
             ) {
@@ -222,7 +275,7 @@ impl NewPeerConnector {
     }
 
     ///Receives a new connection, if no new connection is received whithin the duration, then it times out
-    pub fn recv_timeout(&self, timeout: Duration) -> Result<TcpStream, RecvTimeoutError> {
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<PeerStream, RecvTimeoutError> {
         self.new_workers_receiver.recv_timeout(timeout)
     }
 
@@ -241,29 +294,45 @@ pub struct PeerComunicatorWorkerManager {
 }
 
 impl PeerComunicatorWorkerManager {
-    /// Creates a worker responsible for managing all the other peer communicator workers
+    /// Creates a worker responsible for managing all the other peer communicator workers. It keeps the
+    /// amount of outbound peers set in the connection settings, and reports to the UI every change in
+    /// the amount of connections.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
+        node_version: i32,
+        node_address: SocketAddr,
         new_peer_conector: Option<NewPeerConnector>,
-        outbound_connections: &Vec<TcpStream>,
+        outbound_connections: &Vec<PeerStream>,
+        connection_settings: ConnectionSettings,
         safe_node_info: NodeSharedInformation,
         finished: Arc<Mutex<bool>>,
+        sender_to_ui: GlibSender<UIResponse>,
         logger: Logger,
     ) -> PeerComunicatorWorkerManager {
         let (propagation_channel, message_bytes_receiver) = mpsc::channel();
         let message_bytes_sender = propagation_channel.clone();
 
+        let mut misbehavior_tracker = MisbehaviorTracker::new();
+
         let mut workers = create_peer_comunicator_workers(
             outbound_connections,
             &safe_node_info,
             &propagation_channel,
+            &misbehavior_tracker.get_offense_sender(),
             &finished,
             &logger,
         );
 
+        let mut outbound_connector =
+            OutboundConnector::new(node_version, node_address, connection_settings);
+        let mut connection_count = None;
+
         let thread = thread::spawn(move || loop {
             logger.log(format!("Wormer manager managing {} workers", workers.len()));
             match worker_manager_loop(
                 &new_peer_conector,
+                &mut outbound_connector,
+                &mut misbehavior_tracker,
                 &mut workers,
                 &safe_node_info,
                 &message_bytes_receiver,
@@ -271,7 +340,10 @@ impl PeerComunicatorWorkerManager {
                 &finished,
                 &logger,
             ) {
-                Stops::Continue => continue,
+                Stops::Continue => {
+                    report_connection_count(&workers, &mut connection_count, &sender_to_ui);
+                    continue;
+                }
                 Stops::GracefullStop => {
                     logger.log(Stops::GracefullStop.log_message("peer communicator".to_string()));
                 }
@@ -324,11 +396,31 @@ impl PeerComunicatorWorkerManager {
     }
 }
 
+/// Sends the amount of outbound and inbound peers to the UI if it changed since the last report.
+fn report_connection_count(
+    workers: &[Worker],
+    last_count: &mut Option<(usize, usize)>,
+    sender_to_ui: &GlibSender<UIResponse>,
+) {
+    let outbound = workers.iter().filter(|worker| worker.is_outbound()).count();
+    let count = (outbound, workers.len() - outbound);
+    if *last_count == Some(count) {
+        return;
+    }
+    if sender_to_ui
+        .send(UIResponse::ConnectionCount(count.0, count.1))
+        .is_ok()
+    {
+        *last_count = Some(count);
+    }
+}
+
 ///Creates a PeerCommunicatorWorker for each stream, making each of them responsible for communicating with their corresponding peer
 fn create_peer_comunicator_workers(
-    outbound_connections: &Vec<TcpStream>,
+    outbound_connections: &Vec<PeerStream>,
     safe_node_info: &NodeSharedInformation,
     propagation_channel: &mpsc::Sender<Vec<u8>>,
+    offense_sender: &mpsc::Sender<OffenseReport>,
     finished_working_indicator: &Arc<Mutex<bool>>,
     logger: &Logger,
 ) -> Vec<Worker> {
@@ -350,9 +442,11 @@ fn create_peer_comunicator_workers(
             current_stream,
             safe_node_info.clone(),
             propagation_channel.clone(),
+            offense_sender.clone(),
             logger.clone(),
             finished_working_indicator.clone(),
             id,
+            true,
         );
         workers.push(worker);
     }
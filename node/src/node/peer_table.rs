@@ -0,0 +1,343 @@
+use crate::{
+    messages::{Message, VersionMessage},
+    utils::ui_communication_protocol::{PeerFeatures, PeerInfo},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub type SafePeerTable = Arc<Mutex<PeerTable>>;
+
+/// What a peer announced about itself in its version message during the handshake.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PeerVersion {
+    pub version: i32,
+    pub services: u64,
+    pub user_agent: String,
+    pub start_height: i32,
+}
+
+/// Connected peers of the node, alongside the peers the user asked to disconnect from, the
+/// time since which each peer is behind the tip of the node and when the ones behind for too
+/// long were asked for headers. Peers are added once their handshake is done, and removed when
+/// their worker finishes.
+#[derive(Debug, Default)]
+pub struct PeerTable {
+    peers: HashMap<SocketAddr, PeerInfo>,
+    disconnect_requests: HashSet<SocketAddr>,
+    behind_since: HashMap<SocketAddr, u32>,
+    headers_requested: HashMap<SocketAddr, u32>,
+}
+
+impl PeerVersion {
+    pub fn from(version_message: &VersionMessage) -> PeerVersion {
+        PeerVersion {
+            version: version_message.get_version(),
+            services: version_message.get_services(),
+            user_agent: version_message.get_user_agent(),
+            start_height: version_message.get_start_height(),
+        }
+    }
+}
+
+impl PeerTable {
+    pub fn new() -> PeerTable {
+        PeerTable::default()
+    }
+
+    /// Adds a peer that just finished its handshake, replacing any previous connection from the same address.
+    pub fn add_peer(
+        &mut self,
+        address: SocketAddr,
+        peer_version: PeerVersion,
+        outbound: bool,
+        now: u32,
+    ) {
+        self.peers.insert(
+            address,
+            PeerInfo {
+                address,
+                version: peer_version.version,
+                services: peer_version.services,
+                user_agent: peer_version.user_agent,
+                start_height: peer_version.start_height,
+                best_known_height: peer_version.start_height.max(0) as usize,
+                outbound,
+                connected_since: now,
+                bytes_sent: 0,
+                bytes_received: 0,
+                ping: None,
+                features: PeerFeatures::default(),
+            },
+        );
+    }
+
+    /// Forgets a peer that disconnected.
+    pub fn remove_peer(&mut self, address: &SocketAddr) {
+        self.peers.remove(address);
+        self.disconnect_requests.remove(address);
+        self.behind_since.remove(address);
+        self.headers_requested.remove(address);
+    }
+
+    /// Adds the bytes exchanged with the peer to its totals.
+    pub fn add_traffic(&mut self, address: &SocketAddr, bytes_sent: u64, bytes_received: u64) {
+        if let Some(info) = self.peers.get_mut(address) {
+            info.bytes_sent += bytes_sent;
+            info.bytes_received += bytes_received;
+        }
+    }
+
+    /// Registers the round trip time of the last ping answered by the peer.
+    pub fn set_ping(&mut self, address: &SocketAddr, ping: Duration) {
+        if let Some(info) = self.peers.get_mut(address) {
+            info.ping = Some(ping);
+        }
+    }
+
+    /// Registers that the peer has the block at the height, if it is higher than the best one known.
+    pub fn update_best_known_height(&mut self, address: &SocketAddr, height: usize) {
+        if let Some(info) = self.peers.get_mut(address) {
+            info.best_known_height = info.best_known_height.max(height);
+        }
+    }
+
+    /// Registers the preference the peer negotiated with the message. Returns false if the message
+    /// is not a feature negotiation message.
+    pub fn update_features(&mut self, address: &SocketAddr, message: &Message) -> bool {
+        let features = match self.peers.get_mut(address) {
+            Some(info) => &mut info.features,
+            None => return false,
+        };
+        match message {
+            Message::SendHeaders(_) => features.send_headers = true,
+            Message::FeeFilter(fee_filter) => features.fee_filter = fee_filter.get_fee_rate(),
+            Message::SendCmpct(send_cmpct) => {
+                if send_cmpct.get_version() >= features.compact_blocks_version {
+                    features.compact_blocks_version = send_cmpct.get_version();
+                    features.compact_blocks_announce = send_cmpct.get_announce();
+                }
+            }
+            Message::WtxidRelay(_) => features.wtxid_relay = true,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Returns the preferences negotiated by every connected peer.
+    pub fn get_features(&self) -> HashMap<SocketAddr, PeerFeatures> {
+        self.peers
+            .iter()
+            .map(|(address, info)| (*address, info.features.clone()))
+            .collect()
+    }
+
+    /// Looks for the outbound peers whose best known block has been behind the tip of the node for
+    /// more than the timeout. As the best known block only changes when the peer announces one, those
+    /// peers are asked for headers first: returns the peers that have to be asked, and the ones that
+    /// are still behind response_timeout after being asked, which are stale. Peers that caught up are
+    /// no longer considered behind.
+    pub fn find_stale_peers(
+        &mut self,
+        tip_height: usize,
+        now: u32,
+        timeout: u32,
+        response_timeout: u32,
+    ) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+        let (mut peers_to_ask, mut stale_peers) = (Vec::new(), Vec::new());
+        for (address, info) in &self.peers {
+            if !info.outbound || info.best_known_height >= tip_height {
+                self.behind_since.remove(address);
+                self.headers_requested.remove(address);
+                continue;
+            }
+            let behind_since = *self.behind_since.entry(*address).or_insert(now);
+            if now.saturating_sub(behind_since) <= timeout {
+                continue;
+            }
+            match self.headers_requested.get(address) {
+                Some(requested) if now.saturating_sub(*requested) > response_timeout => {
+                    stale_peers.push(*address);
+                }
+                Some(_) => {}
+                None => {
+                    self.headers_requested.insert(*address, now);
+                    peers_to_ask.push(*address);
+                }
+            }
+        }
+        (peers_to_ask, stale_peers)
+    }
+
+    /// Returns the information of every connected peer, the oldest connections first.
+    pub fn get_peers(&self) -> Vec<PeerInfo> {
+        let mut peers: Vec<PeerInfo> = self.peers.values().cloned().collect();
+        peers.sort_by_key(|info| (info.connected_since, info.address));
+        peers
+    }
+
+    /// Asks the worker manager to disconnect from the peer. Returns false if the peer is not connected.
+    pub fn request_disconnect(&mut self, address: SocketAddr) -> bool {
+        if !self.peers.contains_key(&address) {
+            return false;
+        }
+        self.disconnect_requests.insert(address);
+        true
+    }
+
+    /// Returns the peers the user asked to disconnect from, since the last time it was called.
+    pub fn take_disconnect_requests(&mut self) -> Vec<SocketAddr> {
+        self.disconnect_requests.drain().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{
+        FeeFilterMessage, SendCmpctMessage, SendHeadersMessage, WtxidRelayMessage,
+    };
+
+    const NOW: u32 = 1681084800;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn address(last_byte: u8) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, last_byte], 18333))
+    }
+
+    fn peer_version() -> PeerVersion {
+        PeerVersion {
+            version: 70015,
+            services: 1,
+            user_agent: "/Satoshi:25.0.0/".to_string(),
+            start_height: 2_400_000,
+        }
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn peer_table_test_1_traffic_is_added_to_the_peer() {
+        let mut peer_table = PeerTable::new();
+        peer_table.add_peer(address(2), peer_version(), false, NOW + 1);
+        peer_table.add_peer(address(1), peer_version(), true, NOW);
+
+        peer_table.add_traffic(&address(1), 10, 100);
+        peer_table.add_traffic(&address(1), 5, 0);
+        peer_table.add_traffic(&address(3), 5, 0);
+
+        let peers = peer_table.get_peers();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].address, address(1));
+        assert_eq!((peers[0].bytes_sent, peers[0].bytes_received), (15, 100));
+        assert_eq!((peers[1].bytes_sent, peers[1].bytes_received), (0, 0));
+    }
+
+    #[test]
+    fn peer_table_test_2_only_connected_peers_can_be_disconnected() {
+        let mut peer_table = PeerTable::new();
+        peer_table.add_peer(address(1), peer_version(), true, NOW);
+
+        assert!(peer_table.request_disconnect(address(1)));
+        assert!(!peer_table.request_disconnect(address(2)));
+        assert_eq!(peer_table.take_disconnect_requests(), vec![address(1)]);
+        assert!(peer_table.take_disconnect_requests().is_empty());
+
+        peer_table.remove_peer(&address(1));
+        assert!(peer_table.get_peers().is_empty());
+    }
+
+    #[test]
+    fn peer_table_test_3_outbound_peers_behind_the_tip_become_stale() {
+        let mut peer_table = PeerTable::new();
+        peer_table.add_peer(address(1), peer_version(), true, NOW);
+        peer_table.add_peer(address(2), peer_version(), true, NOW);
+        peer_table.add_peer(address(3), peer_version(), false, NOW);
+        let tip_height = 2_400_010;
+
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height, NOW, 60, 10),
+            (vec![], vec![])
+        );
+        peer_table.update_best_known_height(&address(2), tip_height);
+
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height, NOW + 61, 60, 10),
+            (vec![address(1)], vec![])
+        );
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height, NOW + 71, 60, 10),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height, NOW + 72, 60, 10),
+            (vec![], vec![address(1)])
+        );
+    }
+
+    #[test]
+    fn peer_table_test_4_negotiated_features_are_registered() {
+        let mut peer_table = PeerTable::new();
+        peer_table.add_peer(address(1), peer_version(), true, NOW);
+
+        assert!(peer_table.update_features(
+            &address(1),
+            &Message::SendHeaders(SendHeadersMessage::new())
+        ));
+        assert!(peer_table.update_features(
+            &address(1),
+            &Message::FeeFilter(FeeFilterMessage::new(1000))
+        ));
+        assert!(peer_table.update_features(
+            &address(1),
+            &Message::SendCmpct(SendCmpctMessage::new(true, 2))
+        ));
+        assert!(peer_table.update_features(
+            &address(1),
+            &Message::SendCmpct(SendCmpctMessage::new(false, 1))
+        ));
+        assert!(!peer_table
+            .update_features(&address(2), &Message::WtxidRelay(WtxidRelayMessage::new())));
+        assert!(!peer_table.update_features(&address(1), &Message::UnknownMessage));
+
+        let features = &peer_table.get_features()[&address(1)];
+        assert!(features.send_headers);
+        assert_eq!(features.fee_filter, 1000);
+        assert_eq!(features.compact_blocks_version, 2);
+        assert!(features.compact_blocks_announce);
+        assert!(!features.wtxid_relay);
+    }
+
+    #[test]
+    fn peer_table_test_5_peers_that_answer_the_headers_request_are_not_stale() {
+        let mut peer_table = PeerTable::new();
+        peer_table.add_peer(address(1), peer_version(), true, NOW);
+        let tip_height = 2_400_010;
+
+        peer_table.find_stale_peers(tip_height, NOW, 60, 10);
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height, NOW + 61, 60, 10),
+            (vec![address(1)], vec![])
+        );
+        peer_table.update_best_known_height(&address(1), tip_height);
+
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height, NOW + 72, 60, 10),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height + 1, NOW + 73, 60, 10),
+            (vec![], vec![])
+        );
+        assert_eq!(
+            peer_table.find_stale_peers(tip_height + 1, NOW + 134, 60, 10),
+            (vec![address(1)], vec![])
+        );
+    }
+}
@@ -1,55 +1,110 @@
+//! Shared state of the node, used by the threads that talk to peers.
+//!
+//! Every thread that needs more than one of these locks at the same time takes them in this order,
+//! releasing them in any order:
+//!
+//! 1. data handler (only held by the node and the checkpointer)
+//! 2. blockchain
+//! 3. pending transactions
+//! 4. headers index
+//! 5. block headers
+//! 6. block filter index, then tx index, then address index
+//!
+//! The address book, ban list, peer table and download scheduler are leaves: no other lock is taken
+//! while holding one of them. The headers and their index are read far more often than they are
+//! written, so they are behind a RwLock, and they are append-only while the node is running: once a
+//! position is read from the index it stays valid after releasing the lock.
+
+use super::{
+    address_book::{AddressBook, SafeAddressBook},
+    address_index::{AddressIndex, SafeAddressIndex},
+    ban_list::{BanList, SafeBanList},
+    block_filter_index::{BlockFilterIndex, SafeBlockFilterIndex},
+    block_store::BlockStore,
+    peer_table::{PeerTable, SafePeerTable},
+    tx_index::{SafeTxIndex, TxIndex},
+    SafeBlockChain, SafeHeaderIndex, SafePendingTx, SafeVecHeader,
+};
 use crate::{
     blocks::{blockchain::*, Transaction},
     utils::NodeError,
 };
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{MutexGuard, RwLockReadGuard},
 };
 
-pub type SafeBlockChain = Arc<Mutex<HashMap<[u8; 32], Block>>>;
-pub type SafeVecHeader = Arc<Mutex<Vec<BlockHeader>>>;
-pub type SafePendingTx = Arc<Mutex<HashMap<[u8; 32], Transaction>>>;
-pub type SafeHeadersIndex = Arc<Mutex<HashMap<[u8; 32], usize>>>;
-
 #[derive(Clone, Debug)]
 pub struct NodeSharedInformation {
     pub safe_blockchain: SafeBlockChain,
     pub safe_block_headers: SafeVecHeader,
-    pub safe_headers_index: SafeHeadersIndex,
+    pub safe_headers_index: SafeHeaderIndex,
     pub safe_pending_tx: SafePendingTx,
+    pub safe_address_book: SafeAddressBook,
+    pub safe_ban_list: SafeBanList,
+    pub safe_block_filter_index: SafeBlockFilterIndex,
+    pub safe_tx_index: SafeTxIndex,
+    pub safe_address_index: SafeAddressIndex,
+    pub safe_peer_table: SafePeerTable,
+    pub light_client: bool,
+    pub v2_transport: bool,
+    pub pruned: bool,
+    pub compact_filters: bool,
 }
 
 impl NodeSharedInformation {
+    #[allow(clippy::too_many_arguments)]
     pub fn from(
         safe_blockchain: &SafeBlockChain,
         safe_block_headers: &SafeVecHeader,
-        safe_headers_index: &SafeHeadersIndex,
+        safe_headers_index: &SafeHeaderIndex,
         safe_pending_tx: &SafePendingTx,
+        safe_address_book: &SafeAddressBook,
+        safe_ban_list: &SafeBanList,
+        safe_block_filter_index: &SafeBlockFilterIndex,
+        safe_tx_index: &SafeTxIndex,
+        safe_address_index: &SafeAddressIndex,
+        safe_peer_table: &SafePeerTable,
+        light_client: bool,
+        v2_transport: bool,
+        pruned: bool,
+        compact_filters: bool,
     ) -> NodeSharedInformation {
         NodeSharedInformation {
             safe_blockchain: safe_blockchain.clone(),
             safe_block_headers: safe_block_headers.clone(),
             safe_headers_index: safe_headers_index.clone(),
             safe_pending_tx: safe_pending_tx.clone(),
+            safe_address_book: safe_address_book.clone(),
+            safe_ban_list: safe_ban_list.clone(),
+            safe_block_filter_index: safe_block_filter_index.clone(),
+            safe_tx_index: safe_tx_index.clone(),
+            safe_address_index: safe_address_index.clone(),
+            safe_peer_table: safe_peer_table.clone(),
+            light_client,
+            v2_transport,
+            pruned,
+            compact_filters,
         }
     }
 
-    pub fn lock_blockchain(&self) -> Result<MutexGuard<HashMap<[u8; 32], Block>>, NodeError> {
+    pub fn lock_blockchain(&self) -> Result<MutexGuard<BlockStore>, NodeError> {
         self.safe_blockchain
             .lock()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
 
-    pub fn lock_block_headers(&self) -> Result<MutexGuard<Vec<BlockHeader>>, NodeError> {
+    pub fn read_block_headers(&self) -> Result<RwLockReadGuard<Vec<BlockHeader>>, NodeError> {
         self.safe_block_headers
-            .lock()
+            .read()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
 
-    pub fn lock_headers_index(&self) -> Result<MutexGuard<HashMap<[u8; 32], usize>>, NodeError> {
+    pub fn read_headers_index(
+        &self,
+    ) -> Result<RwLockReadGuard<HashMap<[u8; 32], usize>>, NodeError> {
         self.safe_headers_index
-            .lock()
+            .read()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
 
@@ -60,4 +115,196 @@ impl NodeSharedInformation {
             .lock()
             .map_err(|_| NodeError::ErrorSharingReference)
     }
+
+    pub fn lock_address_book(&self) -> Result<MutexGuard<AddressBook>, NodeError> {
+        self.safe_address_book
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    pub fn lock_ban_list(&self) -> Result<MutexGuard<BanList>, NodeError> {
+        self.safe_ban_list
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    pub fn lock_block_filter_index(&self) -> Result<MutexGuard<BlockFilterIndex>, NodeError> {
+        self.safe_block_filter_index
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    pub fn lock_tx_index(&self) -> Result<MutexGuard<TxIndex>, NodeError> {
+        self.safe_tx_index
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    pub fn lock_address_index(&self) -> Result<MutexGuard<AddressIndex>, NodeError> {
+        self.safe_address_index
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+
+    pub fn lock_peer_table(&self) -> Result<MutexGuard<PeerTable>, NodeError> {
+        self.safe_peer_table
+            .lock()
+            .map_err(|_| NodeError::ErrorSharingReference)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{proof::*, TxOut},
+        messages::{compact_filter_messages::*, *},
+        node::{handle_messages::handle_message, insert_new_headers},
+        utils::{log::Logger, mock_tcp_stream::MockTcpStream},
+    };
+    use std::{
+        sync::{mpsc, Arc, Mutex, RwLock},
+        thread,
+        time::Duration,
+    };
+
+    const EASY_N_BITS: u32 = 0xffffff20;
+    const SIMULATED_PEERS: usize = 16;
+    const CHAIN_LENGTH: usize = 30;
+    const DEADLOCK_TIMEOUT: Duration = Duration::from_secs(60);
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn shared_information() -> NodeSharedInformation {
+        NodeSharedInformation::from(
+            &Arc::new(Mutex::new(BlockStore::new())),
+            &Arc::new(RwLock::new(Vec::new())),
+            &Arc::new(RwLock::new(HashMap::new())),
+            &Arc::new(Mutex::new(HashMap::new())),
+            &Arc::new(Mutex::new(AddressBook::new())),
+            &Arc::new(Mutex::new(BanList::new())),
+            &Arc::new(Mutex::new(BlockFilterIndex::new())),
+            &Arc::new(Mutex::new(TxIndex::new())),
+            &Arc::new(Mutex::new(AddressIndex::new())),
+            &Arc::new(Mutex::new(PeerTable::new())),
+            false,
+            false,
+            false,
+            false,
+        )
+    }
+
+    fn transaction(value: i64) -> Transaction {
+        Transaction::new(1, Vec::new(), vec![TxOut::new(value, Vec::new())], 0)
+    }
+
+    fn mine_chain(length: usize) -> Vec<Block> {
+        let mut blocks: Vec<Block> = Vec::new();
+        for height in 0..length {
+            let prev_hash = match blocks.last() {
+                Some(block) => block.header_hash(),
+                None => [0; 32],
+            };
+            let tx = transaction(height as i64);
+            let merkle_root = calculate_merkle_root(vec![tx.hash()]);
+            let mut header = BlockHeader::new(1, prev_hash, merkle_root, EASY_N_BITS);
+            while !validate_proof_of_work(&header) {
+                header.nonce += 1;
+            }
+            blocks.push(Block::new(header, vec![tx]));
+        }
+        blocks
+    }
+
+    /// Messages a peer sends for each block: the block itself, requests that read the headers, the
+    /// blockchain and the filters, and a new transaction.
+    fn peer_messages(block: &Block, locator_hash: [u8; 32], tx: Transaction) -> Vec<Message> {
+        let block_hash = block.header_hash();
+        vec![
+            Message::Block(BlockMessage::from(block).unwrap()),
+            Message::GetBlockHeaders(GetBlockHeadersMessage::new(
+                70015,
+                vec![locator_hash],
+                [0; 32],
+            )),
+            Message::GetData(GetDataMessage::create_message_inventory_block_type(vec![
+                block_hash,
+            ])),
+            Message::GetCFHeaders(GetCFHeadersMessage {
+                filter_type: 0,
+                start_height: 1,
+                stop_hash: block_hash,
+            }),
+            Message::GetCFCheckpt(GetCFCheckptMessage {
+                filter_type: 0,
+                stop_hash: block_hash,
+            }),
+            Message::Inv(InvMessage::create_message_inventory_block_type(vec![
+                block_hash,
+            ])),
+            Message::Tx(TxMessage::new(tx)),
+        ]
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn safe_node_structure_test_1_concurrent_peers_do_not_deadlock() {
+        let logger = Logger::from_path("tests_txt/safe_node_structure_test_1_log.txt").unwrap();
+        let safe_node_info = shared_information();
+        let blocks = Arc::new(mine_chain(CHAIN_LENGTH));
+        let (finished_sender, finished_receiver) = mpsc::channel();
+
+        for peer in 0..SIMULATED_PEERS {
+            let (safe_node_info, blocks) = (safe_node_info.clone(), blocks.clone());
+            let (logger, finished_sender) = (logger.clone(), finished_sender.clone());
+            thread::spawn(move || {
+                let mut stream = MockTcpStream::new();
+                let genesis_hash = blocks[0].get_header().prev_hash;
+                for (height, block) in blocks.iter().enumerate() {
+                    let tx = transaction(((peer + 1) * CHAIN_LENGTH + height) as i64);
+                    for message in peer_messages(block, genesis_hash, tx) {
+                        handle_message(message, &mut stream, &safe_node_info, &logger, false)
+                            .unwrap();
+                    }
+                }
+                finished_sender.send(stream.write_buffer.len()).unwrap();
+            });
+        }
+        let header_writer_info = safe_node_info.clone();
+        thread::spawn(move || {
+            for _ in 0..CHAIN_LENGTH * SIMULATED_PEERS {
+                insert_new_headers(
+                    Vec::new(),
+                    &header_writer_info.safe_block_headers,
+                    &header_writer_info.safe_headers_index,
+                )
+                .unwrap();
+            }
+        });
+
+        for _ in 0..SIMULATED_PEERS {
+            let written_bytes = finished_receiver
+                .recv_timeout(DEADLOCK_TIMEOUT)
+                .expect("a simulated peer did not finish, the locks may be deadlocked");
+            assert!(written_bytes > 0);
+        }
+        let block_headers = safe_node_info.read_block_headers().unwrap();
+        let headers_index = safe_node_info.read_headers_index().unwrap();
+        assert_eq!(
+            safe_node_info.lock_blockchain().unwrap().len(),
+            CHAIN_LENGTH
+        );
+        assert_eq!(block_headers.len(), CHAIN_LENGTH);
+        for (position, block) in blocks.iter().enumerate() {
+            assert_eq!(block_headers[position].hash(), block.header_hash());
+            assert_eq!(headers_index.get(&block.header_hash()), Some(&position));
+        }
+        assert_eq!(
+            safe_node_info.lock_safe_pending_tx().unwrap().len(),
+            SIMULATED_PEERS * CHAIN_LENGTH
+        );
+    }
 }
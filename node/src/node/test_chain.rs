@@ -0,0 +1,21 @@
+use crate::blocks::{proof::calculate_merkle_root, Block, BlockHeader, Transaction};
+
+/// Builds a chain of blocks that follows the block with the given hash, one block for each list of
+/// transactions, with the given bits and the merkle root of its transactions. Returns the blocks and
+/// their headers, in chain order.
+pub fn chain(
+    mut prev_hash: [u8; 32],
+    bits: u32,
+    transactions: Vec<Vec<Transaction>>,
+) -> (Vec<Block>, Vec<BlockHeader>) {
+    let mut blocks = Vec::new();
+    let mut block_headers = Vec::new();
+    for block_transactions in transactions {
+        let tx_hashes = block_transactions.iter().map(|tx| tx.hash()).collect();
+        let header = BlockHeader::new(1, prev_hash, calculate_merkle_root(tx_hashes), bits);
+        prev_hash = header.hash();
+        blocks.push(Block::new(header.clone(), block_transactions));
+        block_headers.push(header);
+    }
+    (blocks, block_headers)
+}
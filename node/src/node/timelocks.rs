@@ -0,0 +1,340 @@
+use super::safe_node_structure::NodeSharedInformation;
+use crate::{
+    blocks::{
+        blockchain::*, median_time_past, InputConfirmation, Transaction,
+        RELATIVE_LOCKTIME_MIN_VERSION, SEQUENCE_LOCKTIME_DISABLE_FLAG, SEQUENCE_LOCKTIME_MASK,
+    },
+    utils::btc_errors::NodeError,
+};
+use std::collections::{HashMap, HashSet};
+
+/// Amount of blocks read looking for the parents of a transaction when there is no tx index. It is
+/// the largest relative height lock, older parents are considered old enough.
+const MAX_PARENT_SEARCH_DEPTH: usize = SEQUENCE_LOCKTIME_MASK as usize;
+
+/// Returns the hashes of the transactions whose outputs are spent by inputs that have a
+/// relative lock, as those are the only ones that need to be found in the blockchain.
+fn relative_locked_parents(tx: &Transaction) -> HashSet<[u8; 32]> {
+    tx.tx_in
+        .iter()
+        .filter(|tx_in| tx_in.get_sequence() & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0)
+        .map(|tx_in| tx_in.previous_output.hash)
+        .collect()
+}
+
+/// Returns true if every relative lock of the transaction would be satisfied by the next block
+/// even if all its parents were confirmed at the given confirmation. As the heights and median
+/// times past of the blocks only grow, the parents confirmed before it are old enough too.
+fn is_old_enough(
+    tx: &Transaction,
+    confirmation: InputConfirmation,
+    next_height: u32,
+    tip_median_time_past: u32,
+) -> bool {
+    let confirmations = vec![Some(confirmation); tx.tx_in.len()];
+    tx.sequence_locks(&confirmations)
+        .are_satisfied_by(next_height, tip_median_time_past)
+}
+
+/// Returns where the block of the given hash confirms its transactions, or None if it is not
+/// in the headers.
+fn block_confirmation(
+    block_hash: &[u8; 32],
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Option<InputConfirmation>, NodeError> {
+    let index = match safe_node_info.read_headers_index()?.get(block_hash) {
+        Some(index) => *index,
+        None => return Ok(None),
+    };
+    let block_headers = safe_node_info.read_block_headers()?;
+    if index >= block_headers.len() {
+        return Ok(None);
+    }
+
+    Ok(Some(InputConfirmation {
+        height: (index + 1) as u32,
+        median_time_past: median_time_past(&block_headers[..index]),
+    }))
+}
+
+/// Looks for the parents in the tx index, removing the ones found from missing_parents.
+/// Returns false if the index is disabled.
+fn find_parents_in_tx_index(
+    missing_parents: &mut HashSet<[u8; 32]>,
+    parent_confirmations: &mut HashMap<[u8; 32], InputConfirmation>,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<bool, NodeError> {
+    let parent_blocks: Vec<([u8; 32], [u8; 32])> = {
+        let tx_index = safe_node_info.lock_tx_index()?;
+        if !tx_index.is_enabled() {
+            return Ok(false);
+        }
+        missing_parents
+            .iter()
+            .filter_map(|hash| Some((*hash, tx_index.get(hash)?.block_hash)))
+            .collect()
+    };
+
+    for (hash, block_hash) in parent_blocks {
+        if let Some(confirmation) = block_confirmation(&block_hash, safe_node_info)? {
+            missing_parents.remove(&hash);
+            parent_confirmations.insert(hash, confirmation);
+        }
+    }
+    Ok(true)
+}
+
+/// Looks for the parents walking the blockchain backwards from the tip, until every parent is
+/// found, a block is missing, MAX_PARENT_SEARCH_DEPTH blocks were read or the blocks reached
+/// are old enough for the locks of the transaction. The locks are taken for each block and
+/// released before reading the next one.
+fn find_parents_in_blocks(
+    tx: &Transaction,
+    (next_height, tip_median_time_past): (u32, u32),
+    missing_parents: &mut HashSet<[u8; 32]>,
+    parent_confirmations: &mut HashMap<[u8; 32], InputConfirmation>,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<(), NodeError> {
+    let tip_index = next_height as usize - 1;
+    for index in (tip_index.saturating_sub(MAX_PARENT_SEARCH_DEPTH)..tip_index).rev() {
+        if missing_parents.is_empty() {
+            break;
+        }
+        let (block_hash, confirmation) = {
+            let block_headers = safe_node_info.read_block_headers()?;
+            let confirmation = InputConfirmation {
+                height: (index + 1) as u32,
+                median_time_past: median_time_past(&block_headers[..index]),
+            };
+            (block_headers[index].hash(), confirmation)
+        };
+        if is_old_enough(tx, confirmation, next_height, tip_median_time_past) {
+            break;
+        }
+        let found_parents: Vec<[u8; 32]> = {
+            let mut blockchain = safe_node_info.lock_blockchain()?;
+            let block = match blockchain.get(&block_hash) {
+                Some(block) => block,
+                None => break,
+            };
+            block
+                .get_transactions()
+                .iter()
+                .map(|block_tx| block_tx.hash())
+                .filter(|hash| missing_parents.contains(hash))
+                .collect()
+        };
+        for hash in found_parents {
+            missing_parents.remove(&hash);
+            parent_confirmations.insert(hash, confirmation);
+        }
+    }
+    Ok(())
+}
+
+/// Looks for the blocks that confirmed the outputs spent by the relative locked inputs of the
+/// transaction, using the tx index if it is enabled or walking the recent blocks otherwise.
+fn find_input_confirmations(
+    tx: &Transaction,
+    lock_state: (u32, u32),
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Vec<Option<InputConfirmation>>, NodeError> {
+    let mut missing_parents = relative_locked_parents(tx);
+    let mut parent_confirmations = HashMap::new();
+
+    if !missing_parents.is_empty()
+        && !find_parents_in_tx_index(
+            &mut missing_parents,
+            &mut parent_confirmations,
+            safe_node_info,
+        )?
+    {
+        find_parents_in_blocks(
+            tx,
+            lock_state,
+            &mut missing_parents,
+            &mut parent_confirmations,
+            safe_node_info,
+        )?;
+    }
+
+    Ok(tx
+        .tx_in
+        .iter()
+        .map(|tx_in| {
+            parent_confirmations
+                .get(&tx_in.previous_output.hash)
+                .copied()
+        })
+        .collect())
+}
+
+/// Returns the height of the next block and the median time past of the current tip.
+pub fn next_block_lock_state(block_headers: &[BlockHeader]) -> (u32, u32) {
+    (
+        block_headers.len() as u32 + 1,
+        median_time_past(block_headers),
+    )
+}
+
+/// Returns true if the transaction could be included in the next block, checking both its
+/// absolute lock and the relative locks of its inputs. Inputs spending outputs that are still
+/// pending are considered to be confirmed in the next block, while the ones whose parent was not
+/// found in the recent blocks are considered old enough.
+pub fn is_final_for_next_block(
+    tx: &Transaction,
+    safe_node_info: &NodeSharedInformation,
+) -> Result<bool, NodeError> {
+    let (next_height, tip_median_time_past) =
+        next_block_lock_state(&safe_node_info.read_block_headers()?);
+    if !tx.is_final(next_height, tip_median_time_past) {
+        return Ok(false);
+    }
+    if tx.get_version() < RELATIVE_LOCKTIME_MIN_VERSION {
+        return Ok(true);
+    }
+    let mut confirmations =
+        find_input_confirmations(tx, (next_height, tip_median_time_past), safe_node_info)?;
+
+    let pending_tx = safe_node_info.lock_safe_pending_tx()?;
+    for (tx_in, confirmation) in tx.tx_in.iter().zip(confirmations.iter_mut()) {
+        if confirmation.is_none() && pending_tx.contains_key(&tx_in.previous_output.hash) {
+            *confirmation = Some(InputConfirmation {
+                height: next_height,
+                median_time_past: tip_median_time_past,
+            });
+        }
+    }
+
+    Ok(tx
+        .sequence_locks(&confirmations)
+        .are_satisfied_by(next_height, tip_median_time_past))
+}
+
+/// Returns the height and the median time past of the previous blocks of a block whose
+/// previous block is prev_hash, or None if it is not in the headers.
+pub fn block_lock_state(
+    prev_hash: &[u8; 32],
+    safe_node_info: &NodeSharedInformation,
+) -> Result<Option<(u32, u32)>, NodeError> {
+    let prev_index = match safe_node_info.read_headers_index()?.get(prev_hash) {
+        Some(prev_index) => *prev_index,
+        None => return Ok(None),
+    };
+    let block_headers = safe_node_info.read_block_headers()?;
+    if prev_index >= block_headers.len() {
+        return Ok(None);
+    }
+
+    Ok(Some(next_block_lock_state(&block_headers[..=prev_index])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blocks::{Outpoint, TxIn, TxOut};
+    use crate::node::{
+        address_book::AddressBook, address_index::AddressIndex, ban_list::BanList,
+        block_filter_index::BlockFilterIndex, block_store::BlockStore, peer_table::PeerTable,
+        tx_index::TxIndex,
+    };
+    use std::sync::{Arc, Mutex, RwLock};
+
+    const EASY_N_BITS: u32 = 0xffffff20;
+    const CHAIN_LENGTH: usize = 10;
+
+    // Auxiliar functions
+    //=================================================================
+
+    /// Shared information with a chain of CHAIN_LENGTH blocks, each with a single transaction
+    /// paying the height of its block.
+    fn shared_information_with_chain() -> (NodeSharedInformation, Vec<[u8; 32]>) {
+        let mut blockchain = BlockStore::new();
+        let mut block_headers = Vec::new();
+        let mut headers_index = HashMap::new();
+        let mut txids = Vec::new();
+        for height in 1..=CHAIN_LENGTH {
+            let prev_hash = block_headers
+                .last()
+                .map_or([0; 32], |header: &BlockHeader| header.hash());
+            let tx = Transaction::new(
+                1,
+                Vec::new(),
+                vec![TxOut::new(height as i64, Vec::new())],
+                0,
+            );
+            txids.push(tx.hash());
+            let header = BlockHeader::new(1, prev_hash, tx.hash(), EASY_N_BITS);
+            headers_index.insert(header.hash(), block_headers.len());
+            block_headers.push(header.clone());
+            blockchain
+                .insert(Block::new(header, vec![tx]), height as u32)
+                .unwrap();
+        }
+
+        let safe_node_info = NodeSharedInformation::from(
+            &Arc::new(Mutex::new(blockchain)),
+            &Arc::new(RwLock::new(block_headers)),
+            &Arc::new(RwLock::new(headers_index)),
+            &Arc::new(Mutex::new(HashMap::new())),
+            &Arc::new(Mutex::new(AddressBook::new())),
+            &Arc::new(Mutex::new(BanList::new())),
+            &Arc::new(Mutex::new(BlockFilterIndex::new())),
+            &Arc::new(Mutex::new(TxIndex::new())),
+            &Arc::new(Mutex::new(AddressIndex::new())),
+            &Arc::new(Mutex::new(PeerTable::new())),
+            false,
+            false,
+            false,
+            false,
+        );
+        (safe_node_info, txids)
+    }
+
+    fn spending_tx(version: i32, parent: [u8; 32], sequence: u32) -> Transaction {
+        let tx_in = TxIn::new(Outpoint::new(parent, 0), Vec::new(), sequence);
+        Transaction::new(version, vec![tx_in], vec![TxOut::new(1, Vec::new())], 0)
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn timelocks_test_1_relative_height_lock_depends_on_the_parent_block() {
+        let (safe_node_info, txids) = shared_information_with_chain();
+        let tip_parent = txids[CHAIN_LENGTH - 1];
+        let old_parent = txids[CHAIN_LENGTH - 6];
+
+        assert!(!is_final_for_next_block(&spending_tx(2, tip_parent, 5), &safe_node_info).unwrap());
+        assert!(is_final_for_next_block(&spending_tx(2, old_parent, 5), &safe_node_info).unwrap());
+        assert!(is_final_for_next_block(&spending_tx(2, tip_parent, 1), &safe_node_info).unwrap());
+    }
+
+    #[test]
+    fn timelocks_test_2_version_1_transactions_ignore_relative_locks() {
+        let (safe_node_info, txids) = shared_information_with_chain();
+        let tip_parent = txids[CHAIN_LENGTH - 1];
+
+        assert!(is_final_for_next_block(&spending_tx(1, tip_parent, 5), &safe_node_info).unwrap());
+    }
+
+    #[test]
+    fn timelocks_test_3_walk_stops_when_the_blocks_are_old_enough() {
+        let (safe_node_info, txids) = shared_information_with_chain();
+        let tx = spending_tx(2, txids[0], 3);
+        let mut missing_parents = relative_locked_parents(&tx);
+        let mut parent_confirmations = HashMap::new();
+
+        find_parents_in_blocks(
+            &tx,
+            (CHAIN_LENGTH as u32 + 1, 0),
+            &mut missing_parents,
+            &mut parent_confirmations,
+            &safe_node_info,
+        )
+        .unwrap();
+
+        assert!(missing_parents.contains(&txids[0]));
+        assert!(parent_confirmations.is_empty());
+    }
+}
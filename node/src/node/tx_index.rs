@@ -0,0 +1,340 @@
+use crate::{
+    blocks::{blockchain::*, HashPair, Transaction},
+    node::{block_store::BlockStore, Node},
+    utils::{btc_errors::NodeError, TransactionInfo},
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    sync::{Arc, Mutex},
+};
+
+const HASH_SIZE: usize = 32;
+const ENTRY_SIZE: usize = 2 * HASH_SIZE + 4;
+
+pub type SafeTxIndex = Arc<Mutex<TxIndex>>;
+
+/// Where a transaction is: the hash of its block and its position among the transactions of the block.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxLocation {
+    pub block_hash: [u8; 32],
+    pub position: u32,
+}
+
+/// Optional index of the transactions of the stored blocks by their txid. Blocks are indexed in the order
+/// of the chain, like the block filters, and the locations are stored in disk so only the blocks that
+/// arrived since the last execution are indexed when the node starts. An index that is not loaded from a
+/// file is disabled, and updating it does nothing.
+#[derive(Debug, Default)]
+pub struct TxIndex {
+    locations: HashMap<[u8; 32], TxLocation>,
+    indexed_blocks: HashSet<[u8; 32]>,
+    next_position: Option<usize>,
+    file_path: Option<String>,
+}
+
+impl TxIndex {
+    /// Creates a disabled index.
+    pub fn new() -> TxIndex {
+        TxIndex::default()
+    }
+
+    /// Loads the locations stored in the file. If the file does not exist yet the index starts empty.
+    pub fn from_path(file_path: &str) -> Result<TxIndex, NodeError> {
+        let mut index = TxIndex {
+            file_path: Some(file_path.to_string()),
+            ..TxIndex::default()
+        };
+        let mut file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(index),
+        };
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+        for entry in bytes.chunks_exact(ENTRY_SIZE) {
+            let (txid, location) = entry.split_at(HASH_SIZE);
+            let (block_hash, position) = location.split_at(HASH_SIZE);
+            if let (Ok(txid), Ok(block_hash), Ok(position)) =
+                (txid.try_into(), block_hash.try_into(), position.try_into())
+            {
+                index.insert(
+                    txid,
+                    TxLocation {
+                        block_hash,
+                        position: u32::from_le_bytes(position),
+                    },
+                );
+            }
+        }
+        Ok(index)
+    }
+
+    /// Returns true if the index was loaded from a file, which means the node was configured to keep it.
+    pub fn is_enabled(&self) -> bool {
+        self.file_path.is_some()
+    }
+
+    /// Writes every location in its file, replacing the previous one.
+    pub fn save(&self) -> Result<(), NodeError> {
+        let file_path = match &self.file_path {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let temporary_path = format!("{}.tmp", file_path);
+        let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let mut writer = BufWriter::new(file);
+        for (txid, location) in &self.locations {
+            writer
+                .write_all(txid)
+                .and_then(|_| writer.write_all(&location.block_hash))
+                .and_then(|_| writer.write_all(&location.position.to_le_bytes()))
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        writer
+            .flush()
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        fs::rename(temporary_path, file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    /// Indexes the stored blocks that were not indexed yet, from the first one in the chain. Until it is
+    /// called, updating the index does nothing, since blocks may be stored out of order while they are
+    /// being downloaded. Returns the amount of blocks indexed.
+    pub fn build(&mut self, blockchain: &mut BlockStore, block_headers: &[BlockHeader]) -> usize {
+        if !self.is_enabled() {
+            return 0;
+        }
+        self.next_position =
+            first_unindexed_position(blockchain, block_headers, &self.indexed_blocks);
+        self.update(blockchain, block_headers)
+    }
+
+    /// Indexes the stored blocks that follow the last indexed one in the chain, stopping at the first
+    /// block that is not stored yet. Returns the amount of blocks indexed.
+    pub fn update(&mut self, blockchain: &mut BlockStore, block_headers: &[BlockHeader]) -> usize {
+        let mut position = match self.next_position {
+            Some(position) => position,
+            None => return 0,
+        };
+        let first_position = position;
+        while let Some(block) = block_headers
+            .get(position)
+            .and_then(|header| blockchain.get(&header.hash()))
+        {
+            let block_hash = block.header_hash();
+            for (tx_position, tx) in block.get_transactions().iter().enumerate() {
+                let location = TxLocation {
+                    block_hash,
+                    position: tx_position as u32,
+                };
+                self.insert(tx.hash(), location);
+            }
+            position += 1;
+        }
+        self.next_position = Some(position);
+        position - first_position
+    }
+
+    fn insert(&mut self, txid: [u8; 32], location: TxLocation) {
+        self.indexed_blocks.insert(location.block_hash);
+        self.locations.insert(txid, location);
+    }
+
+    /// Returns where the transaction is, if it belongs to an indexed block.
+    pub fn get(&self, txid: &[u8; 32]) -> Option<TxLocation> {
+        self.locations.get(txid).copied()
+    }
+}
+
+/// Returns the position of the headers an index that already has the indexed blocks continues from: the
+/// first block after the indexed ones, starting from the first block that is stored or indexed.
+pub fn first_unindexed_position(
+    blockchain: &BlockStore,
+    block_headers: &[BlockHeader],
+    indexed_blocks: &HashSet<[u8; 32]>,
+) -> Option<usize> {
+    let first_position = block_headers.iter().position(|header| {
+        let hash = header.hash();
+        indexed_blocks.contains(&hash) || blockchain.contains_key(&hash)
+    })?;
+    let indexed_blocks_amount = block_headers[first_position..]
+        .iter()
+        .take_while(|header| indexed_blocks.contains(&header.hash()))
+        .count();
+    Some(first_position + indexed_blocks_amount)
+}
+
+impl Node {
+    /// Indexes the transactions of the stored blocks if the index is enabled, after which it is kept up
+    /// to date as new blocks arrive.
+    pub fn build_tx_index(&mut self) -> Result<(), NodeError> {
+        if !self.get_tx_index()?.is_enabled() {
+            return Ok(());
+        }
+        self.log_and_send_to_ui("Building transaction index");
+        let indexed_blocks = {
+            let mut blockchain = self.get_blockchain()?;
+            let block_headers = self.get_block_headers()?;
+            self.get_tx_index()?.build(&mut blockchain, &block_headers)
+        };
+        self.logger.log(format!(
+            "Transaction index built for {indexed_blocks} blocks"
+        ));
+        Ok(())
+    }
+
+    /// Returns the location of a transaction in the index. Returns ErrorTxIndexDisabled if the node
+    /// does not keep the index, or ErrorFindingTransaction if the transaction is not in it.
+    fn find_transaction(&self, txid: [u8; 32]) -> Result<TxLocation, NodeError> {
+        let tx_index = self.get_tx_index()?;
+        if !tx_index.is_enabled() {
+            return Err(NodeError::ErrorTxIndexDisabled);
+        }
+        tx_index
+            .get(&txid)
+            .ok_or(NodeError::ErrorFindingTransaction)
+    }
+
+    /// Returns the block number of the block of the index location, which must be in the chain.
+    fn block_number_of(&self, location: &TxLocation) -> Result<usize, NodeError> {
+        self.get_header_index()?
+            .get(&location.block_hash)
+            .map(|position| position + 1)
+            .ok_or(NodeError::ErrorFindingBlock)
+    }
+
+    /// Returns any transaction by its txid, alongside its block and confirmations. Pending transactions
+    /// are found with 0 confirmations, while confirmed ones are looked up in the transaction index.
+    pub fn get_transaction_info(&self, txid: [u8; 32]) -> Result<TransactionInfo, NodeError> {
+        if let Some(transaction) = self.get_pending_tx()?.get(&txid) {
+            let transaction = copy_transaction(transaction)?;
+            return Ok(TransactionInfo::new(transaction, None, 0));
+        }
+        let location = self.find_transaction(txid)?;
+
+        let mut blockchain = self.get_blockchain()?;
+        let block_number = self.block_number_of(&location)?;
+        let confirmations = self.get_block_headers()?.len() + 1 - block_number;
+        let transaction = match blockchain
+            .get(&location.block_hash)
+            .and_then(|block| block.get_transactions().get(location.position as usize))
+        {
+            Some(transaction) => copy_transaction(transaction)?,
+            None => return Err(NodeError::ErrorFindingBlock),
+        };
+
+        Ok(TransactionInfo::new(
+            transaction,
+            Some(block_number),
+            confirmations,
+        ))
+    }
+
+    /// Returns the merkle proof of a confirmed transaction, finding its block in the transaction index.
+    pub fn get_merkle_tx_proof_from_txid(
+        &self,
+        txid: [u8; 32],
+    ) -> Result<(Vec<HashPair>, [u8; 32]), NodeError> {
+        let location = self.find_transaction(txid)?;
+        let block_number = self.block_number_of(&location)?;
+        self.get_merkle_tx_proof(txid, block_number)
+    }
+}
+
+/// Returns a copy of the transaction.
+pub fn copy_transaction(transaction: &Transaction) -> Result<Transaction, NodeError> {
+    Transaction::from_bytes(&transaction.to_bytes()).map_err(|_| NodeError::ErrorGettingTx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::test_chain;
+    use std::fs::remove_file;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn chain(length: u32) -> (Vec<Block>, Vec<BlockHeader>) {
+        let transactions = (0..length)
+            .map(|i| {
+                vec![
+                    Transaction::new(1, Vec::new(), Vec::new(), i),
+                    Transaction::new(2, Vec::new(), Vec::new(), i),
+                ]
+            })
+            .collect();
+        test_chain::chain([0; 32], 0x1d00ffff, transactions)
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn tx_index_test_1_indexes_transactions_in_chain_order_once_built() -> Result<(), NodeError> {
+        let (mut blocks, block_headers) = chain(4);
+        let missing = blocks.remove(2);
+        let second_tx_hash = missing.get_transactions()[1].hash();
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let mut index = TxIndex::from_path("tests_txt/tx_index_test_1.bin")?;
+
+        assert_eq!(index.update(&mut blockchain, &block_headers), 0);
+        assert_eq!(index.build(&mut blockchain, &block_headers), 2);
+        assert_eq!(index.get(&second_tx_hash), None);
+
+        blockchain.insert(missing, 0)?;
+        assert_eq!(index.update(&mut blockchain, &block_headers), 2);
+        assert_eq!(
+            index.get(&second_tx_hash),
+            Some(TxLocation {
+                block_hash: block_headers[2].hash(),
+                position: 1,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tx_index_test_2_disabled_index_does_not_index_blocks() -> Result<(), NodeError> {
+        let (blocks, block_headers) = chain(2);
+        let tx_hash = blocks[0].get_transactions()[0].hash();
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let mut index = TxIndex::new();
+
+        assert!(!index.is_enabled());
+        assert_eq!(index.build(&mut blockchain, &block_headers), 0);
+        assert_eq!(index.get(&tx_hash), None);
+        Ok(())
+    }
+
+    #[test]
+    fn tx_index_test_3_saved_index_only_indexes_new_blocks_when_loaded() -> Result<(), NodeError> {
+        let file_path = "tests_txt/tx_index_test_3.bin";
+        let (mut blocks, block_headers) = chain(3);
+        let last_block = blocks.remove(2);
+        let tx_hash = blocks[1].get_transactions()[1].hash();
+        let mut blockchain = BlockStore::new();
+        for block in blocks {
+            blockchain.insert(block, 0)?;
+        }
+        let mut index = TxIndex::from_path(file_path)?;
+        assert_eq!(index.build(&mut blockchain, &block_headers), 2);
+        index.save()?;
+
+        blockchain.insert(last_block, 0)?;
+        let mut loaded_index = TxIndex::from_path(file_path)?;
+        let expected_location = index.get(&tx_hash);
+        remove_file(file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+
+        assert_eq!(loaded_index.get(&tx_hash), expected_location);
+        assert_eq!(loaded_index.build(&mut blockchain, &block_headers), 1);
+        Ok(())
+    }
+}
@@ -1,18 +1,36 @@
 use crate::blocks::transaction::*;
-use crate::node::*;
-use std::collections::HashMap;
+use crate::node::{block_store::MIN_BLOCKS_TO_KEEP, *};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+};
+
+const OUTPOINT_SIZE: usize = 36;
+const UTXO_SET_FILE_HEADER_SIZE: usize = 8 + 32;
+
+/// Position of the last processed block, its hash and the utxo set saved up to it.
+type UtxoSetSnapshot = (usize, [u8; 32], HashMap<Outpoint, TxOut>);
 
 impl Node {
-    /// Gets all utxos of the blockchain blocks
+    /// Gets all utxos of the blockchain blocks, starting from the given position of the headers,
+    /// or from the first stored block if it is not given.
     fn _create_utxo_set(
         &self,
         block_headers: &Vec<BlockHeader>,
+        starting_position: Option<usize>,
         utxo_set: &mut HashMap<Outpoint, TxOut>,
     ) -> Result<(), NodeError> {
-        let blockchain = self
+        let mut blockchain = self
             .get_blockchain()
             .map_err(|_| NodeError::ErrorSharingReference)?;
-        let starting_position = block_headers.len() - blockchain.len();
+        let starting_position = match starting_position {
+            Some(starting_position) => starting_position,
+            None => block_headers
+                .iter()
+                .position(|header| blockchain.contains_key(&header.hash()))
+                .unwrap_or(block_headers.len()),
+        };
 
         for (index, header) in block_headers[starting_position..].iter().enumerate() {
             let hash = header.hash();
@@ -36,7 +54,40 @@ impl Node {
         Ok(())
     }
 
-    /// Creates the utxo set from the blockchain and returns it.
+    /// Loads the utxo set saved before pruning blocks, if it follows the headers.
+    /// Returns the position of the headers its blocks end at.
+    fn load_saved_utxo_set(
+        &self,
+        block_headers: &[BlockHeader],
+        utxo_set: &mut HashMap<Outpoint, TxOut>,
+    ) -> Option<usize> {
+        let (position, last_hash, saved_utxo_set) = match load_utxo_set(&self.utxo_set_path) {
+            Ok(saved_utxo_set) => saved_utxo_set?,
+            Err(error) => {
+                self.logger.log_error(&error);
+                return None;
+            }
+        };
+        let last_header_hash = match position.checked_sub(1) {
+            Some(last_position) => block_headers.get(last_position).map(|header| header.hash()),
+            None => Some([0; 32]),
+        };
+        if last_header_hash != Some(last_hash) {
+            self.logger.log(String::from(
+                "The saved UTxO Set does not follow the headers, it is created from the stored blocks",
+            ));
+            return None;
+        }
+
+        *utxo_set = saved_utxo_set;
+        self.logger.log(format!(
+            "Loaded saved UTxO Set with the blocks of the first {position} headers"
+        ));
+        Some(position)
+    }
+
+    /// Creates the utxo set from the blockchain and returns it. If blocks were pruned, it continues from
+    /// the utxo set saved before pruning them.
     /// Logs when error.
     pub fn create_utxo_set(&mut self) -> Result<(), NodeError> {
         let initialization_str = "Initializing UTxO Set creation";
@@ -45,12 +96,47 @@ impl Node {
         let mut utxo_set = HashMap::new();
 
         match self.get_block_headers() {
-            Ok(block_headers) => self._create_utxo_set(&block_headers, &mut utxo_set)?,
+            Ok(block_headers) => {
+                let starting_position = self.load_saved_utxo_set(&block_headers, &mut utxo_set);
+                self._create_utxo_set(&block_headers, starting_position, &mut utxo_set)?
+            }
             Err(_) => return Err(NodeError::ErrorSharingReference),
         };
 
         self.utxo_set = utxo_set;
 
+        self.prune_blocks()
+    }
+
+    /// Deletes the oldest block files if they exceed the prune target. Only blocks already in the utxo set
+    /// are pruned, leaving the last MIN_BLOCKS_TO_KEEP, and the utxo set is saved first, since it can not
+    /// be created again from the pruned blocks.
+    fn prune_blocks(&self) -> Result<(), NodeError> {
+        let mut blockchain = self.get_blockchain()?;
+        let max_height = self.last_proccesed_block.saturating_sub(MIN_BLOCKS_TO_KEEP) as u32;
+        let files_to_prune = blockchain.files_to_prune(max_height);
+        if files_to_prune.is_empty() {
+            return Ok(());
+        }
+
+        let last_hash = match self.last_proccesed_block.checked_sub(1) {
+            Some(last_position) => match self.get_block_headers()?.get(last_position) {
+                Some(header) => header.hash(),
+                None => return Ok(()),
+            },
+            None => [0; 32],
+        };
+        save_utxo_set(
+            &self.utxo_set_path,
+            &self.utxo_set,
+            self.last_proccesed_block,
+            last_hash,
+        )?;
+        let pruned_blocks = blockchain.prune_files(&files_to_prune)?;
+        self.logger.log(format!(
+            "Pruned {pruned_blocks} blocks, up to height {}",
+            blockchain.pruned_height()
+        ));
         Ok(())
     }
 
@@ -58,7 +144,7 @@ impl Node {
     fn get_utxos_from_unproccessed_blocks(
         &self,
         block_hash: &[u8; 32],
-        blockchain: &HashMap<[u8; 32], Block>,
+        blockchain: &mut BlockStore,
     ) -> Vec<(Outpoint, TxOut)> {
         let mut new_utxos = Vec::new();
 
@@ -74,7 +160,7 @@ impl Node {
     fn get_spent_utxos_from_unproccesed_blocks(
         &self,
         block_hash: &[u8; 32],
-        blockchain: &HashMap<[u8; 32], Block>,
+        blockchain: &mut BlockStore,
     ) -> Vec<Outpoint> {
         let mut spent_utxos = Vec::new();
 
@@ -109,9 +195,12 @@ impl Node {
         };
 
         let (spent_utxos, new_utxos) = match self.get_blockchain() {
-            Ok(blockchain) => (
-                self.get_spent_utxos_from_unproccesed_blocks(&unproccesed_block_hash, &blockchain),
-                self.get_utxos_from_unproccessed_blocks(&unproccesed_block_hash, &blockchain),
+            Ok(mut blockchain) => (
+                self.get_spent_utxos_from_unproccesed_blocks(
+                    &unproccesed_block_hash,
+                    &mut blockchain,
+                ),
+                self.get_utxos_from_unproccessed_blocks(&unproccesed_block_hash, &mut blockchain),
             ),
             Err(error) => return Err(error),
         };
@@ -125,7 +214,27 @@ impl Node {
 
         self.last_proccesed_block += 1;
 
-        Ok(())
+        self.prune_blocks()
+    }
+
+    /// Processes a block the light client built with the transactions that matched the wallet,
+    /// in the order of the block, since they may spend the outputs of each other.
+    pub fn update_utxo_with_filtered_block(
+        &mut self,
+        block: &Block,
+        wallet_utxos: &mut HashMap<Outpoint, i64>,
+    ) {
+        for tx in block.get_transactions() {
+            for tx_in in &tx.tx_in {
+                self.remove_utxo(tx_in.previous_output, wallet_utxos);
+            }
+            for (index, tx_out) in tx.tx_out.iter().enumerate() {
+                if tx_out.pk_hash_under_p2pkh_protocol().is_some() {
+                    let outpoint = Outpoint::new(tx.hash(), index as u32);
+                    self.insert_utxo(outpoint, tx_out.clone(), wallet_utxos);
+                }
+            }
+        }
     }
 
     /// inserts the utxo, in the node and wallet, and updates balance
@@ -135,9 +244,10 @@ impl Node {
         tx_out: TxOut,
         wallet_utxos: &mut HashMap<Outpoint, i64>,
     ) {
-        if tx_out.belongs_to(self.wallet_pk_hash) {
+        if tx_out.belongs_to(self.wallet_pk_hash)
+            && wallet_utxos.insert(key, tx_out.value).is_none()
+        {
             self.balance += tx_out.value;
-            wallet_utxos.insert(key, tx_out.value);
         }
         self.utxo_set.insert(key, tx_out);
     }
@@ -149,16 +259,25 @@ impl Node {
         wallet_utxos: &mut HashMap<Outpoint, i64>,
     ) -> Option<TxOut> {
         let tx_out = self.utxo_set.remove(&key)?;
-        if tx_out.belongs_to(self.wallet_pk_hash) {
+        if tx_out.belongs_to(self.wallet_pk_hash) && wallet_utxos.remove(&key).is_some() {
             self.balance -= tx_out.value;
-            wallet_utxos.remove(&key);
         }
 
         Some(tx_out)
     }
 
-    /// Gets the utxos and their balance belonging to a given pkhash
+    /// Gets the utxos and their balance belonging to a given pkhash. They are taken from the
+    /// address index if it is enabled, otherwise the whole utxo set is scanned.
     pub fn get_utxo_balance(&self, pk_hash: [u8; 20]) -> (HashMap<Outpoint, i64>, i64) {
+        match self.get_indexed_utxos(pk_hash) {
+            Ok(Some(wallet_utxos)) => {
+                let balance = wallet_utxos.values().sum();
+                return (wallet_utxos, balance);
+            }
+            Ok(None) => {}
+            Err(error) => self.logger.log_error(&error),
+        }
+
         let mut balance = 0;
         let mut wallet_utxos = HashMap::new();
 
@@ -173,8 +292,12 @@ impl Node {
         (wallet_utxos, balance)
     }
 
-    /// Gets enough utxos whose values sum up to at least amount
-    pub fn get_utxos_sum_up_to(&self, amount: i64) -> Result<(Vec<Outpoint>, i64), NodeError> {
+    /// Gets enough utxos whose values sum up to at least amount, skipping the frozen ones
+    pub fn get_utxos_sum_up_to(
+        &self,
+        amount: i64,
+        frozen_utxos: &HashSet<Outpoint>,
+    ) -> Result<(Vec<Outpoint>, i64), NodeError> {
         let mut unspent_balance = 0;
         let mut unspent_outpoint = Vec::new();
 
@@ -183,7 +306,7 @@ impl Node {
                 break;
             }
 
-            if utx_out.belongs_to(self.wallet_pk_hash) {
+            if utx_out.belongs_to(self.wallet_pk_hash) && !frozen_utxos.contains(outpoint) {
                 unspent_balance += utx_out.value;
 
                 unspent_outpoint.push(*outpoint);
@@ -231,3 +354,96 @@ fn update_utxo_set_with_transactions(
 
     Ok(())
 }
+
+/// Writes the utxo set in its file, replacing the previous one, after the amount of headers whose blocks
+/// it includes and the hash of the last of them.
+fn save_utxo_set(
+    file_path: &str,
+    utxo_set: &HashMap<Outpoint, TxOut>,
+    position: usize,
+    last_hash: [u8; 32],
+) -> Result<(), NodeError> {
+    let temporary_path = format!("{}.tmp", file_path);
+    let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(&(position as u64).to_le_bytes())
+        .and_then(|_| writer.write_all(&last_hash))
+        .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+    for (outpoint, tx_out) in utxo_set {
+        writer
+            .write_all(&outpoint.to_bytes())
+            .and_then(|_| writer.write_all(&tx_out.to_bytes()))
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+    }
+    writer
+        .flush()
+        .and_then(|_| writer.get_ref().sync_all())
+        .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+    fs::rename(temporary_path, file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+}
+
+/// Reads the utxo set written by save_utxo_set, alongside the amount of headers whose blocks it includes
+/// and the hash of the last of them. Returns None if it was never saved.
+fn load_utxo_set(file_path: &str) -> Result<Option<UtxoSetSnapshot>, NodeError> {
+    let mut file = match File::open(file_path) {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)
+        .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+    if bytes.len() < UTXO_SET_FILE_HEADER_SIZE {
+        return Err(NodeError::ErrorLoadingDataFromDisk);
+    }
+    let (header, mut bytes) = bytes.split_at(UTXO_SET_FILE_HEADER_SIZE);
+    let (position, last_hash) = header.split_at(8);
+    let position = u64::from_le_bytes(
+        position
+            .try_into()
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?,
+    ) as usize;
+    let last_hash = last_hash
+        .try_into()
+        .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+
+    let mut utxo_set = HashMap::new();
+    while !bytes.is_empty() {
+        if bytes.len() < OUTPOINT_SIZE {
+            return Err(NodeError::ErrorLoadingDataFromDisk);
+        }
+        let outpoint = Outpoint::from_bytes(&bytes[..OUTPOINT_SIZE])
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+        let tx_out = TxOut::from_bytes(&bytes[OUTPOINT_SIZE..])
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+        bytes = &bytes[OUTPOINT_SIZE + tx_out.to_bytes().len()..];
+        utxo_set.insert(outpoint, tx_out);
+    }
+    Ok(Some((position, last_hash, utxo_set)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn utxo_set_test_1_saved_utxo_set_is_loaded() -> Result<(), NodeError> {
+        let file_path = "tests_txt/utxo_set_test_1.bin";
+        _ = fs::remove_file(file_path);
+        assert!(load_utxo_set(file_path)?.is_none());
+        let mut utxo_set = HashMap::new();
+        utxo_set.insert(Outpoint::new([1; 32], 0), TxOut::new(50, vec![1, 2, 3]));
+        utxo_set.insert(Outpoint::new([2; 32], 3), TxOut::new(70, Vec::new()));
+
+        save_utxo_set(file_path, &utxo_set, 12, [4; 32])?;
+
+        let (position, last_hash, loaded_utxo_set) = load_utxo_set(file_path)?.unwrap();
+        assert_eq!(position, 12);
+        assert_eq!(last_hash, [4; 32]);
+        assert_eq!(loaded_utxo_set, utxo_set);
+        Ok(())
+    }
+}
@@ -0,0 +1,651 @@
+use super::handshake::PEER_TIMEOUT;
+use crate::{
+    messages::{
+        header_message::{HeaderMessage, START_STRING_TEST_NET},
+        message_trait::MessageTrait,
+    },
+    utils::{
+        btc_errors::NodeError,
+        chacha20_poly1305::{FSChaCha20, FSChaCha20Poly1305, TAG_SIZE},
+        ellswift::{ellswift_create, ellswift_ecdh_x, ELLSWIFT_SIZE},
+    },
+};
+use bitcoin_hashes::{hmac, sha256, Hash, HashEngine};
+use rand::Rng;
+use secp256k1::SecretKey;
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{Shutdown, SocketAddr, TcpStream},
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+const MESSAGE_HEADER_SIZE: usize = 24;
+const COMMAND_NAME_SIZE: usize = 12;
+const MAX_MESSAGE_SIZE: usize = 4_000_000;
+const GARBAGE_TERMINATOR_SIZE: usize = 16;
+const MAX_GARBAGE_SIZE: usize = 4095;
+const LENGTH_FIELD_SIZE: usize = 3;
+const PACKET_HEADER_SIZE: usize = 1;
+const IGNORE_BIT: u8 = 0x80;
+const READ_CHUNK_SIZE: usize = 4096;
+const V1_PREFIX_SIZE: usize = 16;
+const V1_PREFIX_POLL_INTERVAL: Duration = Duration::from_millis(10);
+const ECDH_TAG: &[u8] = b"bip324_ellswift_xonly_ecdh";
+const SHARED_SECRET_SALT: &[u8] = b"bitcoin_v2_shared_secret";
+/// Commands sent with a one byte id instead of their name, the id being their position plus one.
+const SHORT_COMMAND_IDS: [&str; 28] = [
+    "addr",
+    "block",
+    "blocktxn",
+    "cmpctblock",
+    "feefilter",
+    "filteradd",
+    "filterclear",
+    "filterload",
+    "getblocks",
+    "getblocktxn",
+    "getdata",
+    "getheaders",
+    "headers",
+    "inv",
+    "mempool",
+    "merkleblock",
+    "notfound",
+    "ping",
+    "pong",
+    "sendcmpct",
+    "tx",
+    "getcfilters",
+    "cfilter",
+    "getcfheaders",
+    "cfheaders",
+    "getcfcheckpt",
+    "cfcheckpt",
+    "addrv2",
+];
+
+/// Connection with a peer, either over plain TCP (v1) or over the encrypted transport of BIP324 (v2).
+/// Messages are read and written in the v1 format in both cases, translating them to and from v2
+/// packets when needed, so every message is sent and received the same way. Clones share the state
+/// of the ciphers, which is why they have to be made with try_clone.
+#[derive(Debug)]
+pub struct PeerStream {
+    stream: TcpStream,
+    session: Option<Arc<V2Session>>,
+    outgoing: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct V2Session {
+    sender: Mutex<PacketSender>,
+    receiver: Mutex<PacketReceiver>,
+}
+
+#[derive(Debug)]
+struct PacketSender {
+    length_cipher: FSChaCha20,
+    packet_cipher: FSChaCha20Poly1305,
+}
+
+/// Decrypts the packets of the peer. The bytes of a packet not fully received yet are kept, so a
+/// read that times out in the middle of a packet can be continued.
+#[derive(Debug)]
+struct PacketReceiver {
+    length_cipher: FSChaCha20,
+    packet_cipher: FSChaCha20Poly1305,
+    received: Vec<u8>,
+    contents_length: Option<usize>,
+    aad: Vec<u8>,
+    messages: VecDeque<u8>,
+}
+
+/// Keys derived from the shared secret, from the point of view of one of the sides.
+struct SessionKeys {
+    send_length: [u8; 32],
+    send_packet: [u8; 32],
+    receive_length: [u8; 32],
+    receive_packet: [u8; 32],
+    send_garbage_terminator: [u8; GARBAGE_TERMINATOR_SIZE],
+    receive_garbage_terminator: [u8; GARBAGE_TERMINATOR_SIZE],
+}
+
+impl PeerStream {
+    /// Wraps a connection that uses the v1 transport.
+    pub fn v1(stream: TcpStream) -> PeerStream {
+        PeerStream {
+            stream,
+            session: None,
+            outgoing: Vec::new(),
+        }
+    }
+
+    /// Returns true if the connection uses the v2 transport.
+    pub fn is_v2(&self) -> bool {
+        self.session.is_some()
+    }
+
+    pub fn try_clone(&self) -> io::Result<PeerStream> {
+        Ok(PeerStream {
+            stream: self.stream.try_clone()?,
+            session: self.session.clone(),
+            outgoing: Vec::new(),
+        })
+    }
+
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    pub fn shutdown(&self, how: Shutdown) -> io::Result<()> {
+        self.stream.shutdown(how)
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+}
+
+impl Read for PeerStream {
+    /// Reads the bytes of the messages of the peer in the v1 format. Over the v2 transport, packets are
+    /// decrypted until one with a message arrives.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let session = match &self.session {
+            Some(session) => session,
+            None => return self.stream.read(buf),
+        };
+        let mut receiver = session
+            .receiver
+            .lock()
+            .map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+        while receiver.messages.is_empty() {
+            let packet = receiver.receive_packet(&mut self.stream)?;
+            if packet[0] & IGNORE_BIT == 0 {
+                if let Some(message) = v1_message_from_contents(&packet[PACKET_HEADER_SIZE..]) {
+                    receiver.messages.extend(message);
+                }
+            }
+        }
+        let amount = buf.len().min(receiver.messages.len());
+        for (byte, message_byte) in buf.iter_mut().zip(receiver.messages.drain(..amount)) {
+            *byte = message_byte;
+        }
+        Ok(amount)
+    }
+}
+
+impl Write for PeerStream {
+    /// Writes bytes of messages in the v1 format. Over the v2 transport, each message is sent in a packet
+    /// once all of its bytes are written.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let session = match &self.session {
+            Some(session) => session,
+            None => return self.stream.write(buf),
+        };
+        self.outgoing.extend_from_slice(buf);
+        while let Some(message_size) = complete_v1_message_size(&self.outgoing) {
+            let message: Vec<u8> = self.outgoing.drain(..message_size).collect();
+            let contents = v2_contents_from_v1_message(&message)
+                .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+            let mut sender = session
+                .sender
+                .lock()
+                .map_err(|_| io::Error::from(io::ErrorKind::Other))?;
+            let packet = sender.encrypt(&[], &contents);
+            self.stream.write_all(&packet)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl PacketSender {
+    /// Returns the encrypted length of the contents followed by the encrypted packet. Decoy packets are
+    /// never sent, so the header is always zero.
+    fn encrypt(&mut self, aad: &[u8], contents: &[u8]) -> Vec<u8> {
+        let mut plaintext = vec![0];
+        plaintext.extend(contents);
+
+        let length = (contents.len() as u32).to_le_bytes();
+        let mut packet = self.length_cipher.crypt(&length[..LENGTH_FIELD_SIZE]);
+        packet.extend(self.packet_cipher.encrypt(aad, &plaintext));
+        packet
+    }
+}
+
+impl PacketReceiver {
+    /// Decrypts the next packet among the received bytes, if all of its bytes were received. Returns the
+    /// header of the packet followed by its contents.
+    fn next_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let contents_length = match self.contents_length {
+            Some(contents_length) => contents_length,
+            None => {
+                if self.received.len() < LENGTH_FIELD_SIZE {
+                    return Ok(None);
+                }
+                let length_bytes: Vec<u8> = self.received.drain(..LENGTH_FIELD_SIZE).collect();
+                let mut length = [0; 4];
+                length[..LENGTH_FIELD_SIZE]
+                    .copy_from_slice(&self.length_cipher.crypt(&length_bytes));
+                let contents_length = u32::from_le_bytes(length) as usize;
+                if contents_length > MAX_MESSAGE_SIZE + COMMAND_NAME_SIZE + 1 {
+                    return Err(io::Error::from(io::ErrorKind::InvalidData));
+                }
+                self.contents_length = Some(contents_length);
+                contents_length
+            }
+        };
+
+        let packet_size = PACKET_HEADER_SIZE + contents_length + TAG_SIZE;
+        if self.received.len() < packet_size {
+            return Ok(None);
+        }
+        let ciphertext: Vec<u8> = self.received.drain(..packet_size).collect();
+        self.contents_length = None;
+        let aad = std::mem::take(&mut self.aad);
+        match self.packet_cipher.decrypt(&aad, &ciphertext) {
+            Some(plaintext) => Ok(Some(plaintext)),
+            None => Err(io::Error::from(io::ErrorKind::InvalidData)),
+        }
+    }
+
+    /// Reads from the stream until a whole packet is received, and returns it decrypted.
+    fn receive_packet(&mut self, stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+        loop {
+            if let Some(packet) = self.next_packet()? {
+                return Ok(packet);
+            }
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            let amount = stream.read(&mut chunk)?;
+            if amount == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+            }
+            self.received.extend_from_slice(&chunk[..amount]);
+        }
+    }
+}
+
+impl SessionKeys {
+    /// Derives the keys of the session from the shared secret with HKDF-SHA256, as BIP324 defines.
+    /// The salt includes the start string of the network.
+    fn derive(shared_secret: &[u8; 32], initiator: bool, start_string: &[u8]) -> SessionKeys {
+        let mut salt = SHARED_SECRET_SALT.to_vec();
+        salt.extend(start_string);
+        let pseudo_random_key = hmac_sha256(&salt, shared_secret);
+        let expand = |info: &[u8]| {
+            let mut message = info.to_vec();
+            message.push(1);
+            hmac_sha256(&pseudo_random_key, &message)
+        };
+
+        let initiator_length = expand(b"initiator_L");
+        let initiator_packet = expand(b"initiator_P");
+        let responder_length = expand(b"responder_L");
+        let responder_packet = expand(b"responder_P");
+        let garbage_terminators = expand(b"garbage_terminators");
+        let mut initiator_terminator = [0; GARBAGE_TERMINATOR_SIZE];
+        let mut responder_terminator = [0; GARBAGE_TERMINATOR_SIZE];
+        initiator_terminator.copy_from_slice(&garbage_terminators[..GARBAGE_TERMINATOR_SIZE]);
+        responder_terminator.copy_from_slice(&garbage_terminators[GARBAGE_TERMINATOR_SIZE..]);
+
+        if initiator {
+            SessionKeys {
+                send_length: initiator_length,
+                send_packet: initiator_packet,
+                receive_length: responder_length,
+                receive_packet: responder_packet,
+                send_garbage_terminator: initiator_terminator,
+                receive_garbage_terminator: responder_terminator,
+            }
+        } else {
+            SessionKeys {
+                send_length: responder_length,
+                send_packet: responder_packet,
+                receive_length: initiator_length,
+                receive_packet: initiator_packet,
+                send_garbage_terminator: responder_terminator,
+                receive_garbage_terminator: initiator_terminator,
+            }
+        }
+    }
+}
+
+/// Starts the v2 transport on a new outgoing connection. An error means the peer does not support it,
+/// in which case the connection has to be started again with the v1 transport.
+pub fn initiate_v2_transport(stream: TcpStream) -> Result<PeerStream, NodeError> {
+    key_exchange(stream, true).ok_or(NodeError::ErrorV2Handshake)
+}
+
+/// Starts the v2 transport on a new incoming connection, whose peer did not start with a v1 version message.
+pub fn respond_v2_transport(stream: TcpStream) -> Result<PeerStream, NodeError> {
+    key_exchange(stream, false).ok_or(NodeError::ErrorV2Handshake)
+}
+
+/// Returns true if the peer started the connection with a v1 version message, telling apart the peers that
+/// do not use the v2 transport. The bytes are peeked, so they can still be read by the v1 handshake.
+pub fn starts_with_v1_version_message(stream: &TcpStream) -> Result<bool, NodeError> {
+    let mut v1_prefix = START_STRING_TEST_NET.to_vec();
+    v1_prefix.extend(b"version\0\0\0\0\0");
+    let mut prefix = [0; V1_PREFIX_SIZE];
+    let start_time = Instant::now();
+    loop {
+        let peeked = stream
+            .peek(&mut prefix)
+            .map_err(|_| NodeError::ErrorReceivingMessageInHandshake)?;
+        if peeked == 0 {
+            return Err(NodeError::ErrorReceivingMessageInHandshake);
+        }
+        if prefix[..peeked] != v1_prefix[..peeked] {
+            return Ok(false);
+        }
+        if peeked == V1_PREFIX_SIZE {
+            return Ok(true);
+        }
+        if start_time.elapsed() > PEER_TIMEOUT {
+            return Err(NodeError::ErrorPeerTimeout);
+        }
+        sleep(V1_PREFIX_POLL_INTERVAL);
+    }
+}
+
+/// Exchanges ElligatorSwift encoded public keys with the peer, derives the keys of the session and
+/// sends the garbage terminator and the version packet. Then skips the garbage of the peer and waits
+/// for its version packet.
+fn key_exchange(mut stream: TcpStream, initiator: bool) -> Option<PeerStream> {
+    let secret_key = random_secret_key();
+    let our_key = ellswift_create(&secret_key)?;
+    let garbage: Vec<u8> = (0..rand::thread_rng().gen_range(0..=MAX_GARBAGE_SIZE))
+        .map(|_| rand::random())
+        .collect();
+    let mut bytes = our_key.to_vec();
+    bytes.extend(&garbage);
+    stream.write_all(&bytes).ok()?;
+
+    let mut their_key = [0; ELLSWIFT_SIZE];
+    stream.read_exact(&mut their_key).ok()?;
+    let shared_secret = shared_secret(&our_key, &their_key, &secret_key, initiator)?;
+    let keys = SessionKeys::derive(&shared_secret, initiator, &START_STRING_TEST_NET);
+
+    let mut sender = PacketSender {
+        length_cipher: FSChaCha20::new(keys.send_length),
+        packet_cipher: FSChaCha20Poly1305::new(keys.send_packet),
+    };
+    let mut bytes = keys.send_garbage_terminator.to_vec();
+    bytes.extend(sender.encrypt(&garbage, &[]));
+    stream.write_all(&bytes).ok()?;
+
+    let mut received = Vec::new();
+    let terminator = keys.receive_garbage_terminator;
+    let garbage_size = loop {
+        if let Some(position) = received
+            .windows(GARBAGE_TERMINATOR_SIZE)
+            .position(|window| window == terminator)
+        {
+            break position;
+        }
+        if received.len() > MAX_GARBAGE_SIZE + GARBAGE_TERMINATOR_SIZE {
+            return None;
+        }
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let amount = stream.read(&mut chunk).ok()?;
+        if amount == 0 {
+            return None;
+        }
+        received.extend_from_slice(&chunk[..amount]);
+    };
+    if garbage_size > MAX_GARBAGE_SIZE {
+        return None;
+    }
+
+    let after_terminator = received.split_off(garbage_size + GARBAGE_TERMINATOR_SIZE);
+    received.truncate(garbage_size);
+    let mut receiver = PacketReceiver {
+        length_cipher: FSChaCha20::new(keys.receive_length),
+        packet_cipher: FSChaCha20Poly1305::new(keys.receive_packet),
+        received: after_terminator,
+        contents_length: None,
+        aad: received,
+        messages: VecDeque::new(),
+    };
+    // Decoy packets may come before the version packet, whose contents are ignored.
+    while receiver.receive_packet(&mut stream).ok()?[0] & IGNORE_BIT != 0 {}
+
+    Some(PeerStream {
+        stream,
+        session: Some(Arc::new(V2Session {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+        })),
+        outgoing: Vec::new(),
+    })
+}
+
+/// Returns the secret both sides compute from the ElligatorSwift encodings of their keys and the
+/// x coordinate of the ECDH point, or None if the key of the peer is not valid.
+fn shared_secret(
+    our_key: &[u8; ELLSWIFT_SIZE],
+    their_key: &[u8; ELLSWIFT_SIZE],
+    secret_key: &SecretKey,
+    initiator: bool,
+) -> Option<[u8; 32]> {
+    let (initiator_key, responder_key) = match initiator {
+        true => (our_key, their_key),
+        false => (their_key, our_key),
+    };
+    let mut ecdh_data = initiator_key.to_vec();
+    ecdh_data.extend(responder_key);
+    ecdh_data.extend(ellswift_ecdh_x(their_key, secret_key)?);
+    Some(tagged_hash(ECDH_TAG, &ecdh_data))
+}
+
+fn random_secret_key() -> SecretKey {
+    loop {
+        if let Ok(secret_key) = SecretKey::from_slice(&rand::random::<[u8; 32]>()) {
+            return secret_key;
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; 32] {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(key);
+    engine.input(message);
+    hmac::Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+fn tagged_hash(tag: &[u8], message: &[u8]) -> [u8; 32] {
+    let tag_hash = sha256::Hash::hash(tag).to_byte_array();
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag_hash);
+    engine.input(&tag_hash);
+    engine.input(message);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+/// Returns the size of the first message of the bytes in the v1 format, if all of its bytes are there.
+fn complete_v1_message_size(bytes: &[u8]) -> Option<usize> {
+    let header = HeaderMessage::from_bytes(bytes.get(..MESSAGE_HEADER_SIZE)?).ok()?;
+    let message_size = MESSAGE_HEADER_SIZE + header.get_payload_size() as usize;
+    if bytes.len() < message_size {
+        return None;
+    }
+    Some(message_size)
+}
+
+/// Turns a message in the v1 format into the contents of a v2 packet: the short id of the command, or a
+/// zero followed by its name, and the payload.
+fn v2_contents_from_v1_message(message: &[u8]) -> Option<Vec<u8>> {
+    let header = HeaderMessage::from_bytes(message.get(..MESSAGE_HEADER_SIZE)?).ok()?;
+    let command_name = header.get_command_name();
+    let short_id = SHORT_COMMAND_IDS
+        .iter()
+        .position(|command| *command == command_name.trim_end_matches('\0'));
+    let mut contents = match short_id {
+        Some(position) => vec![position as u8 + 1],
+        None => {
+            let mut contents = vec![0];
+            contents.extend(command_name.as_bytes());
+            contents
+        }
+    };
+    contents.extend(&message[MESSAGE_HEADER_SIZE..]);
+    Some(contents)
+}
+
+/// Turns the contents of a v2 packet into a message in the v1 format. Returns None for unknown short ids.
+fn v1_message_from_contents(contents: &[u8]) -> Option<Vec<u8>> {
+    let (command_name, payload) = match contents.first()? {
+        0 => {
+            let command_name = contents.get(1..1 + COMMAND_NAME_SIZE)?;
+            let command_name = String::from_utf8(command_name.to_vec()).ok()?;
+            (command_name, &contents[1 + COMMAND_NAME_SIZE..])
+        }
+        short_id => {
+            let command_name = SHORT_COMMAND_IDS.get(*short_id as usize - 1)?;
+            (command_name.to_string(), &contents[1..])
+        }
+    };
+    let payload = payload.to_vec();
+    let mut message = HeaderMessage::new(&command_name, &payload).ok()?.to_bytes();
+    message.extend(payload);
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{ping_pong_messages::PingMessage, SendHeadersMessage};
+    use crate::wallet::get_bytes_from_hex;
+    use std::{net::TcpListener, thread};
+
+    const START_STRING_MAIN_NET: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9];
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn connected_streams() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let outgoing = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (incoming, _) = listener.accept().unwrap();
+        (outgoing, incoming)
+    }
+
+    fn read_message(stream: &mut PeerStream) -> Vec<u8> {
+        let mut header = [0; MESSAGE_HEADER_SIZE];
+        stream.read_exact(&mut header).unwrap();
+        let payload_size = HeaderMessage::from_bytes(&header)
+            .unwrap()
+            .get_payload_size();
+        let mut message = header.to_vec();
+        let mut payload = vec![0; payload_size as usize];
+        stream.read_exact(&mut payload).unwrap();
+        message.extend(payload);
+        message
+    }
+
+    fn message_bytes<T: MessageTrait>(message: &T) -> Vec<u8> {
+        let mut bytes = message.get_header_message().unwrap().to_bytes();
+        bytes.extend(message.to_bytes());
+        bytes
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn v2_transport_test_1_commands_are_translated_to_and_from_packet_contents() {
+        let ping = message_bytes(&PingMessage::new(7));
+        let send_headers = message_bytes(&SendHeadersMessage::new());
+
+        let ping_contents = v2_contents_from_v1_message(&ping).unwrap();
+        let send_headers_contents = v2_contents_from_v1_message(&send_headers).unwrap();
+
+        assert_eq!(ping_contents[0], 18);
+        assert_eq!(ping_contents[1..], ping[MESSAGE_HEADER_SIZE..]);
+        assert_eq!(send_headers_contents[0], 0);
+        assert_eq!(&send_headers_contents[1..13], b"sendheaders\0");
+        assert_eq!(v1_message_from_contents(&ping_contents), Some(ping));
+        assert_eq!(
+            v1_message_from_contents(&send_headers_contents),
+            Some(send_headers)
+        );
+        assert_eq!(v1_message_from_contents(&[29]), None);
+    }
+
+    #[test]
+    fn v2_transport_test_2_messages_are_exchanged_over_the_v2_transport() {
+        let (outgoing, incoming) = connected_streams();
+        let responder = thread::spawn(move || {
+            assert!(!starts_with_v1_version_message(&incoming).unwrap());
+            let mut stream = respond_v2_transport(incoming).unwrap();
+            let message = read_message(&mut stream);
+            stream.write_all(&message).unwrap();
+            read_message(&mut stream)
+        });
+
+        let mut stream = initiate_v2_transport(outgoing).unwrap();
+        let ping = message_bytes(&PingMessage::new(7));
+        let send_headers = message_bytes(&SendHeadersMessage::new());
+        stream.write_all(&ping[..10]).unwrap();
+        stream.write_all(&ping[10..]).unwrap();
+
+        assert!(stream.is_v2());
+        assert_eq!(read_message(&mut stream), ping);
+        stream
+            .try_clone()
+            .unwrap()
+            .write_all(&send_headers)
+            .unwrap();
+        assert_eq!(responder.join().unwrap(), send_headers);
+    }
+
+    #[test]
+    fn v2_transport_test_3_v1_peers_are_told_apart() {
+        let (mut outgoing, incoming) = connected_streams();
+        let version_header = HeaderMessage::new("version", &vec![0; 10]).unwrap();
+
+        outgoing.write_all(&version_header.to_bytes()).unwrap();
+
+        assert!(starts_with_v1_version_message(&incoming).unwrap());
+        let mut stream = PeerStream::v1(incoming);
+        let mut header = [0; MESSAGE_HEADER_SIZE];
+        stream.read_exact(&mut header).unwrap();
+        assert_eq!(header.to_vec(), version_header.to_bytes());
+    }
+
+    /// First vector of packet_encoding_test_vectors.csv of BIP324, in which the initiator encrypts
+    /// its second packet on mainnet.
+    #[test]
+    fn v2_transport_test_4_packets_match_the_bip324_vector() {
+        let secret_key = SecretKey::from_slice(
+            &get_bytes_from_hex(
+                "61062ea5071d800bbfd59e2e8b53d47d194b095ae5a4df04936b49772ef0d4d7".to_string(),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        let our_key: [u8; ELLSWIFT_SIZE] = get_bytes_from_hex("ec0adff257bbfe500c188c80b4fdd640f6b45a482bbc15fc7cef5931deff0aa186f6eb9bba7b85dc4dcc28b28722de1e3d9108b985e2967045668f66098e475b".to_string()).unwrap().try_into().unwrap();
+        let their_key: [u8; ELLSWIFT_SIZE] = get_bytes_from_hex("a4a94dfce69b4a2a0a099313d10f9f7e7d649d60501c9e1d274c300e0d89aafaffffffffffffffffffffffffffffffffffffffffffffffffffffffff8faf88d5".to_string()).unwrap().try_into().unwrap();
+
+        let shared_secret = shared_secret(&our_key, &their_key, &secret_key, true).unwrap();
+        let keys = SessionKeys::derive(&shared_secret, true, &START_STRING_MAIN_NET);
+        let mut sender = PacketSender {
+            length_cipher: FSChaCha20::new(keys.send_length),
+            packet_cipher: FSChaCha20Poly1305::new(keys.send_packet),
+        };
+        sender.encrypt(&[], &[]);
+
+        assert_eq!(
+            sender.encrypt(&[], &[0x8e]),
+            get_bytes_from_hex("7530d2a18720162ac09c25329a60d75adf36eda3c3".to_string()).unwrap()
+        );
+    }
+}
@@ -1,19 +1,28 @@
 use crate::{
     blocks::{proof_of_transaction_included_in, HashPair, Transaction},
     messages::TxMessage,
-    node::Node,
+    node::{timelocks::is_final_for_next_block, tx_index::copy_transaction, Node},
     utils::{btc_errors::NodeError, ui_communication_protocol::TxInfo, BlockInfo},
     wallet::Wallet,
 };
 use secp256k1::PublicKey;
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+const HELD_TX_FILE_NAME: &str = "held_tx.bin";
 
 impl Node {
-    /// Returns a vec of TxInfo of all the tx in pending_tx that belong to a certain PubKey
+    /// Returns a vec of TxInfo of all the tx in pending_tx, or held until their lock time,
+    /// that belong to a certain PubKey
     fn get_pending_tx_info_from(&self, pub_key: &PublicKey) -> Result<Vec<TxInfo>, NodeError> {
         let pending_tx = self.get_pending_tx()?;
         let mut wallet_pending_tx = Vec::new();
 
-        for (_, tx) in pending_tx.iter() {
+        for tx in pending_tx.values().chain(self.held_tx.values()) {
             let mut tx_in_amount = 0;
 
             for tx_in in &tx.tx_in {
@@ -51,7 +60,12 @@ impl Node {
     /// Sets a new wallet as active for the node
     pub fn set_wallet(&mut self, wallet: &mut Wallet) -> Result<(), NodeError> {
         self.wallet_pk_hash = wallet.get_pk_hash();
+        if self.is_light_client() {
+            self.load_light_client_filter(wallet);
+            self.sync_light_client(&mut HashMap::new())?;
+        }
         (wallet.utxos, wallet.balance) = self.get_utxo_balance(self.wallet_pk_hash);
+        self.exclude_held_outputs(wallet);
         self.balance = wallet.balance;
 
         self.update_pending_tx(wallet)?;
@@ -68,14 +82,136 @@ impl Node {
             }
             None => return Err(NodeError::ErrorDisconectedFromBlockchain),
         }
-        self.update_utxo(&mut wallet.utxos)?;
+        if self.is_light_client() {
+            self.sync_light_client(&mut wallet.utxos)?;
+        } else {
+            self.update_utxo(&mut wallet.utxos)?;
+        }
         wallet.balance = self.balance;
+        self.release_final_transactions(wallet)?;
         self.update_pending_tx(wallet)?;
         Ok(())
     }
 
+    /// Returns true if the transaction can be included in the next block, according to
+    /// its absolute and relative locks
+    pub fn is_final_for_next_block(&self, transaction: &Transaction) -> Result<bool, NodeError> {
+        is_final_for_next_block(transaction, &self.get_safe_node_info())
+    }
+
+    /// Keeps a transaction that cannot be mined yet until its locks allow it, instead of
+    /// broadcasting it. The utxos it spends are removed so they are not used again, and the
+    /// held transactions are saved next to the utxo set so they are kept after a restart.
+    pub fn hold_transaction(
+        &mut self,
+        wallet: &mut Wallet,
+        transaction: Transaction,
+    ) -> Result<(), NodeError> {
+        for tx_in in &transaction.tx_in {
+            self.remove_utxo(tx_in.previous_output, &mut wallet.utxos);
+        }
+        self.held_tx.insert(transaction.hash(), transaction);
+        self.save_held_transactions()?;
+        self.update_pending_tx(wallet)?;
+
+        self.logger
+            .log("Se retuvo una transaccion hasta que se cumpla su lock time".to_string());
+        Ok(())
+    }
+
+    /// Broadcasts every held transaction that has become final. The ones that could not be sent
+    /// stay held, so they are sent on a later update.
+    fn release_final_transactions(&mut self, wallet: &mut Wallet) -> Result<(), NodeError> {
+        let mut final_transactions = Vec::new();
+        for (hash, transaction) in &self.held_tx {
+            if self.is_final_for_next_block(transaction)? {
+                final_transactions.push((*hash, copy_transaction(transaction)?));
+            }
+        }
+        if final_transactions.is_empty() {
+            return Ok(());
+        }
+
+        for (hash, transaction) in final_transactions {
+            match self.send_transaction(wallet, transaction) {
+                Ok(()) => {
+                    self.held_tx.remove(&hash);
+                }
+                Err(error) => self.logger.log_error(&error),
+            }
+        }
+        self.save_held_transactions()
+    }
+
+    /// Removes the outputs the held transactions spend from the utxos of the wallet, since they
+    /// are found again when the utxos are taken from the utxo set.
+    fn exclude_held_outputs(&mut self, wallet: &mut Wallet) {
+        for transaction in self.held_tx.values() {
+            for tx_in in &transaction.tx_in {
+                self.utxo_set.remove(&tx_in.previous_output);
+                if let Some(value) = wallet.utxos.remove(&tx_in.previous_output) {
+                    wallet.balance -= value;
+                }
+            }
+        }
+    }
+
+    /// Returns where the held transactions are saved, next to the utxo set, or None if the node
+    /// does not save the utxo set.
+    fn held_tx_path(&self) -> Option<PathBuf> {
+        if self.utxo_set_path.is_empty() {
+            return None;
+        }
+        Some(Path::new(&self.utxo_set_path).with_file_name(HELD_TX_FILE_NAME))
+    }
+
+    /// Writes every held transaction in its file, replacing the previous one.
+    fn save_held_transactions(&self) -> Result<(), NodeError> {
+        let file_path = match self.held_tx_path() {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let temporary_path = file_path.with_extension("tmp");
+        let file = File::create(&temporary_path).map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        let mut writer = BufWriter::new(file);
+        for transaction in self.held_tx.values() {
+            writer
+                .write_all(&transaction.to_bytes())
+                .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        }
+        writer
+            .flush()
+            .and_then(|_| writer.get_ref().sync_all())
+            .map_err(|_| NodeError::ErrorSavingDataToDisk)?;
+        fs::rename(temporary_path, file_path).map_err(|_| NodeError::ErrorSavingDataToDisk)
+    }
+
+    /// Loads the transactions that were held when the node stopped. If they were never saved
+    /// there are none.
+    pub fn load_held_transactions(&mut self) -> Result<(), NodeError> {
+        let file_path = match self.held_tx_path() {
+            Some(file_path) => file_path,
+            None => return Ok(()),
+        };
+        let mut file = match File::open(file_path) {
+            Ok(file) => file,
+            Err(_) => return Ok(()),
+        };
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)
+            .map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+        let mut bytes = bytes.as_slice();
+        while !bytes.is_empty() {
+            let transaction =
+                Transaction::from_bytes(bytes).map_err(|_| NodeError::ErrorLoadingDataFromDisk)?;
+            bytes = &bytes[transaction.amount_of_bytes()..];
+            self.held_tx.insert(transaction.hash(), transaction);
+        }
+        Ok(())
+    }
+
     fn get_block_info(&self, hash: [u8; 32], block_number: usize) -> Result<BlockInfo, NodeError> {
-        let blockchain = self.get_blockchain()?;
+        let mut blockchain = self.get_blockchain()?;
         let block = match blockchain.get(&hash) {
             Some(block) => block,
             None => return Err(NodeError::ErrorFindingBlock),
@@ -174,7 +310,7 @@ impl Node {
             Err(error) => return Err(error),
         };
         match self.get_blockchain() {
-            Ok(block_chain) => match block_chain.get(&block_hash) {
+            Ok(mut block_chain) => match block_chain.get(&block_hash) {
                 Some(block) => Ok(proof_of_transaction_included_in(transaction_hash, block)),
                 None => Err(NodeError::ErrorFindingBlock),
             },
@@ -182,3 +318,89 @@ impl Node {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        blocks::{Outpoint, TxIn, TxOut},
+        node::data_handler::NodeDataHandler,
+        utils::{log::Logger, UIResponse},
+    };
+    use secp256k1::{Secp256k1, SecretKey};
+
+    const VERSION: i32 = 70015;
+    const LOCAL_ADDRESS: ([u8; 4], u16) = ([127, 0, 0, 1], 1001);
+    const STARTING_BLOCK_TIME: u32 = 1681084800;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn node(utxo_set_path: &str) -> Node {
+        let logger = Logger::from_path("tests_txt/test_log.txt").unwrap();
+        let data_handler =
+            NodeDataHandler::new("tests_txt/wallet_communication_headers.bin").unwrap();
+        let (sx, _rx) = glib::MainContext::channel::<UIResponse>(glib::PRIORITY_DEFAULT);
+        let mut node = Node::_new(
+            VERSION,
+            LOCAL_ADDRESS,
+            logger,
+            data_handler,
+            STARTING_BLOCK_TIME,
+            sx,
+        );
+        node.utxo_set_path = utxo_set_path.to_string();
+        node
+    }
+
+    fn wallet() -> Wallet {
+        let priv_key = SecretKey::from_slice(&[7; 32]).unwrap();
+        Wallet::new(priv_key.public_key(&Secp256k1::new()), priv_key)
+    }
+
+    fn final_transaction() -> Transaction {
+        let tx_in = TxIn::new(Outpoint::new([1; 32], 0), Vec::new(), u32::MAX);
+        Transaction::new(1, vec![tx_in], vec![TxOut::new(1000, Vec::new())], 0)
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn wallet_communication_test_1_transaction_stays_held_if_it_can_not_be_sent(
+    ) -> Result<(), NodeError> {
+        let mut node = node("");
+        let mut wallet = wallet();
+        let transaction = final_transaction();
+        let hash = transaction.hash();
+        node.hold_transaction(&mut wallet, transaction)?;
+
+        node.release_final_transactions(&mut wallet)?;
+
+        assert!(node.held_tx.contains_key(&hash));
+        assert!(node.get_pending_tx()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn wallet_communication_test_2_held_transactions_are_loaded_after_a_restart(
+    ) -> Result<(), NodeError> {
+        let utxo_set_path = "tests_txt/wallet_communication_test_2_utxo_set.bin";
+        _ = fs::remove_file("tests_txt/held_tx.bin");
+        let mut wallet = wallet();
+        let transaction = final_transaction();
+        let hash = transaction.hash();
+        node(utxo_set_path).hold_transaction(&mut wallet, transaction)?;
+
+        let mut node = node(utxo_set_path);
+        node.load_held_transactions()?;
+
+        assert_eq!(
+            node.held_tx
+                .get(&hash)
+                .map(|transaction| transaction.to_bytes()),
+            Some(final_transaction().to_bytes())
+        );
+        Ok(())
+    }
+}
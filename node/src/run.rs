@@ -1,16 +1,29 @@
 use crate::utils::ui_communication_protocol::{UIRequest, UIResponse};
-use crate::{node::*, utils::config::*, utils::WalletError, wallet::*};
+use crate::{
+    node::{maintenance::MaintenanceOptions, *},
+    utils::config::*,
+    utils::WalletError,
+    wallet::*,
+};
 use glib::Sender as GlibSender;
 use std::sync::mpsc;
 
 /// Creates a new node correctely and sets workers for receiving messages from other peers.
 /// If an error occurs it returns None.
 pub fn initialize_node(args: Vec<String>, sender_to_ui: GlibSender<UIResponse>) -> Option<Node> {
-    if args.len() != 2 {
+    if args.len() < 2 {
         eprintln!("Cantidad de argumentos inválida");
         return None;
     }
 
+    let maintenance_options = match MaintenanceOptions::from_args(&args[2..]) {
+        Some(maintenance_options) => maintenance_options,
+        None => {
+            eprintln!("Argumentos inválidos, las opciones son --reindex y --verify-chain N");
+            return None;
+        }
+    };
+
     let config = match Config::from_path(args[1].as_str()) {
         Ok(config) => config,
         Err(error) => {
@@ -26,6 +39,7 @@ pub fn initialize_node(args: Vec<String>, sender_to_ui: GlibSender<UIResponse>)
             return None;
         }
     };
+    node.set_maintenance_options(maintenance_options);
 
     if let Err(error) = node.initial_block_download() {
         eprintln!("Error IBD: {:?}", error);
@@ -37,6 +51,21 @@ pub fn initialize_node(args: Vec<String>, sender_to_ui: GlibSender<UIResponse>)
         return None;
     };
 
+    if let Err(error) = node.build_tx_index() {
+        eprintln!("Error building transaction index: {:?}", error);
+        return None;
+    };
+
+    if let Err(error) = node.build_block_filters() {
+        eprintln!("Error building block filters: {:?}", error);
+        return None;
+    };
+
+    if let Err(error) = node.build_address_index() {
+        eprintln!("Error building address index: {:?}", error);
+        return None;
+    };
+
     node.start_receiving_messages();
 
     Some(node)
@@ -14,6 +14,8 @@ pub enum BlockChainError {
     ErrorSendingBlock,
     ErrorCreatingBlockHeader,
     ErrorSendingBlockHeader,
+    ErrorCreatingBlockFilter,
+    ErrorCreatingBloomFilter,
 }
 
 impl BtcError for BlockChainError {}
@@ -26,6 +28,7 @@ pub enum TransactionError {
     ErrorCreatingTxOutFromBytes,
     ErrorCreatingOutpointFromBytes,
     ErrorCreatingSignature,
+    ErrorCreatingSweepTransaction,
 }
 
 impl BtcError for TransactionError {}
@@ -59,6 +62,42 @@ pub enum MessageError {
     ErrorSendingPingMessage,
     ErrorCreatingPongMessage,
     ErrorSendingPongMessage,
+    ErrorCreatingAddrMessage,
+    ErrorSendingAddrMessage,
+    ErrorCreatingGetAddrMessage,
+    ErrorSendingGetAddrMessage,
+    ErrorCreatingAddrV2Message,
+    ErrorSendingAddrV2Message,
+    ErrorCreatingSendHeadersMessage,
+    ErrorSendingSendHeadersMessage,
+    ErrorCreatingFeeFilterMessage,
+    ErrorSendingFeeFilterMessage,
+    ErrorCreatingSendCmpctMessage,
+    ErrorSendingSendCmpctMessage,
+    ErrorCreatingWtxidRelayMessage,
+    ErrorSendingWtxidRelayMessage,
+    ErrorCreatingCmpctBlockMessage,
+    ErrorSendingCmpctBlockMessage,
+    ErrorCreatingGetBlockTxnMessage,
+    ErrorSendingGetBlockTxnMessage,
+    ErrorCreatingBlockTxnMessage,
+    ErrorSendingBlockTxnMessage,
+    ErrorCreatingGetCFiltersMessage,
+    ErrorSendingGetCFiltersMessage,
+    ErrorCreatingCFilterMessage,
+    ErrorSendingCFilterMessage,
+    ErrorCreatingGetCFHeadersMessage,
+    ErrorSendingGetCFHeadersMessage,
+    ErrorCreatingCFHeadersMessage,
+    ErrorSendingCFHeadersMessage,
+    ErrorCreatingGetCFCheckptMessage,
+    ErrorSendingGetCFCheckptMessage,
+    ErrorCreatingCFCheckptMessage,
+    ErrorSendingCFCheckptMessage,
+    ErrorCreatingFilterLoadMessage,
+    ErrorSendingFilterLoadMessage,
+    ErrorCreatingMerkleBlockMessage,
+    ErrorSendingMerkleBlockMessage,
     UnknownMessage,
 }
 
@@ -78,6 +117,7 @@ pub enum BlockDownloaderError {
     BundleNotFound,
     ErrorAllWorkersFailed,
     ErrorCreatingBlockDownloader,
+    ErrorSharingReference,
 }
 
 impl BtcError for BlockDownloaderError {}
@@ -109,6 +149,14 @@ pub enum ConfigError {
     ErrorParsingDate,
     ErrorInvalidDate,
     ErrorParsingIPV6Bool,
+    ErrorParsingLightClientBool,
+    ErrorParsingV2TransportBool,
+    ErrorParsingOutboundPeers,
+    ErrorParsingPruneTarget,
+    ErrorParsingTxIndexBool,
+    ErrorTxIndexWithPruning,
+    ErrorParsingAddressIndexBool,
+    ErrorAddressIndexWithPruning,
     ErrorInvalidParameter,
     ErrorNoExternalAddressGiven,
     ErrorParameterNotFound,
@@ -141,12 +189,22 @@ pub enum NodeError {
     ErrorSendingTransaction,
     ErrorNotEnoughSatoshis,
     ErrorFindingBlock,
+    ErrorFindingTransaction,
+    ErrorTxIndexDisabled,
+    ErrorAddressIndexDisabled,
     ErrorSendingThroughChannel,
     ErrorJoiningThread,
     ErrorPeerTimeout,
     ErrorReceivingMessageHeader,
     DoubleHeader,
     ErrorDisconectedFromBlockchain,
+    ErrorInvalidProofOfWork,
+    ErrorInvalidMerkleRoot,
+    ErrorInvalidHeaders,
+    ErrorInvalidScript,
+    ErrorOversizedMessage,
+    ErrorPeerNotConnected,
+    ErrorV2Handshake,
     ErrorMessage(MessageError),
 }
 
@@ -169,6 +227,7 @@ pub enum PeerComunicatorError {
     ErrorSendingMessage,
     ErrorPropagating,
     LostConnectionToManager,
+    ErrorBanningPeer,
 }
 
 impl BtcError for PeerComunicatorError {}
@@ -193,10 +252,20 @@ pub enum WalletError {
     ErrorFindingBlock,
     ErrorGettingBlockInfo,
     ErrorObtainingTxProof,
+    ErrorFindingTransaction,
+    ErrorTxIndexDisabled,
+    ErrorAddressIndexDisabled,
     ErrorReceivingFromUI,
     ErrorUpdatingWallet,
     InvalidAmount,
     ErrorDisconectedFromBlockchain,
+    ErrorParsingPaymentUri,
+    ErrorCreatingQrCode,
+    ErrorHandlingSignature,
+    ErrorFindingUtxo,
+    ErrorGettingPeerInfo,
+    ErrorFindingPeer,
+    ErrorBanningPeer,
 }
 
 impl BtcError for WalletError {}
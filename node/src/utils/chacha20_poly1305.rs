@@ -0,0 +1,403 @@
+/// Amount of messages after which the forward secure ciphers of BIP324 change their key.
+const REKEY_INTERVAL: u32 = 224;
+const BLOCK_SIZE: usize = 64;
+const KEY_SIZE: usize = 32;
+pub const TAG_SIZE: usize = 16;
+const CHACHA20_CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+/// Returns the ChaCha20 (RFC 8439) keystream block of the given counter.
+fn chacha20_block(key: &[u8; 32], counter: u32, nonce: &[u8; 12]) -> [u8; 64] {
+    let mut initial_state = [0u32; 16];
+    initial_state[..4].copy_from_slice(&CHACHA20_CONSTANTS);
+    for (i, word) in key.chunks_exact(4).enumerate() {
+        initial_state[4 + i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    initial_state[12] = counter;
+    for (i, word) in nonce.chunks_exact(4).enumerate() {
+        initial_state[13 + i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+
+    let mut state = initial_state;
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    let mut block = [0; BLOCK_SIZE];
+    for (i, word) in state.iter().enumerate() {
+        let word = word.wrapping_add(initial_state[i]);
+        block[4 * i..4 * i + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    block
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// XORs the data with the ChaCha20 keystream, starting at the given block counter.
+fn chacha20_xor(key: &[u8; 32], counter: u32, nonce: &[u8; 12], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (byte, key_byte) in chunk.iter_mut().zip(block.iter()) {
+            *byte ^= key_byte;
+        }
+    }
+}
+
+/// Nonce made of a 32 bit and a 64 bit number, as BIP324 uses them.
+fn nonce(first: u32, second: u64) -> [u8; 12] {
+    let mut nonce = [0; 12];
+    nonce[..4].copy_from_slice(&first.to_le_bytes());
+    nonce[4..].copy_from_slice(&second.to_le_bytes());
+    nonce
+}
+
+/// Returns the Poly1305 (RFC 8439) tag of the message.
+fn poly1305(key: &[u8; 32], message: &[u8]) -> [u8; 16] {
+    const MASK: u32 = 0x3ffffff;
+    let le32 = |bytes: &[u8], i: usize| {
+        u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+    };
+
+    let r = [
+        le32(key, 0) & 0x3ffffff,
+        (le32(key, 3) >> 2) & 0x3ffff03,
+        (le32(key, 6) >> 4) & 0x3ffc0ff,
+        (le32(key, 9) >> 6) & 0x3f03fff,
+        (le32(key, 12) >> 8) & 0x00fffff,
+    ];
+    let s = [r[1] * 5, r[2] * 5, r[3] * 5, r[4] * 5];
+    let mut h = [0u32; 5];
+
+    for chunk in message.chunks(16) {
+        let mut block = [0u8; 17];
+        block[..chunk.len()].copy_from_slice(chunk);
+        block[chunk.len()] = 1;
+        let high_bit = block[16] as u32;
+
+        h[0] += le32(&block, 0) & MASK;
+        h[1] += (le32(&block, 3) >> 2) & MASK;
+        h[2] += (le32(&block, 6) >> 4) & MASK;
+        h[3] += (le32(&block, 9) >> 6) & MASK;
+        h[4] += (le32(&block, 12) >> 8) | (high_bit << 24);
+
+        let m = |a: u32, b: u32| a as u64 * b as u64;
+        let d = [
+            m(h[0], r[0]) + m(h[1], s[3]) + m(h[2], s[2]) + m(h[3], s[1]) + m(h[4], s[0]),
+            m(h[0], r[1]) + m(h[1], r[0]) + m(h[2], s[3]) + m(h[3], s[2]) + m(h[4], s[1]),
+            m(h[0], r[2]) + m(h[1], r[1]) + m(h[2], r[0]) + m(h[3], s[3]) + m(h[4], s[2]),
+            m(h[0], r[3]) + m(h[1], r[2]) + m(h[2], r[1]) + m(h[3], r[0]) + m(h[4], s[3]),
+            m(h[0], r[4]) + m(h[1], r[3]) + m(h[2], r[2]) + m(h[3], r[1]) + m(h[4], r[0]),
+        ];
+
+        let mut carry = 0;
+        for i in 0..5 {
+            let value = d[i] + carry;
+            h[i] = value as u32 & MASK;
+            carry = value >> 26;
+        }
+        h[0] += carry as u32 * 5;
+        h[1] += h[0] >> 26;
+        h[0] &= MASK;
+    }
+
+    let mut carry = 0;
+    for value in h.iter_mut().skip(1) {
+        *value += carry;
+        carry = *value >> 26;
+        *value &= MASK;
+    }
+    h[0] += carry * 5;
+    h[1] += h[0] >> 26;
+    h[0] &= MASK;
+
+    // Subtracts 2^130 - 5 if h is not smaller.
+    let mut g = [0u32; 5];
+    let mut carry = 5;
+    for i in 0..5 {
+        let value = h[i] + carry;
+        g[i] = value & MASK;
+        carry = value >> 26;
+    }
+    if carry != 0 {
+        h = g;
+    }
+
+    let words = [
+        h[0] | (h[1] << 26),
+        (h[1] >> 6) | (h[2] << 20),
+        (h[2] >> 12) | (h[3] << 14),
+        (h[3] >> 18) | (h[4] << 8),
+    ];
+    let mut tag = [0; 16];
+    let mut carry = 0;
+    for (i, word) in words.iter().enumerate() {
+        let value = *word as u64 + le32(key, 16 + 4 * i) as u64 + carry;
+        tag[4 * i..4 * i + 4].copy_from_slice(&(value as u32).to_le_bytes());
+        carry = value >> 32;
+    }
+    tag
+}
+
+/// Returns the Poly1305 tag of the AEAD construction of RFC 8439.
+fn aead_tag(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], ciphertext: &[u8]) -> [u8; 16] {
+    let mut poly_key = [0; KEY_SIZE];
+    poly_key.copy_from_slice(&chacha20_block(key, 0, nonce)[..KEY_SIZE]);
+
+    let mut mac_data = aad.to_vec();
+    mac_data.resize(aad.len().div_ceil(16) * 16, 0);
+    mac_data.extend(ciphertext);
+    mac_data.resize(mac_data.len().div_ceil(16) * 16, 0);
+    mac_data.extend((aad.len() as u64).to_le_bytes());
+    mac_data.extend((ciphertext.len() as u64).to_le_bytes());
+    poly1305(&poly_key, &mac_data)
+}
+
+/// Encrypts the plaintext with ChaCha20-Poly1305 (RFC 8439), returning the ciphertext followed by the tag.
+pub fn aead_encrypt(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+    let mut ciphertext = plaintext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut ciphertext);
+    let tag = aead_tag(key, nonce, aad, &ciphertext);
+    ciphertext.extend(tag);
+    ciphertext
+}
+
+/// Decrypts a ciphertext followed by its tag. Returns None if the tag does not authenticate it.
+pub fn aead_decrypt(key: &[u8; 32], nonce: &[u8; 12], aad: &[u8], data: &[u8]) -> Option<Vec<u8>> {
+    let tag_start = data.len().checked_sub(TAG_SIZE)?;
+    let (ciphertext, tag) = data.split_at(tag_start);
+    let expected_tag = aead_tag(key, nonce, aad, ciphertext);
+    let difference = expected_tag
+        .iter()
+        .zip(tag)
+        .fold(0, |difference, (a, b)| difference | (a ^ b));
+    if difference != 0 {
+        return None;
+    }
+    let mut plaintext = ciphertext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut plaintext);
+    Some(plaintext)
+}
+
+/// Forward secure ChaCha20 stream cipher of BIP324, which encrypts the lengths of the packets. The
+/// keystream continues from one chunk to the next, and the key changes every REKEY_INTERVAL chunks.
+#[derive(Debug)]
+pub struct FSChaCha20 {
+    key: [u8; 32],
+    rekey_interval: u32,
+    chunk_counter: u32,
+    rekey_counter: u64,
+    block_counter: u32,
+    keystream: Vec<u8>,
+}
+
+impl FSChaCha20 {
+    pub fn new(key: [u8; 32]) -> FSChaCha20 {
+        FSChaCha20 {
+            key,
+            rekey_interval: REKEY_INTERVAL,
+            chunk_counter: 0,
+            rekey_counter: 0,
+            block_counter: 0,
+            keystream: Vec::new(),
+        }
+    }
+
+    /// Creates the cipher changing its key every rekey_interval chunks, as some test vectors do.
+    #[cfg(test)]
+    fn with_rekey_interval(key: [u8; 32], rekey_interval: u32) -> FSChaCha20 {
+        FSChaCha20 {
+            rekey_interval,
+            ..FSChaCha20::new(key)
+        }
+    }
+
+    fn keystream_bytes(&mut self, amount: usize) -> Vec<u8> {
+        while self.keystream.len() < amount {
+            let nonce = nonce(0, self.rekey_counter);
+            let block = chacha20_block(&self.key, self.block_counter, &nonce);
+            self.keystream.extend(block);
+            self.block_counter += 1;
+        }
+        self.keystream.drain(..amount).collect()
+    }
+
+    /// Encrypts or decrypts the chunk.
+    pub fn crypt(&mut self, chunk: &[u8]) -> Vec<u8> {
+        let keystream = self.keystream_bytes(chunk.len());
+        let output = chunk.iter().zip(keystream).map(|(a, b)| a ^ b).collect();
+
+        self.chunk_counter += 1;
+        if self.chunk_counter == self.rekey_interval {
+            let new_key = self.keystream_bytes(KEY_SIZE);
+            self.key.copy_from_slice(&new_key);
+            self.keystream.clear();
+            self.block_counter = 0;
+            self.chunk_counter = 0;
+            self.rekey_counter += 1;
+        }
+        output
+    }
+}
+
+/// Forward secure ChaCha20-Poly1305 AEAD of BIP324, which encrypts the contents of the packets.
+/// Every packet uses a different nonce, and the key changes every REKEY_INTERVAL packets.
+#[derive(Debug)]
+pub struct FSChaCha20Poly1305 {
+    key: [u8; 32],
+    packet_counter: u32,
+    rekey_counter: u64,
+}
+
+impl FSChaCha20Poly1305 {
+    pub fn new(key: [u8; 32]) -> FSChaCha20Poly1305 {
+        FSChaCha20Poly1305 {
+            key,
+            packet_counter: 0,
+            rekey_counter: 0,
+        }
+    }
+
+    pub fn encrypt(&mut self, aad: &[u8], plaintext: &[u8]) -> Vec<u8> {
+        let nonce = nonce(self.packet_counter, self.rekey_counter);
+        let ciphertext = aead_encrypt(&self.key, &nonce, aad, plaintext);
+        self.next_packet();
+        ciphertext
+    }
+
+    /// Returns None if the packet is not authenticated. The cipher moves on to the next packet anyway.
+    pub fn decrypt(&mut self, aad: &[u8], ciphertext: &[u8]) -> Option<Vec<u8>> {
+        let nonce = nonce(self.packet_counter, self.rekey_counter);
+        let plaintext = aead_decrypt(&self.key, &nonce, aad, ciphertext);
+        self.next_packet();
+        plaintext
+    }
+
+    fn next_packet(&mut self) {
+        self.packet_counter += 1;
+        if self.packet_counter == REKEY_INTERVAL {
+            let nonce = nonce(u32::MAX, self.rekey_counter);
+            let block = chacha20_block(&self.key, 1, &nonce);
+            self.key.copy_from_slice(&block[..KEY_SIZE]);
+            self.packet_counter = 0;
+            self.rekey_counter += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::get_bytes_from_hex;
+
+    // Auxiliar functions
+    //=================================================================
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn chacha20_poly1305_test_1_matches_the_rfc_8439_vector() {
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+        let mut key = [0; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = 0x80 + i as u8;
+        }
+        let nonce: [u8; 12] = get_bytes_from_hex("070000004041424344454647".to_string())
+            .unwrap()
+            .try_into()
+            .unwrap();
+        let aad = get_bytes_from_hex("50515253c0c1c2c3c4c5c6c7".to_string()).unwrap();
+
+        let ciphertext = aead_encrypt(&key, &nonce, &aad, plaintext);
+
+        assert_eq!(ciphertext, get_bytes_from_hex("d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3ff4def08e4b7a9de576d26586cec64b61161ae10b594f09e26a7e902ecbd0600691".to_string()).unwrap());
+        assert_eq!(
+            aead_decrypt(&key, &nonce, &aad, &ciphertext),
+            Some(plaintext.to_vec())
+        );
+        assert_eq!(aead_decrypt(&key, &nonce, &[], &ciphertext), None);
+    }
+
+    #[test]
+    fn chacha20_poly1305_test_2_fschacha20_matches_the_bip324_vectors() {
+        let vectors = [
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "0000000000000000000000000000000000000000000000000000000000000000",
+                256,
+                "a93df4ef03011f3db95f60d996e1785df5de38fc39bfcb663a47bb5561928349",
+            ),
+            (
+                "01",
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                5,
+                "ea",
+            ),
+            (
+                "e93fdb5c762804b9a706816aca31e35b11d2aa3080108ef46a5b1f1508819c0a",
+                "8ec4c3ccdaea336bdeb245636970be01266509b33f3d2642504eaf412206207a",
+                4096,
+                "8bfaa4eacff308fdb4a94a5ff25bd9d0c1f84b77f81239f67ff39d6e1ac280c9",
+            ),
+        ];
+
+        for (plaintext, key, rekey_interval, ciphertext_after_rekey) in vectors {
+            let plaintext = get_bytes_from_hex(plaintext.to_string()).unwrap();
+            let key: [u8; 32] = get_bytes_from_hex(key.to_string())
+                .unwrap()
+                .try_into()
+                .unwrap();
+            let mut cipher = FSChaCha20::with_rekey_interval(key, rekey_interval);
+            let mut stream_cipher_output = plaintext.clone();
+            chacha20_xor(&key, 0, &nonce(0, 0), &mut stream_cipher_output);
+
+            assert_eq!(cipher.crypt(&plaintext), stream_cipher_output);
+            for _ in 1..rekey_interval {
+                cipher.crypt(&plaintext);
+            }
+            assert_eq!(
+                cipher.crypt(&plaintext),
+                get_bytes_from_hex(ciphertext_after_rekey.to_string()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn chacha20_poly1305_test_3_fschacha20poly1305_matches_the_bip324_vector() {
+        let key: [u8; 32] = get_bytes_from_hex(
+            "5c9e1c3951a74fba66708bf9d2c217571684556b6a6a3573bff2847d38612654".to_string(),
+        )
+        .unwrap()
+        .try_into()
+        .unwrap();
+        let plaintext = get_bytes_from_hex("d6a4cb04ef0f7c09c1866ed29dc24d820e75b0491032a51b4c3366f9ca35c19ea3047ec6be9d45f9637b63e1cf9eb4c2523a5aab7b851ebeba87199db0e839cf0d5c25e50168306377aedbe9089fd2463ded88b83211cf51b73b150608cc7a600d0f11b9a742948482e1b109d8faf15b450aa7322e892fa2208c6691e3fecf4c711191b14d75a72147".to_string()).unwrap();
+        let aad = get_bytes_from_hex("786cb9b6ebf44288974cf0".to_string()).unwrap();
+        let mut sender = FSChaCha20Poly1305::new(key);
+        let mut receiver = FSChaCha20Poly1305::new(key);
+
+        // The packet 500 is encrypted with the key after two changes.
+        for _ in 0..500 {
+            let packet = sender.encrypt(&[], &[]);
+            assert_eq!(receiver.decrypt(&[], &packet), Some(Vec::new()));
+        }
+        let packet = sender.encrypt(&aad, &plaintext);
+
+        assert_eq!(packet, get_bytes_from_hex("9dcebbd3281ea3dd8e9a1ef7d55a97abd6743e56ebc0c190cb2c4e14160b385e0bf508dddf754bd02c7c208447c131ce23e47a4a14dfaf5dd8bc601323950f754e05d46e9232f83fc5120fbbef6f5347a826ec79a93820718d4ec7a2b7cfaaa44b21e16d726448b62f803811aff4f6d827ed78e738ce8a507b81a8ae131311928039213de18a5120dc9b7370baca878f50ff254418de3da50c".to_string()).unwrap());
+        assert_eq!(receiver.decrypt(&aad, &packet), Some(plaintext));
+    }
+}
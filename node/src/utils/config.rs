@@ -4,6 +4,7 @@ use std::{
     collections::HashMap,
     fs::File,
     io::{BufRead, BufReader},
+    path::Path,
 };
 
 const VERSION: &str = "version";
@@ -12,12 +13,41 @@ const BEGIN_TIME: &str = "starting_date";
 const LOG_PATH: &str = "log_file_path";
 const HEADERS_PATH: &str = "headers_file_path";
 const BLOCKS_PATH: &str = "blocks_file_path";
+const BLOCK_INDEX_PATH: &str = "block_index_file_path";
+const PEERS_PATH: &str = "peers_file_path";
+const BANLIST_PATH: &str = "banlist_file_path";
+const FILTER_HEADERS_PATH: &str = "filter_headers_file_path";
+const UTXO_SET_PATH: &str = "utxo_set_file_path";
+const TX_INDEX_PATH: &str = "tx_index_file_path";
+const ADDRESS_INDEX_PATH: &str = "address_index_file_path";
+const OUTBOUND_PEERS: &str = "outbound_peers";
 const IPV6_ENABLED: &str = "ipv6_enabled";
+const LIGHT_CLIENT: &str = "light_client";
+const V2_TRANSPORT: &str = "v2_transport";
+const PRUNE_TARGET: &str = "prune_target_mb";
+const TX_INDEX: &str = "txindex";
+const ADDRESS_INDEX: &str = "addressindex";
 const DNS: &str = "DNS";
 const EXTERNAL_ADDR: &str = "external_addr";
 
 const CONFIG_FILENAME: &str = "nodo.conf";
 const PARAMETER_AMOUNT: usize = 9;
+const OPTIONAL_PARAMETER_AMOUNT: usize = 13;
+const MIN_PRUNE_TARGET_MB: u64 = 550;
+
+const DEFAULT_BLOCK_INDEX_FILENAME: &str = "block_index.bin";
+const DEFAULT_PEERS_FILENAME: &str = "peers.txt";
+const DEFAULT_BANLIST_FILENAME: &str = "banlist.txt";
+const DEFAULT_FILTER_HEADERS_FILENAME: &str = "block_filters.bin";
+const DEFAULT_UTXO_SET_FILENAME: &str = "utxo_set.bin";
+const DEFAULT_TX_INDEX_FILENAME: &str = "tx_index.bin";
+const DEFAULT_ADDRESS_INDEX_FILENAME: &str = "address_index.bin";
+const DEFAULT_OUTBOUND_PEERS: &str = "8";
+const DEFAULT_LIGHT_CLIENT: &str = "false";
+const DEFAULT_V2_TRANSPORT: &str = "false";
+const DEFAULT_PRUNE_TARGET: &str = "0";
+const DEFAULT_TX_INDEX: &str = "false";
+const DEFAULT_ADDRESS_INDEX: &str = "false";
 
 const IP_DELIMETER: char = ',';
 const PORT_DELIMETER: char = ':';
@@ -32,7 +62,20 @@ pub struct Config {
     pub log_path: String,
     pub headers_path: String,
     pub blocks_path: String,
+    pub block_index_path: String,
+    pub peers_path: String,
+    pub banlist_path: String,
+    pub filter_headers_path: String,
+    pub utxo_set_path: String,
+    pub tx_index_path: String,
+    pub address_index_path: String,
+    pub outbound_peers: usize,
     pub ipv6_enabled: bool,
+    pub light_client: bool,
+    pub v2_transport: bool,
+    pub prune_target_mb: u64,
+    pub tx_index: bool,
+    pub address_index: bool,
     pub dns: Vec<(String, u16)>,
     pub external_addresses: Vec<([u8; 4], u16)>,
 }
@@ -66,9 +109,12 @@ impl Config {
     }
 
     /// It receives the fields for the configuration, validates them and returns a Config if they were valid.
+    /// Only the original PARAMETER_AMOUNT parameters are mandatory, the rest take a default value when missing.
     /// Returns a ConfigError when parsing failed or a parameter is invalid.
     fn _from(config_fields: HashMap<String, String>) -> Result<Config, ConfigError> {
-        if config_fields.len() != PARAMETER_AMOUNT {
+        if config_fields.len() < PARAMETER_AMOUNT
+            || config_fields.len() > PARAMETER_AMOUNT + OPTIONAL_PARAMETER_AMOUNT
+        {
             return Err(ConfigError::ErrorMismatchedQuantityOfParameters);
         }
 
@@ -84,7 +130,82 @@ impl Config {
         let log_path = get_handler(&config_fields, LOG_PATH)?;
         let headers_path = get_handler(&config_fields, HEADERS_PATH)?;
         let blocks_path = get_handler(&config_fields, BLOCKS_PATH)?;
+        let block_index_path = get_path_or_default(
+            &config_fields,
+            BLOCK_INDEX_PATH,
+            &blocks_path,
+            DEFAULT_BLOCK_INDEX_FILENAME,
+        );
+        let peers_path = get_path_or_default(
+            &config_fields,
+            PEERS_PATH,
+            &blocks_path,
+            DEFAULT_PEERS_FILENAME,
+        );
+        let banlist_path = get_path_or_default(
+            &config_fields,
+            BANLIST_PATH,
+            &blocks_path,
+            DEFAULT_BANLIST_FILENAME,
+        );
+        let filter_headers_path = get_path_or_default(
+            &config_fields,
+            FILTER_HEADERS_PATH,
+            &blocks_path,
+            DEFAULT_FILTER_HEADERS_FILENAME,
+        );
+        let utxo_set_path = get_path_or_default(
+            &config_fields,
+            UTXO_SET_PATH,
+            &blocks_path,
+            DEFAULT_UTXO_SET_FILENAME,
+        );
+        let tx_index_path = get_path_or_default(
+            &config_fields,
+            TX_INDEX_PATH,
+            &blocks_path,
+            DEFAULT_TX_INDEX_FILENAME,
+        );
+        let address_index_path = get_path_or_default(
+            &config_fields,
+            ADDRESS_INDEX_PATH,
+            &blocks_path,
+            DEFAULT_ADDRESS_INDEX_FILENAME,
+        );
+        let outbound_peers = parse_outbound_peers(&get_or_default(
+            &config_fields,
+            OUTBOUND_PEERS,
+            DEFAULT_OUTBOUND_PEERS,
+        ))?;
         let ipv6_enabled = parse_ipv6_enabled(&get_handler(&config_fields, IPV6_ENABLED)?)?;
+        let light_client = parse_light_client(&get_or_default(
+            &config_fields,
+            LIGHT_CLIENT,
+            DEFAULT_LIGHT_CLIENT,
+        ))?;
+        let v2_transport = parse_v2_transport(&get_or_default(
+            &config_fields,
+            V2_TRANSPORT,
+            DEFAULT_V2_TRANSPORT,
+        ))?;
+        let prune_target_mb = parse_prune_target(&get_or_default(
+            &config_fields,
+            PRUNE_TARGET,
+            DEFAULT_PRUNE_TARGET,
+        ))?;
+        let tx_index = parse_tx_index(&get_or_default(&config_fields, TX_INDEX, DEFAULT_TX_INDEX))?;
+        let address_index = parse_address_index(&get_or_default(
+            &config_fields,
+            ADDRESS_INDEX,
+            DEFAULT_ADDRESS_INDEX,
+        ))?;
+
+        if tx_index && prune_target_mb > 0 {
+            return Err(ConfigError::ErrorTxIndexWithPruning);
+        }
+        if address_index && prune_target_mb > 0 {
+            return Err(ConfigError::ErrorAddressIndexWithPruning);
+        }
 
         let mut dns = Vec::new();
         dns.extend(parse_dns_vector(&get_handler(&config_fields, DNS)?)?);
@@ -106,7 +227,20 @@ impl Config {
             log_path,
             headers_path,
             blocks_path,
+            block_index_path,
+            peers_path,
+            banlist_path,
+            filter_headers_path,
+            utxo_set_path,
+            tx_index_path,
+            address_index_path,
+            outbound_peers,
             ipv6_enabled,
+            light_client,
+            v2_transport,
+            prune_target_mb,
+            tx_index,
+            address_index,
             dns,
             external_addresses,
         })
@@ -142,12 +276,48 @@ fn get_handler(
     }
 }
 
+/// Returns the value of an optional field, or the given default if it is not in the config.
+fn get_or_default(config_fields: &HashMap<String, String>, field: &str, default: &str) -> String {
+    match config_fields.get(field) {
+        Some(data) => data.to_string(),
+        None => default.to_string(),
+    }
+}
+
+/// Returns the path of an optional field, or a file with the default name placed next to the
+/// blocks file if it is not in the config.
+fn get_path_or_default(
+    config_fields: &HashMap<String, String>,
+    field: &str,
+    blocks_path: &str,
+    default_file_name: &str,
+) -> String {
+    match config_fields.get(field) {
+        Some(data) => data.to_string(),
+        None => match Path::new(blocks_path).parent() {
+            Some(directory) => directory
+                .join(default_file_name)
+                .to_string_lossy()
+                .to_string(),
+            None => default_file_name.to_string(),
+        },
+    }
+}
+
 /// It parses an string into a version number.
 fn parse_version(data: &str) -> Result<i32, ConfigError> {
     data.parse::<i32>()
         .map_err(|_| ConfigError::ErrorParsingVersion)
 }
 
+/// It parses an string into the amount of outbound peers, which must be at least one.
+fn parse_outbound_peers(data: &str) -> Result<usize, ConfigError> {
+    match data.parse::<usize>() {
+        Ok(outbound_peers) if outbound_peers > 0 => Ok(outbound_peers),
+        _ => Err(ConfigError::ErrorParsingOutboundPeers),
+    }
+}
+
 /// It parses an string into a vector of address.
 fn parse_address_vector(addresses: &str) -> Result<Vec<([u8; 4], u16)>, ConfigError> {
     let splitted_addresses: Vec<&str> = addresses.split(ARRAY_DELIMETER).collect();
@@ -231,6 +401,41 @@ fn parse_ipv6_enabled(data: &str) -> Result<bool, ConfigError> {
         .map_err(|_| ConfigError::ErrorParsingIPV6Bool)
 }
 
+/// It parses an string into a boolean (run as a light client, without downloading full blocks).
+fn parse_light_client(data: &str) -> Result<bool, ConfigError> {
+    data.parse::<bool>()
+        .map_err(|_| ConfigError::ErrorParsingLightClientBool)
+}
+
+/// It parses an string into a boolean (attempt the v2 transport of BIP324 with peers).
+fn parse_v2_transport(data: &str) -> Result<bool, ConfigError> {
+    data.parse::<bool>()
+        .map_err(|_| ConfigError::ErrorParsingV2TransportBool)
+}
+
+/// It parses an string into the size in MiB the block files can take before pruning the oldest ones.
+/// It is 0 if blocks are never pruned, otherwise it must be at least MIN_PRUNE_TARGET_MB.
+fn parse_prune_target(data: &str) -> Result<u64, ConfigError> {
+    match data.parse::<u64>() {
+        Ok(prune_target) if prune_target == 0 || prune_target >= MIN_PRUNE_TARGET_MB => {
+            Ok(prune_target)
+        }
+        _ => Err(ConfigError::ErrorParsingPruneTarget),
+    }
+}
+
+/// It parses an string into a boolean (keep an index of the transactions by their txid).
+fn parse_tx_index(data: &str) -> Result<bool, ConfigError> {
+    data.parse::<bool>()
+        .map_err(|_| ConfigError::ErrorParsingTxIndexBool)
+}
+
+/// It parses an string into a boolean (keep an index of the outputs and history of every script).
+fn parse_address_index(data: &str) -> Result<bool, ConfigError> {
+    data.parse::<bool>()
+        .map_err(|_| ConfigError::ErrorParsingAddressIndexBool)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -239,6 +444,14 @@ mod tests {
     const LOG_FILE_PATH: &str = "tests_txt/config_test_log.txt";
     const HEADERS_FILE_PATH: &str = "tests_txt/headers.bin";
     const BLOCKS_FILE_PATH: &str = "tests_txt/blocks.bin";
+    const BLOCK_INDEX_FILE_PATH: &str = "tests_txt/block_index.bin";
+    const PEERS_FILE_PATH: &str = "tests_txt/peers.txt";
+    const BANLIST_FILE_PATH: &str = "tests_txt/banlist.txt";
+    const FILTER_HEADERS_FILE_PATH: &str = "tests_txt/block_filters.bin";
+    const UTXO_SET_FILE_PATH: &str = "tests_txt/utxo_set.bin";
+    const TX_INDEX_FILE_PATH: &str = "tests_txt/tx_index.bin";
+    const ADDRESS_INDEX_FILE_PATH: &str = "tests_txt/address_index.bin";
+    const OUTBOUND_PEERS_AMOUNT: usize = 8;
 
     // Auxiliar functions
     //=================================================================
@@ -259,7 +472,32 @@ mod tests {
         paramenters.insert(LOG_PATH.to_string(), LOG_FILE_PATH.to_string());
         paramenters.insert(HEADERS_PATH.to_string(), HEADERS_FILE_PATH.to_string());
         paramenters.insert(BLOCKS_PATH.to_string(), BLOCKS_FILE_PATH.to_string());
+        paramenters.insert(
+            BLOCK_INDEX_PATH.to_string(),
+            BLOCK_INDEX_FILE_PATH.to_string(),
+        );
+        paramenters.insert(PEERS_PATH.to_string(), PEERS_FILE_PATH.to_string());
+        paramenters.insert(BANLIST_PATH.to_string(), BANLIST_FILE_PATH.to_string());
+        paramenters.insert(
+            FILTER_HEADERS_PATH.to_string(),
+            FILTER_HEADERS_FILE_PATH.to_string(),
+        );
+        paramenters.insert(UTXO_SET_PATH.to_string(), UTXO_SET_FILE_PATH.to_string());
+        paramenters.insert(TX_INDEX_PATH.to_string(), TX_INDEX_FILE_PATH.to_string());
+        paramenters.insert(
+            ADDRESS_INDEX_PATH.to_string(),
+            ADDRESS_INDEX_FILE_PATH.to_string(),
+        );
+        paramenters.insert(
+            OUTBOUND_PEERS.to_string(),
+            OUTBOUND_PEERS_AMOUNT.to_string(),
+        );
         paramenters.insert(IPV6_ENABLED.to_string(), ipv6_enabled.to_string());
+        paramenters.insert(LIGHT_CLIENT.to_string(), false.to_string());
+        paramenters.insert(V2_TRANSPORT.to_string(), true.to_string());
+        paramenters.insert(PRUNE_TARGET.to_string(), 0.to_string());
+        paramenters.insert(TX_INDEX.to_string(), false.to_string());
+        paramenters.insert(ADDRESS_INDEX.to_string(), false.to_string());
         paramenters.insert(DNS.to_string(), dns_vector.to_string());
         paramenters.insert(EXTERNAL_ADDR.to_string(), ext_addr_vector.to_string());
 
@@ -307,7 +545,26 @@ mod tests {
         assert_eq!(config.log_path, LOG_FILE_PATH.to_string());
         assert_eq!(config.headers_path, HEADERS_FILE_PATH.to_string());
         assert_eq!(config.blocks_path, BLOCKS_FILE_PATH.to_string());
+        assert_eq!(config.block_index_path, BLOCK_INDEX_FILE_PATH.to_string());
+        assert_eq!(config.peers_path, PEERS_FILE_PATH.to_string());
+        assert_eq!(config.banlist_path, BANLIST_FILE_PATH.to_string());
+        assert_eq!(
+            config.filter_headers_path,
+            FILTER_HEADERS_FILE_PATH.to_string()
+        );
+        assert_eq!(config.utxo_set_path, UTXO_SET_FILE_PATH.to_string());
+        assert_eq!(config.tx_index_path, TX_INDEX_FILE_PATH.to_string());
+        assert_eq!(
+            config.address_index_path,
+            ADDRESS_INDEX_FILE_PATH.to_string()
+        );
+        assert_eq!(config.outbound_peers, OUTBOUND_PEERS_AMOUNT);
         assert_eq!(config.ipv6_enabled, false);
+        assert_eq!(config.light_client, false);
+        assert_eq!(config.v2_transport, true);
+        assert_eq!(config.prune_target_mb, 0);
+        assert!(!config.tx_index);
+        assert!(!config.address_index);
         assert_eq!(config.dns, expected_dns);
         assert_eq!(config.external_addresses, expected_external_addresses);
     }
@@ -422,4 +679,168 @@ mod tests {
 
         assert!(Config::_from(parameters).is_err());
     }
+
+    #[test]
+    fn config_test_10_zero_outbound_peers_cannot_create_config() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            true,
+            "dns_vector:1",
+            "",
+        );
+        parameters.insert(OUTBOUND_PEERS.to_string(), "0".to_string());
+
+        assert!(matches!(
+            Config::_from(parameters),
+            Err(ConfigError::ErrorParsingOutboundPeers)
+        ));
+    }
+
+    #[test]
+    fn config_test_11_invalid_light_client_parameter_cannot_create_config() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            true,
+            "dns_vector:1",
+            "",
+        );
+        parameters.insert(LIGHT_CLIENT.to_string(), "spv".to_string());
+
+        assert!(matches!(
+            Config::_from(parameters),
+            Err(ConfigError::ErrorParsingLightClientBool)
+        ));
+    }
+
+    #[test]
+    fn config_test_12_invalid_v2_transport_parameter_cannot_create_config() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            true,
+            "dns_vector:1",
+            "",
+        );
+        parameters.insert(V2_TRANSPORT.to_string(), "bip324".to_string());
+
+        assert!(matches!(
+            Config::_from(parameters),
+            Err(ConfigError::ErrorParsingV2TransportBool)
+        ));
+    }
+
+    #[test]
+    fn config_test_13_prune_target_below_the_minimum_cannot_create_config() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            true,
+            "dns_vector:1",
+            "",
+        );
+        parameters.insert(PRUNE_TARGET.to_string(), "549".to_string());
+
+        assert!(matches!(
+            Config::_from(parameters),
+            Err(ConfigError::ErrorParsingPruneTarget)
+        ));
+    }
+
+    #[test]
+    fn config_test_14_tx_index_with_pruning_cannot_create_config() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            true,
+            "dns_vector:1",
+            "",
+        );
+        parameters.insert(PRUNE_TARGET.to_string(), "550".to_string());
+        parameters.insert(TX_INDEX.to_string(), true.to_string());
+
+        assert!(matches!(
+            Config::_from(parameters),
+            Err(ConfigError::ErrorTxIndexWithPruning)
+        ));
+    }
+
+    #[test]
+    fn config_test_15_address_index_with_pruning_cannot_create_config() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            true,
+            "dns_vector:1",
+            "",
+        );
+        parameters.insert(PRUNE_TARGET.to_string(), "550".to_string());
+        parameters.insert(ADDRESS_INDEX.to_string(), true.to_string());
+
+        assert!(matches!(
+            Config::_from(parameters),
+            Err(ConfigError::ErrorAddressIndexWithPruning)
+        ));
+    }
+
+    #[test]
+    fn config_test_16_config_with_only_the_original_parameters_uses_defaults() {
+        let mut parameters = create_parameters(
+            "70015",
+            "127,0,0,1:1001",
+            STARTING_TIME,
+            false,
+            "dns_vector:1",
+            "",
+        );
+        for optional_field in [
+            BLOCK_INDEX_PATH,
+            PEERS_PATH,
+            BANLIST_PATH,
+            FILTER_HEADERS_PATH,
+            UTXO_SET_PATH,
+            TX_INDEX_PATH,
+            ADDRESS_INDEX_PATH,
+            OUTBOUND_PEERS,
+            LIGHT_CLIENT,
+            V2_TRANSPORT,
+            PRUNE_TARGET,
+            TX_INDEX,
+            ADDRESS_INDEX,
+        ] {
+            parameters.remove(optional_field);
+        }
+        assert_eq!(parameters.len(), PARAMETER_AMOUNT);
+
+        let config = Config::_from(parameters)
+            .expect("Could not create config from the original parameters.");
+
+        assert_eq!(config.blocks_path, BLOCKS_FILE_PATH.to_string());
+        assert_eq!(config.block_index_path, BLOCK_INDEX_FILE_PATH.to_string());
+        assert_eq!(config.peers_path, PEERS_FILE_PATH.to_string());
+        assert_eq!(config.banlist_path, BANLIST_FILE_PATH.to_string());
+        assert_eq!(
+            config.filter_headers_path,
+            FILTER_HEADERS_FILE_PATH.to_string()
+        );
+        assert_eq!(config.utxo_set_path, UTXO_SET_FILE_PATH.to_string());
+        assert_eq!(config.tx_index_path, TX_INDEX_FILE_PATH.to_string());
+        assert_eq!(
+            config.address_index_path,
+            ADDRESS_INDEX_FILE_PATH.to_string()
+        );
+        assert_eq!(config.outbound_peers, OUTBOUND_PEERS_AMOUNT);
+        assert!(!config.light_client);
+        assert!(!config.v2_transport);
+        assert_eq!(config.prune_target_mb, 0);
+        assert!(!config.tx_index);
+        assert!(!config.address_index);
+    }
 }
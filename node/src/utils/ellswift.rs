@@ -0,0 +1,406 @@
+use secp256k1::{ecdh, PublicKey, Secp256k1, SecretKey};
+
+/// Size of an ElligatorSwift encoded public key.
+pub const ELLSWIFT_SIZE: usize = 64;
+/// 2^256 - P, the field modulus of secp256k1.
+const FIELD_OFFSET: u64 = 0x1000003D1;
+const P: [u64; 4] = [
+    0xFFFFFFFEFFFFFC2F,
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+];
+const P_MINUS_2: [u64; 4] = [
+    0xFFFFFFFEFFFFFC2D,
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+];
+const P_PLUS_1_DIV_4: [u64; 4] = [
+    0xFFFFFFFFBFFFFF0C,
+    0xFFFFFFFFFFFFFFFF,
+    0xFFFFFFFFFFFFFFFF,
+    0x3FFFFFFFFFFFFFFF,
+];
+const CURVE_B: u64 = 7;
+const MAX_ENCODING_ATTEMPTS: usize = 1024;
+
+/// Element of the field of secp256k1, as four little endian 64 bit limbs smaller than P.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FieldElement([u64; 4]);
+
+impl FieldElement {
+    fn from_u64(value: u64) -> FieldElement {
+        FieldElement([value, 0, 0, 0])
+    }
+
+    /// Interprets the bytes as a big endian number, reduced modulo P.
+    fn from_bytes(bytes: &[u8]) -> FieldElement {
+        let mut limbs = [0; 4];
+        for (i, chunk) in bytes.chunks_exact(8).rev().enumerate() {
+            let mut limb = [0; 8];
+            limb.copy_from_slice(chunk);
+            limbs[i] = u64::from_be_bytes(limb);
+        }
+        FieldElement(limbs).reduced()
+    }
+
+    fn to_bytes(self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        for (i, limb) in self.0.iter().rev().enumerate() {
+            bytes[8 * i..8 * i + 8].copy_from_slice(&limb.to_be_bytes());
+        }
+        bytes
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    /// Subtracts P if the limbs are not smaller than it.
+    fn reduced(self) -> FieldElement {
+        let is_smaller = self
+            .0
+            .iter()
+            .rev()
+            .zip(P.iter().rev())
+            .find(|(a, b)| a != b);
+        match is_smaller {
+            Some((a, b)) if a < b => self,
+            _ => self.sub_p(),
+        }
+    }
+
+    fn sub_p(self) -> FieldElement {
+        let mut limbs = [0; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (value, borrow_1) = self.0[i].overflowing_sub(P[i]);
+            let (value, borrow_2) = value.overflowing_sub(borrow as u64);
+            *limb = value;
+            borrow = borrow_1 || borrow_2;
+        }
+        FieldElement(limbs)
+    }
+
+    fn add(self, other: FieldElement) -> FieldElement {
+        let mut limbs = [0; 4];
+        let mut carry = 0;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let value = self.0[i] as u128 + other.0[i] as u128 + carry;
+            *limb = value as u64;
+            carry = value >> 64;
+        }
+        if carry != 0 {
+            // 2^256 is FIELD_OFFSET modulo P, and the sum is smaller than P + 2^256.
+            let mut carry = FIELD_OFFSET as u128;
+            for limb in limbs.iter_mut() {
+                let value = *limb as u128 + carry;
+                *limb = value as u64;
+                carry = value >> 64;
+            }
+        }
+        FieldElement(limbs).reduced()
+    }
+
+    fn neg(self) -> FieldElement {
+        if self.is_zero() {
+            return self;
+        }
+        let mut limbs = [0; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (value, borrow_1) = P[i].overflowing_sub(self.0[i]);
+            let (value, borrow_2) = value.overflowing_sub(borrow as u64);
+            *limb = value;
+            borrow = borrow_1 || borrow_2;
+        }
+        FieldElement(limbs)
+    }
+
+    fn sub(self, other: FieldElement) -> FieldElement {
+        self.add(other.neg())
+    }
+
+    fn mul(self, other: FieldElement) -> FieldElement {
+        let mut wide = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0;
+            for j in 0..4 {
+                let value = wide[i + j] as u128 + self.0[i] as u128 * other.0[j] as u128 + carry;
+                wide[i + j] = value as u64;
+                carry = value >> 64;
+            }
+            wide[i + 4] = carry as u64;
+        }
+
+        // Folds the upper half, since 2^256 is FIELD_OFFSET modulo P.
+        let mut folded = [0u64; 5];
+        let mut carry = 0;
+        for i in 0..4 {
+            let value = wide[i] as u128 + wide[i + 4] as u128 * FIELD_OFFSET as u128 + carry;
+            folded[i] = value as u64;
+            carry = value >> 64;
+        }
+        folded[4] = carry as u64;
+
+        let mut limbs = [0; 4];
+        let mut carry = folded[4] as u128 * FIELD_OFFSET as u128;
+        for i in 0..4 {
+            let value = folded[i] as u128 + carry;
+            limbs[i] = value as u64;
+            carry = value >> 64;
+        }
+        let mut result = FieldElement(limbs);
+        if carry != 0 {
+            result = result.add(FieldElement::from_u64(FIELD_OFFSET));
+        }
+        result.reduced()
+    }
+
+    fn square(self) -> FieldElement {
+        self.mul(self)
+    }
+
+    fn pow(self, exponent: &[u64; 4]) -> FieldElement {
+        let mut result = FieldElement::from_u64(1);
+        for limb in exponent.iter().rev() {
+            for bit in (0..64).rev() {
+                result = result.square();
+                if (limb >> bit) & 1 == 1 {
+                    result = result.mul(self);
+                }
+            }
+        }
+        result
+    }
+
+    /// Multiplicative inverse. Zero has none, so it is returned as is.
+    fn inv(self) -> FieldElement {
+        self.pow(&P_MINUS_2)
+    }
+
+    fn div(self, other: FieldElement) -> FieldElement {
+        self.mul(other.inv())
+    }
+
+    /// Returns a square root of the element, if it is a square.
+    fn sqrt(self) -> Option<FieldElement> {
+        let root = self.pow(&P_PLUS_1_DIV_4);
+        if root.square() == self {
+            Some(root)
+        } else {
+            None
+        }
+    }
+
+    fn is_square(self) -> bool {
+        self.sqrt().is_some()
+    }
+}
+
+/// Returns u^3 + 7, the right side of the curve equation.
+fn curve_equation(x: FieldElement) -> FieldElement {
+    x.square().mul(x).add(FieldElement::from_u64(CURVE_B))
+}
+
+fn is_valid_x(x: FieldElement) -> bool {
+    curve_equation(x).is_square()
+}
+
+fn sqrt_minus_3() -> Option<FieldElement> {
+    FieldElement::from_u64(3).neg().sqrt()
+}
+
+/// Maps a pair of field elements to the X coordinate of a point of the curve (XSwiftEC, BIP324).
+fn xswiftec(mut u: FieldElement, mut t: FieldElement) -> Option<FieldElement> {
+    let one = FieldElement::from_u64(1);
+    let two = FieldElement::from_u64(2);
+    if u.is_zero() {
+        u = one;
+    }
+    if t.is_zero() {
+        t = one;
+    }
+    if curve_equation(u).add(t.square()).is_zero() {
+        t = t.add(t);
+    }
+    let x = curve_equation(u).sub(t.square()).div(two.mul(t));
+    let y = x.add(t).div(sqrt_minus_3()?.mul(u));
+
+    let candidates = [
+        u.add(FieldElement::from_u64(4).mul(y.square())),
+        x.neg().div(y).sub(u).div(two),
+        x.div(y).sub(u).div(two),
+    ];
+    candidates.into_iter().find(|x| is_valid_x(*x))
+}
+
+/// Returns a t such that xswiftec(u, t) is x, for one of the 8 cases of the inverse map, if it exists.
+fn xswiftec_inv(x: FieldElement, u: FieldElement, case: u8) -> Option<FieldElement> {
+    let two = FieldElement::from_u64(2);
+    let (v, s) = if case & 2 == 0 {
+        if is_valid_x(x.neg().sub(u)) {
+            return None;
+        }
+        let v = if case & 1 == 0 { x } else { x.neg().sub(u) };
+        let denominator = u.square().add(u.mul(v)).add(v.square());
+        (v, curve_equation(u).neg().div(denominator))
+    } else {
+        let s = x.sub(u);
+        if s.is_zero() {
+            return None;
+        }
+        let three_s_u2 = FieldElement::from_u64(3).mul(s).mul(u.square());
+        let four_g = FieldElement::from_u64(4).mul(curve_equation(u));
+        let mut r = s.neg().mul(four_g.add(three_s_u2)).sqrt()?;
+        if case & 1 == 1 {
+            if r.is_zero() {
+                return None;
+            }
+            r = r.neg();
+        }
+        (r.div(s).sub(u).div(two), s)
+    };
+
+    let w = s.sqrt()?;
+    let sqrt_minus_3 = sqrt_minus_3()?;
+    let one = FieldElement::from_u64(1);
+    let t = match case & 5 {
+        0 => w.neg().mul(u.mul(one.sub(sqrt_minus_3)).div(two).add(v)),
+        1 => w.mul(u.mul(one.add(sqrt_minus_3)).div(two).add(v)),
+        4 => w.mul(u.mul(one.sub(sqrt_minus_3)).div(two).add(v)),
+        _ => w.neg().mul(u.mul(one.add(sqrt_minus_3)).div(two).add(v)),
+    };
+    Some(t)
+}
+
+/// Decodes the 64 bytes of an ElligatorSwift encoding to the X coordinate of a public key.
+fn ellswift_decode(encoding: &[u8; ELLSWIFT_SIZE]) -> Option<FieldElement> {
+    let u = FieldElement::from_bytes(&encoding[..32]);
+    let t = FieldElement::from_bytes(&encoding[32..]);
+    xswiftec(u, t)
+}
+
+/// Encodes the public key of the secret key as 64 bytes that are indistinguishable from random
+/// ones (ElligatorSwift, BIP324). Returns None in the unlikely case no encoding is found.
+pub fn ellswift_create(secret_key: &SecretKey) -> Option<[u8; ELLSWIFT_SIZE]> {
+    let public_key = PublicKey::from_secret_key(&Secp256k1::new(), secret_key);
+    let x = FieldElement::from_bytes(&public_key.serialize()[1..]);
+    for _ in 0..MAX_ENCODING_ATTEMPTS {
+        let u = FieldElement::from_bytes(&rand::random::<[u8; 32]>());
+        let case = rand::random::<u8>() % 8;
+        let t = match xswiftec_inv(x, u, case) {
+            Some(t) => t,
+            None => continue,
+        };
+        if xswiftec(u, t) == Some(x) {
+            let mut encoding = [0; ELLSWIFT_SIZE];
+            encoding[..32].copy_from_slice(&u.to_bytes());
+            encoding[32..].copy_from_slice(&t.to_bytes());
+            return Some(encoding);
+        }
+    }
+    None
+}
+
+/// Returns the X coordinate of the secret key times the public key of the encoding, the shared
+/// secret of an x-only ECDH.
+pub fn ellswift_ecdh_x(encoding: &[u8; ELLSWIFT_SIZE], secret_key: &SecretKey) -> Option<[u8; 32]> {
+    let mut serialized_key = [2; 33];
+    serialized_key[1..].copy_from_slice(&ellswift_decode(encoding)?.to_bytes());
+    let public_key = PublicKey::from_slice(&serialized_key).ok()?;
+    let point = ecdh::shared_secret_point(&public_key, secret_key);
+    let mut x = [0; 32];
+    x.copy_from_slice(&point[..32]);
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::get_bytes_from_hex;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn decode_hex(u: &str, t: &str) -> Vec<u8> {
+        let mut encoding = [0; ELLSWIFT_SIZE];
+        encoding.copy_from_slice(&get_bytes_from_hex(format!("{u}{t}")).unwrap());
+        ellswift_decode(&encoding).unwrap().to_bytes().to_vec()
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn ellswift_test_1_decodes_like_the_reference_implementation() {
+        let zero = "0000000000000000000000000000000000000000000000000000000000000000";
+        let p_minus_1 = "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e";
+        let p_minus_2 = "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2d";
+
+        assert_eq!(
+            decode_hex(zero, zero),
+            get_bytes_from_hex(
+                "edd1fd3e327ce90cc7a3542614289aee9682003e9cf7dcc9cf2ca9743be5aa0c".to_string()
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            decode_hex(
+                "0000000000000000000000000000000000000000000000000000000000003039",
+                "0000000000000000000000000000000000000000000000000000000000010932"
+            ),
+            get_bytes_from_hex(
+                "12c1b96dbbdcba3f99772d3f6fc685f1609d32a060cc6b51b01d24ae7040ef2f".to_string()
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            decode_hex(p_minus_1, p_minus_2),
+            get_bytes_from_hex(
+                "55555555555555555555555555555555555555555555555555555554fffffeb1".to_string()
+            )
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn ellswift_test_2_inverse_map_results_decode_to_the_same_x() {
+        let x = FieldElement::from_bytes(
+            &get_bytes_from_hex(
+                "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798".to_string(),
+            )
+            .unwrap(),
+        );
+        let mut found = 0;
+        for seed in 1..20u64 {
+            let u = FieldElement::from_u64(seed).mul(FieldElement::from_u64(0x9e3779b97f4a7c15));
+            for case in 0..8 {
+                if let Some(t) = xswiftec_inv(x, u, case) {
+                    assert_eq!(xswiftec(u, t), Some(x));
+                    found += 1;
+                }
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn ellswift_test_3_both_sides_compute_the_same_shared_secret() {
+        let secret_key_a = SecretKey::from_slice(&[1; 32]).unwrap();
+        let secret_key_b = SecretKey::from_slice(&[2; 32]).unwrap();
+        let encoding_a = ellswift_create(&secret_key_a).unwrap();
+        let encoding_b = ellswift_create(&secret_key_b).unwrap();
+
+        let public_key_a = PublicKey::from_secret_key(&Secp256k1::new(), &secret_key_a);
+        assert_eq!(
+            ellswift_decode(&encoding_a).unwrap().to_bytes(),
+            public_key_a.serialize()[1..]
+        );
+        assert_eq!(
+            ellswift_ecdh_x(&encoding_b, &secret_key_a),
+            ellswift_ecdh_x(&encoding_a, &secret_key_b)
+        );
+    }
+}
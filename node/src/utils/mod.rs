@@ -1,5 +1,7 @@
 pub mod btc_errors;
+pub mod chacha20_poly1305;
 pub mod config;
+pub mod ellswift;
 pub mod log;
 pub mod mock_tcp_stream;
 pub mod ui_communication_protocol;
@@ -1,8 +1,10 @@
 use crate::blocks::proof::HashPair;
 use crate::blocks::BlockHeader;
 use crate::blocks::Outpoint;
+use crate::blocks::Transaction;
 use crate::utils::btc_errors::WalletError;
 use crate::wallet::Wallet;
+use std::{net::SocketAddr, time::Duration};
 
 pub const TX_PAGE_LENGTH: usize = 30;
 pub const BLOCK_PAGE_LENGTH: usize = 10;
@@ -13,13 +15,34 @@ pub enum UIRequest {
         /* amount*/ i64,
         /* fee*/ i64,
         /* address */ String,
+        /* selected utxos, None for automatic selection */ Option<Vec<Outpoint>>,
+        /* lock time, block height or unix timestamp, 0 to send it now */ u32,
     ), //ui manda en distintas bases el address, se fijan las longitudes
     ObtainTxProof(/*txhash */ [u8; 32], /*block number */ usize),
+    ObtainTxProofFromTxid(/*txhash */ [u8; 32]),
+    GetTransaction(/*txhash */ [u8; 32]),
+    GetAddressInfo(/* address*/ String),
     EndOfProgram,
     UpdateWallet,
     LastBlockInfo,
     NextBlockInfo,
     PrevBlockInfo,
+    CreatePaymentRequest(
+        /* amount*/ Option<i64>,
+        /* label*/ Option<String>,
+        /* message*/ Option<String>,
+    ),
+    SignMessage(/* message*/ String),
+    VerifyMessage(
+        /* address*/ String,
+        /* signature in base64*/ String,
+        /* message*/ String,
+    ),
+    SweepPrivKey(/* private key*/ String, /* fee*/ i64),
+    SetUtxoFrozen(Outpoint, /* frozen*/ bool),
+    GetPeerInfo,
+    DisconnectPeer(SocketAddr),
+    BanPeer(SocketAddr),
 }
 
 pub enum UIResponse {
@@ -32,6 +55,16 @@ pub enum UIResponse {
     TxSent,
     WalletFinished,
     LoadingScreenUpdate(LoadingScreenInfo),
+    PaymentRequest(PaymentRequestInfo),
+    MessageSigned(
+        /* address*/ String,
+        /* signature in base64*/ String,
+    ),
+    MessageVerified(bool),
+    ConnectionCount(/*outbound*/ usize, /*inbound*/ usize),
+    PeerInfo(Vec<PeerInfo>),
+    TransactionInfo(TransactionInfo),
+    AddressInfo(AddressInfo),
 }
 
 pub enum LoadingScreenInfo {
@@ -54,7 +87,9 @@ impl WalletInfo {
         let utxos = wallet
             .utxos
             .iter()
-            .map(|(outpoint, amount)| UTxOInfo::new(*outpoint, *amount))
+            .map(|(outpoint, amount)| {
+                UTxOInfo::new(*outpoint, *amount, wallet.frozen_utxos.contains(outpoint))
+            })
             .collect();
 
         WalletInfo {
@@ -71,11 +106,16 @@ impl WalletInfo {
 pub struct UTxOInfo {
     pub outpoint: Outpoint,
     pub amount: i64,
+    pub frozen: bool,
 }
 
 impl UTxOInfo {
-    pub fn new(outpoint: Outpoint, amount: i64) -> UTxOInfo {
-        UTxOInfo { outpoint, amount }
+    pub fn new(outpoint: Outpoint, amount: i64, frozen: bool) -> UTxOInfo {
+        UTxOInfo {
+            outpoint,
+            amount,
+            frozen,
+        }
     }
 }
 
@@ -99,6 +139,44 @@ impl BlockInfo {
     }
 }
 
+/// A transaction found by its txid. Pending transactions have no block number and 0 confirmations.
+pub struct TransactionInfo {
+    pub transaction: Transaction,
+    pub block_number: Option<usize>,
+    pub confirmations: usize,
+}
+
+impl TransactionInfo {
+    pub fn new(
+        transaction: Transaction,
+        block_number: Option<usize>,
+        confirmations: usize,
+    ) -> TransactionInfo {
+        TransactionInfo {
+            transaction,
+            block_number,
+            confirmations,
+        }
+    }
+}
+
+/// Balance, unspent outputs and history of an address, according to the indexed blocks.
+pub struct AddressInfo {
+    pub balance: i64,
+    pub utxos: Vec<(Outpoint, i64)>,
+    pub history: Vec<[u8; 32]>,
+}
+
+impl AddressInfo {
+    pub fn new(balance: i64, utxos: Vec<(Outpoint, i64)>, history: Vec<[u8; 32]>) -> AddressInfo {
+        AddressInfo {
+            balance,
+            utxos,
+            history,
+        }
+    }
+}
+
 #[derive(Clone, PartialEq)]
 pub struct TxInfo {
     pub hash: [u8; 32],
@@ -115,3 +193,48 @@ impl TxInfo {
         }
     }
 }
+
+/// Information about a connected peer. Most of it is what the peer announced in its version message.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PeerInfo {
+    pub address: SocketAddr,
+    pub user_agent: String,
+    pub version: i32,
+    pub services: u64,
+    pub start_height: i32,
+    pub best_known_height: usize,
+    pub outbound: bool,
+    pub connected_since: u32,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+    pub ping: Option<Duration>,
+    pub features: PeerFeatures,
+}
+
+/// Preferences a peer negotiated after its handshake.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PeerFeatures {
+    pub send_headers: bool,
+    /// Minimum fee rate, in satoshis per kilobyte, of the transactions the peer wants announced.
+    pub fee_filter: u64,
+    /// Highest compact blocks version the peer supports, 0 if it did not send any sendcmpct.
+    pub compact_blocks_version: u64,
+    pub compact_blocks_announce: bool,
+    pub wtxid_relay: bool,
+}
+
+pub struct PaymentRequestInfo {
+    pub address: String,
+    pub uri: String,
+    pub qr_code: Vec<Vec<bool>>,
+}
+
+impl PaymentRequestInfo {
+    pub fn new(address: String, uri: String, qr_code: Vec<Vec<bool>>) -> PaymentRequestInfo {
+        PaymentRequestInfo {
+            address,
+            uri,
+            qr_code,
+        }
+    }
+}
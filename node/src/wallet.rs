@@ -1,11 +1,15 @@
+pub mod bip21;
 pub mod handle_ui_requests;
+pub mod signed_message;
 
 use crate::node::Node;
 use crate::utils::ui_communication_protocol::TxInfo;
 use crate::{blocks::transaction::*, utils::WalletError};
+use bip21::encode_address;
 use bitcoin_hashes::{hash160, Hash};
 use secp256k1::{PublicKey, Secp256k1, SecretKey};
-use std::collections::HashMap;
+use signed_message::sign_message;
+use std::collections::{HashMap, HashSet};
 
 const BASE_58_CHAR_PRIV_KEY_LENGTH: usize = 52;
 const HEX_CHAR_PRIV_KEY_LENGTH: usize = 64;
@@ -18,6 +22,7 @@ pub struct Wallet {
     pub sending_pending_balance: i64,
     pub pending_tx: Vec<TxInfo>,
     pub utxos: HashMap<Outpoint, i64>,
+    pub frozen_utxos: HashSet<Outpoint>,
     current_block: usize,
 }
 
@@ -32,6 +37,7 @@ impl Wallet {
             sending_pending_balance: 0,
             pending_tx: Vec::new(),
             utxos: HashMap::new(),
+            frozen_utxos: HashSet::new(),
             current_block: 0,
         }
     }
@@ -40,6 +46,16 @@ impl Wallet {
         hash160::Hash::hash(&self.pub_key.serialize()).to_byte_array()
     }
 
+    /// Returns the testnet P2PKH address of the wallet in base58check.
+    pub fn get_address(&self) -> String {
+        encode_address(self.get_pk_hash())
+    }
+
+    /// Signs the message with the wallet private key, returning the base64 compact signature.
+    pub fn sign_message(&self, message: &str) -> String {
+        sign_message(&self.priv_key, message)
+    }
+
     /// Creates a wallet interpreting a string as a priv_key written in b58 or hex.
     pub fn from(priv_key_string: String) -> Result<Wallet, WalletError> {
         let priv_key = match priv_key_string.len() {
@@ -65,17 +81,68 @@ impl Wallet {
         Ok(Wallet::new(pub_key, priv_key))
     }
 
-    /// Creates a transaction and asks the node to send it
+    /// Freezes or unfreezes one of the wallet utxos. Frozen utxos are skipped by the
+    /// automatic selection, but they can still be spent if they are chosen explicitly.
+    pub fn set_utxo_frozen(&mut self, outpoint: Outpoint, frozen: bool) -> Result<(), WalletError> {
+        if !self.utxos.contains_key(&outpoint) {
+            return Err(WalletError::ErrorFindingUtxo);
+        }
+        if frozen {
+            self.frozen_utxos.insert(outpoint);
+        } else {
+            self.frozen_utxos.remove(&outpoint);
+        }
+        Ok(())
+    }
+
+    /// Returns the outpoints chosen by the user alongside the sum of their values,
+    /// checking that all of them belong to the wallet and that they cover the amount.
+    fn get_selected_utxos(
+        &self,
+        selected_outpoints: Vec<Outpoint>,
+        amount: i64,
+    ) -> Result<(Vec<Outpoint>, i64), WalletError> {
+        let mut unspent_balance = 0;
+        let mut unspent_outpoints: Vec<Outpoint> = Vec::new();
+
+        for outpoint in selected_outpoints {
+            if unspent_outpoints.contains(&outpoint) {
+                continue;
+            }
+            match self.utxos.get(&outpoint) {
+                Some(value) => unspent_balance += value,
+                None => return Err(WalletError::ErrorFindingUtxo),
+            }
+            unspent_outpoints.push(outpoint);
+        }
+
+        if unspent_balance < amount {
+            return Err(WalletError::ErrorNotEnoughSatoshis);
+        }
+        Ok((unspent_outpoints, unspent_balance))
+    }
+
+    /// Creates a transaction and asks the node to send it. If selected_outpoints is Some,
+    /// only those utxos are used as inputs, otherwise they are chosen automatically.
+    /// If the lock_time does not allow the transaction to be mined yet, the node holds it
+    /// until it does.
     pub fn create_transaction(
         &mut self,
         node: &mut Node,
         amount: i64,
         fee: i64,
         address: [u8; 25],
+        selected_outpoints: Option<Vec<Outpoint>>,
+        lock_time: u32,
     ) -> Result<(), WalletError> {
-        let (unspent_outpoints, unspent_balance) = node
-            .get_utxos_sum_up_to(amount + fee)
-            .map_err(|_| WalletError::ErrorNotEnoughSatoshis)?;
+        let (unspent_outpoints, unspent_balance) = match selected_outpoints {
+            Some(selected_outpoints) => {
+                self.get_selected_utxos(selected_outpoints, amount + fee)?
+            }
+            None => node
+                .get_utxos_sum_up_to(amount + fee, &self.frozen_utxos)
+                .map_err(|_| WalletError::ErrorNotEnoughSatoshis)?,
+        };
         let transaction = Transaction::create(
             amount,
             fee,
@@ -84,9 +151,19 @@ impl Wallet {
             self.pub_key,
             self.priv_key,
             address,
+            lock_time,
         )
         .map_err(|_| WalletError::ErrorCreatingTx)?;
 
+        let is_final = node
+            .is_final_for_next_block(&transaction)
+            .map_err(|_| WalletError::ErrorSendingTx)?;
+        if !is_final {
+            return node
+                .hold_transaction(self, transaction)
+                .map_err(|_| WalletError::ErrorSendingTx);
+        }
+
         node.logger
             .log("se empezo a enviar la transaccion".to_string());
         node.send_transaction(self, transaction)
@@ -95,6 +172,42 @@ impl Wallet {
         Ok(())
     }
 
+    /// Moves every utxo that belongs to the private key into this wallet, with a single
+    /// transaction that pays the fee. The swept key is only used to sign and is never stored.
+    pub fn sweep(
+        &mut self,
+        node: &mut Node,
+        priv_key_string: String,
+        fee: i64,
+    ) -> Result<(), WalletError> {
+        let swept_wallet = Wallet::from(priv_key_string)?;
+        let (swept_utxos, swept_balance) = node.get_utxo_balance(swept_wallet.get_pk_hash());
+        if swept_balance <= fee {
+            return Err(WalletError::ErrorNotEnoughSatoshis);
+        }
+        let mut address: [u8; 25] = [0; 25];
+        address[1..21].copy_from_slice(&self.get_pk_hash());
+
+        let transaction = Transaction::create_sweep(
+            fee,
+            swept_utxos.into_keys().collect(),
+            swept_balance,
+            swept_wallet.pub_key,
+            swept_wallet.priv_key,
+            address,
+        )
+        .map_err(|_| WalletError::ErrorCreatingTx)?;
+
+        node.logger.log(format!(
+            "Se barrieron {} satoshis hacia la wallet actual",
+            swept_balance - fee
+        ));
+        node.send_transaction(self, transaction)
+            .map_err(|_| WalletError::ErrorSendingTx)?;
+
+        Ok(())
+    }
+
     /// Updates the wallet information regarding unspent transactions
     pub fn update_pending_tx(&mut self, pending_tx_info: Vec<TxInfo>) {
         let mut new_pending_tx_info = Vec::new();
@@ -133,3 +246,61 @@ pub fn get_bytes_from_hex(hex_string: String) -> Result<Vec<u8>, Box<dyn std::er
     }
     Ok(result)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn wallet_with_utxos() -> Wallet {
+        let mut wallet = Wallet::from("07".repeat(32)).unwrap();
+        wallet.utxos.insert(Outpoint::new([1; 32], 0), 1000);
+        wallet.utxos.insert(Outpoint::new([2; 32], 1), 500);
+        wallet
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn wallet_test_1_selected_utxos_are_used_as_inputs() {
+        let wallet = wallet_with_utxos();
+        let selected = vec![Outpoint::new([2; 32], 1), Outpoint::new([2; 32], 1)];
+
+        let (outpoints, balance) = wallet.get_selected_utxos(selected, 400).unwrap();
+
+        assert_eq!(outpoints, vec![Outpoint::new([2; 32], 1)]);
+        assert_eq!(balance, 500);
+    }
+
+    #[test]
+    fn wallet_test_2_selected_utxos_must_cover_amount_and_belong_to_wallet() {
+        let wallet = wallet_with_utxos();
+
+        assert_eq!(
+            wallet.get_selected_utxos(vec![Outpoint::new([2; 32], 1)], 600),
+            Err(WalletError::ErrorNotEnoughSatoshis)
+        );
+        assert_eq!(
+            wallet.get_selected_utxos(vec![Outpoint::new([3; 32], 0)], 1),
+            Err(WalletError::ErrorFindingUtxo)
+        );
+    }
+
+    #[test]
+    fn wallet_test_3_only_wallet_utxos_can_be_frozen() {
+        let mut wallet = wallet_with_utxos();
+        let outpoint = Outpoint::new([1; 32], 0);
+
+        wallet.set_utxo_frozen(outpoint, true).unwrap();
+        assert!(wallet.frozen_utxos.contains(&outpoint));
+        wallet.set_utxo_frozen(outpoint, false).unwrap();
+        assert!(wallet.frozen_utxos.is_empty());
+        assert_eq!(
+            wallet.set_utxo_frozen(Outpoint::new([3; 32], 0), true),
+            Err(WalletError::ErrorFindingUtxo)
+        );
+    }
+}
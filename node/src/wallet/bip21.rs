@@ -0,0 +1,308 @@
+use crate::utils::btc_errors::WalletError;
+use bitcoin_hashes::{sha256d, Hash};
+use qrcode::{Color, QrCode};
+
+const URI_SCHEME: &str = "bitcoin:";
+const AMOUNT_PARAMETER: &str = "amount";
+const LABEL_PARAMETER: &str = "label";
+const MESSAGE_PARAMETER: &str = "message";
+const REQUIRED_PARAMETER_PREFIX: &str = "req-";
+const SATOSHIS_DECIMALS: usize = 8;
+const SATOSHIS_PER_BITCOIN: i64 = 100_000_000;
+const ADDRESS_BYTES_LENGTH: usize = 25;
+const CHECKSUM_LENGTH: usize = 4;
+pub const TESTNET_P2PKH_VERSION: u8 = 0x6f;
+
+/// Represents a BIP21 payment request: bitcoin:<address>[?amount=..&label=..&message=..]
+#[derive(Debug, PartialEq, Clone)]
+pub struct PaymentUri {
+    pub address: String,
+    pub amount: Option<i64>,
+    pub label: Option<String>,
+    pub message: Option<String>,
+}
+
+impl PaymentUri {
+    pub fn new(
+        address: String,
+        amount: Option<i64>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> PaymentUri {
+        PaymentUri {
+            address,
+            amount,
+            label,
+            message,
+        }
+    }
+
+    /// Parses a bitcoin: uri. The address must be a valid base58check address, the amount is
+    /// interpreted in bitcoins and stored in satoshis. Unknown parameters are ignored unless they
+    /// are prefixed with req-, in which case the uri is rejected as the standard requires.
+    pub fn parse(uri: &str) -> Result<PaymentUri, WalletError> {
+        let uri = uri.trim();
+        match uri.get(..URI_SCHEME.len()) {
+            Some(scheme) if scheme.eq_ignore_ascii_case(URI_SCHEME) => {}
+            _ => return Err(WalletError::ErrorParsingPaymentUri),
+        }
+        let uri = &uri[URI_SCHEME.len()..];
+        let (address, query) = match uri.split_once('?') {
+            Some((address, query)) => (address, query),
+            None => (uri, ""),
+        };
+        decode_address(address).map_err(|_| WalletError::ErrorParsingPaymentUri)?;
+
+        let mut payment_uri = PaymentUri::new(address.to_string(), None, None, None);
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (key, value) = match parameter.split_once('=') {
+                Some((key, value)) => (key, percent_decode(value)?),
+                None => (parameter, String::new()),
+            };
+            match key {
+                AMOUNT_PARAMETER => payment_uri.amount = Some(parse_btc_amount(&value)?),
+                LABEL_PARAMETER => payment_uri.label = Some(value),
+                MESSAGE_PARAMETER => payment_uri.message = Some(value),
+                _ => {
+                    if key.starts_with(REQUIRED_PARAMETER_PREFIX) {
+                        return Err(WalletError::ErrorParsingPaymentUri);
+                    }
+                }
+            }
+        }
+        Ok(payment_uri)
+    }
+
+    /// Returns the uri as a string, percent encoding the label and message.
+    pub fn to_uri_string(&self) -> String {
+        let mut parameters = Vec::new();
+        if let Some(amount) = self.amount {
+            parameters.push(format!(
+                "{}={}",
+                AMOUNT_PARAMETER,
+                format_btc_amount(amount)
+            ));
+        }
+        if let Some(label) = &self.label {
+            parameters.push(format!("{}={}", LABEL_PARAMETER, percent_encode(label)));
+        }
+        if let Some(message) = &self.message {
+            parameters.push(format!("{}={}", MESSAGE_PARAMETER, percent_encode(message)));
+        }
+
+        let mut uri = format!("{}{}", URI_SCHEME, self.address);
+        if !parameters.is_empty() {
+            uri.push('?');
+            uri.push_str(&parameters.join("&"));
+        }
+        uri
+    }
+
+    /// Returns the modules of the QR code that represents the uri, row by row.
+    /// True means a dark module.
+    pub fn to_qr_code(&self) -> Result<Vec<Vec<bool>>, WalletError> {
+        let code = QrCode::new(self.to_uri_string().as_bytes())
+            .map_err(|_| WalletError::ErrorCreatingQrCode)?;
+        let width = code.width();
+        let modules = code
+            .to_colors()
+            .chunks(width)
+            .map(|row| row.iter().map(|color| *color == Color::Dark).collect())
+            .collect();
+        Ok(modules)
+    }
+}
+
+/// Decodes a base58check address, verifying its length and checksum.
+pub fn decode_address(address: &str) -> Result<[u8; ADDRESS_BYTES_LENGTH], WalletError> {
+    let address_bytes = bs58::decode(address)
+        .into_vec()
+        .map_err(|_| WalletError::ErrorHandlingAddress)?;
+    if address_bytes.len() != ADDRESS_BYTES_LENGTH {
+        return Err(WalletError::ErrorHandlingAddress);
+    }
+    let payload_length = ADDRESS_BYTES_LENGTH - CHECKSUM_LENGTH;
+    let checksum = sha256d::Hash::hash(&address_bytes[..payload_length]).to_byte_array();
+    if checksum[..CHECKSUM_LENGTH] != address_bytes[payload_length..] {
+        return Err(WalletError::ErrorHandlingAddress);
+    }
+    let mut address: [u8; ADDRESS_BYTES_LENGTH] = [0; ADDRESS_BYTES_LENGTH];
+    address.copy_from_slice(&address_bytes);
+    Ok(address)
+}
+
+/// Returns the base58check testnet address that corresponds to the pk hash.
+pub fn encode_address(pk_hash: [u8; 20]) -> String {
+    let mut address_bytes = vec![TESTNET_P2PKH_VERSION];
+    address_bytes.extend(pk_hash);
+    let checksum = sha256d::Hash::hash(&address_bytes).to_byte_array();
+    address_bytes.extend(&checksum[..CHECKSUM_LENGTH]);
+    bs58::encode(address_bytes).into_string()
+}
+
+/// Parses a decimal bitcoin amount into satoshis without going through floating point.
+fn parse_btc_amount(amount: &str) -> Result<i64, WalletError> {
+    let (integer_part, decimal_part) = match amount.split_once('.') {
+        Some((integer_part, decimal_part)) => (integer_part, decimal_part),
+        None => (amount, ""),
+    };
+    if (integer_part.is_empty() && decimal_part.is_empty())
+        || decimal_part.len() > SATOSHIS_DECIMALS
+        || !integer_part.chars().all(|c| c.is_ascii_digit())
+        || !decimal_part.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(WalletError::ErrorParsingPaymentUri);
+    }
+    let mut bitcoins = 0;
+    if !integer_part.is_empty() {
+        bitcoins = integer_part
+            .parse::<i64>()
+            .map_err(|_| WalletError::ErrorParsingPaymentUri)?;
+    }
+    let decimals = format!("{:0<width$}", decimal_part, width = SATOSHIS_DECIMALS)
+        .parse::<i64>()
+        .map_err(|_| WalletError::ErrorParsingPaymentUri)?;
+
+    bitcoins
+        .checked_mul(SATOSHIS_PER_BITCOIN)
+        .and_then(|satoshis| satoshis.checked_add(decimals))
+        .ok_or(WalletError::ErrorParsingPaymentUri)
+}
+
+/// Formats satoshis as a decimal bitcoin amount, without trailing zeros.
+fn format_btc_amount(amount: i64) -> String {
+    let bitcoins = amount / SATOSHIS_PER_BITCOIN;
+    let decimals = amount % SATOSHIS_PER_BITCOIN;
+    if decimals == 0 {
+        return bitcoins.to_string();
+    }
+    let decimals = format!("{:0>width$}", decimals, width = SATOSHIS_DECIMALS);
+    format!("{}.{}", bitcoins, decimals.trim_end_matches('0'))
+}
+
+/// Percent encodes every character that is not unreserved acording to RFC 3986.
+fn percent_encode(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Decodes a percent encoded string. A '+' is kept as is, as BIP21 does not treat it as a space.
+fn percent_decode(value: &str) -> Result<String, WalletError> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = bytes
+                .get(i + 1..i + 3)
+                .ok_or(WalletError::ErrorParsingPaymentUri)?;
+            let hex = std::str::from_utf8(hex).map_err(|_| WalletError::ErrorParsingPaymentUri)?;
+            let byte =
+                u8::from_str_radix(hex, 16).map_err(|_| WalletError::ErrorParsingPaymentUri)?;
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| WalletError::ErrorParsingPaymentUri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Auxiliar functions
+    //=================================================================
+
+    const ADDRESS: &str = "mnJvq7mbGiPNNhUne4FAqq27Q8xZrAsVun";
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn bip21_test_1_parses_uri_with_every_parameter() {
+        let uri = format!(
+            "bitcoin:{}?amount=20.3&label=Luke-Jr&message=Donation%20for%20project%20xyz",
+            ADDRESS
+        );
+
+        let payment_uri = PaymentUri::parse(&uri).unwrap();
+
+        assert_eq!(payment_uri.address, ADDRESS);
+        assert_eq!(payment_uri.amount, Some(2_030_000_000));
+        assert_eq!(payment_uri.label, Some("Luke-Jr".to_string()));
+        assert_eq!(
+            payment_uri.message,
+            Some("Donation for project xyz".to_string())
+        );
+    }
+
+    #[test]
+    fn bip21_test_2_rejects_invalid_uris() {
+        let invalid_uris = [
+            format!("bitcoin{}", ADDRESS),
+            format!("bitcoin:{}x", ADDRESS),
+            format!("bitcoin:{}?amount=1.123456789", ADDRESS),
+            format!("bitcoin:{}?amount=-1", ADDRESS),
+            format!("bitcoin:{}?req-somethingyoudontunderstand=50", ADDRESS),
+        ];
+
+        for uri in invalid_uris {
+            assert_eq!(
+                PaymentUri::parse(&uri),
+                Err(WalletError::ErrorParsingPaymentUri)
+            );
+        }
+    }
+
+    #[test]
+    fn bip21_test_3_generated_uri_can_be_parsed_back() {
+        let payment_uri = PaymentUri::new(
+            ADDRESS.to_string(),
+            Some(50_000),
+            Some("Papas Rusticas".to_string()),
+            Some("Pago & cobro".to_string()),
+        );
+
+        let uri = payment_uri.to_uri_string();
+
+        assert_eq!(
+            uri,
+            format!(
+                "bitcoin:{}?amount=0.0005&label=Papas%20Rusticas&message=Pago%20%26%20cobro",
+                ADDRESS
+            )
+        );
+        assert_eq!(PaymentUri::parse(&uri).unwrap(), payment_uri);
+    }
+
+    #[test]
+    fn bip21_test_4_address_encoding_matches_decoding() {
+        let address = decode_address(ADDRESS).unwrap();
+        let mut pk_hash: [u8; 20] = [0; 20];
+        pk_hash.copy_from_slice(&address[1..21]);
+
+        assert_eq!(address[0], TESTNET_P2PKH_VERSION);
+        assert_eq!(encode_address(pk_hash), ADDRESS);
+    }
+
+    #[test]
+    fn bip21_test_5_qr_code_is_square() {
+        let payment_uri = PaymentUri::new(ADDRESS.to_string(), Some(1), None, None);
+
+        let qr_code = payment_uri.to_qr_code().unwrap();
+
+        assert!(!qr_code.is_empty());
+        assert!(qr_code.iter().all(|row| row.len() == qr_code.len()));
+    }
+}
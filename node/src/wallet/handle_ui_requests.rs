@@ -1,6 +1,11 @@
+use super::bip21::{decode_address, PaymentUri};
+use super::signed_message::verify_message;
 use super::Wallet;
+use crate::blocks::{HashPair, Outpoint};
 use crate::node::Node;
-use crate::utils::ui_communication_protocol::{UIRequest, UIResponse, WalletInfo};
+use crate::utils::ui_communication_protocol::{
+    PaymentRequestInfo, UIRequest, UIResponse, WalletInfo,
+};
 use crate::utils::NodeError;
 use crate::utils::WalletError;
 use glib::Sender as GlibSender;
@@ -24,8 +29,8 @@ impl Wallet {
                     Err(wallet_error) => Err(wallet_error),
                 }
             }
-            UIRequest::CreateTx(amount, fee, address) => {
-                self.handle_create_tx(node, amount, fee, address)
+            UIRequest::CreateTx(amount, fee, address, selected_outpoints, lock_time) => {
+                self.handle_create_tx(node, amount, fee, address, selected_outpoints, lock_time)
             }
             UIRequest::UpdateWallet => self.handle_update_wallet(node),
             UIRequest::LastBlockInfo => self.handle_last_block_info(node),
@@ -34,6 +39,36 @@ impl Wallet {
             UIRequest::ObtainTxProof(hash, block_index) => {
                 self.handle_obtain_tx_proof(node, hash, block_index)
             }
+            UIRequest::ObtainTxProofFromTxid(hash) => {
+                self.handle_obtain_tx_proof_from_txid(node, hash)
+            }
+            UIRequest::GetTransaction(hash) => handle_get_transaction(node, hash),
+            UIRequest::GetAddressInfo(address) => handle_get_address_info(node, &address),
+            UIRequest::CreatePaymentRequest(amount, label, message) => {
+                self.handle_create_payment_request(amount, label, message)
+            }
+            UIRequest::SignMessage(message) => Ok(UIResponse::MessageSigned(
+                self.get_address(),
+                self.sign_message(&message),
+            )),
+            UIRequest::VerifyMessage(address, signature, message) => {
+                verify_message(&address, &signature, &message).map(UIResponse::MessageVerified)
+            }
+            UIRequest::SweepPrivKey(priv_key_string, fee) => {
+                self.handle_sweep_priv_key(node, priv_key_string, fee)
+            }
+            UIRequest::SetUtxoFrozen(outpoint, frozen) => self
+                .set_utxo_frozen(outpoint, frozen)
+                .and_then(|_| self.handle_update_wallet(node)),
+            UIRequest::GetPeerInfo => handle_get_peer_info(node),
+            UIRequest::DisconnectPeer(peer_address) => node
+                .disconnect_peer(peer_address)
+                .map_err(|_| WalletError::ErrorFindingPeer)
+                .and_then(|_| handle_get_peer_info(node)),
+            UIRequest::BanPeer(peer_address) => node
+                .ban_peer(peer_address)
+                .map_err(|_| WalletError::ErrorBanningPeer)
+                .and_then(|_| handle_get_peer_info(node)),
             UIRequest::EndOfProgram => {
                 *program_running = false;
                 return Ok(self);
@@ -61,6 +96,8 @@ impl Wallet {
             }
             _ => WalletError::ErrorUpdatingWallet,
         })?;
+        self.frozen_utxos
+            .retain(|outpoint| self.utxos.contains_key(outpoint));
 
         let wallet_info = WalletInfo::from(self);
 
@@ -125,26 +162,65 @@ impl Wallet {
     }
 
     /// Creates and sends a transaction to the receiver address of value amount and fee.
+    /// If the user selected utxos, only those are spent. A lock_time different from 0
+    /// makes the node hold the transaction until it can be mined.
     fn handle_create_tx(
         &mut self,
         node: &mut Node,
         amount: i64,
         fee: i64,
         receiver_address: String,
+        selected_outpoints: Option<Vec<Outpoint>>,
+        lock_time: u32,
     ) -> Result<UIResponse, WalletError> {
         if amount + fee <= 0 {
             return Err(WalletError::InvalidAmount);
         }
-        let address_bytes = bs58::decode(receiver_address)
-            .into_vec()
-            .map_err(|_| WalletError::ErrorSendingTx)?;
-        let mut address: [u8; 25] = [0; 25];
-        address.copy_from_slice(&address_bytes);
-        self.create_transaction(node, amount, fee, address)?;
+        let address = decode_address(&receiver_address)?;
+        self.create_transaction(node, amount, fee, address, selected_outpoints, lock_time)?;
 
         Ok(UIResponse::TxSent)
     }
 
+    /// Sweeps the funds of the private key into the current wallet.
+    fn handle_sweep_priv_key(
+        &mut self,
+        node: &mut Node,
+        priv_key_string: String,
+        fee: i64,
+    ) -> Result<UIResponse, WalletError> {
+        if fee < 0 {
+            return Err(WalletError::InvalidAmount);
+        }
+        self.sweep(node, priv_key_string, fee)?;
+
+        Ok(UIResponse::TxSent)
+    }
+
+    /// Builds a BIP21 uri for the wallet address with the optional amount, label and message,
+    /// alongside the QR code that represents it.
+    fn handle_create_payment_request(
+        &self,
+        amount: Option<i64>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> Result<UIResponse, WalletError> {
+        if let Some(amount) = amount {
+            if amount <= 0 {
+                return Err(WalletError::InvalidAmount);
+            }
+        }
+        let address = self.get_address();
+        let payment_uri = PaymentUri::new(address.clone(), amount, label, message);
+        let qr_code = payment_uri.to_qr_code()?;
+
+        Ok(UIResponse::PaymentRequest(PaymentRequestInfo::new(
+            address,
+            payment_uri.to_uri_string(),
+            qr_code,
+        )))
+    }
+
     /// Requests the merkle proof of inclution to the node and verifies it.
     pub fn handle_obtain_tx_proof(
         &self,
@@ -156,23 +232,82 @@ impl Wallet {
             .get_merkle_tx_proof(tx_hash, block_index)
             .map_err(|_| WalletError::ErrorObtainingTxProof)?;
 
-        let mut prev_hash = tx_hash;
+        Ok(verify_tx_proof(tx_hash, merkle_proof, merkle_root))
+    }
+
+    /// Requests the merkle proof of inclution of a transaction to the node, which finds its block
+    /// in the transaction index, and verifies it.
+    pub fn handle_obtain_tx_proof_from_txid(
+        &self,
+        node: &Node,
+        tx_hash: [u8; 32],
+    ) -> Result<UIResponse, WalletError> {
+        let (merkle_proof, merkle_root) = node
+            .get_merkle_tx_proof_from_txid(tx_hash)
+            .map_err(|error| tx_lookup_error(error, WalletError::ErrorObtainingTxProof))?;
+
+        Ok(verify_tx_proof(tx_hash, merkle_proof, merkle_root))
+    }
+}
 
-        for hash_pair in &merkle_proof {
-            if hash_pair.equals_path_side(prev_hash) {
-                prev_hash = hash_pair.hash();
-            } else {
-                return Ok(UIResponse::ResultOFTXProof(None));
-            }
-        }
+/// Verifies that the merkle proof leads from the transaction to the merkle root, returning the
+/// proof inside a UIResponse if it does.
+fn verify_tx_proof(
+    tx_hash: [u8; 32],
+    merkle_proof: Vec<HashPair>,
+    merkle_root: [u8; 32],
+) -> UIResponse {
+    let mut prev_hash = tx_hash;
 
-        if prev_hash != merkle_root {
-            return Ok(UIResponse::ResultOFTXProof(None));
+    for hash_pair in &merkle_proof {
+        if hash_pair.equals_path_side(prev_hash) {
+            prev_hash = hash_pair.hash();
+        } else {
+            return UIResponse::ResultOFTXProof(None);
         }
+    }
 
-        Ok(UIResponse::ResultOFTXProof(Some((
-            merkle_proof,
-            merkle_root,
-        ))))
+    if prev_hash != merkle_root {
+        return UIResponse::ResultOFTXProof(None);
     }
+
+    UIResponse::ResultOFTXProof(Some((merkle_proof, merkle_root)))
+}
+
+/// Returns the transaction with the given txid inside a UIResponse, alongside its confirmations.
+fn handle_get_transaction(node: &Node, tx_hash: [u8; 32]) -> Result<UIResponse, WalletError> {
+    node.get_transaction_info(tx_hash)
+        .map(UIResponse::TransactionInfo)
+        .map_err(|error| tx_lookup_error(error, WalletError::ErrorFindingTransaction))
+}
+
+/// Returns the balance, utxos and history of any address inside a UIResponse.
+fn handle_get_address_info(node: &Node, address: &str) -> Result<UIResponse, WalletError> {
+    let address = decode_address(address)?;
+    let mut pk_hash = [0; 20];
+    pk_hash.copy_from_slice(&address[1..21]);
+
+    node.get_address_info(pk_hash)
+        .map(UIResponse::AddressInfo)
+        .map_err(|error| match error {
+            NodeError::ErrorAddressIndexDisabled => WalletError::ErrorAddressIndexDisabled,
+            _ => WalletError::ErrorHandlingAddress,
+        })
+}
+
+/// Converts the error of looking up a transaction by its txid into the one shown to the user,
+/// which is the given one if the lookup itself did not fail.
+fn tx_lookup_error(error: NodeError, other_error: WalletError) -> WalletError {
+    match error {
+        NodeError::ErrorTxIndexDisabled => WalletError::ErrorTxIndexDisabled,
+        NodeError::ErrorFindingTransaction => WalletError::ErrorFindingTransaction,
+        _ => other_error,
+    }
+}
+
+/// Returns the information of the peers the node is connected to inside a UIResponse.
+fn handle_get_peer_info(node: &Node) -> Result<UIResponse, WalletError> {
+    node.get_peer_info()
+        .map(UIResponse::PeerInfo)
+        .map_err(|_| WalletError::ErrorGettingPeerInfo)
 }
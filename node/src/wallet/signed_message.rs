@@ -0,0 +1,208 @@
+use super::bip21::decode_address;
+use crate::utils::btc_errors::WalletError;
+use crate::utils::variable_length_integer::VarLenInt;
+use bitcoin_hashes::{hash160, sha256d, Hash};
+use secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use secp256k1::{Message, Secp256k1, SecretKey};
+
+const MESSAGE_MAGIC: &str = "Bitcoin Signed Message:\n";
+const COMPACT_SIGNATURE_LENGTH: usize = 65;
+const HEADER_BASE: u8 = 27;
+const COMPRESSED_KEY_FLAG: u8 = 4;
+const MAX_HEADER: u8 = HEADER_BASE + COMPRESSED_KEY_FLAG + 3;
+const BASE_64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE_64_PADDING: u8 = b'=';
+
+/// Returns the message hashed as the standard requires, prefixing it with the magic string
+/// and both lengths as variable length integers.
+fn signed_message_hash(message: &str) -> Message {
+    let mut bytes = VarLenInt::new(MESSAGE_MAGIC.len()).to_bytes();
+    bytes.extend(MESSAGE_MAGIC.as_bytes());
+    bytes.extend(VarLenInt::new(message.len()).to_bytes());
+    bytes.extend(message.as_bytes());
+
+    Message::from_hashed_data::<sha256d::Hash>(&bytes)
+}
+
+/// Signs the message with the private key, returning the compact recoverable signature
+/// encoded in base64. The header byte indicates the recovery id and that the key is compressed.
+pub fn sign_message(priv_key: &SecretKey, message: &str) -> String {
+    let signature =
+        Secp256k1::new().sign_ecdsa_recoverable(&signed_message_hash(message), priv_key);
+    let (recovery_id, compact_signature) = signature.serialize_compact();
+
+    let mut bytes = vec![HEADER_BASE + COMPRESSED_KEY_FLAG + recovery_id.to_i32() as u8];
+    bytes.extend(compact_signature);
+    base64_encode(&bytes)
+}
+
+/// Recovers the public key from the signature and checks that it corresponds to the
+/// P2PKH address. Returns Ok(false) if the signature is well formed but does not match.
+pub fn verify_message(address: &str, signature: &str, message: &str) -> Result<bool, WalletError> {
+    let address = decode_address(address)?;
+    let bytes = base64_decode(signature.trim())?;
+    if bytes.len() != COMPACT_SIGNATURE_LENGTH || bytes[0] < HEADER_BASE || bytes[0] > MAX_HEADER {
+        return Err(WalletError::ErrorHandlingSignature);
+    }
+    let header = bytes[0] - HEADER_BASE;
+    let compressed = header & COMPRESSED_KEY_FLAG != 0;
+    let recovery_id = RecoveryId::from_i32((header & 3) as i32)
+        .map_err(|_| WalletError::ErrorHandlingSignature)?;
+    let signature = RecoverableSignature::from_compact(&bytes[1..], recovery_id)
+        .map_err(|_| WalletError::ErrorHandlingSignature)?;
+
+    let pub_key = match Secp256k1::new().recover_ecdsa(&signed_message_hash(message), &signature) {
+        Ok(pub_key) => pub_key,
+        Err(_) => return Ok(false),
+    };
+    let mut pk_hash = hash160::Hash::hash(&pub_key.serialize_uncompressed());
+    if compressed {
+        pk_hash = hash160::Hash::hash(&pub_key.serialize());
+    }
+
+    Ok(pk_hash.to_byte_array() == address[1..21])
+}
+
+/// Encodes the bytes in base64 with padding.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let mut group: [u8; 3] = [0; 3];
+        group[..chunk.len()].copy_from_slice(chunk);
+        let value = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (value >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE_64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push(BASE_64_PADDING as char);
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes a base64 string with padding. The padding is only accepted at the end of the last group.
+fn base64_decode(encoded: &str) -> Result<Vec<u8>, WalletError> {
+    let chunks = encoded.as_bytes().chunks_exact(4);
+    if !chunks.remainder().is_empty() {
+        return Err(WalletError::ErrorHandlingSignature);
+    }
+    let chunk_amount = chunks.len();
+    let mut decoded = Vec::new();
+    for (i, chunk) in chunks.enumerate() {
+        let mut value: u32 = 0;
+        let mut padding = 0;
+        for character in chunk {
+            let index = match BASE_64_ALPHABET.iter().position(|c| c == character) {
+                Some(_) if padding > 0 => return Err(WalletError::ErrorHandlingSignature),
+                Some(index) => index as u32,
+                None if *character == BASE_64_PADDING => {
+                    padding += 1;
+                    0
+                }
+                None => return Err(WalletError::ErrorHandlingSignature),
+            };
+            value = (value << 6) | index;
+        }
+        if padding > 2 || (padding > 0 && i + 1 < chunk_amount) {
+            return Err(WalletError::ErrorHandlingSignature);
+        }
+        decoded.extend(&value.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::bip21::encode_address;
+
+    const CORE_PRIV_KEY: &str = "cUeKHd5orzT3mz8P9pxyREHfsWtVfgsfDjiZZBcjUBAaGk1BTj7N";
+    const CORE_ADDRESS: &str = "mpLQjfK79b7CCV4VMJWEWAj5Mpx8Up5zxB";
+    const CORE_MESSAGE: &str = "This is just a test message";
+    const CORE_SIGNATURE: &str =
+        "INbVnW4e6PeRmsv2Qgu8NuopvrVjkcxob+sX8OcZG0SALhWybUjzMLPdAsXI46YZGb0KQTRii+wWIQzRpG/U+S0=";
+
+    // Auxiliar functions
+    //=================================================================
+
+    fn priv_key() -> SecretKey {
+        SecretKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn address() -> String {
+        let pub_key = priv_key().public_key(&Secp256k1::new());
+        encode_address(hash160::Hash::hash(&pub_key.serialize()).to_byte_array())
+    }
+
+    fn core_priv_key() -> SecretKey {
+        let mut bytes = bs58::decode(CORE_PRIV_KEY).into_vec().unwrap();
+        bytes.remove(0);
+        bytes.truncate(bytes.len() - 5);
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    // Tests
+    //=================================================================
+
+    #[test]
+    fn signed_message_test_1_signature_can_be_verified() {
+        let signature = sign_message(&priv_key(), "Papas Rusticas");
+
+        assert_eq!(base64_decode(&signature).unwrap().len(), 65);
+        assert!(verify_message(&address(), &signature, "Papas Rusticas").unwrap());
+    }
+
+    #[test]
+    fn signed_message_test_2_signature_does_not_verify_other_message() {
+        let signature = sign_message(&priv_key(), "Papas Rusticas");
+
+        assert!(!verify_message(&address(), &signature, "Papas Fritas").unwrap());
+    }
+
+    #[test]
+    fn signed_message_test_3_malformed_signature_is_an_error() {
+        assert_eq!(
+            verify_message(&address(), "not a signature", "Papas Rusticas"),
+            Err(WalletError::ErrorHandlingSignature)
+        );
+    }
+
+    #[test]
+    fn signed_message_test_4_base64_round_trip() {
+        for length in 0..7 {
+            let bytes: Vec<u8> = (0..length).map(|i| i * 51).collect();
+
+            assert_eq!(base64_decode(&base64_encode(&bytes)).unwrap(), bytes);
+        }
+        assert_eq!(base64_encode(b"Man"), "TWFu");
+        assert_eq!(base64_encode(b"Ma"), "TWE=");
+    }
+
+    #[test]
+    fn signed_message_test_5_padding_is_only_accepted_at_the_end() {
+        assert_eq!(base64_decode("TWE=").unwrap(), b"Ma");
+        assert_eq!(
+            base64_decode("TWE=TWFu"),
+            Err(WalletError::ErrorHandlingSignature)
+        );
+        assert_eq!(
+            base64_decode("TW=u"),
+            Err(WalletError::ErrorHandlingSignature)
+        );
+        assert_eq!(
+            base64_decode("T==="),
+            Err(WalletError::ErrorHandlingSignature)
+        );
+    }
+
+    #[test]
+    fn signed_message_test_6_matches_a_signature_made_by_bitcoin_core() {
+        // Vector from Bitcoin Core's signmessage functional test.
+        assert!(verify_message(CORE_ADDRESS, CORE_SIGNATURE, CORE_MESSAGE).unwrap());
+        assert_eq!(sign_message(&core_priv_key(), CORE_MESSAGE), CORE_SIGNATURE);
+        assert!(!verify_message(CORE_ADDRESS, CORE_SIGNATURE, "This is just a test").unwrap());
+    }
+}
@@ -1,13 +1,17 @@
 use crate::activate_adjustments;
 use crate::error_handling::*;
 use crate::loading_screen::*;
+use crate::node_peers::{activate_refresh_peers_button, handle_connection_count, handle_peer_info};
 use crate::utils::node_status::NodeStatus;
 use crate::wallet_actions::*;
 use crate::wallet_adder::*;
+use crate::wallet_coin_control::update_coin_control_list;
+use crate::wallet_receive::{activate_receive_button, handle_payment_request};
 use crate::wallet_send::{
-    activate_clear_all_button, activate_send_button, activate_use_available_balance,
-    update_adjustments_max_value,
+    activate_clear_all_button, activate_pay_to_entry, activate_send_button, activate_sweep_button,
+    activate_use_available_balance, update_adjustments_max_value,
 };
+use crate::wallet_signed_message::*;
 use crate::wallet_transactions::*;
 use glib::Receiver as GlibReceiver;
 use gtk::prelude::*;
@@ -31,7 +35,10 @@ fn ui_response_to_message(
 ) {
     match action {
         UIResponse::ResultOFTXProof(result) => handle_result_of_tx_proof(&builder, result),
-        UIResponse::WalletInfo(wallet_info) => handle_wallet_info(&wallet_info, &builder),
+        UIResponse::WalletInfo(wallet_info) => {
+            handle_wallet_info(&wallet_info, &builder);
+            update_coin_control_list(&builder, &wallet_info.utxos, sender);
+        }
         UIResponse::BlockInfo(block_info) => handle_block_info(&block_info, &builder),
         UIResponse::FinishedInitializingNode => {
             if let Err(error) = start_window(&app, &builder, sender, node_status) {
@@ -47,6 +54,24 @@ fn ui_response_to_message(
         UIResponse::LoadingScreenUpdate(progress) => {
             handle_loading_screen_update(&builder, progress)
         }
+        UIResponse::PaymentRequest(payment_request) => {
+            handle_payment_request(&builder, &payment_request)
+        }
+        UIResponse::MessageSigned(address, signature) => {
+            handle_message_signed(&builder, &address, &signature)
+        }
+        UIResponse::MessageVerified(valid) => handle_message_verified(&builder, valid),
+        UIResponse::ConnectionCount(outbound, inbound) => {
+            handle_connection_count(&builder, outbound, inbound);
+            if sender.send(UIRequest::GetPeerInfo).is_err() {
+                eprintln!("Error requesting peer info to Node/Wallet thread");
+            }
+        }
+        UIResponse::PeerInfo(peers) => handle_peer_info(&builder, &peers, sender),
+        UIResponse::TransactionInfo(transaction_info) => {
+            handle_transaction_info(&builder, &transaction_info)
+        }
+        UIResponse::AddressInfo(address_info) => handle_address_info(&builder, &address_info),
     }
 }
 
@@ -138,9 +163,15 @@ fn initialize_elements(builder: &Builder, sender: &Sender<UIRequest>) {
     initialize_wallet_adder_actions(builder, sender);
     connect_block_switcher_buttons(builder, sender);
     activate_send_button(builder, sender);
+    activate_pay_to_entry(builder);
+    activate_sweep_button(builder, sender);
+    activate_receive_button(builder, sender);
+    activate_signed_message_dialog(builder, sender);
+    activate_refresh_peers_button(builder, sender);
     initialize_wallet_selector(builder, sender);
     initialize_change_wallet(builder, sender);
     initialize_merkle_proof_button(builder, sender);
+    initialize_tx_lookup_buttons(builder, sender);
     update_adjustments_max_value(builder);
 }
 
@@ -1,9 +1,17 @@
+pub mod node_peers;
 pub mod wallet_adder;
+pub mod wallet_coin_control;
 pub mod wallet_overview;
+pub mod wallet_receive;
 pub mod wallet_send;
+pub mod wallet_signed_message;
 pub mod wallet_transactions;
 
+pub use node_peers::*;
 pub use wallet_adder::*;
+pub use wallet_coin_control::*;
 pub use wallet_overview::*;
+pub use wallet_receive::*;
 pub use wallet_send::*;
+pub use wallet_signed_message::*;
 pub use wallet_transactions::*;
@@ -0,0 +1,119 @@
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use gtk::prelude::*;
+use gtk::{Align, Box, Builder, Button, Label, ListBox, Orientation};
+use node::utils::ui_communication_protocol::{PeerInfo, UIRequest};
+use std::sync::mpsc::Sender;
+
+const PEERS_SEND_ERROR: &str = "Error sending peers request to Node/Wallet thread";
+const BYTES_IN_KB: f64 = 1024.0;
+
+/// Shows the amount of outbound and inbound peers the node is connected to
+pub fn handle_connection_count(builder: &Builder, outbound: usize, inbound: usize) {
+    let connections_label: Label = builder
+        .object("Connections Label")
+        .expect("Couldn't find connections label");
+    connections_label.set_text(&format!(
+        "Peers: {} outbound, {} inbound",
+        outbound, inbound
+    ));
+    if outbound == 0 {
+        connections_label.set_tooltip_text(Some("Not connected to any peer, reconnecting..."));
+    } else {
+        connections_label.set_tooltip_text(None);
+    }
+}
+
+/// Returns the time at which the connection started, as shown to the user
+fn format_connection_time(connected_since: u32) -> String {
+    match &NaiveDateTime::from_timestamp_opt(connected_since as i64, 0) {
+        Some(date) => Utc.from_utc_datetime(date).to_string(),
+        None => String::from("-"),
+    }
+}
+
+/// Builds the row of a peer, with buttons to disconnect from it and to ban it
+fn build_peer_row(peer: &PeerInfo, sender: &Sender<UIRequest>) -> Box {
+    let row_box = Box::new(Orientation::Horizontal, 10);
+    let direction = if peer.outbound { "Outbound" } else { "Inbound" };
+    let ping = match peer.ping {
+        Some(ping) => format!("{} ms", ping.as_millis()),
+        None => String::from("-"),
+    };
+    let announcements = if peer.features.send_headers {
+        "headers"
+    } else {
+        "inv"
+    };
+
+    let info_label = Label::new(Some(
+        format!(
+            "{}   {}   {}\nVersion: {}   Services: {:#x}   Start height: {}   Synced height: {}\nPing: {}   Sent: {:.1} KB   Received: {:.1} KB   Connected since: {}\nBlock announcements: {}   Fee filter: {} sat/kB",
+            peer.address,
+            direction,
+            peer.user_agent,
+            peer.version,
+            peer.services,
+            peer.start_height,
+            peer.best_known_height,
+            ping,
+            peer.bytes_sent as f64 / BYTES_IN_KB,
+            peer.bytes_received as f64 / BYTES_IN_KB,
+            format_connection_time(peer.connected_since),
+            announcements,
+            peer.features.fee_filter,
+        )
+        .as_str(),
+    ));
+    info_label.set_halign(Align::Start);
+    info_label.set_hexpand(true);
+    let disconnect_button = Button::with_label("Disconnect");
+    disconnect_button.set_valign(Align::Center);
+    let ban_button = Button::with_label("Ban");
+    ban_button.set_valign(Align::Center);
+
+    let address = peer.address;
+    let sender_clone = sender.clone();
+    disconnect_button.connect_clicked(move |_| {
+        sender_clone
+            .send(UIRequest::DisconnectPeer(address))
+            .expect(PEERS_SEND_ERROR);
+    });
+    let sender_clone = sender.clone();
+    ban_button.connect_clicked(move |_| {
+        sender_clone
+            .send(UIRequest::BanPeer(address))
+            .expect(PEERS_SEND_ERROR);
+    });
+
+    row_box.pack_start(&info_label, true, true, 0);
+    row_box.pack_start(&disconnect_button, false, false, 0);
+    row_box.pack_start(&ban_button, false, false, 0);
+    row_box.show_all();
+    row_box
+}
+
+/// Rebuilds the peers list with the information of every connected peer
+pub fn handle_peer_info(builder: &Builder, peers: &[PeerInfo], sender: &Sender<UIRequest>) {
+    let peers_list: ListBox = builder
+        .object("Peers List")
+        .expect("Couldn't find peers list");
+    for widget in peers_list.children() {
+        peers_list.remove(&widget);
+    }
+    for peer in peers {
+        peers_list.insert(&build_peer_row(peer, sender), -1);
+    }
+}
+
+/// Connects the refresh button so it asks the node for the information of its peers
+pub fn activate_refresh_peers_button(builder: &Builder, sender: &Sender<UIRequest>) {
+    let refresh_button: Button = builder
+        .object("Refresh Peers Button")
+        .expect("Couldn't find refresh peers button");
+    let sender_clone = sender.clone();
+    refresh_button.connect_clicked(move |_| {
+        sender_clone
+            .send(UIRequest::GetPeerInfo)
+            .expect(PEERS_SEND_ERROR);
+    });
+}
@@ -0,0 +1,135 @@
+use crate::hex_bytes_to_string::{get_hex_from_bytes, get_string_representation_from_bytes};
+use gtk::prelude::*;
+use gtk::{Align, Box, Builder, CheckButton, Label, ListBox, ListBoxRow, Orientation};
+use node::blocks::Outpoint;
+use node::utils::ui_communication_protocol::{UIRequest, UTxOInfo};
+use node::wallet::get_bytes_from_hex;
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+
+const SATOSHI_TO_BTC: f64 = 100000000.0;
+const FREEZE_SEND_ERROR: &str = "Error sending freeze request to Node/Wallet thread";
+const OUTPOINT_SEPARATOR: char = ':';
+
+/// Returns the name that identifies the row of an outpoint inside the coin control list
+fn outpoint_to_row_name(outpoint: &Outpoint) -> String {
+    format!(
+        "{}{}{}",
+        get_hex_from_bytes(&outpoint.hash),
+        OUTPOINT_SEPARATOR,
+        outpoint.index
+    )
+}
+
+/// Recovers the outpoint from the name of its row in the coin control list
+fn row_name_to_outpoint(row_name: &str) -> Option<Outpoint> {
+    let (hash, index) = row_name.split_once(OUTPOINT_SEPARATOR)?;
+    let hash: [u8; 32] = get_bytes_from_hex(hash.to_string()).ok()?.try_into().ok()?;
+    let index = index.parse::<u32>().ok()?;
+    Some(Outpoint::new(hash, index))
+}
+
+/// Returns the box of each row in the coin control list, alongside its select check button
+fn get_rows(list_box: &ListBox) -> Vec<(Box, CheckButton)> {
+    let mut rows = Vec::new();
+    for row in list_box.children() {
+        let row_box = match row
+            .downcast::<ListBoxRow>()
+            .ok()
+            .and_then(|row| row.child())
+        {
+            Some(child) => match child.downcast::<Box>() {
+                Ok(row_box) => row_box,
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        let select_button = match row_box.children().first() {
+            Some(widget) => match widget.clone().downcast::<CheckButton>() {
+                Ok(select_button) => select_button,
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+        rows.push((row_box, select_button));
+    }
+    rows
+}
+
+/// Builds the row of an utxo, with a check button to select it for the next transaction
+/// and another one to freeze it so the automatic selection skips it
+fn build_coin_control_row(utxo_info: &UTxOInfo, selected: bool, sender: &Sender<UIRequest>) -> Box {
+    let row_box = Box::new(Orientation::Horizontal, 10);
+    row_box.set_widget_name(&outpoint_to_row_name(&utxo_info.outpoint));
+    let amount_btc: f64 = utxo_info.amount as f64 / SATOSHI_TO_BTC;
+    let hash_as_string =
+        get_string_representation_from_bytes(&mut utxo_info.outpoint.hash.to_vec());
+
+    let select_button = CheckButton::with_label("Use");
+    select_button.set_active(selected && !utxo_info.frozen);
+    select_button.set_sensitive(!utxo_info.frozen);
+    let info_label = Label::new(Some(
+        format!(
+            "{}:{}   Amount: {}",
+            hash_as_string, utxo_info.outpoint.index, amount_btc
+        )
+        .as_str(),
+    ));
+    info_label.set_halign(Align::Start);
+    info_label.set_hexpand(true);
+    let freeze_button = CheckButton::with_label("Frozen");
+    freeze_button.set_active(utxo_info.frozen);
+
+    let outpoint = utxo_info.outpoint;
+    let sender_clone = sender.clone();
+    freeze_button.connect_toggled(move |button| {
+        sender_clone
+            .send(UIRequest::SetUtxoFrozen(outpoint, button.is_active()))
+            .expect(FREEZE_SEND_ERROR);
+    });
+
+    row_box.pack_start(&select_button, false, false, 0);
+    row_box.pack_start(&info_label, true, true, 0);
+    row_box.pack_start(&freeze_button, false, false, 0);
+    row_box.show_all();
+    row_box
+}
+
+/// Rebuilds the coin control list with the wallet utxos, keeping selected
+/// the ones the user had already chosen
+pub fn update_coin_control_list(builder: &Builder, utxos: &[UTxOInfo], sender: &Sender<UIRequest>) {
+    let coin_control_list: ListBox = builder
+        .object("Coin Control List")
+        .expect("Coin Control List not found");
+    let mut selected_rows = HashSet::new();
+    for (row_box, select_button) in get_rows(&coin_control_list) {
+        if select_button.is_active() {
+            selected_rows.insert(row_box.widget_name().to_string());
+        }
+    }
+    for widget in coin_control_list.children() {
+        coin_control_list.remove(&widget);
+    }
+
+    for utxo in utxos {
+        let selected = selected_rows.contains(&outpoint_to_row_name(&utxo.outpoint));
+        coin_control_list.insert(&build_coin_control_row(utxo, selected, sender), -1);
+    }
+}
+
+/// Returns the utxos selected by the user, or None if the wallet should choose them
+pub fn get_selected_outpoints(builder: &Builder) -> Option<Vec<Outpoint>> {
+    let coin_control_list: ListBox = builder
+        .object("Coin Control List")
+        .expect("Coin Control List not found");
+    let selected_outpoints: Vec<Outpoint> = get_rows(&coin_control_list)
+        .iter()
+        .filter(|(_, select_button)| select_button.is_active())
+        .filter_map(|(row_box, _)| row_name_to_outpoint(row_box.widget_name().as_str()))
+        .collect();
+
+    if selected_outpoints.is_empty() {
+        return None;
+    }
+    Some(selected_outpoints)
+}
@@ -0,0 +1,94 @@
+use gtk::gdk_pixbuf::{Colorspace, Pixbuf};
+use gtk::prelude::*;
+use gtk::{Builder, Button, Entry, Image, Label, SpinButton};
+use node::utils::ui_communication_protocol::{PaymentRequestInfo, UIRequest};
+use std::sync::mpsc::Sender;
+
+const BITCOIN_TO_SATOSHIS: f64 = 100000000.0;
+const QR_MODULE_SIZE: usize = 6;
+const QR_QUIET_ZONE: usize = 4;
+const PAYMENT_REQUEST_SEND_ERROR: &str = "Error sending payment request to Node/Wallet thread";
+
+/// Returns the text of the entry, or None if it is empty
+fn optional_text(entry: &Entry) -> Option<String> {
+    let text = entry.text().trim().to_string();
+    if text.is_empty() {
+        return None;
+    }
+    Some(text)
+}
+
+/// Builds a pixbuf drawing each dark module of the QR code as a black square,
+/// surrounded by the white quiet zone the standard requires
+fn build_qr_pixbuf(qr_code: &[Vec<bool>]) -> Option<Pixbuf> {
+    let side = (qr_code.len() + 2 * QR_QUIET_ZONE) * QR_MODULE_SIZE;
+    let pixbuf = Pixbuf::new(Colorspace::Rgb, false, 8, side as i32, side as i32)?;
+    pixbuf.fill(0xffffffff);
+    for (row_index, row) in qr_code.iter().enumerate() {
+        for (column_index, dark) in row.iter().enumerate() {
+            if !dark {
+                continue;
+            }
+            let x = (column_index + QR_QUIET_ZONE) * QR_MODULE_SIZE;
+            let y = (row_index + QR_QUIET_ZONE) * QR_MODULE_SIZE;
+            for i in 0..QR_MODULE_SIZE {
+                for j in 0..QR_MODULE_SIZE {
+                    pixbuf.put_pixel((x + i) as u32, (y + j) as u32, 0, 0, 0, 255);
+                }
+            }
+        }
+    }
+    Some(pixbuf)
+}
+
+/// Shows the payment request generated by the wallet: the address, the uri and its QR code
+pub fn handle_payment_request(builder: &Builder, payment_request: &PaymentRequestInfo) {
+    let address_label: Label = builder
+        .object("Receive Address Label")
+        .expect("Couldn't find receive address label");
+    let uri_label: Label = builder
+        .object("Receive URI Label")
+        .expect("Couldn't find receive uri label");
+    let qr_image: Image = builder
+        .object("Receive QR Image")
+        .expect("Couldn't find receive qr image");
+
+    address_label.set_text(&payment_request.address);
+    uri_label.set_text(&payment_request.uri);
+    match build_qr_pixbuf(&payment_request.qr_code) {
+        Some(pixbuf) => qr_image.set_from_pixbuf(Some(&pixbuf)),
+        None => qr_image.clear(),
+    }
+}
+
+/// Connects the signal of the generate payment request button, so it asks the wallet
+/// for a BIP21 uri with the amount, label and message written by the user.
+pub fn activate_receive_button(builder: &Builder, sender: &Sender<UIRequest>) {
+    let button: Button = builder
+        .object("Generate Payment Request Button")
+        .expect("Couldn't find generate payment request button");
+    let amount: SpinButton = builder
+        .object("Receive Amount")
+        .expect("Couldn't find receive amount spin button");
+    let label_entry: Entry = builder
+        .object("Receive Label Entry")
+        .expect("Couldn't find receive label entry");
+    let message_entry: Entry = builder
+        .object("Receive Message Entry")
+        .expect("Couldn't find receive message entry");
+    let sender_clone = sender.clone();
+    button.connect_clicked(move |_| {
+        let amount_in_sth = (amount.value() * BITCOIN_TO_SATOSHIS).round() as i64;
+        let amount_in_sth = match amount_in_sth {
+            0 => None,
+            amount_in_sth => Some(amount_in_sth),
+        };
+        sender_clone
+            .send(UIRequest::CreatePaymentRequest(
+                amount_in_sth,
+                optional_text(&label_entry),
+                optional_text(&message_entry),
+            ))
+            .expect(PAYMENT_REQUEST_SEND_ERROR);
+    });
+}
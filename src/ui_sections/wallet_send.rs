@@ -1,11 +1,14 @@
 use crate::utils::error_handling::{handle_ui_error, UiError};
+use crate::wallet_coin_control::get_selected_outpoints;
 use gtk::prelude::*;
 use gtk::{Adjustment, Builder, Button, Dialog, Entry, Label, SpinButton};
 use node::utils::ui_communication_protocol::UIRequest;
+use node::wallet::bip21::PaymentUri;
 use std::sync::mpsc::Sender;
 const ADDRESS_LEN: usize = 34;
 const BITCOIN_TO_SATOSHIS: f64 = 100000000.0;
 const TX_SEND_ERROR: &str = "Error sending transaction info to Node/Wallet thread";
+const PAYMENT_URI_SCHEME: &str = "bitcoin:";
 
 /// Updates the balance label with the new balance.
 pub fn update_balance(balance: &Builder, amount: &str) {
@@ -45,9 +48,23 @@ fn use_available_balance(available_balance_label: &SpinButton, balance_amount: &
     available_balance_label.set_value(new_value);
 }
 
+/// Reads the lock time entry, which can be empty to send the transaction right away,
+/// or hold a block height or a unix timestamp until which the transaction is locked.
+fn get_lock_time(builder: &Builder) -> Option<u32> {
+    let lock_time_entry: Entry = builder
+        .object("Lock Time Entry")
+        .expect("Couldn't find lock time entry");
+    let lock_time = lock_time_entry.text().trim().to_string();
+    if lock_time.is_empty() {
+        return Some(0);
+    }
+    lock_time.parse::<u32>().ok()
+}
+
 /// Handles the transaction sending process. It checks if the address is valid and if the amount is valid.
 /// If the fields are not correct, it shows an error dialog.
-/// If the fields are correct, it sends a CreateTx message to the wallet.
+/// If the fields are correct, it sends a CreateTx message to the wallet, with the utxos
+/// selected in the coin control list if there are any and the lock time.
 fn handle_transaction_sending(
     builder: &Builder,
     address: &str,
@@ -74,6 +91,10 @@ fn handle_transaction_sending(
         error_dialog.hide();
         return;
     }
+    let lock_time = match get_lock_time(builder) {
+        Some(lock_time) => lock_time,
+        None => return handle_ui_error(builder, UiError::ErrorParsingLockTime),
+    };
     let amount_in_sth = (amount * BITCOIN_TO_SATOSHIS).round() as i64;
     let fee_in_sth = (fee * BITCOIN_TO_SATOSHIS).round() as i64;
     sender
@@ -81,6 +102,8 @@ fn handle_transaction_sending(
             amount_in_sth,
             fee_in_sth,
             address.to_string(),
+            get_selected_outpoints(builder),
+            lock_time,
         ))
         .expect(TX_SEND_ERROR);
 }
@@ -142,11 +165,65 @@ pub fn activate_clear_all_button(builder: &Builder) {
         Some(pay_to_entry) => pay_to_entry,
         None => return,
     };
+    let payment_request_label: Label = match builder.object("Payment Request Label") {
+        Some(payment_request_label) => payment_request_label,
+        None => return,
+    };
 
     button.connect_clicked(move |_| {
         available_balance_button.set_value(0.0);
         fee_button.set_value(0.0);
         pay_to_entry.set_text("");
+        payment_request_label.set_text("");
+    });
+}
+
+/// Returns the text to show to the user with the label and message of a payment request
+fn payment_request_description(payment_uri: &PaymentUri) -> String {
+    let mut description = String::new();
+    if let Some(label) = &payment_uri.label {
+        description.push_str(&format!("Payment request from: {}   ", label));
+    }
+    if let Some(message) = &payment_uri.message {
+        description.push_str(&format!("Message: {}", message));
+    }
+    description
+}
+
+/// Fills the send form with the address, amount, label and message of a bitcoin: uri
+fn prefill_from_payment_uri(builder: &Builder, pay_to_entry: &Entry, uri: &str) {
+    let payment_request_label: Label = builder
+        .object("Payment Request Label")
+        .expect("Couldn't find payment request label");
+    let payment_uri = match PaymentUri::parse(uri) {
+        Ok(payment_uri) => payment_uri,
+        Err(_) => {
+            payment_request_label.set_text("Invalid payment URI");
+            return;
+        }
+    };
+    if let Some(amount) = payment_uri.amount {
+        let send_amount: SpinButton = builder
+            .object("Send Amount")
+            .expect("Couldn't find send amount spin button");
+        send_amount.set_value(amount as f64 / BITCOIN_TO_SATOSHIS);
+    }
+    payment_request_label.set_text(&payment_request_description(&payment_uri));
+    pay_to_entry.set_text(&payment_uri.address);
+}
+
+/// Connects the signal of the pay to entry so that pasting a BIP21 payment uri
+/// fills the address, amount and description of the payment
+pub fn activate_pay_to_entry(builder: &Builder) {
+    let pay_to_entry: Entry = builder
+        .object("Pay To Entry")
+        .expect("Couldn't find pay to entry");
+    let builder_clone = builder.clone();
+    pay_to_entry.connect_changed(move |entry| {
+        let text = entry.text();
+        if text.starts_with(PAYMENT_URI_SCHEME) {
+            prefill_from_payment_uri(&builder_clone, entry, text.as_str());
+        }
     });
 }
 
@@ -243,3 +320,54 @@ pub fn update_adjustments_max_value(builder: &Builder) {
     send_amount_adjustment.set_upper(balance);
     fee_amount_adjustment.set_upper(balance);
 }
+
+/// Connects the signals of the sweep dialog, which asks the wallet to move every utxo
+/// of a private key into the current wallet. The private key is cleared once it is sent.
+pub fn activate_sweep_button(builder: &Builder, sender: &Sender<UIRequest>) {
+    let sweep_button: Button = builder
+        .object("Sweep Button")
+        .expect("Couldn't find sweep button");
+    let sweep_dialog: Dialog = builder
+        .object("Sweep Dialog")
+        .expect("Couldn't find sweep dialog");
+    let cancel_button: Button = builder
+        .object("Sweep Cancel Button")
+        .expect("Couldn't find sweep cancel button");
+    let confirm_button: Button = builder
+        .object("Sweep Confirm Button")
+        .expect("Couldn't find sweep confirm button");
+    let priv_key_entry: Entry = builder
+        .object("Sweep Private Key Entry")
+        .expect("Couldn't find sweep private key entry");
+    let fee: SpinButton = builder
+        .object("Sweep Fee Amount")
+        .expect("Couldn't find sweep fee spin button");
+
+    sweep_dialog.set_title("Sweep Private Key");
+    let dialog_clone = sweep_dialog.clone();
+    sweep_button.connect_clicked(move |_| {
+        dialog_clone.show_all();
+        dialog_clone.run();
+        dialog_clone.hide();
+    });
+
+    let dialog_clone = sweep_dialog.clone();
+    let priv_key_entry_clone = priv_key_entry.clone();
+    cancel_button.connect_clicked(move |_| {
+        priv_key_entry_clone.set_text("");
+        dialog_clone.hide();
+    });
+
+    let sender_clone = sender.clone();
+    confirm_button.connect_clicked(move |_| {
+        let fee_in_sth = (fee.value() * BITCOIN_TO_SATOSHIS).round() as i64;
+        sender_clone
+            .send(UIRequest::SweepPrivKey(
+                priv_key_entry.text().trim().to_string(),
+                fee_in_sth,
+            ))
+            .expect(TX_SEND_ERROR);
+        priv_key_entry.set_text("");
+        sweep_dialog.hide();
+    });
+}
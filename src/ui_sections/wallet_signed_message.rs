@@ -0,0 +1,108 @@
+use gtk::prelude::*;
+use gtk::{Builder, Button, Dialog, Entry, Label};
+use node::utils::ui_communication_protocol::UIRequest;
+use std::sync::mpsc::Sender;
+
+const SIGNED_MESSAGE_SEND_ERROR: &str =
+    "Error sending signed message request to Node/Wallet thread";
+
+/// Shows the signature of the message alongside the address of the wallet that signed it
+pub fn handle_message_signed(builder: &Builder, address: &str, signature: &str) {
+    let address_entry: Entry = builder
+        .object("Signed Message Address Entry")
+        .expect("Couldn't find signed message address entry");
+    let signature_entry: Entry = builder
+        .object("Signature Entry")
+        .expect("Couldn't find signature entry");
+    let result_label: Label = builder
+        .object("Signed Message Result Label")
+        .expect("Couldn't find signed message result label");
+    address_entry.set_text(address);
+    signature_entry.set_text(signature);
+    result_label.set_text("Message signed with the current wallet");
+}
+
+/// Shows the result of verifying a signed message
+pub fn handle_message_verified(builder: &Builder, valid: bool) {
+    let result_label: Label = builder
+        .object("Signed Message Result Label")
+        .expect("Couldn't find signed message result label");
+    if valid {
+        result_label.set_text("The signature is valid for the address");
+    } else {
+        result_label.set_text("The signature does NOT match the address");
+    }
+}
+
+/// Connects the sign and verify buttons of the dialog so they send the
+/// corresponding request to the wallet
+fn activate_signed_message_buttons(builder: &Builder, sender: &Sender<UIRequest>) {
+    let sign_button: Button = builder
+        .object("Sign Message Button")
+        .expect("Couldn't find sign message button");
+    let verify_button: Button = builder
+        .object("Verify Message Button")
+        .expect("Couldn't find verify message button");
+    let close_button: Button = builder
+        .object("Signed Message Close Button")
+        .expect("Couldn't find signed message close button");
+    let dialog: Dialog = builder
+        .object("Signed Message Dialog")
+        .expect("Couldn't find signed message dialog");
+    let address_entry: Entry = builder
+        .object("Signed Message Address Entry")
+        .expect("Couldn't find signed message address entry");
+    let message_entry: Entry = builder
+        .object("Signed Message Entry")
+        .expect("Couldn't find signed message entry");
+    let signature_entry: Entry = builder
+        .object("Signature Entry")
+        .expect("Couldn't find signature entry");
+
+    let sender_clone = sender.clone();
+    let message_entry_clone = message_entry.clone();
+    sign_button.connect_clicked(move |_| {
+        sender_clone
+            .send(UIRequest::SignMessage(
+                message_entry_clone.text().to_string(),
+            ))
+            .expect(SIGNED_MESSAGE_SEND_ERROR);
+    });
+
+    let sender_clone = sender.clone();
+    verify_button.connect_clicked(move |_| {
+        sender_clone
+            .send(UIRequest::VerifyMessage(
+                address_entry.text().trim().to_string(),
+                signature_entry.text().trim().to_string(),
+                message_entry.text().to_string(),
+            ))
+            .expect(SIGNED_MESSAGE_SEND_ERROR);
+    });
+
+    close_button.connect_clicked(move |_| {
+        dialog.hide();
+    });
+}
+
+/// Initializes the sign/verify message dialog, which lets the user prove the ownership of the
+/// wallet address or check a signature made by someone else
+pub fn activate_signed_message_dialog(builder: &Builder, sender: &Sender<UIRequest>) {
+    let button: Button = builder
+        .object("Signed Message Menu Button")
+        .expect("Couldn't find signed message menu button");
+    let dialog: Dialog = builder
+        .object("Signed Message Dialog")
+        .expect("Couldn't find signed message dialog");
+    let result_label: Label = builder
+        .object("Signed Message Result Label")
+        .expect("Couldn't find signed message result label");
+    dialog.set_title("Sign/Verify Message");
+    button.connect_clicked(move |_| {
+        result_label.set_text("");
+        dialog.show_all();
+        dialog.run();
+        dialog.hide();
+    });
+    activate_signed_message_buttons(builder, sender);
+}
@@ -1,9 +1,9 @@
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use gtk::prelude::*;
-use gtk::{glib, Builder, Button, Dialog, Label, TreeSelection, TreeStore};
+use gtk::{glib, Builder, Button, Dialog, Entry, Label, TreeSelection, TreeStore};
 use node::blocks::proof::{hash_pairs_for_merkle_tree, HashPair};
 use node::blocks::BlockHeader;
-use node::utils::ui_communication_protocol::UIRequest;
+use node::utils::ui_communication_protocol::{AddressInfo, TransactionInfo, UIRequest};
 use node::wallet::get_bytes_from_hex;
 use std::sync::mpsc::Sender;
 
@@ -155,6 +155,105 @@ pub fn initialize_merkle_proof_button(builder: &Builder, sender: &Sender<UIReque
     });
 }
 
+/// Returns the txid written by the user in hex, in the byte order used by the node.
+fn parse_txid(txid: &str) -> Option<[u8; 32]> {
+    let mut txid_bytes = get_bytes_from_hex(txid.trim().to_string()).ok()?;
+    txid_bytes.reverse();
+    txid_bytes.try_into().ok()
+}
+
+/// Connects the buttons that look up what the user wrote: a transaction, either to show it
+/// or to request its merkle proof without knowing its block, or an address.
+pub fn initialize_tx_lookup_buttons(builder: &Builder, sender: &Sender<UIRequest>) {
+    let tx_lookup_entry: Entry = builder
+        .object("Tx Lookup Entry")
+        .expect("Couldn't find Tx Lookup Entry");
+    let tx_lookup_button: Button = builder
+        .object("Tx Lookup Button")
+        .expect("Couldn't find Tx Lookup Button");
+    let tx_lookup_proof_button: Button = builder
+        .object("Tx Lookup Proof Button")
+        .expect("Couldn't find Tx Lookup Proof Button");
+    let address_lookup_button: Button = builder
+        .object("Address Lookup Button")
+        .expect("Couldn't find Address Lookup Button");
+
+    let entry_clone = tx_lookup_entry.clone();
+    let builder_clone = builder.clone();
+    let sender_clone = sender.clone();
+    tx_lookup_button.connect_clicked(move |_| match parse_txid(&entry_clone.text()) {
+        Some(txid) => sender_clone
+            .send(UIRequest::GetTransaction(txid))
+            .expect(SENDER_ERROR),
+        None => handle_ui_error(&builder_clone, UiError::ErrorParsingTxid),
+    });
+
+    let entry_clone = tx_lookup_entry.clone();
+    let sender_clone = sender.clone();
+    address_lookup_button.connect_clicked(move |_| {
+        sender_clone
+            .send(UIRequest::GetAddressInfo(
+                entry_clone.text().trim().to_string(),
+            ))
+            .expect(SENDER_ERROR);
+    });
+
+    let builder_clone = builder.clone();
+    let sender_clone = sender.clone();
+    tx_lookup_proof_button.connect_clicked(move |_| match parse_txid(&tx_lookup_entry.text()) {
+        Some(txid) => sender_clone
+            .send(UIRequest::ObtainTxProofFromTxid(txid))
+            .expect(SENDER_ERROR),
+        None => handle_ui_error(&builder_clone, UiError::ErrorParsingTxid),
+    });
+}
+
+/// Shows the block and confirmations of the transaction that was looked up.
+pub fn handle_transaction_info(builder: &Builder, transaction_info: &TransactionInfo) {
+    let tx_lookup_result_label: Label = builder
+        .object("Tx Lookup Result Label")
+        .expect("Couldn't find Tx Lookup Result Label");
+    let tx_out_total: i64 = transaction_info
+        .transaction
+        .tx_out
+        .iter()
+        .map(|tx_out| tx_out.value)
+        .sum();
+
+    let location = match transaction_info.block_number {
+        Some(block_number) => format!("Block N° #{}", block_number),
+        None => String::from("Pending"),
+    };
+    tx_lookup_result_label.set_label(
+        format!(
+            "{}, {} confirmations, {} inputs, {} outputs, {} satoshis sent",
+            location,
+            transaction_info.confirmations,
+            transaction_info.transaction.tx_in.len(),
+            transaction_info.transaction.tx_out.len(),
+            tx_out_total
+        )
+        .as_str(),
+    );
+}
+
+/// Shows the balance, amount of utxos and amount of transactions of the address that was looked up.
+pub fn handle_address_info(builder: &Builder, address_info: &AddressInfo) {
+    let tx_lookup_result_label: Label = builder
+        .object("Tx Lookup Result Label")
+        .expect("Couldn't find Tx Lookup Result Label");
+
+    tx_lookup_result_label.set_label(
+        format!(
+            "Balance: {} satoshis, {} utxos, {} transactions",
+            address_info.balance,
+            address_info.utxos.len(),
+            address_info.history.len()
+        )
+        .as_str(),
+    );
+}
+
 fn add_merkle_root_for_tree_store(merkle_path_tree_store: &TreeStore, merkle_root: [u8; 32]) {
     let tree_iter = merkle_path_tree_store.append(None);
     let merkle_root_string = format!(
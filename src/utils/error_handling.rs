@@ -15,6 +15,8 @@ pub enum UiError {
     ErrorParsingBlockNumber,
     ErrorParsingBlockDate,
     ErrorParsingAmount,
+    ErrorParsingLockTime,
+    ErrorParsingTxid,
 }
 
 fn handle_error(builder: &Builder, text: String) {
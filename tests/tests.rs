@@ -27,7 +27,20 @@ mod test {
             begin_time: BEGIN_TIME_EPOCH,
             headers_path: String::from("tests_txt/headers.bin"),
             blocks_path: String::from("tests_txt/blocks.bin"),
+            block_index_path: String::from("tests_txt/block_index.bin"),
+            peers_path: String::from("tests_txt/peers.txt"),
+            banlist_path: String::from("tests_txt/banlist.txt"),
+            filter_headers_path: String::from("tests_txt/filter_headers.bin"),
+            utxo_set_path: String::from("tests_txt/utxo_set.bin"),
+            tx_index_path: String::from("tests_txt/tx_index.bin"),
+            address_index_path: String::from("tests_txt/address_index.bin"),
+            outbound_peers: 8,
             ipv6_enabled: false,
+            light_client: false,
+            v2_transport: false,
+            prune_target_mb: 0,
+            tx_index: false,
+            address_index: false,
             dns,
             external_addresses,
         }